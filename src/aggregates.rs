@@ -0,0 +1,705 @@
+// Per-line aggregates that can be saved to, and reloaded from, a compact
+// binary file so charts and exports can be regenerated without re-parsing
+// the CSV.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use clap::Args;
+
+use crate::bands::TimeBands;
+use crate::business::DayStart;
+use crate::record::is_placeholder;
+use crate::dates::{no_records_error, parse_date};
+use crate::filters::{DayOptions, DaySelection, DirectionFilter, Mode, ServiceOptions, TimeRange};
+
+// File header: magic bytes followed by a little-endian format version.
+const MAGIC: &[u8; 6] = b"PTVAGG";
+const FORMAT_VERSION: u32 = 18;
+
+/// Which business dates the hourly time series covers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DateSelection {
+    /// The first business date in the file.
+    #[default]
+    First,
+    /// One business date, from `--date`.
+    Date(String),
+    /// Every business date, averaged per hour (`--all-dates`).
+    All,
+}
+
+impl DateSelection {
+    /// Reads `--date <YYYY-MM-DD>` or `--all-dates`, defaulting to the first
+    /// date in the file.
+    pub fn from_args(options: &AggregateOptions) -> DateSelection {
+        match (&options.date, options.all_dates) {
+            (Some(date), _) => DateSelection::Date(date.clone()),
+            (None, true) => DateSelection::All,
+            (None, false) => DateSelection::First,
+        }
+    }
+}
+
+/// The options generateData and generateGraph build their aggregates with,
+/// which aggregates reloaded by `--load-agg` must match.
+#[derive(Args, Debug, Clone, Default)]
+pub struct AggregateOptions {
+    /// Business date of the time series, YYYY-MM-DD (default: the first in the file)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    pub date: Option<String>,
+    /// Average the time series over every business date
+    #[arg(long, conflicts_with = "date")]
+    pub all_dates: bool,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    pub day_start: Option<DayStart>,
+    /// Keep one direction of travel: U, D or both
+    #[arg(long, value_parser = DirectionFilter::parse)]
+    pub direction: Option<DirectionFilter>,
+    /// Count each line's up and down services separately, e.g. Pakenham_U
+    #[arg(long)]
+    pub split_direction: bool,
+    #[command(flatten)]
+    pub services: ServiceOptions,
+    #[command(flatten)]
+    pub days: DayOptions,
+    #[command(flatten)]
+    pub bands: TimeBands,
+    /// Reload aggregates saved by --save-agg instead of reading the CSV
+    #[arg(long, value_name = "PATH")]
+    pub load_agg: Option<String>,
+    /// Save the aggregates for a later --load-agg
+    #[arg(long, value_name = "PATH")]
+    pub save_agg: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Aggregates {
+    pub boardings_per_line: HashMap<String, i32>,
+    pub alightings_per_line: HashMap<String, i32>,
+    // Records per line, one per stop made.
+    pub stops_count: HashMap<String, i32>,
+    // Distinct services per line.
+    pub services: ServiceCounts,
+    // Hourly total movements per line, by business hour from `day_start`,
+    // summed over `time_series_dates`; `time_series()` averages them.
+    pub hourly_totals: HashMap<String, [i64; 24]>,
+    pub time_series_dates: BTreeSet<String>,
+    // Hourly total movements per (business date, line) for every date,
+    // whatever the date selection; generateData writes one file per pair
+    // without --date or --all-dates.
+    pub hourly_by_date: HashMap<(String, String), [i64; 24]>,
+    pub date_selection: DateSelection,
+    pub day_start: DayStart,
+    // Records outside this direction were left out of every total.
+    pub direction: DirectionFilter,
+    // Records of other modes were left out of every total.
+    pub mode: Mode,
+    // Records on other days (`--day-type`, `--day-of-week`) were left out of
+    // every total.
+    pub days: DaySelection,
+    // Records departing outside this range (`--from`, `--to`) were left out
+    // of every total.
+    pub time_range: TimeRange,
+    // With `--split-direction`, every per-line map is keyed by `line_key`,
+    // e.g. `Pakenham_U`, instead of the line name.
+    pub split_direction: bool,
+    // The single date the time series covers; `None` with `--all-dates`, or
+    // before the first date has been seen.
+    pub selected_business_date: Option<String>,
+    // Total movements per line per business date, across the whole file.
+    pub daily_movements: HashMap<String, HashMap<String, i64>>,
+    // Total movements per line in each time band (see `bands::BAND_NAMES`),
+    // using the band boundaries in effect when the CSV was aggregated.
+    pub band_movements: HashMap<String, [i64; 4]>,
+    // Sum of Passenger_Departure_Load over every record per line; divided by
+    // `stops_count` for the average occupancy.
+    pub departure_load_per_line: HashMap<String, i64>,
+    // Totals per Station_Name across every line, for `--by station`.
+    pub stations: HashMap<String, StationTotals>,
+    // Total movements per (Line_Name, Direction), whatever `split_direction`
+    // says, for the Up against Down chart.
+    pub direction_movements: HashMap<(String, String), i64>,
+    // Movements per Line_Name and Station_Name in each business hour, summed
+    // over `time_series_dates` like `hourly_totals`, for the station heatmap.
+    pub station_hours: HashMap<String, HashMap<String, StationHours>>,
+    // The largest Passenger_Departure_Load per line in each business hour
+    // over `time_series_dates`; `None` in an hour with no departures.
+    pub max_loads: HashMap<String, [Option<i32>; 24]>,
+}
+
+/// Distinct services per line. A service is a Train_Number on one
+/// Business_Date, since train numbers repeat from day to day. Keys are kept
+/// as hashes so the sets stay small over the full dataset.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceCounts(HashMap<String, HashSet<u64>>);
+
+impl ServiceCounts {
+    /// Counts the service `train_number` on `business_date` once for `line`,
+    /// however many of its stops are added.
+    pub fn add(&mut self, line: &str, business_date: &str, train_number: &str) {
+        let mut hasher = DefaultHasher::new();
+        (business_date, train_number).hash(&mut hasher);
+        if let Some(services) = self.0.get_mut(line) {
+            services.insert(hasher.finish());
+        } else {
+            self.0.insert(line.to_string(), HashSet::from([hasher.finish()]));
+        }
+    }
+
+    /// The number of services seen on `line`.
+    pub fn count(&self, line: &str) -> i64 {
+        self.0.get(line).map_or(0, |services| services.len() as i64)
+    }
+
+    /// The number of lines with services.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// One station's movements on a line in each business hour, `None` in an
+/// hour with no stops there, and its stop sequence numbers, which order the
+/// line's stations.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationHours {
+    pub hours: [Option<i64>; 24],
+    // Sums and counts of Stop_Sequence_Number over Down records, which run
+    // from the city, and over every record.
+    down_sequence: (i64, i64),
+    any_sequence: (i64, i64),
+}
+
+impl StationHours {
+    /// Where the station falls along the line: its average stop sequence on
+    /// Down services, or on every service if it has none.
+    fn position(&self, by_down: bool) -> f64 {
+        let (total, count) = if by_down && self.down_sequence.1 > 0 { self.down_sequence } else { self.any_sequence };
+        let position = total as f64 / count.max(1) as f64;
+        // On a line with Down records, stations without any go after those with them.
+        if by_down && self.down_sequence.1 == 0 { position + f64::from(i32::MAX) } else { position }
+    }
+}
+
+/// A line's stations in order along it, each with its movements per
+/// business hour; `None` where no stop was made in that hour.
+pub type StationGrid = Vec<(String, [Option<f64>; 24])>;
+
+/// One station's boardings, alightings and stops made there, one per record.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationTotals {
+    // From the station's first row with usable coordinates, for --sqlite.
+    pub coordinates: Option<(f64, f64)>,
+    pub boardings: i64,
+    pub alightings: i64,
+    pub services: i64,
+}
+
+impl StationTotals {
+    pub fn movements(&self) -> i64 {
+        self.boardings + self.alightings
+    }
+
+    /// Boardings per service stopping at the station; `None` with no stops.
+    pub fn boardings_per_service(&self) -> Option<f64> {
+        if self.services > 0 { Some(self.boardings as f64 / self.services as f64) } else { None }
+    }
+}
+
+impl Aggregates {
+    /// Writes the aggregates to `path` with a versioned header.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads aggregates previously written by `save`, rejecting files with
+    /// the wrong header or an unsupported format version.
+    pub fn load(path: &str) -> Result<Aggregates, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(format!("'{}' is not a saved aggregates file", path).into());
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "'{}' uses aggregates format version {}, expected {}",
+                path, version, FORMAT_VERSION
+            ).into());
+        }
+
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
+    /// Key for a record's line in the per-line maps: the line name, or with
+    /// `--split-direction` the line and direction, e.g. `Pakenham_U`.
+    pub fn line_key(&self, line: &str, direction: &str) -> String {
+        if self.split_direction {
+            format!("{}_{}", line, direction.trim())
+        } else {
+            line.to_string()
+        }
+    }
+
+    /// Errors if an option the aggregates are built with differs from the
+    /// one they were built with: `--day-start`, since the hourly series
+    /// can't be re-bucketed, `--date` or `--all-dates`, `--direction`,
+    /// `--mode`, `--day-type` or `--day-of-week`, `--from` or `--to`, and
+    /// `--split-direction` for aggregates built without it.
+    pub fn check(&self, options: &AggregateOptions) -> Result<(), Box<dyn Error>> {
+        if options.day_start.is_some_and(|day_start| day_start != self.day_start) {
+            return Err(format!(
+                "the aggregates were built with --day-start {}; rebuild them to use another day start",
+                self.day_start.hour_label(0)
+            ).into());
+        }
+        let requested = DateSelection::from_args(options);
+        if requested != DateSelection::First && requested != self.date_selection {
+            return Err(format!(
+                "the aggregates hold the time series for {}; rebuild them to use another date",
+                self.time_series_label().unwrap_or_else(|| "no date".to_string())
+            ).into());
+        }
+        if options.direction.is_some_and(|direction| direction != self.direction) {
+            return Err(format!(
+                "the aggregates were built with --direction {}; rebuild them to use another direction",
+                self.direction.code().unwrap_or("both")
+            ).into());
+        }
+        if options.services.mode.is_some_and(|mode| mode != self.mode) {
+            return Err(format!(
+                "the aggregates were built with --mode {}; rebuild them to use another mode",
+                self.mode.name()
+            ).into());
+        }
+        let days = DaySelection::from_args(&options.days);
+        if days.is_active() && days != self.days {
+            return Err(format!(
+                "the aggregates were built for {}; rebuild them to select other days",
+                self.days.describe()
+            ).into());
+        }
+        let range = TimeRange::from_args(&options.days)?;
+        if range.is_active() && range != self.time_range {
+            return Err(format!(
+                "the aggregates were built for departures {}; rebuild them to select other times",
+                self.time_range.describe()
+            ).into());
+        }
+        if options.split_direction && !self.split_direction {
+            return Err("the aggregates were built without --split-direction; rebuild them to split by direction".into());
+        }
+        Ok(())
+    }
+
+    /// Whether a record on `business_date` counts towards the time series.
+    /// By default the first date seen is selected and every other is left
+    /// out.
+    pub fn in_time_series(&mut self, business_date: &str) -> bool {
+        match &self.date_selection {
+            DateSelection::First => {
+                let selected = self.selected_business_date.get_or_insert_with(|| business_date.to_string());
+                selected == business_date
+            }
+            DateSelection::Date(date) => {
+                self.selected_business_date.get_or_insert_with(|| date.clone());
+                date == business_date
+            }
+            DateSelection::All => true,
+        }
+    }
+
+    /// Adds `movements` in business hour `hour` on `business_date`, which
+    /// `in_time_series` has accepted.
+    pub fn add_hourly(&mut self, line: &str, business_date: &str, hour: usize, movements: i64) {
+        self.hourly_totals.entry(line.to_string()).or_insert([0; 24])[hour] += movements;
+        if !self.time_series_dates.contains(business_date) {
+            self.time_series_dates.insert(business_date.to_string());
+        }
+    }
+
+    /// Adds one stop's boardings and alightings to the station's totals.
+    pub fn add_station(&mut self, station: &str, coordinates: Option<(f64, f64)>, boardings: i32, alightings: i32) {
+        let totals = self.stations.entry(station.to_string()).or_default();
+        if totals.coordinates.is_none() {
+            totals.coordinates = coordinates.filter(|&(lat, lon)| !is_placeholder(lat, lon));
+        }
+        totals.boardings += boardings as i64;
+        totals.alightings += alightings as i64;
+        totals.services += 1;
+    }
+
+    /// Adds one stop's movements to its line's total in its direction.
+    pub fn add_direction(&mut self, line: &str, direction: &str, movements: i64) {
+        *self.direction_movements.entry((line.to_string(), direction.to_string())).or_insert(0) += movements;
+    }
+
+    /// Stations with their totals, busiest (most movements) first.
+    pub fn stations_by_movements(&self) -> Vec<(&String, &StationTotals)> {
+        let mut stations: Vec<(&String, &StationTotals)> = self.stations.iter().collect();
+        stations.sort_by(|a, b| b.1.movements().cmp(&a.1.movements()).then_with(|| a.0.cmp(b.0)));
+        stations
+    }
+
+    /// Adds `movements` in business hour `hour` to `line` on `business_date`
+    /// in the per-date series, for every date.
+    pub fn add_hourly_by_date(&mut self, business_date: &str, line: &str, hour: usize, movements: i64) {
+        self.hourly_by_date.entry((business_date.to_string(), line.to_string())).or_insert([0; 24])[hour] += movements;
+    }
+
+    /// Counts one stop departure's load in business hour `hour`, on a date
+    /// `in_time_series` has accepted, towards `line`'s largest in that hour.
+    pub fn add_max_load(&mut self, line: &str, hour: usize, departure_load: i32) {
+        let loads = match self.max_loads.get_mut(line) {
+            Some(loads) => loads,
+            None => self.max_loads.entry(line.to_string()).or_insert([None; 24]),
+        };
+        loads[hour] = loads[hour].max(Some(departure_load));
+    }
+
+    /// Adds one stop's `movements` in business hour `hour`, on a date
+    /// `in_time_series` has accepted, to its station's row on `line`.
+    pub fn add_station_hour(&mut self, line: &str, station: &str, direction: &str, stop_sequence: i32, hour: usize, movements: i64) {
+        let stations = match self.station_hours.get_mut(line) {
+            Some(stations) => stations,
+            None => self.station_hours.entry(line.to_string()).or_default(),
+        };
+        let row = match stations.get_mut(station) {
+            Some(row) => row,
+            None => stations.entry(station.to_string()).or_default(),
+        };
+        *row.hours[hour].get_or_insert(0) += movements;
+        let sequence = stop_sequence as i64;
+        if direction.trim() == "D" {
+            row.down_sequence.0 += sequence;
+            row.down_sequence.1 += 1;
+        }
+        row.any_sequence.0 += sequence;
+        row.any_sequence.1 += 1;
+    }
+
+    /// The line named `name` (ignoring case) and its stations in stop
+    /// sequence order, with movements averaged over the time series dates
+    /// like `time_series`. Errors with the available lines if there's none.
+    pub fn station_grid(&self, name: &str) -> Result<(String, StationGrid), String> {
+        let Some((line, stations)) = self.station_hours.iter().find(|(line, _)| line.eq_ignore_ascii_case(name)) else {
+            let mut available: Vec<&str> = self.station_hours.keys().map(|line| line.as_str()).collect();
+            available.sort();
+            return Err(format!("--line: no line '{}' in the time series; available: {}", name, available.join(", ")));
+        };
+        let by_down = stations.values().any(|row| row.down_sequence.1 > 0);
+        let mut ordered: Vec<(&String, &StationHours)> = stations.iter().collect();
+        ordered.sort_by(|a, b| a.1.position(by_down).total_cmp(&b.1.position(by_down)).then_with(|| a.0.cmp(b.0)));
+        let days = self.time_series_dates.len().max(1) as f64;
+        let grid = ordered.into_iter()
+            .map(|(station, row)| (station.clone(), row.hours.map(|total| total.map(|total| total as f64 / days))))
+            .collect();
+        Ok((line.clone(), grid))
+    }
+
+    /// Hourly movements per line: the selected date's, or with `--all-dates`
+    /// the average over every date in the time series. A line missing from
+    /// a date counts as zero on it.
+    pub fn time_series(&self) -> HashMap<String, [f64; 24]> {
+        let days = self.time_series_dates.len().max(1) as f64;
+        self.hourly_totals.iter()
+            .map(|(line, totals)| (line.clone(), totals.map(|total| total as f64 / days)))
+            .collect()
+    }
+
+    /// What the time series covers, for chart captions and messages, e.g.
+    /// `2022-09-12` or `2022-09-12 to 2022-09-16, 5-date average`.
+    pub fn time_series_label(&self) -> Option<String> {
+        match self.date_selection {
+            DateSelection::All => {
+                let (first, last) = (self.time_series_dates.first()?, self.time_series_dates.last()?);
+                Some(format!("{} to {}, {}-date average", first, last, self.time_series_dates.len()))
+            }
+            _ => self.selected_business_date.clone(),
+        }
+    }
+
+    /// Fails if `--date` matched no records, listing the dates in the file.
+    pub fn check_selected_date(&self) -> Result<(), String> {
+        match &self.date_selection {
+            DateSelection::Date(date) if self.time_series_dates.is_empty() => {
+                let available = self.daily_movements.values().flat_map(|dates| dates.keys().cloned()).collect();
+                Err(no_records_error(date, &available))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Says which dates the time series covers, and how to choose others
+    /// when the first date was picked by default.
+    pub fn describe_time_series(&self) -> String {
+        match (&self.date_selection, self.time_series_label()) {
+            (DateSelection::First, Some(date)) => format!(
+                "Time series for business date {}, the first in the file; use --date or --all-dates to choose.",
+                date
+            ),
+            (_, Some(label)) => format!("Time series for {}.", label),
+            (_, None) => "No records with departure times; the time series is empty.".to_string(),
+        }
+    }
+
+    /// Prints the number of keys held by each accumulator map.
+    pub fn print_sizes(&self) {
+        println!("Accumulator sizes:");
+        println!("  boardings_per_line:  {} keys", self.boardings_per_line.len());
+        println!("  alightings_per_line: {} keys", self.alightings_per_line.len());
+        println!("  stops_count:         {} keys", self.stops_count.len());
+        println!("  services:            {} keys", self.services.len());
+        println!("  hourly_totals:       {} keys", self.hourly_totals.len());
+        println!("  hourly_by_date:      {} keys", self.hourly_by_date.len());
+        println!("  band_movements:      {} keys", self.band_movements.len());
+        println!("  departure_load:      {} keys", self.departure_load_per_line.len());
+        println!("  stations:            {} keys", self.stations.len());
+        println!("  direction_movements: {} keys", self.direction_movements.len());
+        println!("  daily_movements:     {} keys", self.daily_movements.values().map(|dates| dates.len()).sum::<usize>());
+    }
+
+    /// Total movements (boardings + alightings) per line.
+    pub fn total_movements(&self) -> HashMap<String, i32> {
+        self.boardings_per_line.iter()
+            .map(|(line, &boardings)| {
+                let alightings = self.alightings_per_line.get(line).unwrap_or(&0);
+                (line.clone(), boardings + alightings)
+            })
+            .collect()
+    }
+
+    /// Load-weighted average occupancy per line: the mean departure load
+    /// over every recorded stop departure. Each stop counts once, so a line
+    /// is weighted by how many stops its services make across the day, not
+    /// by passenger numbers or by service. `None` for a line with no stops.
+    pub fn average_occupancy(&self) -> BTreeMap<String, Option<f64>> {
+        self.stops_count.iter()
+            .map(|(line, &stops)| {
+                let load = self.departure_load_per_line.get(line).copied().unwrap_or(0);
+                let average = if stops > 0 { Some(load as f64 / stops as f64) } else { None };
+                (line.clone(), average)
+            })
+            .collect()
+    }
+}
+
+/// Parses the nominal train capacity given by `--capacity <n>`, in
+/// passengers, for loads as a percentage of it.
+pub fn capacity(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(capacity) if capacity > 0.0 => Ok(capacity),
+        _ => Err("expected a positive number of passengers".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+
+    #[test]
+    fn all_dates_averages_over_every_date_in_the_series() {
+        let mut agg = Aggregates { date_selection: DateSelection::All, ..Aggregates::default() };
+        for (line, date, hour, movements) in [
+            ("Pakenham", "2022-09-12", 5, 100),
+            ("Pakenham", "2022-09-13", 5, 300),
+            ("Pakenham", "2022-09-13", 6, 50),
+            // Only runs on the second date, so averages in a zero for the first.
+            ("Sandringham", "2022-09-13", 5, 40),
+        ] {
+            assert!(agg.in_time_series(date));
+            agg.add_hourly(line, date, hour, movements);
+        }
+
+        let series = agg.time_series();
+        assert_eq!(series["Pakenham"][5], 200.0);
+        assert_eq!(series["Pakenham"][6], 25.0);
+        assert_eq!(series["Sandringham"][5], 20.0);
+        assert_eq!(series["Pakenham"][7], 0.0);
+        assert_eq!(agg.selected_business_date, None);
+        assert_eq!(agg.time_series_label().unwrap(), "2022-09-12 to 2022-09-13, 2-date average");
+    }
+
+    #[test]
+    fn station_grid_orders_stations_by_down_stop_sequence() {
+        let mut agg = Aggregates { date_selection: DateSelection::All, ..Aggregates::default() };
+        for (date, station, direction, sequence, hour, movements) in [
+            ("2022-09-12", "Flinders Street", "D", 1, 5, 400),
+            ("2022-09-12", "Richmond", "D", 2, 5, 120),
+            ("2022-09-12", "Pakenham", "D", 20, 6, 30),
+            // Up services number the stops the other way.
+            ("2022-09-13", "Pakenham", "U", 1, 4, 90),
+            ("2022-09-13", "Richmond", "U", 19, 5, 200),
+            ("2022-09-13", "Flinders Street", "U", 20, 6, 600),
+        ] {
+            assert!(agg.in_time_series(date));
+            agg.add_hourly("Pakenham", date, hour, movements);
+            agg.add_station_hour("Pakenham", station, direction, sequence, hour, movements);
+        }
+
+        let (line, grid) = agg.station_grid("pakenham").unwrap();
+        assert_eq!(line, "Pakenham");
+        let stations: Vec<&str> = grid.iter().map(|(station, _)| station.as_str()).collect();
+        assert_eq!(stations, ["Flinders Street", "Richmond", "Pakenham"]);
+        // Averaged over both dates, and None in hours with no stops.
+        assert_eq!(grid[1].1[5], Some(160.0));
+        assert_eq!(grid[0].1[6], Some(300.0));
+        assert_eq!(grid[0].1[4], None);
+        assert!(agg.station_grid("Frankston").unwrap_err().ends_with("available: Pakenham"));
+    }
+
+    #[test]
+    fn max_loads_keep_the_largest_departure_load_in_each_hour() {
+        let mut agg = Aggregates::default();
+        for (hour, load) in [(5, 320), (5, 610), (5, 0), (6, 45)] {
+            agg.add_max_load("Pakenham", hour, load);
+        }
+        let loads = agg.max_loads["Pakenham"];
+        assert_eq!((loads[4], loads[5], loads[6]), (None, Some(610), Some(45)));
+
+        assert_eq!(capacity("800"), Ok(800.0));
+        assert!(capacity("0").is_err() && capacity("many").is_err());
+    }
+
+    #[test]
+    fn saved_aggregates_load_back_unchanged() {
+        let path = std::env::temp_dir().join(format!("ptv_data_aggregates_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut agg = Aggregates { date_selection: DateSelection::Date("2022-09-12".to_string()), ..Aggregates::default() };
+        agg.boardings_per_line.insert("Pakenham".to_string(), 5_210);
+        agg.alightings_per_line.insert("Pakenham".to_string(), 4_980);
+        agg.stops_count.insert("Pakenham".to_string(), 812);
+        agg.services.add("Pakenham", "2022-09-12", "1001");
+        assert!(agg.in_time_series("2022-09-12"));
+        agg.add_hourly("Pakenham", "2022-09-12", 5, 400);
+        agg.add_hourly_by_date("2022-09-12", "Pakenham", 5, 400);
+        agg.add_station_hour("Pakenham", "Richmond", "D", 2, 5, 120);
+        agg.add_max_load("Pakenham", 5, 610);
+        agg.save(path).unwrap();
+        assert_eq!(Aggregates::load(path).unwrap(), agg);
+
+        // Another format version is refused rather than misread.
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION - 1).to_le_bytes());
+        std::fs::write(path, &bytes).unwrap();
+        let err = Aggregates::load(path).unwrap_err().to_string();
+        assert!(err.contains(&format!("version {}, expected {}", FORMAT_VERSION - 1, FORMAT_VERSION)), "{}", err);
+
+        // As is a file that isn't saved aggregates at all.
+        std::fs::write(path, b"Business_Date,Line_Name\n").unwrap();
+        let err = Aggregates::load(path).unwrap_err().to_string();
+        assert!(err.contains("is not a saved aggregates file"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn single_dates_keep_their_totals() {
+        let mut first = Aggregates::default();
+        let mut chosen = Aggregates { date_selection: DateSelection::Date("2022-09-13".to_string()), ..Aggregates::default() };
+        for agg in [&mut first, &mut chosen] {
+            for (date, movements) in [("2022-09-12", 100), ("2022-09-13", 300)] {
+                if agg.in_time_series(date) {
+                    agg.add_hourly("Pakenham", date, 5, movements);
+                }
+            }
+        }
+        assert_eq!(first.time_series()["Pakenham"][5], 100.0);
+        assert_eq!(first.selected_business_date.as_deref(), Some("2022-09-12"));
+        assert_eq!(chosen.time_series()["Pakenham"][5], 300.0);
+        assert_eq!(chosen.time_series_label().as_deref(), Some("2022-09-13"));
+    }
+
+    #[test]
+    fn every_date_keeps_its_own_hourly_series() {
+        let mut agg = Aggregates::default();
+        for (date, hour, movements) in [("2022-09-12", 5, 100), ("2022-09-13", 5, 300), ("2022-09-13", 5, 20)] {
+            agg.in_time_series(date);
+            agg.add_hourly_by_date(date, "Pakenham", hour, movements);
+        }
+        assert_eq!(agg.hourly_by_date.len(), 2);
+        assert_eq!(agg.hourly_by_date[&("2022-09-12".to_string(), "Pakenham".to_string())][5], 100);
+        assert_eq!(agg.hourly_by_date[&("2022-09-13".to_string(), "Pakenham".to_string())][5], 320);
+    }
+
+    #[test]
+    fn stations_with_equal_movements_go_by_name() {
+        let mut agg = Aggregates::default();
+        for (station, boardings, alightings) in [
+            ("Richmond", 300, 200),
+            ("Flinders Street", 900, 800),
+            ("Melbourne Central", 250, 250),
+            ("Richmond", 10, 0),
+            ("Caulfield", 500, 0),
+        ] {
+            agg.add_station(station, None, boardings, alightings);
+        }
+        let order: Vec<(&str, i64)> = agg.stations_by_movements().into_iter()
+            .map(|(station, totals)| (station.as_str(), totals.movements()))
+            .collect();
+        assert_eq!(order, [
+            ("Flinders Street", 1700),
+            ("Richmond", 510),
+            ("Caulfield", 500),
+            ("Melbourne Central", 500),
+        ]);
+    }
+
+    #[test]
+    fn services_count_each_train_once_per_date() {
+        let mut services = ServiceCounts::default();
+        for (line, date, train) in [
+            ("Pakenham", "2022-09-12", "1001"),
+            ("Pakenham", "2022-09-12", "1001"),
+            ("Pakenham", "2022-09-12", "1003"),
+            // The same train number on the next day is another service.
+            ("Pakenham", "2022-09-13", "1001"),
+            ("Frankston", "2022-09-12", "1001"),
+        ] {
+            services.add(line, date, train);
+        }
+        assert_eq!((services.count("Pakenham"), services.count("Frankston"), services.count("Upfield")), (3, 1, 0));
+        assert_eq!(services.len(), 2);
+    }
+
+    #[test]
+    fn date_flags_conflict() {
+        assert!(parse_group::<AggregateOptions>(&["--date", "2022-09-12", "--all-dates"]).is_err());
+        assert!(parse_group::<AggregateOptions>(&["--date", "12/09/2022"]).is_err());
+        let options: AggregateOptions = parse_group(&["--all-dates"]).unwrap();
+        assert_eq!(DateSelection::from_args(&options), DateSelection::All);
+    }
+
+    #[test]
+    fn loaded_aggregates_must_match_the_options_given() {
+        let agg = Aggregates { day_start: DayStart::parse("04:00").unwrap(), ..Aggregates::default() };
+        let check = |args: &[&str]| agg.check(&parse_group(args).unwrap()).map_err(|err| err.to_string());
+        assert!(check(&[]).is_ok());
+        assert!(check(&["--day-start", "04:00", "--mode", "all", "--direction", "both"]).is_ok());
+        assert!(check(&["--day-start", "03:00"]).unwrap_err().contains("--day-start 04:00"));
+        assert!(check(&["--mode", "metro"]).unwrap_err().contains("--mode all"));
+        assert!(check(&["--date", "2022-09-12"]).is_err());
+        assert!(check(&["--split-direction"]).is_err());
+    }
+}
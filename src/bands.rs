@@ -0,0 +1,104 @@
+// Time-of-day bands used for peak reporting: AM peak, interpeak, PM peak
+// and off-peak (everything else, including overnight).
+
+use chrono::NaiveTime;
+use clap::Args;
+
+pub const BAND_NAMES: [&str; 4] = ["AM Peak", "Interpeak", "PM Peak", "Off-peak"];
+
+pub const AM_PEAK: usize = 0;
+pub const INTERPEAK: usize = 1;
+pub const PM_PEAK: usize = 2;
+pub const OFF_PEAK: usize = 3;
+
+/// The peak windows, from `--am-peak` and `--pm-peak`. A window may wrap
+/// past midnight.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct TimeBands {
+    // Half-open [start, end) windows; interpeak runs from the end of the AM
+    // peak to the start of the PM peak.
+    /// AM peak window, HH:MM-HH:MM
+    #[arg(long, value_name = "HH:MM-HH:MM", default_value = "07:00-09:30", value_parser = parse_window)]
+    pub am_peak: (NaiveTime, NaiveTime),
+    /// PM peak window, HH:MM-HH:MM
+    #[arg(long, value_name = "HH:MM-HH:MM", default_value = "15:00-18:30", value_parser = parse_window)]
+    pub pm_peak: (NaiveTime, NaiveTime),
+}
+
+impl Default for TimeBands {
+    fn default() -> TimeBands {
+        TimeBands {
+            am_peak: (hm(7, 0), hm(9, 30)),
+            pm_peak: (hm(15, 0), hm(18, 30)),
+        }
+    }
+}
+
+impl TimeBands {
+    /// Returns the index into `BAND_NAMES` of the band containing `time`.
+    pub fn band(&self, time: NaiveTime) -> usize {
+        if in_window(time, self.am_peak) {
+            AM_PEAK
+        } else if in_window(time, self.pm_peak) {
+            PM_PEAK
+        } else if in_window(time, (self.am_peak.1, self.pm_peak.0)) {
+            INTERPEAK
+        } else {
+            OFF_PEAK
+        }
+    }
+}
+
+/// Whether `time` falls in the half-open window, which may wrap past midnight.
+fn in_window(time: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start <= end {
+        time >= start && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+fn parse_window(value: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let invalid = || "expected HH:MM-HH:MM".to_string();
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+    Ok((start, end))
+}
+
+fn hm(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).expect("valid time")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+
+    #[test]
+    fn windows_are_half_open_at_band_boundaries() {
+        let bands = TimeBands::default();
+        let band = |hour, minute, second| BAND_NAMES[bands.band(NaiveTime::from_hms_opt(hour, minute, second).unwrap())];
+        assert_eq!(band(6, 59, 59), "Off-peak");
+        assert_eq!(band(7, 0, 0), "AM Peak");
+        assert_eq!(band(9, 29, 59), "AM Peak");
+        assert_eq!(band(9, 30, 0), "Interpeak");
+        assert_eq!(band(14, 59, 59), "Interpeak");
+        assert_eq!(band(15, 0, 0), "PM Peak");
+        assert_eq!(band(18, 30, 0), "Off-peak");
+    }
+
+    #[test]
+    fn a_window_may_straddle_midnight() {
+        let bands: TimeBands = parse_group(&["--pm-peak", "22:00-01:00"]).unwrap();
+        let band = |hour, minute| bands.band(hm(hour, minute));
+        assert_eq!(band(21, 59), INTERPEAK);
+        assert_eq!(band(22, 0), PM_PEAK);
+        assert_eq!(band(0, 0), PM_PEAK);
+        assert_eq!(band(0, 59), PM_PEAK);
+        assert_eq!(band(1, 0), OFF_PEAK);
+        assert_eq!(band(8, 0), AM_PEAK);
+
+        assert!(parse_group::<TimeBands>(&["--am-peak", "7am-9am"]).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+// The business day that hourly and 15-minute buckets count from. data.csv
+// dates each stop by a business day running 03:00 to 02:59, with stops after
+// midnight kept on the date before. `--day-start HH:00` moves the boundary;
+// stops that end up on the other side of it move to the neighbouring date,
+// so every bucket runs from the start of the day it's counted on.
+
+use chrono::{Duration, NaiveDate, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::record::Record;
+
+// The hour data.csv's business dates start at.
+const SOURCE_DAY_START: u32 = 3;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DayStart {
+    hour: u32,
+}
+
+impl Default for DayStart {
+    fn default() -> DayStart {
+        DayStart { hour: SOURCE_DAY_START }
+    }
+}
+
+impl DayStart {
+    /// Parses a `--day-start` whole hour as `HH:00` or just `HH`, e.g.
+    /// `00:00` or `4`; buckets stay aligned with clock hours. Without the
+    /// flag the day starts at 03:00.
+    pub fn parse(value: &str) -> Result<DayStart, String> {
+        let (hour, minute) = value.trim().split_once(':').unwrap_or((value.trim(), "00"));
+        match (hour.parse::<u32>(), minute) {
+            (Ok(hour), "00") if hour < 24 => Ok(DayStart { hour }),
+            _ => Err("expected a whole hour from 00:00 to 23:00".to_string()),
+        }
+    }
+
+    pub fn hour(&self) -> u32 {
+        self.hour
+    }
+
+    /// Where a stop at `hour:minute` on a record's Business_Date falls.
+    /// Hours past 23, as some extracts write after-midnight stops, are
+    /// accepted.
+    pub fn locate(&self, hour: u32, minute: u32) -> BusinessTime {
+        // Minutes from midnight at the start of the record's business date.
+        let mut clock = (hour * 60 + minute) as i64;
+        if hour < SOURCE_DAY_START {
+            clock += MINUTES_PER_DAY;
+        }
+        let since_start = clock - (self.hour * 60) as i64;
+        BusinessTime {
+            days: since_start.div_euclid(MINUTES_PER_DAY),
+            minutes: since_start.rem_euclid(MINUTES_PER_DAY) as u32,
+        }
+    }
+
+    /// Where a stop at clock time `time` on a record's Business_Date falls.
+    pub fn at(&self, time: NaiveTime) -> BusinessTime {
+        self.locate(time.hour(), time.minute())
+    }
+
+    /// The business date a record's stop counts on, and where its departure
+    /// falls in that day. Stops without a departure time stay on their
+    /// Business_Date.
+    pub fn business_date(&self, record: &Record) -> (String, Option<BusinessTime>) {
+        self.departure(&record.business_date, &record.departure_time_scheduled)
+    }
+
+    /// As `business_date`, for a Business_Date and scheduled departure time
+    /// taken from anything other than a `Record`.
+    pub fn departure(&self, business_date: &str, departure_time: &str) -> (String, Option<BusinessTime>) {
+        let at = parse_service_time(departure_time).map(|time| self.at(time));
+        let date = at.map_or_else(|| business_date.to_string(), |at| at.date(business_date));
+        (date, at)
+    }
+
+    /// Hours from the start of the business day to `time`, with minutes and
+    /// seconds as a fraction, e.g. 1.5 at 04:30 with the default start.
+    pub fn decimal(&self, time: NaiveTime) -> f64 {
+        self.at(time).minutes as f64 / 60.0 + time.second() as f64 / 3600.0
+    }
+
+    /// Business hour in which the clock hour `clock_hour` falls.
+    pub fn business_hour(&self, clock_hour: u32) -> usize {
+        ((clock_hour + 24 - self.hour) % 24) as usize
+    }
+
+    /// Calendar date on which the clock hour `clock_hour` of business date
+    /// `date` falls.
+    pub fn calendar_date(&self, date: NaiveDate, clock_hour: u32) -> NaiveDate {
+        if clock_hour < self.hour {
+            date + Duration::days(1)
+        } else {
+            date
+        }
+    }
+
+    /// Clock time a business hour starts at, e.g. `03:00` for hour 0.
+    pub fn hour_label(&self, business_hour: usize) -> String {
+        format!("{:02}:00", (self.hour as usize + business_hour) % 24)
+    }
+
+    /// Clock time `minutes` into the business day as HH:MM; hours past 23
+    /// are after midnight, e.g. 25:15.
+    pub fn time_label(&self, minutes: usize) -> String {
+        format!("{:02}:{:02}", self.hour as usize + minutes / 60, minutes % 60)
+    }
+
+    /// Timestamp `minutes` into business date `date`, as `date HH:MM` with
+    /// the time from `time_label`.
+    pub fn timestamp(&self, date: &str, minutes: usize) -> String {
+        format!("{} {}", date, self.time_label(minutes))
+    }
+
+    /// Axis description for charts over business hours 0 to 23.
+    pub fn axis_desc(&self) -> String {
+        format!("Business Hour (0 = {}, 23 = {})", self.hour_label(0), self.hour_label(23))
+    }
+}
+
+/// Business hour, 0 to 23, of clock time `t` in a business day starting at
+/// `start_hour`. Hours before the start wrap to the end of the day, and a
+/// start hour past 23 wraps round the clock.
+pub fn business_hour(t: NaiveTime, start_hour: u32) -> u32 {
+    DayStart { hour: start_hour % 24 }.at(t).hour() as u32
+}
+
+/// Hours from `start_hour` to clock time `t`, wrapping after midnight, e.g.
+/// 21.5 at 00:30 with a 03:00 start.
+pub fn business_decimal(t: NaiveTime, start_hour: u32) -> f64 {
+    DayStart { hour: start_hour % 24 }.decimal(t)
+}
+
+/// The business hour with the most movements in an hourly series: the
+/// earliest of several equal hours, and `None` when every hour is zero, so a
+/// line with no movements isn't reported as peaking at the day start.
+pub fn peak_hour(series: &[i32]) -> Option<usize> {
+    let (hour, &peak) = series.iter().enumerate().rev().max_by_key(|&(_, movements)| movements)?;
+    if peak > 0 { Some(hour) } else { None }
+}
+
+/// Parses a scheduled `HH:MM:SS` time, accepting the hours 24 to 47 that
+/// GTFS-style feeds write for stops after midnight, e.g. 25:10:00 for
+/// 01:10:00. The rollover needs no flag: data.csv keeps after-midnight stops
+/// on the business date before, so the clock time alone puts them in the
+/// right business hour.
+pub fn parse_service_time(value: &str) -> Option<NaiveTime> {
+    if let Ok(time) = NaiveTime::parse_from_str(value, "%H:%M:%S") {
+        return Some(time);
+    }
+    let (hour, rest) = value.split_once(':')?;
+    match hour.parse::<u32>() {
+        Ok(hour) if (24..48).contains(&hour) => {
+            NaiveTime::parse_from_str(&format!("{:02}:{}", hour - 24, rest), "%H:%M:%S").ok()
+        }
+        _ => None,
+    }
+}
+
+/// A stop's place in the business day: its date relative to the record's
+/// Business_Date and the minutes since the day started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusinessTime {
+    days: i64,
+    pub minutes: u32,
+}
+
+impl BusinessTime {
+    /// Business hour, 0 to 23.
+    pub fn hour(&self) -> usize {
+        self.minutes as usize / 60
+    }
+
+    /// Index of the `size`-minute interval, counted from the day start.
+    pub fn interval(&self, size: u32) -> usize {
+        (self.minutes / size) as usize
+    }
+
+    /// Index of the nearest `size`-minute block boundary, counted from the
+    /// day start. `None` in the last half block of the day, which rounds to
+    /// the start of the next business day rather than into this one.
+    pub fn nearest_block(&self, size: u32) -> Option<usize> {
+        let block = (self.minutes as f64 / size as f64).round() as usize;
+        (block < (MINUTES_PER_DAY as u32 / size) as usize).then_some(block)
+    }
+
+    /// The business date the stop counts on, given the record's
+    /// Business_Date; unchanged unless the day start moved the stop.
+    pub fn date(&self, business_date: &str) -> String {
+        if self.days == 0 {
+            return business_date.to_string();
+        }
+        match NaiveDate::parse_from_str(business_date, "%Y-%m-%d") {
+            Ok(date) => (date + Duration::days(self.days)).format("%Y-%m-%d").to_string(),
+            Err(_) => business_date.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day_start(value: &str) -> DayStart {
+        DayStart::parse(value).unwrap()
+    }
+
+    #[test]
+    fn default_matches_three_am_arithmetic() {
+        let start = DayStart::default();
+        for hour in 0..24 {
+            let expected = if hour < 3 { hour + 21 } else { hour - 3 };
+            let at = start.locate(hour, 30);
+            assert_eq!(at.hour(), expected as usize);
+            assert_eq!(at.date("2022-09-12"), "2022-09-12");
+        }
+        assert_eq!(start.locate(2, 59).interval(15), 95);
+        assert_eq!(start.locate(25, 1).hour(), 22);
+        assert_eq!(start.time_label(22 * 60 + 15), "25:15");
+        assert_eq!(start.axis_desc(), "Business Hour (0 = 03:00, 23 = 02:00)");
+        assert_eq!(start.business_hour(2), 23);
+    }
+
+    #[test]
+    fn midnight_start_moves_after_midnight_stops_to_the_next_date() {
+        let start = day_start("00:00");
+        let at = start.locate(1, 15);
+        assert_eq!((at.hour(), at.interval(15)), (1, 5));
+        assert_eq!(at.date("2022-09-12"), "2022-09-13");
+        // Hours written past 23 mean the same as the clock time.
+        assert_eq!(start.locate(25, 15), at);
+
+        let at = start.locate(3, 0);
+        assert_eq!((at.hour(), at.date("2022-09-12").as_str()), (3, "2022-09-12"));
+        assert_eq!(start.locate(23, 59).interval(15), 95);
+        assert_eq!(start.time_label(23 * 60 + 45), "23:45");
+        assert_eq!(start.axis_desc(), "Business Hour (0 = 00:00, 23 = 23:00)");
+        assert_eq!(start.business_hour(2), 2);
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        assert_eq!(start.calendar_date(date, 2), date);
+    }
+
+    #[test]
+    fn four_am_start_moves_the_three_am_hour_to_the_date_before() {
+        let start = day_start("04:00");
+        let at = start.locate(3, 30);
+        assert_eq!(at.hour(), 23);
+        assert_eq!(at.date("2022-09-12"), "2022-09-11");
+        // Across a month boundary.
+        assert_eq!(at.date("2022-10-01"), "2022-09-30");
+
+        let at = start.locate(4, 0);
+        assert_eq!((at.hour(), at.interval(15)), (0, 0));
+        assert_eq!(at.date("2022-09-12"), "2022-09-12");
+
+        // 02:59 is still the business day that started the morning before.
+        let at = start.locate(2, 59);
+        assert_eq!((at.hour(), at.interval(15)), (22, 91));
+        assert_eq!(at.date("2022-09-12"), "2022-09-12");
+        assert_eq!(start.hour_label(23), "03:00");
+        assert_eq!(start.time_label(22 * 60), "26:00");
+        assert_eq!(start.business_hour(3), 23);
+        let date = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        assert_eq!(start.calendar_date(date, 2), NaiveDate::from_ymd_opt(2022, 10, 2).unwrap());
+    }
+
+    fn time(hour: u32, minute: u32, second: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn business_hour_and_decimal_around_the_three_am_boundary() {
+        assert_eq!(business_hour(time(0, 0, 0), 3), 21);
+        assert_eq!(business_decimal(time(0, 0, 0), 3), 21.0);
+        assert_eq!(business_hour(time(2, 59, 0), 3), 23);
+        assert_eq!(business_decimal(time(2, 59, 0), 3), 23.0 + 59.0 / 60.0);
+        assert_eq!(business_hour(time(3, 0, 0), 3), 0);
+        assert_eq!(business_decimal(time(3, 0, 0), 3), 0.0);
+        assert_eq!(business_hour(time(23, 59, 0), 3), 20);
+        assert_eq!(business_decimal(time(23, 59, 0), 3), 20.0 + 59.0 / 60.0);
+
+        // The last second of the business day wraps to the first.
+        let last = business_decimal(time(2, 59, 59), 3);
+        assert!(last < 24.0 && last > 23.99);
+        assert_eq!(business_decimal(time(3, 0, 0), 3), 0.0);
+        assert_eq!(business_decimal(time(4, 30, 0), 3), 1.5);
+    }
+
+    #[test]
+    fn nearest_block_stops_at_the_end_of_the_business_day() {
+        let start = DayStart::default();
+        assert_eq!(start.at(time(3, 7, 0)).nearest_block(15), Some(0));
+        assert_eq!(start.at(time(3, 8, 0)).nearest_block(15), Some(1));
+        assert_eq!(start.at(time(2, 52, 0)).nearest_block(15), Some(95));
+        // Rounds to 03:00, the next business day, so it isn't put in 02:45.
+        assert_eq!(start.at(time(2, 59, 59)).nearest_block(15), None);
+        assert_eq!(start.at(time(2, 59, 59)).interval(15), 95);
+        assert_eq!(start.at(time(2, 57, 0)).nearest_block(5), Some(287));
+        assert_eq!(start.at(time(2, 58, 0)).nearest_block(5), None);
+    }
+
+    #[test]
+    fn business_hour_wraps_for_any_start_hour() {
+        // A 00:00 start is the calendar day.
+        for hour in 0..24 {
+            assert_eq!(business_hour(time(hour, 30, 0), 0), hour);
+        }
+        assert_eq!(business_decimal(time(23, 59, 0), 0), 23.0 + 59.0 / 60.0);
+
+        // Hours before a late start wrap to the end of the day instead of
+        // going negative.
+        assert_eq!(business_hour(time(4, 0, 0), 5), 23);
+        assert_eq!(business_hour(time(0, 0, 0), 23), 1);
+        assert_eq!(business_hour(time(22, 59, 0), 23), 23);
+        assert_eq!(business_decimal(time(22, 30, 0), 23), 23.5);
+        for start in 0..24 {
+            for hour in 0..24 {
+                assert!(business_hour(time(hour, 0, 0), start) < 24);
+            }
+        }
+
+        // At each start from 00:00 to 06:00, the start minute opens hour 0 and
+        // the minute before it closes hour 23.
+        for start in 0..=6 {
+            assert_eq!(business_hour(time(start, 0, 0), start), 0);
+            assert_eq!(business_decimal(time(start, 0, 0), start), 0.0);
+            let before = time((start + 23) % 24, 59, 0);
+            assert_eq!(business_hour(before, start), 23);
+            assert_eq!(business_decimal(before, start), 23.0 + 59.0 / 60.0);
+        }
+
+        // A start past 23 wraps round the clock.
+        assert_eq!(business_hour(time(1, 0, 0), 27), 22);
+        assert_eq!(business_hour(time(3, 0, 0), 24), 3);
+    }
+
+    #[test]
+    fn service_times_past_midnight_roll_over() {
+        assert_eq!(parse_service_time("24:00:00"), Some(time(0, 0, 0)));
+        assert_eq!(parse_service_time("25:45:30"), Some(time(1, 45, 30)));
+        assert_eq!(parse_service_time("14:05:00"), Some(time(14, 5, 0)));
+        // Still the end of the business day that started at 03:00.
+        assert_eq!(business_hour(parse_service_time("25:45:30").unwrap(), 3), 22);
+        assert_eq!(business_hour(parse_service_time("24:00:00").unwrap(), 3), 21);
+        for value in ["48:00:00", "24:60:00", "", "25:45", "ab:00:00"] {
+            assert_eq!(parse_service_time(value), None, "{} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn peak_hour_is_the_earliest_busiest_hour() {
+        // An AM peak at business hour 5 (08:00) and a smaller PM peak.
+        let mut series = [10; 24];
+        series[5] = 900;
+        series[14] = 700;
+        assert_eq!(peak_hour(&series), Some(5));
+        // A tie goes to the earlier hour.
+        series[14] = 900;
+        assert_eq!(peak_hour(&series), Some(5));
+        series[23] = 901;
+        assert_eq!(peak_hour(&series), Some(23));
+        assert_eq!(peak_hour(&[0; 24]), None);
+        assert_eq!(peak_hour(&[]), None);
+    }
+
+    #[test]
+    fn decimal_follows_the_day_start() {
+        let start = day_start("00:00");
+        assert_eq!(start.decimal(time(0, 0, 0)), 0.0);
+        assert_eq!(start.decimal(time(2, 59, 0)), 2.0 + 59.0 / 60.0);
+        assert_eq!(start.at(time(23, 59, 0)).hour(), 23);
+    }
+
+    fn departing(date: &str, departure: &str) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: "Friday".to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: "Pakenham".to_string(),
+            group: "Caulfield".to_string(),
+            direction: "D".to_string(),
+            origin_station: "Flinders Street".to_string(),
+            destination_station: "East Pakenham".to_string(),
+            station_name: "Richmond".to_string(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: departure.to_string(),
+            passenger_boardings: 0,
+            passenger_alightings: 0,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    #[test]
+    fn business_date_follows_the_departure() {
+        let (date, at) = DayStart::default().business_date(&departing("2022-09-30", "00:30:00"));
+        assert_eq!((date.as_str(), at.map(|at| at.hour())), ("2022-09-30", Some(21)));
+
+        // With a midnight start the stop moves to the next date, across the
+        // month boundary.
+        let (date, at) = day_start("00:00").business_date(&departing("2022-09-30", "00:30:00"));
+        assert_eq!((date.as_str(), at.map(|at| at.hour())), ("2022-10-01", Some(0)));
+        let (date, _) = day_start("04:00").business_date(&departing("2022-10-01", "03:15:00"));
+        assert_eq!(date, "2022-09-30");
+
+        // Without a departure time the stop stays on its Business_Date.
+        for departure in ["", "not a time"] {
+            assert_eq!(day_start("00:00").business_date(&departing("2022-09-30", departure)), ("2022-09-30".to_string(), None));
+        }
+        assert_eq!(day_start("00:00").departure("2022-09-30", "25:30:00"), ("2022-10-01".to_string(), Some(day_start("00:00").locate(1, 30))));
+    }
+
+    #[test]
+    fn rejects_anything_but_a_whole_hour() {
+        for value in ["24:00", "03:30", "24", "", "ab:00", "-1:00", "3:"] {
+            assert!(DayStart::parse(value).is_err(), "{} should be rejected", value);
+        }
+        assert_eq!(day_start("7:00").hour(), 7);
+        assert_eq!(day_start("4").hour(), 4);
+        assert_eq!(day_start("00").hour(), 0);
+    }
+}
@@ -0,0 +1,36 @@
+// Station_Chainage is the distance along the line from the city. Extracts
+// normally give it in metres, but some record whole kilometres instead; the
+// unit is inferred per line from the largest chainage seen, so distances
+// given on the command line can always be in metres.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChainageUnit {
+    Metres,
+    Kilometres,
+}
+
+impl ChainageUnit {
+    /// Every line is longer than a kilometre and shorter than a thousand, so
+    /// a largest chainage under 1000 can only be kilometres.
+    pub fn detect(max_chainage: i32) -> ChainageUnit {
+        if max_chainage > 0 && max_chainage < 1000 {
+            ChainageUnit::Kilometres
+        } else {
+            ChainageUnit::Metres
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChainageUnit::Metres => "metres",
+            ChainageUnit::Kilometres => "kilometres",
+        }
+    }
+
+    pub fn to_metres(self, chainage: i32) -> i64 {
+        match self {
+            ChainageUnit::Metres => chainage as i64,
+            ChainageUnit::Kilometres => chainage as i64 * 1000,
+        }
+    }
+}
@@ -0,0 +1,1701 @@
+// Chart drawing for generateGraph. Each chart draws onto a drawing area
+// from any plotters backend, so the binary can render PNG or SVG files
+// while the snapshot tests below render into memory.
+
+use chrono::NaiveDate;
+use clap::{Args, ValueEnum};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use crate::bands::BAND_NAMES;
+use crate::business::DayStart;
+use crate::format::LabelFormat;
+
+/// Pixel size of every chart unless `--width` and `--height` say otherwise.
+/// Font sizes, margins and label areas below are given for this size and
+/// scaled to the size drawn at.
+pub const CHART_SIZE: (u32, u32) = (1600, 1200);
+
+// Smallest font drawn, in pixels, however small the chart.
+const MIN_FONT_SIZE: f64 = 10.0;
+
+// Caption font size, in pixels at `CHART_SIZE`, unless `--caption-size`
+// says otherwise.
+const CAPTION_SIZE: u32 = 50;
+
+/// Pixel size and caption font size of generateGraph's charts, from
+/// `--width`, `--height` and `--caption-size`, each defaulting to the size
+/// charts were first drawn at.
+#[derive(Args, Debug, Clone, Copy, PartialEq)]
+pub struct ChartConfig {
+    /// Chart width in pixels
+    #[arg(long, default_value_t = CHART_SIZE.0, value_parser = pixels(CHART_SIZE.0 / 10))]
+    pub width: u32,
+    /// Chart height in pixels
+    #[arg(long, default_value_t = CHART_SIZE.1, value_parser = pixels(CHART_SIZE.1 / 10))]
+    pub height: u32,
+    // Given for `CHART_SIZE` and scaled like the rest of the text.
+    /// Caption font size in pixels, at 1600x1200 and scaled with the chart
+    #[arg(long, default_value_t = CAPTION_SIZE, value_parser = pixels(MIN_FONT_SIZE as u32))]
+    pub caption_size: u32,
+}
+
+impl Default for ChartConfig {
+    fn default() -> ChartConfig {
+        ChartConfig { width: CHART_SIZE.0, height: CHART_SIZE.1, caption_size: CAPTION_SIZE }
+    }
+}
+
+impl ChartConfig {
+    pub fn pixels(self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+/// Parses a number of pixels, at least `min`.
+fn pixels(min: u32) -> impl Fn(&str) -> Result<u32, String> + Clone + Send + Sync + 'static {
+    move |value| match value.parse::<u32>() {
+        Ok(n) if n >= min => Ok(n),
+        _ => Err(format!("expected a number of pixels, at least {}", min)),
+    }
+}
+
+/// `--format`, `--svg`, `--palette` and the chart size: how generateGraph
+/// draws its charts.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct ChartOptions {
+    /// Chart file format (default png)
+    #[arg(long, value_enum)]
+    pub format: Option<ChartFormat>,
+    /// Short for --format svg
+    #[arg(long)]
+    pub svg: bool,
+    /// Chart colors; cud and viridis are colorblind-safe
+    #[arg(long, value_enum, default_value_t = Palette::Default)]
+    pub palette: Palette,
+    #[command(flatten)]
+    pub config: ChartConfig,
+}
+
+impl ChartOptions {
+    /// The format given by `--format`, or `--svg` for `--format svg`,
+    /// defaulting to PNG.
+    pub fn format(&self) -> Result<ChartFormat, String> {
+        match (self.format, self.svg) {
+            (Some(ChartFormat::Png), true) => Err("--svg can't be combined with --format png".to_string()),
+            (Some(format), _) => Ok(format),
+            (None, true) => Ok(ChartFormat::Svg),
+            (None, false) => Ok(ChartFormat::Png),
+        }
+    }
+}
+
+/// Pixels plotters puts between an axis and its labels, at any size.
+const TEXT_GAP: f64 = 20.0;
+
+/// How much a chart is scaled from `CHART_SIZE`: by its narrower side
+/// relative to that size, so text fits either way.
+#[derive(Debug, Clone, Copy)]
+struct Scale(f64);
+
+impl Scale {
+    fn of<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>) -> Scale {
+        let (width, height) = root.dim_in_pixel();
+        Scale((width as f64 / CHART_SIZE.0 as f64).min(height as f64 / CHART_SIZE.1 as f64))
+    }
+
+    /// A length given for `CHART_SIZE`, in pixels at this scale.
+    fn px(self, length: u32) -> u32 {
+        (length as f64 * self.0).round() as u32
+    }
+
+    /// A font size given for `CHART_SIZE`, at this scale but never below
+    /// `MIN_FONT_SIZE`.
+    fn font(self, size: u32) -> f64 {
+        (size as f64 * self.0).round().max(MIN_FONT_SIZE)
+    }
+
+    /// A length that holds text, such as a legend key, given for
+    /// `CHART_SIZE`. It scales with the 30px label font, so it still fits
+    /// the text once fonts stop shrinking.
+    fn text_px(self, length: u32) -> u32 {
+        (length as f64 * self.font(30) / 30.0).round() as u32
+    }
+
+    /// A label area size given for `CHART_SIZE`. Like `text_px`, but tick
+    /// marks and the gaps around labels stay the same size whatever the
+    /// scale, so a shrunk area keeps `TEXT_GAP` pixels back for them.
+    fn label_area(self, size: u32) -> u32 {
+        let ratio = self.font(30) / 30.0;
+        (size as f64 * ratio + TEXT_GAP * (1.0 - ratio).max(0.0)).round() as u32
+    }
+}
+
+/// File format of generateGraph's charts, from `--format png|svg`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ChartFormat {
+    Png,
+    Svg,
+}
+
+impl ChartFormat {
+    /// The chart's file name, e.g. `time_series_chart.svg`.
+    pub fn file_name(self, stem: &str) -> String {
+        match self {
+            ChartFormat::Png => format!("{}.png", stem),
+            ChartFormat::Svg => format!("{}.svg", stem),
+        }
+    }
+}
+
+/// Draws `$draw(root, $config, $arg...)` into the file `$path` in
+/// `$format`, at the size `$config` gives. The draw functions are generic
+/// over the backend, which a closure can't be, so the call is written out
+/// once per format.
+macro_rules! render_chart {
+    ($format:expr, $config:expr, $path:expr, $draw:ident($($arg:expr),* $(,)?)) => {
+        match $format {
+            $crate::charts::ChartFormat::Png => $draw(
+                &plotters::prelude::IntoDrawingArea::into_drawing_area(
+                    plotters::prelude::BitMapBackend::new($path, $crate::charts::ChartConfig::pixels($config)),
+                ),
+                $config,
+                $($arg),*
+            ),
+            $crate::charts::ChartFormat::Svg => $draw(
+                &plotters::prelude::IntoDrawingArea::into_drawing_area(
+                    plotters::prelude::SVGBackend::new($path, $crate::charts::ChartConfig::pixels($config)),
+                ),
+                $config,
+                $($arg),*
+            ),
+        }
+    };
+}
+pub(crate) use render_chart;
+
+/// One line's daily total movements in chronological order, with the
+/// percentage change from the previous calendar day. The change is `None`
+/// for the first date, after a gap in the dates, or when the previous day
+/// had no movements; gaps are never interpolated.
+pub type DailyTrend = Vec<(NaiveDate, i64, Option<f64>)>;
+
+/// Colors of generateGraph's charts, from `--palette default|cud|viridis`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Palette {
+    Default,
+    /// The Okabe-Ito colorblind-safe set from Color Universal Design.
+    Cud,
+    /// Evenly spaced steps of the perceptually uniform viridis ramp.
+    Viridis,
+}
+
+// Steps sampled from the viridis ramp, enough for every metro line to get
+// its own shade. A power of two, for the ordering below.
+const VIRIDIS_STEPS: u32 = 16;
+
+/// Returns a palette of distinct colors. Charts with more series than
+/// colors reuse them from the start.
+pub fn get_color_palette(palette: Palette) -> Vec<RGBColor> {
+    match palette {
+        Palette::Default => vec![
+            RGBColor(255, 0, 0),       // red
+            RGBColor(0, 0, 255),       // blue
+            RGBColor(0, 128, 0),       // green
+            RGBColor(255, 165, 0),     // orange
+            RGBColor(128, 0, 128),     // purple
+            RGBColor(0, 128, 128),     // teal
+            RGBColor(255, 192, 203),   // pink
+            RGBColor(128, 128, 0),     // olive
+            RGBColor(0, 0, 0),         // black
+            RGBColor(165, 42, 42),     // brown
+            RGBColor(0, 255, 255),     // cyan
+            RGBColor(255, 215, 0),     // gold
+        ],
+        Palette::Cud => vec![
+            RGBColor(230, 159, 0),     // orange
+            RGBColor(86, 180, 233),    // sky blue
+            RGBColor(0, 158, 115),     // bluish green
+            RGBColor(240, 228, 66),    // yellow
+            RGBColor(0, 114, 178),     // blue
+            RGBColor(213, 94, 0),      // vermillion
+            RGBColor(204, 121, 167),   // reddish purple
+            RGBColor(0, 0, 0),         // black
+        ],
+        // Taken in bit-reversed order (0, 8, 4, 12, ...) so that charts with
+        // only a few series still get colors from across the whole ramp.
+        Palette::Viridis => (0..VIRIDIS_STEPS)
+            .map(|i| i.reverse_bits() >> (32 - VIRIDIS_STEPS.trailing_zeros()))
+            .map(|step| ViridisRGB.get_color(step as f32 / (VIRIDIS_STEPS - 1) as f32))
+            .collect(),
+    }
+}
+
+/// Splits a `--split-direction` key such as `Pakenham_D` into the line and
+/// its direction code. Other keys are a line with no direction.
+pub fn split_line_key(key: &str) -> (&str, Option<&str>) {
+    match key.rsplit_once('_') {
+        Some((line, direction @ ("U" | "D"))) => (line, Some(direction)),
+        _ => (key, None),
+    }
+}
+
+/// Legend label for a line key: `Pakenham (Up)` for `Pakenham_U`.
+fn series_label(key: &str) -> String {
+    match split_line_key(key) {
+        (line, Some("U")) => format!("{} (Up)", line),
+        (line, Some(_)) => format!("{} (Down)", line),
+        (line, None) => line.to_string(),
+    }
+}
+
+/// Assigns each line a palette color in sorted line order. Built once from
+/// every line in the data so a line keeps its color across all charts; both
+/// directions of a split line share its color.
+pub fn line_colors<'a>(lines: impl Iterator<Item = &'a String>, palette: Palette) -> BTreeMap<String, RGBColor> {
+    let keys: Vec<&String> = lines.collect();
+    let mut lines: Vec<&str> = keys.iter().map(|key| split_line_key(key).0).collect();
+    lines.sort();
+    lines.dedup();
+    let palette = get_color_palette(palette);
+    keys.into_iter()
+        .map(|key| {
+            let i = lines.binary_search(&split_line_key(key).0).unwrap_or(0);
+            (key.clone(), palette[i % palette.len()])
+        })
+        .collect()
+}
+
+// Color of the lines kept for context behind a highlighted line.
+const DIMMED: RGBColor = RGBColor(200, 200, 200);
+
+/// Orders lines for drawing and picks each one's color and stroke width.
+/// With a highlighted line, every other line is drawn thin and gray, and the
+/// highlighted one is drawn last, thicker, in its usual color.
+fn series_styles<'a>(
+    lines: impl Iterator<Item = &'a String>,
+    colors: &BTreeMap<String, RGBColor>,
+    highlight: Option<&str>,
+) -> Vec<(&'a String, RGBColor, u32)> {
+    let mut lines: Vec<&String> = lines.collect();
+    lines.sort_by_key(|line| (Some(line.as_str()) == highlight, line.as_str()));
+    lines.into_iter()
+        .map(|line| match highlight {
+            Some(name) if name == line => (line, colors[line], 5),
+            Some(_) => (line, DIMMED, 2),
+            None => (line, colors[line], 3),
+        })
+        .collect()
+}
+
+/// Generates a vertical bar chart for overall total movements per line.
+/// With `split`, each line's boardings and alightings, every bar stacks
+/// the line's boardings on its alightings, in its color and a lighter
+/// shade of it, with a legend for the two shades.
+pub fn draw_total_movements_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, i32>,
+    split: Option<(&HashMap<String, i32>, &HashMap<String, i32>)>,
+    colors: &BTreeMap<String, RGBColor>,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Sort data by line name.
+    let mut data_vec: Vec<(&String, &i32)> = data.iter().collect();
+    data_vec.sort_by(|a, b| a.0.cmp(b.0));
+
+    root.fill(&WHITE)?;
+    let max_value = data_vec.iter().map(|(_, &v)| v).max().unwrap_or(0);
+    // Stacked bars leave headroom for the legend.
+    let headroom = if split.is_some() { max_value / 3 } else { max_value / 10 };
+
+    // Increase margins and label areas.
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(100))
+        .y_label_area_size(scale.label_area(80))
+        .build_cartesian_2d(0..data_vec.len(), 0..(max_value + headroom + 1))?;
+
+    // Configure mesh with larger fonts.
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(data_vec.len())
+        .x_label_formatter(&|idx| {
+            if *idx < data_vec.len() {
+                data_vec[*idx].0.clone()
+            } else {
+                "".to_string()
+            }
+        })
+        .x_desc("Line")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    match split {
+        // Draw a vertical bar for each line.
+        None => {
+            for (i, (line, &value)) in data_vec.iter().enumerate() {
+                let color = &colors[*line];
+                chart.draw_series(std::iter::once(Rectangle::new(
+                    [(i, 0), (i + 1, value)],
+                    color.filled(),
+                )))?;
+            }
+        }
+        // Alightings at the bottom of each bar, boardings on top; a line
+        // missing from one map has none of that kind.
+        Some((boardings, alightings)) => {
+            let key = scale.text_px(10) as i32;
+            let count = |map: &HashMap<String, i32>, line: &String| map.get(line).copied().unwrap_or(0);
+            // Listed top down, as stacked.
+            chart.draw_series(data_vec.iter().enumerate().map(|(i, (line, _))| {
+                let base = count(alightings, line);
+                Rectangle::new([(i, base), (i + 1, base + count(boardings, line))], colors[*line].filled())
+            }))?
+            .label("Boardings")
+            .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], LEGEND_SHADE.filled()));
+            chart.draw_series(data_vec.iter().enumerate().map(|(i, (line, _))| {
+                Rectangle::new([(i, 0), (i + 1, count(alightings, line))], lighter(colors[*line]).filled())
+            }))?
+            .label("Alightings")
+            .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], lighter(LEGEND_SHADE).filled()));
+        }
+    }
+    // Label each bar with its value.
+    for (i, (_, &value)) in data_vec.iter().enumerate() {
+        chart.draw_series(std::iter::once(Text::new(
+            labels.format(value as i64),
+            ((i + 1), value + max_value / 50),
+            ("sans-serif", scale.font(30)).into_font().color(&BLACK),
+        ).into_dyn()))?;
+    }
+    if split.is_some() {
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(&WHITE.mix(0.8))
+            .border_style(&BLACK)
+            .label_font(("sans-serif", scale.font(30)))
+            .draw()?;
+    }
+    Ok(())
+}
+
+// Legend keys for a stacked total movements chart, whose shades differ by
+// line: a neutral grey and its lighter shade.
+const LEGEND_SHADE: RGBColor = RGBColor(96, 96, 96);
+
+/// `color` mixed halfway to white.
+fn lighter(color: RGBColor) -> RGBColor {
+    let mix = |channel: u8| channel + (255 - channel) / 2;
+    RGBColor(mix(color.0), mix(color.1), mix(color.2))
+}
+
+/// Generates a stacked bar chart of each line's boardings under its
+/// alightings, so flows that run mostly one way stand out. Each bar is
+/// labelled above with both counts, "B: x / A: y", split over two lines
+/// when one would be wider than the bar's slot.
+pub fn draw_boardings_alightings_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    boardings: &HashMap<String, i32>,
+    alightings: &HashMap<String, i32>,
+    palette: Palette,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    // Sort by line name; a line missing from one map has none of that kind.
+    let mut lines: Vec<&String> = boardings.keys().chain(alightings.keys()).collect();
+    lines.sort();
+    lines.dedup();
+    let data_vec: Vec<(&String, i64, i64)> = lines.into_iter()
+        .map(|line| {
+            let count = |map: &HashMap<String, i32>| map.get(line).copied().unwrap_or(0) as i64;
+            (line, count(boardings), count(alightings))
+        })
+        .collect();
+
+    root.fill(&WHITE)?;
+    let max_value = data_vec.iter().map(|&(_, on, off)| on + off).max().unwrap_or(0);
+    // Segmented ranges include their end, so this gives one bar per line.
+    let last_bar = data_vec.len().saturating_sub(1);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(100))
+        .y_label_area_size(scale.label_area(80))
+        // Headroom for the legend over a two-line label on the tallest bar.
+        .build_cartesian_2d((0..last_bar).into_segmented(), 0..(max_value + max_value / 3 + 1))?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(data_vec.len())
+        .x_label_formatter(&|bar| match bar {
+            SegmentValue::CenterOf(i) => data_vec.get(*i).map_or("".to_string(), |&(line, _, _)| line.clone()),
+            _ => "".to_string(),
+        })
+        .x_desc("Line")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    let palette = get_color_palette(palette);
+    let (boardings_color, alightings_color) = (palette[0], palette[1]);
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(boardings_color.filled())
+            .margin(scale.px(20))
+            .data(data_vec.iter().enumerate().map(|(i, &(_, on, _))| (i, on))),
+    )?
+    .label("Boardings")
+    .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], boardings_color.filled()));
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(alightings_color.filled())
+            .margin(scale.px(20))
+            .baseline_func(|bar| match bar {
+                SegmentValue::Exact(i) => data_vec.get(*i).map_or(0, |&(_, on, _)| on),
+                _ => 0,
+            })
+            .data(data_vec.iter().enumerate().map(|(i, &(_, on, off))| (i, on + off))),
+    )?
+    .label("Alightings")
+    .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], alightings_color.filled()));
+
+    // Label each bar above its top, on two lines if one is too wide.
+    let font_size = scale.font(30);
+    let label_style = ("sans-serif", font_size).into_font().color(&BLACK).pos(Pos::new(HPos::Center, VPos::Bottom));
+    let slot_width = chart.plotting_area().dim_in_pixel().0 / data_vec.len().max(1) as u32;
+    let gap = scale.px(8) as i32;
+    let line_height = font_size as i32 + gap;
+    for (i, &(_, on, off)) in data_vec.iter().enumerate() {
+        let label = format!("B: {} / A: {}", labels.format(on), labels.format(off));
+        let (label_width, _) = root.estimate_text_size(&label, &label_style)?;
+        let rows = if label_width + 2 * gap as u32 <= slot_width {
+            vec![label]
+        } else {
+            vec![format!("B: {}", labels.format(on)), format!("A: {}", labels.format(off))]
+        };
+        let top = rows.len() as i32 - 1;
+        chart.draw_series(rows.into_iter().enumerate().map(|(row, text)| {
+            EmptyElement::at((SegmentValue::CenterOf(i), on + off))
+                + Text::new(text, (0, -gap - (top - row as i32) * line_height), label_style.clone())
+        }))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a grouped bar chart with each line's Up and Down total
+/// movements side by side, so lines whose flow runs mostly one way stand
+/// out. `data` is keyed by (line, direction code); other codes are ignored.
+pub fn draw_direction_movements_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<(String, String), i64>,
+    palette: Palette,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    const DIRECTIONS: [(&str, &str); 2] = [("U", "Up"), ("D", "Down")];
+
+    // Sort by line name.
+    let mut lines: Vec<&String> = data.keys()
+        .filter(|(_, direction)| DIRECTIONS.iter().any(|&(code, _)| code == direction))
+        .map(|(line, _)| line)
+        .collect();
+    lines.sort();
+    lines.dedup();
+    let total = |line: &String, code: &str| data.get(&(line.clone(), code.to_string())).copied().unwrap_or(0);
+
+    root.fill(&WHITE)?;
+    let max_value = lines.iter()
+        .flat_map(|line| DIRECTIONS.map(|(code, _)| total(line, code)))
+        .max()
+        .unwrap_or(0);
+    // Usize ranges include their end, so this gives one slot per line. Each
+    // slot runs from 0 to 1 inside, with the pair of bars in its middle.
+    let last_line = lines.len().saturating_sub(1);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(100))
+        .y_label_area_size(scale.label_area(80))
+        .build_cartesian_2d((0..last_line).nested_coord(|_| 0.0..1.0), 0..(max_value + max_value / 10 + 1))?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(lines.len())
+        .x_label_formatter(&|slot| match slot {
+            NestedValue::Category(i) => lines.get(*i).map_or("".to_string(), |line| line.to_string()),
+            NestedValue::Value(..) => "".to_string(),
+        })
+        .x_desc("Line")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    let palette = get_color_palette(palette);
+    let label_style = ("sans-serif", scale.font(30)).into_font().color(&BLACK).pos(Pos::new(HPos::Center, VPos::Bottom));
+    for (d, &(code, name)) in DIRECTIONS.iter().enumerate() {
+        let color = palette[d];
+        let (left, right) = (0.1 + 0.4 * d as f64, 0.5 + 0.4 * d as f64);
+        chart.draw_series(lines.iter().enumerate().map(|(i, line)| {
+            Rectangle::new(
+                [(NestedValue::Value(i, left), 0), (NestedValue::Value(i, right), total(line, code))],
+                color.filled(),
+            )
+        }))?
+        .label(name)
+        .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], color.filled()));
+        // Label each bar with its value, centered just above it.
+        chart.draw_series(lines.iter().enumerate().map(|(i, line)| {
+            let value = total(line, code);
+            Text::new(
+                labels.format(value),
+                (NestedValue::Value(i, (left + right) / 2.0), value + max_value / 100),
+                label_style.clone(),
+            )
+        }))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a non-cumulative time series line chart (with markers)
+/// for hourly total movements for the selected business day.
+pub fn draw_time_series_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, [i32; 24]>,
+    colors: &BTreeMap<String, RGBColor>,
+    highlight: Option<&str>,
+    day_start: &DayStart,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    let marker_size = scale.px(7).max(2);
+    root.fill(&WHITE)?;
+
+    // Find the maximum hourly value for scaling.
+    let max_hourly = data.values().flat_map(|arr| arr.iter()).cloned().max().unwrap_or(0);
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .set_label_area_size(LabelAreaPosition::Left, scale.label_area(100))
+        .set_label_area_size(LabelAreaPosition::Bottom, scale.label_area(80))
+        // Hour 23 is plotted on the right edge of the 0..23 range, not
+        // excluded from it.
+        .build_cartesian_2d(0..23, 0..(max_hourly + max_hourly / 10 + 1))?;
+
+    chart.configure_mesh()
+        .x_desc(day_start.axis_desc())
+        .y_desc("Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    // For each line, plot the 24 hourly points as a line with markers.
+    for (line, color, width) in series_styles(data.keys(), colors, highlight) {
+        let hourly_counts = &data[line];
+        let series: Vec<(i32, i32)> = hourly_counts
+            .iter()
+            .enumerate()
+            .map(|(hr, &count)| (hr as i32, count))
+            .collect();
+
+        // Down services are drawn dashed with hollow markers.
+        let down = split_line_key(line).1 == Some("D");
+        let marker = if down { color.stroke_width(3) } else { color.filled() };
+        if down {
+            chart.draw_series(DashedLineSeries::new(series.clone(), 15, 10, color.stroke_width(width)))?;
+        } else {
+            chart.draw_series(LineSeries::new(series.clone(), color.stroke_width(width)))?;
+        }
+        chart.draw_series(series.iter().map(|&point| {
+            Circle::new(point, marker_size, marker)
+        }))?
+        .label(series_label(line))
+        .legend(move |(x, y)| {
+            Circle::new((x + key, y), marker_size, marker)
+        });
+    }
+
+    // Place the legend at the upper right with a white background.
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a line chart of each line's largest departure load in every
+/// business hour, which is what crowding passengers feel rather than how
+/// many board. Lines are broken across hours with no departures. With a
+/// `capacity`, loads are plotted as a percentage of it, with a dashed line
+/// at 100%.
+pub fn draw_max_load_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, [Option<i32>; 24]>,
+    colors: &BTreeMap<String, RGBColor>,
+    capacity: Option<f64>,
+    day_start: &DayStart,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    let marker_size = scale.px(7).max(2);
+    let value = |load: i32| capacity.map_or(load as f64, |capacity| load as f64 * 100.0 / capacity);
+    root.fill(&WHITE)?;
+
+    let max_load = data.values().flat_map(|loads| loads.iter().flatten()).map(|&load| value(load)).fold(0.0, f64::max);
+    // Keep the 100% line on the chart even when every load is below it.
+    let top = if capacity.is_some() { max_load.max(100.0) } else { max_load };
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .set_label_area_size(LabelAreaPosition::Left, scale.label_area(100))
+        .set_label_area_size(LabelAreaPosition::Bottom, scale.label_area(80))
+        .build_cartesian_2d(0.0..23.0, 0.0..(top * 1.1).max(1.0))?;
+
+    chart.configure_mesh()
+        .x_labels(24)
+        .x_label_formatter(&|hour| format!("{:.0}", hour))
+        .y_label_formatter(&|load| format!("{:.0}", load))
+        .x_desc(day_start.axis_desc())
+        .y_desc(if capacity.is_some() { "Max Load (% of Capacity)" } else { "Max Departure Load" })
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    if capacity.is_some() {
+        chart.draw_series(DashedLineSeries::new([(0.0, 100.0), (23.0, 100.0)], 10, 10, BLACK.stroke_width(2)))?
+            .label("Capacity")
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 2 * key, y)], BLACK.stroke_width(2)));
+    }
+
+    for (line, color, width) in series_styles(data.keys(), colors, None) {
+        let points: Vec<Option<(f64, f64)>> = data[line].iter().enumerate()
+            .map(|(hour, load)| load.map(|load| (hour as f64, value(load))))
+            .collect();
+        // Down services are drawn dashed with hollow markers.
+        let down = split_line_key(line).1 == Some("D");
+        let marker = if down { color.stroke_width(3) } else { color.filled() };
+        for run in points.split(|point| point.is_none()).filter(|run| !run.is_empty()) {
+            let run: Vec<(f64, f64)> = run.iter().flatten().copied().collect();
+            if down {
+                chart.draw_series(DashedLineSeries::new(run, 15, 10, color.stroke_width(width)))?;
+            } else {
+                chart.draw_series(LineSeries::new(run, color.stroke_width(width)))?;
+            }
+        }
+        chart.draw_series(points.iter().flatten().map(|&point| Circle::new(point, marker_size, marker)))?
+            .label(series_label(line))
+            .legend(move |(x, y)| Circle::new((x + key, y), marker_size, marker));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a cumulative time series line chart (with markers)
+/// for hourly cumulative total movements for the selected business day.
+/// With `from_zero`, each curve starts from an explicit zero half an hour
+/// before the business day begins.
+pub fn draw_cumulative_time_series_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, [i32; 24]>,
+    colors: &BTreeMap<String, RGBColor>,
+    highlight: Option<&str>,
+    from_zero: bool,
+    day_start: &DayStart,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    let marker_size = scale.px(7).max(2);
+    // Create cumulative sums for each line.
+    let mut cumulative_data: HashMap<String, Vec<i32>> = HashMap::new();
+    for (line, hourly_counts) in data {
+        let mut cum_vec = Vec::with_capacity(24);
+        let mut sum = 0;
+        for &count in hourly_counts.iter() {
+            sum += count;
+            cum_vec.push(sum);
+        }
+        cumulative_data.insert(line.clone(), cum_vec);
+    }
+
+    root.fill(&WHITE)?;
+
+    // Determine maximum cumulative value.
+    let max_cumulative = cumulative_data.values()
+        .flat_map(|vec| vec.iter())
+        .cloned()
+        .max()
+        .unwrap_or(0);
+
+    let x_start = if from_zero { -0.5 } else { 0.0 };
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .set_label_area_size(LabelAreaPosition::Left, scale.label_area(100))
+        .set_label_area_size(LabelAreaPosition::Bottom, scale.label_area(80))
+        .build_cartesian_2d(x_start..23.0, 0..(max_cumulative + max_cumulative / 10 + 1))?;
+
+    chart.configure_mesh()
+        .x_label_formatter(&|hour| format!("{:.0}", hour))
+        .x_desc(day_start.axis_desc())
+        .y_desc("Cumulative Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    for (line, color, width) in series_styles(cumulative_data.keys(), colors, highlight) {
+        let series: Vec<(f64, i32)> = cumulative_data[line]
+            .iter()
+            .enumerate()
+            .map(|(hr, &value)| (hr as f64, value))
+            .collect();
+
+        // The zero anchor is drawn as part of the line but gets no marker.
+        let anchor = if from_zero { Some((x_start, 0)) } else { None };
+        let points = anchor.into_iter().chain(series.iter().cloned());
+        let down = split_line_key(line).1 == Some("D");
+        let marker = if down { color.stroke_width(3) } else { color.filled() };
+        if down {
+            chart.draw_series(DashedLineSeries::new(points, 15, 10, color.stroke_width(width)))?;
+        } else {
+            chart.draw_series(LineSeries::new(points, color.stroke_width(width)))?;
+        }
+        chart.draw_series(series.iter().map(|&point| {
+            Circle::new(point, marker_size, marker)
+        }))?
+        .label(series_label(line))
+        .legend(move |(x, y)| {
+            Circle::new((x + key, y), marker_size, marker)
+        });
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+// Steps the heatmap's color scale is drawn in.
+const HEAT_STEPS: usize = 100;
+
+/// Color of a heatmap cell holding `fraction` of the largest value: the
+/// viridis ramp with `--palette viridis`, otherwise white shading into the
+/// palette's first color.
+fn heat_color(palette: Palette, fraction: f64) -> RGBColor {
+    let fraction = fraction.clamp(0.0, 1.0);
+    match palette {
+        Palette::Viridis => ViridisRGB.get_color(fraction as f32),
+        _ => {
+            let RGBColor(r, g, b) = get_color_palette(palette)[0];
+            let shade = |channel: u8| (255.0 - (255.0 - channel as f64) * fraction).round() as u8;
+            RGBColor(shade(r), shade(g), shade(b))
+        }
+    }
+}
+
+/// Generates a heatmap of hourly movements, one row per line and one column
+/// per business hour, with a color scale beside it. The busiest lines by
+/// total movements are at the top, so lines with 15 or more series stay
+/// readable where the overlaid time series chart doesn't.
+pub fn draw_heatmap_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, [i32; 24]>,
+    palette: Palette,
+    day_start: &DayStart,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let mut lines: Vec<(&String, i64)> = data.iter()
+        .map(|(line, hours)| (line, hours.iter().map(|&count| count as i64).sum()))
+        .collect();
+    lines.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let rows: Vec<(String, [Option<f64>; 24])> = lines.into_iter()
+        .map(|(line, _)| (series_label(line), data[line].map(|count| Some(count as f64))))
+        .collect();
+    draw_heat_grid(root, config, caption, &rows, palette, day_start, labels)
+}
+
+/// Generates a heatmap of one line's movements, one row per station in stop
+/// sequence order and one column per business hour, with a color scale
+/// beside it. Hours with no stops at a station are left white.
+pub fn draw_station_heatmap_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    stations: &[(String, [Option<f64>; 24])],
+    palette: Palette,
+    day_start: &DayStart,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_heat_grid(root, config, caption, stations, palette, day_start, labels)
+}
+
+/// Draws labelled rows of hourly values, top to bottom, as a grid of cells
+/// shaded against the largest value, with the color scale beside it. `None`
+/// cells are white.
+fn draw_heat_grid<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    rows: &[(String, [Option<f64>; 24])],
+    palette: Palette,
+    day_start: &DayStart,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Small charts label every other hour, so the labels don't run together.
+    let hour_step = if scale.0 < 0.5 { 2 } else { 1 };
+    let max_hourly = rows.iter().flat_map(|(_, hours)| hours.iter().flatten()).fold(0.0, |max: f64, &value| max.max(value));
+    let max_hourly = (max_hourly.ceil() as i32).max(1);
+    // Rows count up from the bottom, so the first row is at the top.
+    let last_row = rows.len().saturating_sub(1);
+    let row_at = |row: usize| rows.get(last_row - row);
+
+    root.fill(&WHITE)?;
+    let (width, _) = root.dim_in_pixel();
+    let (grid_area, scale_area) = root.split_horizontally(width.saturating_sub(scale.text_px(300)));
+
+    let mut chart = ChartBuilder::on(&grid_area)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(80))
+        .y_label_area_size(scale.label_area(260))
+        .build_cartesian_2d((0..23).into_segmented(), (0..last_row).into_segmented())?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(24)
+        .x_label_formatter(&|hour: &SegmentValue<i32>| match hour {
+            SegmentValue::CenterOf(hour) if hour % hour_step == 0 => hour.to_string(),
+            _ => "".to_string(),
+        })
+        .y_labels(rows.len())
+        .y_label_formatter(&|row| match row {
+            SegmentValue::CenterOf(row) => row_at(*row).map_or("".to_string(), |(label, _)| label.clone()),
+            _ => "".to_string(),
+        })
+        .x_desc(day_start.axis_desc())
+        .label_style(("sans-serif", scale.font(24)))
+        .draw()?;
+
+    chart.draw_series((0..rows.len()).flat_map(|row| {
+        let hours = &row_at(row).unwrap().1;
+        (0..24).map(move |hour| {
+            let color = hours[hour].map_or(WHITE, |value| heat_color(palette, value / max_hourly as f64));
+            Rectangle::new(
+                [
+                    (SegmentValue::Exact(hour as i32), SegmentValue::Exact(row)),
+                    (SegmentValue::Exact(hour as i32 + 1), SegmentValue::Exact(row + 1)),
+                ],
+                color.filled(),
+            )
+        })
+    }))?;
+
+    // The color scale: a bar from no movements at the bottom to the busiest
+    // hour at the top, labelled in movements.
+    let mut key = ChartBuilder::on(&scale_area)
+        .margin(scale.px(60))
+        .margin_top(scale.px(60) + scale.text_px(80))
+        .margin_left(0)
+        .y_label_area_size(scale.label_area(140))
+        .build_cartesian_2d(0..1, 0..max_hourly)?;
+
+    key.configure_mesh()
+        .disable_mesh()
+        .disable_x_axis()
+        .y_label_formatter(&|movements| labels.format(*movements as i64))
+        .y_desc("Movements")
+        .label_style(("sans-serif", scale.font(24)))
+        .draw()?;
+
+    let step = max_hourly as f64 / HEAT_STEPS as f64;
+    key.draw_series((0..HEAT_STEPS).map(|i| {
+        let (bottom, top) = ((i as f64 * step) as i32, ((i + 1) as f64 * step).ceil() as i32);
+        let color = heat_color(palette, (i as f64 + 0.5) / HEAT_STEPS as f64);
+        Rectangle::new([(0, bottom), (1, top)], color.filled())
+    }))?;
+
+    Ok(())
+}
+
+/// Generates a line chart of each line's day-over-day percentage change in
+/// total movements, with dates on the x axis. Lines are broken at gaps in
+/// the dates rather than joined across them.
+pub fn draw_daily_trends_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    trends: &BTreeMap<String, DailyTrend>,
+    colors: &BTreeMap<String, RGBColor>,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    let marker_size = scale.px(7).max(2);
+    // Every date observed on any line, in order, forms the x axis.
+    let mut dates: Vec<NaiveDate> = trends.values()
+        .flat_map(|trend| trend.iter().map(|(date, _, _)| *date))
+        .collect();
+    dates.sort();
+    dates.dedup();
+
+    let changes = trends.values().flat_map(|trend| trend.iter().filter_map(|(_, _, c)| *c));
+    let (min_change, max_change) = changes.fold((0.0f64, 0.0f64), |(lo, hi), c| (lo.min(c), hi.max(c)));
+    let padding = (max_change - min_change) / 10.0 + 1.0;
+
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .set_label_area_size(LabelAreaPosition::Left, scale.label_area(100))
+        .set_label_area_size(LabelAreaPosition::Bottom, scale.label_area(80))
+        .build_cartesian_2d(0..dates.len(), (min_change - padding)..(max_change + padding))?;
+
+    chart.configure_mesh()
+        .x_labels(dates.len().min(12))
+        .x_label_formatter(&|idx| {
+            dates.get(*idx).map_or("".to_string(), |date| date.format("%Y-%m-%d").to_string())
+        })
+        .x_desc("Business Date")
+        .y_desc("Change (%)")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    for (line, trend) in trends {
+        let color = colors[line];
+
+        // Split the series into contiguous runs so gaps stay visible.
+        let mut segments: Vec<Vec<(usize, f64)>> = vec![Vec::new()];
+        for (date, _, change) in trend {
+            match change {
+                Some(c) => {
+                    let idx = dates.binary_search(date).unwrap_or(0);
+                    segments.last_mut().unwrap().push((idx, *c));
+                }
+                None => segments.push(Vec::new()),
+            }
+        }
+
+        let points: Vec<(usize, f64)> = segments.iter().flatten().cloned().collect();
+        for segment in segments.iter().filter(|s| s.len() > 1) {
+            chart.draw_series(LineSeries::new(segment.clone(), color.stroke_width(3)))?;
+        }
+        chart.draw_series(points.iter().map(|&point| {
+            Circle::new(point, marker_size, color.filled())
+        }))?
+        .label(line)
+        .legend(move |(x, y)| {
+            Circle::new((x + key, y), marker_size, color.filled())
+        });
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a stacked bar chart of each line's movements split into the
+/// AM peak, interpeak, PM peak and off-peak bands.
+pub fn draw_peak_windows_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    data: &HashMap<String, [i64; 4]>,
+    palette: Palette,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    // Legend keys sit beside legend text, so scale with it.
+    let key = scale.text_px(10) as i32;
+    let mut data_vec: Vec<(&String, &[i64; 4])> = data.iter().collect();
+    data_vec.sort_by(|a, b| a.0.cmp(b.0));
+
+    root.fill(&WHITE)?;
+    let max_value = data_vec.iter().map(|(_, bands)| bands.iter().sum::<i64>()).max().unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(100))
+        .y_label_area_size(scale.label_area(80))
+        .build_cartesian_2d(0..data_vec.len(), 0..(max_value + max_value / 10 + 1))?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(data_vec.len())
+        .x_label_formatter(&|idx| {
+            data_vec.get(*idx).map_or("".to_string(), |&(line, _)| line.clone())
+        })
+        .x_desc("Line")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    let palette = get_color_palette(palette);
+    for (band, name) in BAND_NAMES.iter().enumerate() {
+        let color = palette[band % palette.len()];
+        chart.draw_series(data_vec.iter().enumerate().map(|(i, (_, bands))| {
+            let bottom: i64 = bands[..band].iter().sum();
+            Rectangle::new([(i, bottom), (i + 1, bottom + bands[band])], color.filled())
+        }))?
+        .label(*name)
+        .legend(move |(x, y)| Rectangle::new([(x, y - key), (x + 2 * key, y + key)], color.filled()));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", scale.font(30)))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a horizontal bar chart of the busiest stations by movements,
+/// drawn top to bottom in the order given.
+pub fn draw_top_stations_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    config: ChartConfig,
+    caption: &str,
+    stations: &[(&str, i64)],
+    palette: Palette,
+    labels: LabelFormat,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let scale = Scale::of(root);
+    root.fill(&WHITE)?;
+    let max_value = stations.iter().map(|&(_, movements)| movements).max().unwrap_or(0);
+    // Segmented ranges include their end, so this gives one row per station.
+    let last_row = stations.len().saturating_sub(1);
+    // Rows count up from the bottom, so the busiest station is at the top.
+    let station_at = |row: usize| stations.get(last_row - row);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", scale.font(config.caption_size)))
+        .margin(scale.px(60))
+        .x_label_area_size(scale.label_area(80))
+        .y_label_area_size(scale.label_area(320))
+        .build_cartesian_2d(0..(max_value + max_value / 5 + 1), (0..last_row).into_segmented())?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .y_labels(stations.len())
+        .y_label_formatter(&|row| match row {
+            SegmentValue::CenterOf(row) => station_at(*row).map_or("".to_string(), |&(station, _)| station.to_string()),
+            _ => "".to_string(),
+        })
+        .x_desc("Total Movements")
+        .y_desc("Station")
+        .label_style(("sans-serif", scale.font(24)))
+        .draw()?;
+
+    chart.draw_series(
+        Histogram::horizontal(&chart)
+            .style(get_color_palette(palette)[0].filled())
+            .margin(scale.px(8))
+            .data((0..stations.len()).map(|row| (row, station_at(row).map_or(0, |&(_, movements)| movements)))),
+    )?;
+    // Label each bar with its value, just past its end.
+    let label_style = ("sans-serif", scale.font(24)).into_font().color(&BLACK).pos(Pos::new(HPos::Left, VPos::Center));
+    chart.draw_series((0..stations.len()).filter_map(|row| {
+        let &(_, movements) = station_at(row)?;
+        Some(Text::new(
+            labels.format(movements),
+            (movements + max_value / 100, SegmentValue::CenterOf(row)),
+            label_style.clone(),
+        ))
+    }))?;
+    Ok(())
+}
+
+// Snapshot tests: each chart is rendered from fixed data into memory and
+// reduced to a perceptual hash, which must stay within HASH_TOLERANCE bits
+// of the hash checked in under tests/snapshots. Run with
+// UPDATE_SNAPSHOTS=1 to rewrite the expected hashes after an intended
+// change to a chart.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Cells per side of the grid each color channel is averaged over.
+    const HASH_GRID: usize = 32;
+    // Bits allowed to differ, out of 3 * HASH_GRID^2, to absorb font
+    // rendering differences between machines.
+    const HASH_TOLERANCE: u32 = 48;
+
+    // Snapshots are drawn at the default size and caption size.
+    const CONFIG: ChartConfig = ChartConfig { width: CHART_SIZE.0, height: CHART_SIZE.1, caption_size: CAPTION_SIZE };
+
+    const TIME_SERIES_CAPTION: &str = "Hourly Total Movements on 2022-09-12 (Business Day)";
+
+    fn render<F>(draw: F) -> Vec<u8>
+    where
+        F: FnOnce(&DrawingArea<BitMapBackend, Shift>) -> Result<(), Box<dyn Error>>,
+    {
+        let (width, height) = CHART_SIZE;
+        let mut buffer = vec![0u8; (width * height * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, CHART_SIZE).into_drawing_area();
+            draw(&root).unwrap();
+            root.present().unwrap();
+        }
+        buffer
+    }
+
+    /// Average hash of each RGB channel over a HASH_GRID x HASH_GRID grid:
+    /// one bit per cell and channel, set when the cell is brighter than that
+    /// channel's mean. Hashing the channels separately makes color changes
+    /// show up, not just changes in shape.
+    fn perceptual_hash(buffer: &[u8]) -> Vec<bool> {
+        let (width, height) = (CHART_SIZE.0 as usize, CHART_SIZE.1 as usize);
+        let mut cells = vec![[0u64; 3]; HASH_GRID * HASH_GRID];
+        let mut counts = vec![0u64; HASH_GRID * HASH_GRID];
+        for y in 0..height {
+            for x in 0..width {
+                let cell = (y * HASH_GRID / height) * HASH_GRID + x * HASH_GRID / width;
+                let pixel = (y * width + x) * 3;
+                for channel in 0..3 {
+                    cells[cell][channel] += buffer[pixel + channel] as u64;
+                }
+                counts[cell] += 1;
+            }
+        }
+
+        let mut bits = Vec::with_capacity(3 * cells.len());
+        for channel in 0..3 {
+            let averages: Vec<f64> = cells.iter().zip(&counts)
+                .map(|(cell, &count)| cell[channel] as f64 / count as f64)
+                .collect();
+            let mean = averages.iter().sum::<f64>() / averages.len() as f64;
+            bits.extend(averages.iter().map(|&average| average > mean));
+        }
+        bits
+    }
+
+    fn to_hex(bits: &[bool]) -> String {
+        bits.chunks(4)
+            .map(|nibble| {
+                let value = nibble.iter().fold(0u32, |acc, &bit| acc << 1 | bit as u32);
+                std::char::from_digit(value, 16).unwrap()
+            })
+            .collect()
+    }
+
+    fn from_hex(hex: &str) -> Vec<bool> {
+        hex.chars()
+            .flat_map(|c| {
+                let value = c.to_digit(16).expect("snapshot hashes are hex");
+                (0..4).rev().map(move |shift| value >> shift & 1 == 1)
+            })
+            .collect()
+    }
+
+    fn assert_snapshot(name: &str, buffer: &[u8]) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots", &format!("{}.hash", name)]
+            .iter()
+            .collect();
+        let actual = perceptual_hash(buffer);
+
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, to_hex(&actual) + "\n").unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!("cannot read {}: {}; run with UPDATE_SNAPSHOTS=1 to create it", path.display(), err)
+        });
+        let expected = from_hex(expected.trim());
+        assert_eq!(expected.len(), actual.len(), "{}: hash size changed", name);
+        let distance = expected.iter().zip(&actual).filter(|(a, b)| a != b).count() as u32;
+        assert!(
+            distance <= HASH_TOLERANCE,
+            "{} differs from its snapshot by {} bits (tolerance {}); if the change is intended, rerun with UPDATE_SNAPSHOTS=1",
+            name, distance, HASH_TOLERANCE
+        );
+    }
+
+    fn lines() -> Vec<String> {
+        ["Frankston", "Pakenham", "Sandringham"].iter().map(|line| line.to_string()).collect()
+    }
+
+    fn hourly() -> HashMap<String, [i32; 24]> {
+        lines().into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let mut counts = [0; 24];
+                for (hour, count) in counts.iter_mut().enumerate() {
+                    // Morning and evening peaks, scaled per line.
+                    let peak = if hour == 5 || hour == 14 { 3 } else { 1 };
+                    *count = (hour as i32 % 7 + 1) * 100 * peak * (i as i32 + 1);
+                }
+                (line, counts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn total_movements_chart() {
+        let data: HashMap<String, i32> = lines().into_iter().zip([12_000, 30_500, 8_250]).collect();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| {
+            draw_total_movements_chart(root, CONFIG, "Total Movements by Line", &data, None, &colors, LabelFormat { thousands_sep: true })
+        });
+        assert_snapshot("total_movements_chart", &buffer);
+    }
+
+    #[test]
+    fn stacked_total_movements_chart() {
+        let boardings: HashMap<String, i32> = lines().into_iter().zip([6_500, 24_000, 300]).collect();
+        let alightings: HashMap<String, i32> = lines().into_iter().zip([5_500, 6_500, 7_950]).collect();
+        let data: HashMap<String, i32> = boardings.iter().map(|(line, &on)| (line.clone(), on + alightings[line])).collect();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| {
+            draw_total_movements_chart(
+                root,
+                CONFIG,
+                "Total Movements by Line",
+                &data,
+                Some((&boardings, &alightings)),
+                &colors,
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("stacked_total_movements_chart", &buffer);
+    }
+
+    #[test]
+    fn boardings_alightings_chart() {
+        let boardings: HashMap<String, i32> = lines().into_iter().zip([6_500, 24_000, 300]).collect();
+        let alightings: HashMap<String, i32> = lines().into_iter().zip([5_500, 6_500, 7_950]).collect();
+        let buffer = render(|root| {
+            draw_boardings_alightings_chart(
+                root,
+                CONFIG,
+                "Boardings and Alightings by Line",
+                &boardings,
+                &alightings,
+                Palette::Default,
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("boardings_alightings_chart", &buffer);
+    }
+
+    #[test]
+    fn direction_movements_chart() {
+        let data: HashMap<(String, String), i64> = [
+            ("Frankston", "U", 9_000), ("Frankston", "D", 8_200),
+            ("Pakenham", "U", 21_000), ("Pakenham", "D", 6_400),
+            ("Sandringham", "U", 1_500), ("Sandringham", "D", 4_900),
+        ]
+            .iter()
+            .map(|&(line, direction, total)| ((line.to_string(), direction.to_string()), total))
+            .collect();
+        let buffer = render(|root| {
+            draw_direction_movements_chart(
+                root,
+                CONFIG,
+                "Up and Down Movements by Line",
+                &data,
+                Palette::Default,
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("direction_movements_chart", &buffer);
+    }
+
+    #[test]
+    fn time_series_chart() {
+        let data = hourly();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| draw_time_series_chart(root, CONFIG, TIME_SERIES_CAPTION, &data, &colors, None, &DayStart::default()));
+        assert_snapshot("time_series_chart", &buffer);
+    }
+
+    #[test]
+    fn highlighted_time_series_chart() {
+        let data = hourly();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| draw_time_series_chart(root, CONFIG, TIME_SERIES_CAPTION, &data, &colors, Some("Pakenham"), &DayStart::default()));
+        assert_snapshot("highlighted_time_series_chart", &buffer);
+    }
+
+    #[test]
+    fn last_business_hour_is_plotted() {
+        let series = |last: i32| -> HashMap<String, [i32; 24]> {
+            let mut counts = [0; 24];
+            counts[0] = 1000;
+            counts[23] = last;
+            HashMap::from([("Pakenham".to_string(), counts)])
+        };
+        // Hour 0 keeps the y scale the same whether or not hour 23 has
+        // movements, and hour 23 stays below the legend.
+        let (with_last, without_last) = (series(400), series(0));
+        let colors = line_colors(with_last.keys(), Palette::Default);
+        let (width, height) = (CHART_SIZE.0 as usize, CHART_SIZE.1 as usize);
+        // Pixels that differ in the rightmost tenth of the chart, below the
+        // legend.
+        let right_edge_changes = |a: &[u8], b: &[u8]| {
+            (height / 4..height)
+                .flat_map(|y| (width * 9 / 10..width).map(move |x| (y * width + x) * 3))
+                .filter(|&pixel| a[pixel..pixel + 3] != b[pixel..pixel + 3])
+                .count()
+        };
+
+        let hourly = |data: &HashMap<String, [i32; 24]>| {
+            render(|root| draw_time_series_chart(root, CONFIG, TIME_SERIES_CAPTION, data, &colors, None, &DayStart::default()))
+        };
+        assert!(right_edge_changes(&hourly(&with_last), &hourly(&without_last)) > 0);
+
+        let cumulative = |data: &HashMap<String, [i32; 24]>| {
+            render(|root| draw_cumulative_time_series_chart(root, CONFIG, TIME_SERIES_CAPTION, data, &colors, None, false, &DayStart::default()))
+        };
+        assert!(right_edge_changes(&cumulative(&with_last), &cumulative(&without_last)) > 0);
+    }
+
+    #[test]
+    fn split_directions_share_their_line_color() {
+        let keys: Vec<String> = ["Pakenham_D", "Frankston", "Pakenham_U"].iter().map(|key| key.to_string()).collect();
+        let colors = line_colors(keys.iter(), Palette::Default);
+        assert_eq!(colors["Pakenham_U"], colors["Pakenham_D"]);
+        assert_ne!(colors["Frankston"], colors["Pakenham_U"]);
+        assert_eq!(series_label("Pakenham_D"), "Pakenham (Down)");
+        assert_eq!(split_line_key("Pakenham_U"), ("Pakenham", Some("U")));
+        assert_eq!(split_line_key("Frankston"), ("Frankston", None));
+    }
+
+    #[test]
+    fn cumulative_time_series_chart() {
+        let data = hourly();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| {
+            draw_cumulative_time_series_chart(root, CONFIG, "Cumulative Movements on 2022-09-12 (Business Day)", &data, &colors, None, true, &DayStart::default())
+        });
+        assert_snapshot("cumulative_time_series_chart", &buffer);
+    }
+
+    #[test]
+    fn heatmap_chart() {
+        let data = hourly();
+        let buffer = render(|root| {
+            draw_heatmap_chart(
+                root,
+                CONFIG,
+                "Hourly Movements by Line on 2022-09-12 (Business Day)",
+                &data,
+                Palette::Default,
+                &DayStart::default(),
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("heatmap_chart", &buffer);
+
+        assert_eq!(heat_color(Palette::Default, 0.0), WHITE);
+        assert_eq!(heat_color(Palette::Default, 1.0), RGBColor(255, 0, 0));
+        assert_eq!(heat_color(Palette::Viridis, 0.0), RGBColor(68, 1, 84));
+    }
+
+    #[test]
+    fn max_load_chart() {
+        let mut data: HashMap<String, [Option<i32>; 24]> = HashMap::new();
+        for (line, scale) in [("Pakenham", 1.0), ("Frankston", 0.7)] {
+            let mut loads = [None; 24];
+            for hour in 2..22 {
+                let peak = if (4..7).contains(&hour) || (13..16).contains(&hour) { 900.0 } else { 350.0 };
+                loads[hour] = Some((peak * scale) as i32);
+            }
+            data.insert(line.to_string(), loads);
+        }
+        // A gap with no departures breaks the line.
+        data.get_mut("Frankston").unwrap()[10] = None;
+        let colors = line_colors(data.keys(), Palette::Default);
+        let buffer = render(|root| {
+            draw_max_load_chart(root, CONFIG, "Max Load on 2022-09-12 (Business Day)", &data, &colors, Some(800.0), &DayStart::default())
+        });
+        assert_snapshot("max_load_chart", &buffer);
+    }
+
+    #[test]
+    fn station_heatmap_chart() {
+        let mut stations = Vec::new();
+        for (i, station) in ["Flinders Street", "Richmond", "Caulfield", "Dandenong", "Pakenham"].iter().enumerate() {
+            let mut hours = [None; 24];
+            for hour in 2..21 {
+                // Busiest in the city and at the peaks; Pakenham has no late services.
+                let peak = if (3..6).contains(&hour) || (11..14).contains(&hour) { 3.0 } else { 1.0 };
+                if !(i == 4 && hour > 17) {
+                    hours[hour] = Some(peak * 400.0 / (i + 1) as f64);
+                }
+            }
+            stations.push((station.to_string(), hours));
+        }
+        let buffer = render(|root| {
+            draw_station_heatmap_chart(
+                root,
+                CONFIG,
+                "Pakenham Station Movements on 2022-09-12 (Business Day)",
+                &stations,
+                Palette::Viridis,
+                &DayStart::default(),
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("station_heatmap_chart", &buffer);
+    }
+
+    #[test]
+    fn daily_trends_chart() {
+        let date = |day| NaiveDate::from_ymd_opt(2022, 9, day).unwrap();
+        let trends: BTreeMap<String, DailyTrend> = lines().into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let offset = i as f64 * 5.0;
+                // A gap after the 14th breaks each line.
+                let trend = vec![
+                    (date(12), 1000, None),
+                    (date(13), 1100, Some(10.0 + offset)),
+                    (date(14), 990, Some(-10.0 - offset)),
+                    (date(16), 1200, None),
+                    (date(17), 1260, Some(5.0 + offset)),
+                ];
+                (line, trend)
+            })
+            .collect();
+        let colors = line_colors(trends.keys(), Palette::Default);
+        let buffer = render(|root| draw_daily_trends_chart(root, CONFIG, "Day-over-Day Change in Total Movements", &trends, &colors));
+        assert_snapshot("daily_trends_chart", &buffer);
+    }
+
+    #[test]
+    fn peak_windows_chart() {
+        let data: HashMap<String, [i64; 4]> = lines().into_iter()
+            .zip([[4000, 2500, 3800, 900], [9000, 6000, 8800, 2100], [1500, 900, 1400, 300]])
+            .collect();
+        let buffer = render(|root| draw_peak_windows_chart(root, CONFIG, "Movements by Time Band", &data, Palette::Default));
+        assert_snapshot("peak_windows_chart", &buffer);
+    }
+
+    #[test]
+    fn top_stations_chart() {
+        let stations = [
+            ("Flinders Street", 48_000),
+            ("Southern Cross", 31_500),
+            ("Richmond", 22_750),
+            ("Melbourne Central", 22_750),
+            ("Caulfield", 9_400),
+        ];
+        let buffer = render(|root| {
+            draw_top_stations_chart(
+                root,
+                CONFIG,
+                "Top 5 Stations by Total Movements",
+                &stations,
+                Palette::Default,
+                LabelFormat { thousands_sep: true },
+            )
+        });
+        assert_snapshot("top_stations_chart", &buffer);
+    }
+
+    #[test]
+    fn draws_at_other_sizes() {
+        let hourly = hourly();
+        let totals: HashMap<String, i32> = lines().into_iter().zip([12_000, 30_500, 8_250]).collect();
+        let colors = line_colors(totals.keys(), Palette::Default);
+        for size in [(1920, 1080), (320, 240)] {
+            let mut buffer = vec![0u8; (size.0 * size.1 * 3) as usize];
+            let root = BitMapBackend::with_buffer(&mut buffer, size).into_drawing_area();
+            let (top, bottom) = root.split_vertically(size.1 / 2);
+            // Each half is drawn at half the height, so gets half the scale.
+            draw_total_movements_chart(&top, CONFIG, "Total Movements by Line", &totals, None, &colors, LabelFormat { thousands_sep: true }).unwrap();
+            draw_cumulative_time_series_chart(&bottom, CONFIG, TIME_SERIES_CAPTION, &hourly, &colors, None, true, &DayStart::default()).unwrap();
+            root.present().unwrap();
+        }
+
+        assert_eq!(Scale(1.0).px(60), 60);
+        assert_eq!(Scale(0.5).font(50), 25.0);
+        assert_eq!(Scale(0.1).font(30), MIN_FONT_SIZE);
+        assert_eq!(Scale(1.0).label_area(80), 80);
+        // Tick marks keep their size, so a small chart keeps room for them.
+        assert_eq!(Scale(0.1).label_area(60), 33);
+
+        assert_eq!(
+            parse_group::<ChartConfig>(&["--width", "1920", "--height", "1080", "--caption-size", "36"]).unwrap(),
+            ChartConfig { width: 1920, height: 1080, caption_size: 36 }
+        );
+        assert_eq!(parse_group::<ChartConfig>(&[]).unwrap(), ChartConfig::default());
+        assert!(parse_group::<ChartConfig>(&["--width", "40"]).is_err());
+        assert!(parse_group::<ChartConfig>(&["--caption-size", "4"]).is_err());
+    }
+
+    #[test]
+    fn renders_png_and_svg_files() {
+        let data: HashMap<String, i32> = lines().into_iter().zip([12_000, 30_500, 8_250]).collect();
+        let colors = line_colors(data.keys(), Palette::Default);
+        let dir = env::temp_dir().join(format!("ptv_data_charts_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for format in [ChartFormat::Png, ChartFormat::Svg] {
+            let path = dir.join(format.file_name("total_movements_chart"));
+            render_chart!(format, CONFIG, &path, draw_total_movements_chart(
+                "Total Movements by Line",
+                &data,
+                None,
+                &colors,
+                LabelFormat { thousands_sep: true },
+            )).unwrap();
+            let contents = fs::read(&path).unwrap();
+            match format {
+                ChartFormat::Png => assert!(contents.starts_with(b"\x89PNG"), "not a PNG"),
+                ChartFormat::Svg => {
+                    let text = String::from_utf8(contents).unwrap();
+                    assert!(text.starts_with("<svg") && text.contains("Total Movements by Line"), "{}", &text[..80]);
+                    // Line labels are text elements, so they can be selected
+                    // and searched, not paths traced from glyphs.
+                    let labels: Vec<&str> = text.split("<text").skip(1)
+                        .filter_map(|element| element.split_once('>')?.1.split_once("</text>"))
+                        .map(|(label, _)| label.trim())
+                        .collect();
+                    for line in lines() {
+                        assert!(labels.contains(&line.as_str()), "no text element for {} in {:?}", line, labels);
+                    }
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        let format = |args: &[&str]| parse_group::<ChartOptions>(args).map_err(|err| err.to_string())?.format();
+        assert_eq!(format(&["--format", "svg"]), Ok(ChartFormat::Svg));
+        assert_eq!(format(&["--svg"]), Ok(ChartFormat::Svg));
+        assert_eq!(format(&[]), Ok(ChartFormat::Png));
+        assert!(format(&["--svg", "--format", "png"]).is_err());
+        assert!(format(&["--format", "pdf"]).is_err());
+    }
+
+    #[test]
+    fn palettes_have_distinct_colors() {
+        for palette in [Palette::Default, Palette::Cud, Palette::Viridis] {
+            let colors = get_color_palette(palette);
+            for (i, color) in colors.iter().enumerate() {
+                assert!(!colors[..i].contains(color), "{:?} repeats {:?}", palette, color);
+            }
+        }
+        let viridis = get_color_palette(Palette::Viridis);
+        assert_eq!(viridis.len(), VIRIDIS_STEPS as usize);
+        // The darkest end, then the far end of the ramp's first half.
+        assert_eq!(viridis[0], RGBColor(68, 1, 84));
+        assert_eq!(viridis[1], ViridisRGB.get_color(8.0 / 15.0));
+        assert_eq!(viridis[15], RGBColor(254, 232, 37));
+
+        // Lines past the end of the palette start it again.
+        let keys: Vec<String> = (1..=10).map(|n| format!("Line {:02}", n)).collect();
+        let colors = line_colors(keys.iter(), Palette::Cud);
+        assert_eq!(colors["Line 01"], RGBColor(230, 159, 0));
+        assert_eq!(colors["Line 09"], colors["Line 01"]);
+
+        let palette = |value: &str| parse_group::<ChartOptions>(&["--palette", value]).map(|options| options.palette);
+        assert_eq!(palette("cud").unwrap(), Palette::Cud);
+        assert_eq!(palette("viridis").unwrap(), Palette::Viridis);
+        assert!(palette("rainbow").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+// Command-line options shared by the binaries. Each command parses its own
+// `Options` with clap, flattening in the groups it takes from here and from
+// the modules that own the other shared flags.
+
+use clap::Args;
+#[cfg(test)]
+use clap::FromArgMatches;
+
+// Read when no --input is given.
+const DEFAULT_INPUT: &str = "data.csv";
+
+/// `--input`: the CSVs to read.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Input {
+    /// CSV to read (default data.csv); repeat it, or give a quoted glob, to read several as one
+    #[arg(long = "input", value_name = "PATH")]
+    pub paths: Vec<String>,
+}
+
+impl Input {
+    /// The CSV to read: the first `--input`, or data.csv in the working
+    /// directory.
+    pub fn path(&self) -> &str {
+        self.paths.first().map_or(DEFAULT_INPUT, String::as_str)
+    }
+
+    /// Reads `file`, the positional argument the time-series generators
+    /// also take their CSV from, when no `--input` is given.
+    pub fn or_positional(&mut self, file: Option<String>) {
+        if self.paths.is_empty() {
+            self.paths.extend(file);
+        }
+    }
+}
+
+/// Minutes per block for `--block-size`, which must divide the hour.
+pub fn block_size(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(minutes) if minutes > 0 && 60 % minutes == 0 => Ok(minutes),
+        _ => Err("expected minutes that divide the hour".to_string()),
+    }
+}
+
+/// A count that must be at least one, such as `--top`.
+pub fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
+
+/// Error for an input CSV that can't be opened, naming the path tried.
+pub fn input_error(path: &str, err: impl std::fmt::Display) -> String {
+    if !std::path::Path::new(path).exists() {
+        return format!("input file '{}' does not exist", path);
+    }
+    format!("cannot read input '{}': {}", path, err)
+}
+
+// Written to when no --output-dir is given.
+const DEFAULT_OUTPUT: &str = "processed";
+
+// Existing files named in the overwrite warning before it just counts them.
+const OVERWRITE_LISTED: usize = 10;
+
+/// `--output-dir` and `--overwrite`: where outputs go.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Output {
+    /// Directory to write outputs to (default processed)
+    #[arg(long = "output-dir", visible_alias = "output", value_name = "DIR")]
+    pub dir: Option<String>,
+    /// Don't warn about files an earlier run left in the output directory
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+impl Output {
+    /// The directory to write outputs to: `--output-dir <dir>`, or processed.
+    pub fn path(&self) -> &str {
+        self.dir.as_deref().unwrap_or(DEFAULT_OUTPUT)
+    }
+
+    /// Where to write `name`, one of the outputs (charts, and generateGraph's
+    /// CSVs) that go to the working directory unless `--output-dir` is given.
+    pub fn file(&self, name: &str) -> String {
+        match &self.dir {
+            Some(dir) => format!("{}/{}", dir, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Creates the output directory, including any missing parents. Unless
+    /// `--overwrite` is given, warns first if it already holds files that
+    /// this run may overwrite.
+    pub fn create(&self) -> Result<(), String> {
+        let dir = self.path();
+        if !self.overwrite {
+            let existing = existing_files(dir);
+            if !existing.is_empty() {
+                eprintln!(
+                    "Warning: '{}' already holds {} files from an earlier run, which may be overwritten (pass --overwrite to silence this):",
+                    dir, existing.len()
+                );
+                for name in existing.iter().take(OVERWRITE_LISTED) {
+                    eprintln!("  {}", name);
+                }
+                if existing.len() > OVERWRITE_LISTED {
+                    eprintln!("  and {} more", existing.len() - OVERWRITE_LISTED);
+                }
+            }
+        }
+        ensure_output_dir(dir)
+    }
+}
+
+/// Creates the output directory `dir` if it doesn't exist. A file already
+/// at that path is an error rather than a failure on the first write.
+pub fn ensure_output_dir(dir: &str) -> Result<(), String> {
+    let path = std::path::Path::new(dir);
+    if path.exists() && !path.is_dir() {
+        return Err(format!("output path '{}' exists but is not a directory", dir));
+    }
+    std::fs::create_dir_all(path).map_err(|err| format!("cannot create output directory '{}': {}", dir, err))
+}
+
+/// Names of the files directly in `dir`, sorted; none if it doesn't exist.
+fn existing_files(dir: &str) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries.flatten()
+                .filter(|entry| entry.path().is_file())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Parses `args`, without a binary name, into the options group `T` on
+/// its own, for the groups' tests.
+#[cfg(test)]
+pub(crate) fn parse_group<T: Args + FromArgMatches>(args: &[&str]) -> Result<T, clap::Error> {
+    let command = T::augment_args(clap::Command::new("test").no_binary_name(true));
+    T::from_arg_matches(&command.try_get_matches_from(args)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_land_in_the_requested_directory() {
+        let root = std::env::temp_dir().join(format!("ptv_data_output_{}", std::process::id()));
+        let dir = root.join("runs/2023-04");
+        let dir = dir.to_str().unwrap();
+        let output: Output = parse_group(&["--output-dir", dir, "--overwrite"]).unwrap();
+
+        assert_eq!(output.path(), dir);
+        output.create().unwrap();
+        let chart = output.file("total_movements_chart.png");
+        std::fs::write(&chart, b"png").unwrap();
+        assert_eq!(existing_files(dir), vec!["total_movements_chart.png".to_string()]);
+
+        // Without --output-dir, charts stay in the working directory.
+        let default: Output = parse_group(&[]).unwrap();
+        assert_eq!(default.file("total_movements_chart.png"), "total_movements_chart.png");
+        assert_eq!(default.path(), "processed");
+        // --output is the older spelling.
+        assert_eq!(parse_group::<Output>(&["--output", dir]).unwrap().path(), dir);
+
+        // A file where the directory should be is an error.
+        assert!(ensure_output_dir(&chart).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn input_comes_from_the_flag_or_the_first_positional() {
+        let input = |args: &[&str], file: Option<&str>| {
+            let mut input: Input = parse_group(args).unwrap();
+            input.or_positional(file.map(str::to_string));
+            input.path().to_string()
+        };
+        assert_eq!(input(&[], None), "data.csv");
+        assert_eq!(input(&[], Some("april.csv")), "april.csv");
+        assert_eq!(input(&["--input", "may.csv"], Some("april.csv")), "may.csv");
+        assert_eq!(input_error("no/such/file.csv", "os error 2"), "input file 'no/such/file.csv' does not exist");
+
+        assert_eq!(block_size("15"), Ok(15));
+        assert!(block_size("7").is_err() && block_size("0").is_err());
+        assert_eq!(positive("20"), Ok(20));
+        assert!(positive("0").is_err() && positive("-1").is_err());
+    }
+}
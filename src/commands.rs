@@ -0,0 +1,28 @@
+// One module per command. Each has an `Options` parser; `run` parses the
+// command line as the binary would see it, with `args[0]` the binary's
+// name, and `run_with` takes the options `ptv-data` has already parsed, so
+// the standalone binaries and `ptv-data` share the same option handling.
+
+pub mod alerts;
+pub mod arrivals;
+pub mod blocks;
+pub mod convert;
+pub mod event;
+pub mod flow;
+pub mod graph;
+pub mod hourly;
+pub mod index_build;
+pub mod line_quarter_hour;
+pub mod load_profile;
+pub mod od_matrix;
+pub mod parallel_graph;
+pub mod peak_loads;
+pub mod profile;
+pub mod quarter_hour;
+pub mod schema_export;
+pub mod screenline;
+pub mod seasonality;
+pub mod stations_export;
+pub mod stations_geojson;
+pub mod train_profile;
+pub mod utilisation;
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use crate::Record;
+use crate::business::DayStart;
+
+use crate::cli::Output;
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::rules::{MetricTables, Severity, Stop, evaluate, findings_json, load_rules};
+
+/// `alerts`: evaluates threshold rules and writes the ones that triggered.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// TOML file of [[rule]] tables to evaluate
+    #[arg(long, value_name = "PATH")]
+    rules: String,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let rules = load_rules(&options.rules)?;
+    let day_start = options.day_start.unwrap_or_default();
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut tables = MetricTables::new(day_start);
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+
+        tables.add(&Stop {
+            business_date: &record.business_date,
+            line: &record.line_name,
+            train_number: &record.train_number,
+            station: &record.station_name,
+            departure_time: &record.departure_time_scheduled,
+            boardings: record.passenger_boardings,
+            alightings: record.passenger_alightings,
+            departure_load: record.passenger_departure_load,
+        });
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let findings = evaluate(&rules, &tables);
+    let output_file_path = format!("{}/findings.json", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", findings_json(&findings, rules.len()))?;
+
+    if findings.is_empty() {
+        println!("None of the {} rules triggered.", rules.len());
+    } else {
+        println!("Triggered rules:");
+        for finding in &findings {
+            println!("  [{}] {}: {} matches", finding.rule.severity.name(), finding.rule.name, finding.matches.len());
+        }
+    }
+    println!("Findings saved to '{}'.", output_file_path);
+
+    let errors = findings.iter().filter(|finding| finding.rule.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(format!("{} rules with severity \"error\" triggered", errors).into());
+    }
+
+    Ok(())
+}
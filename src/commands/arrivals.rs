@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use plotters::prelude::*;
+use crate::Record;
+use crate::business::{DayStart, parse_service_time};
+
+use crate::cli::Output;
+use crate::dates::parse_date;
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+use crate::stations::normalize_station;
+
+// The City Loop and Flinders Street, used when --stations isn't given.
+const CBD_STATIONS: [&str; 5] = [
+    "Flinders Street",
+    "Southern Cross",
+    "Flagstaff",
+    "Melbourne Central",
+    "Parliament",
+];
+
+const WEEKDAYS: [&str; 5] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday"];
+
+// 15-minute intervals in a business day.
+const INTERVALS: usize = 96;
+
+// Alightings per 15-minute arrival interval at the wanted stations, on one
+// business date or summed over every weekday date.
+struct ArrivalProfile {
+    weekday_average: bool,
+    // The profiled date without --weekday-average; the first date added
+    // when --date isn't given.
+    selected_date: Option<String>,
+    alightings: [i64; INTERVALS],
+    dates: HashSet<String>,
+    // Normalized names of the stations with at least one arrival counted.
+    seen: HashSet<String>,
+}
+
+impl ArrivalProfile {
+    fn new(selected_date: Option<String>, weekday_average: bool) -> ArrivalProfile {
+        ArrivalProfile { weekday_average, selected_date, alightings: [0; INTERVALS], dates: HashSet::new(), seen: HashSet::new() }
+    }
+
+    /// Counts the record's alightings at `station`, bucketed by when the
+    /// train arrives, since alighting passengers get off on arrival, not
+    /// departure. Records without an arrival time are ignored.
+    fn add(&mut self, record: &Record, station: String, day_start: &DayStart) {
+        let Some(arrival_time) = parse_service_time(&record.arrival_time_scheduled) else {
+            return;
+        };
+        let at = day_start.at(arrival_time);
+        let business_date = at.date(&record.business_date);
+        let included = if self.weekday_average {
+            WEEKDAYS.contains(&record.day_of_week.as_str())
+        } else {
+            self.selected_date.get_or_insert_with(|| business_date.clone()) == &business_date
+        };
+        if included {
+            self.alightings[at.interval(15)] += record.passenger_alightings as i64;
+            self.dates.insert(business_date);
+            self.seen.insert(station);
+        }
+    }
+
+    /// Alightings per interval, averaged over the dates seen; a single date
+    /// is its own average.
+    fn averaged(&self) -> Vec<f64> {
+        let days = self.dates.len().max(1) as f64;
+        self.alightings.iter().map(|&total| total as f64 / days).collect()
+    }
+}
+
+/// `arrivals`: alightings at a set of stations in 15-minute intervals.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Stations to count, comma-separated (default: Flinders Street and the City Loop)
+    #[arg(long, value_name = "NAMES")]
+    stations: Option<String>,
+    /// Business date to profile, YYYY-MM-DD (default: the first in the file)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Average over every Monday-Friday date, public holidays excluded
+    #[arg(long, conflicts_with = "date")]
+    weekday_average: bool,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let float_format = options.float_format;
+    let day_start = options.day_start.unwrap_or_default();
+    let weekday_average = options.weekday_average;
+
+    // Station names are matched after normalization; keep the given spelling
+    // for reporting stations that never appear.
+    let station_list: Vec<String> = match &options.stations {
+        Some(list) => list.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        None => CBD_STATIONS.iter().map(|s| s.to_string()).collect(),
+    };
+    let wanted: HashSet<String> = station_list.iter().map(|station| normalize_station(station)).collect();
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    // Without --weekday-average, profile one date: --date, or the first
+    // business date in the file like the other time-series outputs.
+    let mut profile = ArrivalProfile::new(options.date.clone(), weekday_average);
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+
+        let station = normalize_station(&record.station_name);
+        if !wanted.contains(&station) {
+            pb.inc(1);
+            continue;
+        }
+
+        profile.add(&record, station, &day_start);
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    for station in &station_list {
+        if !profile.seen.contains(&normalize_station(station)) {
+            eprintln!("Warning: no arrivals recorded at '{}'.", station);
+        }
+    }
+
+    let averaged = profile.averaged();
+
+    let output_file_path = format!("{}/arrival_profile.csv", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::ARRIVAL_PROFILE.header())?;
+    for (interval, alightings) in averaged.iter().enumerate() {
+        writeln!(file, "{},{}", day_start.time_label(interval * 15), float_format.format(*alightings, 2))?;
+    }
+
+    let caption = if weekday_average {
+        format!("Weekday Average Arrivals ({} days)", profile.dates.len())
+    } else {
+        format!("Arrivals on {}", profile.selected_date.as_deref().unwrap_or("(no data)"))
+    };
+    let mut skips = ChartSkips::new("arrivals", &options.output, options.allow_empty_charts);
+    if skips.check("arrival_profile_chart.png", [averaged.iter().cloned()]) {
+        let caption = mode_filter.mode.caption(&caption);
+        generate_arrival_chart(&options.output.file("arrival_profile_chart.png"), &caption, &averaged, &day_start)?;
+    }
+
+    println!("Arrival profile for {} stations saved to '{}'.", wanted.len(), output_file_path);
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Generates a line chart of alightings per 15-minute arrival interval.
+fn generate_arrival_chart(filename: &str, caption: &str, data: &[f64], day_start: &DayStart) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_value = data.iter().cloned().fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 80)
+        .build_cartesian_2d(0..INTERVALS - 1, 0.0..(max_value * 1.1 + 1.0))?;
+
+    chart.configure_mesh()
+        .x_labels(12)
+        .x_label_formatter(&|interval| day_start.time_label(*interval * 15))
+        .x_desc("Scheduled Arrival")
+        .y_desc("Alightings")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(
+        data.iter().enumerate().map(|(interval, &value)| (interval, value)),
+        RGBColor(0, 0, 255).stroke_width(3),
+    ))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrival(date: &str, day: &str, station: &str, arrival: &str, alightings: i32) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: day.to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: "Pakenham".to_string(),
+            group: "Caulfield".to_string(),
+            direction: "U".to_string(),
+            origin_station: "East Pakenham".to_string(),
+            destination_station: "Flinders Street".to_string(),
+            station_name: station.to_string(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: arrival.to_string(),
+            departure_time_scheduled: String::new(),
+            passenger_boardings: 0,
+            passenger_alightings: alightings,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    fn profile_of(records: &[Record], selected_date: Option<&str>, weekday_average: bool) -> ArrivalProfile {
+        let mut profile = ArrivalProfile::new(selected_date.map(|d| d.to_string()), weekday_average);
+        for record in records {
+            profile.add(record, normalize_station(&record.station_name), &DayStart::default());
+        }
+        profile
+    }
+
+    #[test]
+    fn counts_alightings_by_arrival_interval() {
+        let records = [
+            arrival("2022-09-12", "Monday", "Flinders Street", "08:05:00", 120),
+            arrival("2022-09-12", "Monday", "Flinders Street", "08:14:00", 30),
+            arrival("2022-09-12", "Monday", "Parliament", "08:15:00", 40),
+            // After midnight, at the end of the 12th's business day.
+            arrival("2022-09-12", "Monday", "Flinders Street", "00:30:00", 5),
+            // No arrival time: nothing to bucket by.
+            arrival("2022-09-12", "Monday", "Flinders Street", "", 99),
+            // A later business date isn't the profiled one.
+            arrival("2022-09-13", "Tuesday", "Flinders Street", "08:05:00", 500),
+        ];
+        let profile = profile_of(&records, None, false);
+        assert_eq!(profile.selected_date.as_deref(), Some("2022-09-12"));
+
+        // 03:00 is interval 0, so 08:00-08:14 is 20 and 00:30 is 86.
+        let counted: Vec<(usize, i64)> = profile.alightings.iter().cloned().enumerate().filter(|&(_, n)| n > 0).collect();
+        assert_eq!(counted, [(20, 150), (21, 40), (86, 5)]);
+        assert_eq!(profile.seen.len(), 2);
+    }
+
+    #[test]
+    fn weekday_average_divides_by_the_dates_seen() {
+        let records = [
+            arrival("2022-09-12", "Monday", "Flinders Street", "08:05:00", 100),
+            arrival("2022-09-13", "Tuesday", "Flinders Street", "08:05:00", 50),
+            arrival("2022-09-17", "Saturday", "Flinders Street", "08:05:00", 1000),
+        ];
+        let averaged = profile_of(&records, None, true).averaged();
+        assert_eq!(averaged[20], 75.0);
+        assert_eq!(averaged.iter().sum::<f64>(), 75.0);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Write};
+use clap::Parser;
+use crate::Record;
+use crate::business::DayStart;
+
+use crate::aggregates::ServiceCounts;
+use crate::cli::{Output, block_size};
+use crate::dates::{DatePicker, parse_date};
+use crate::filters::{DayFilter, DayOptions, ModeFilter, ServiceOptions, TimeFilter, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::sqlite::{self, Stations};
+use crate::summary::{OutputFormat, Summary};
+use crate::timing::StageTimings;
+
+/// `generateData5min`: movements per line in blocks of a few minutes.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    #[command(flatten)]
+    days: DayOptions,
+    /// Minutes per block, which must divide the hour
+    #[arg(long, visible_alias = "interval", value_name = "MINUTES", default_value_t = 5, value_parser = block_size)]
+    block_size: u32,
+    /// Write only this line, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Business date of the time series, YYYY-MM-DD (default: the first in the file)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// csv, or json to write summary.json in place of the per-line CSVs
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Write summary.json alongside the CSVs
+    #[arg(long)]
+    json: bool,
+    /// Save the totals and time series to a SQLite database
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<String>,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+
+    options.source.input.or_positional(options.file.take());
+    let output_dir = options.output.path();
+    let float_format = options.float_format;
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let day_start = options.day_start.unwrap_or_default();
+    let mut dates = DatePicker::new(options.date.clone());
+    // `--format json` writes summary.json in place of the per-line CSVs.
+    let json_only = options.format == OutputFormat::Json;
+    let sqlite_path = options.sqlite.as_deref();
+    let block_size = options.block_size;
+    let specified_line = options.line.as_ref().map(|s| s.to_lowercase());
+
+    let intervals_per_hour = 60 / block_size;
+    let total_intervals = (24 - 3) * intervals_per_hour;
+
+    options.output.create()?;
+
+    let mut timings = StageTimings::new();
+    let mut source = RecordSource::open(&options.source)?;
+
+    let mut boardings_per_line: HashMap<String, i64> = HashMap::new();
+    let mut alightings_per_line: HashMap<String, i64> = HashMap::new();
+    let mut services = ServiceCounts::default();
+    let mut time_series: HashMap<String, Vec<f64>> = HashMap::new();
+    // Station totals, only kept for --sqlite.
+    let mut stations = Stations::new();
+
+    let mut pb = source.progress(options.source.no_progress);
+
+    timings.start("aggregation");
+    let mut rows = 0;
+    // Departures rounding past the last block of the series.
+    let mut past_last_block = 0u64;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let mut day_filter = DayFilter::from_args(&options.days);
+    let mut time_filter = TimeFilter::from_args(&options.days, day_start)?;
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        rows += 1;
+        if !train_filter.matches(&record.train_number)
+            || !mode_filter.matches(&record.mode)
+            || !day_filter.matches(&record.day_type, &record.day_of_week)
+            || !time_filter.matches(&record.departure_time_scheduled, &record.arrival_time_scheduled)
+        {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        let line = record.line_name.to_lowercase();
+        if specified_line.as_ref().is_some_and(|specified| line != *specified) {
+            pb.inc(1);
+            continue;
+        }
+        // Totals over every date, for --json.
+        *boardings_per_line.entry(line.clone()).or_insert(0) += record.passenger_boardings as i64;
+        *alightings_per_line.entry(line.clone()).or_insert(0) += record.passenger_alightings as i64;
+        services.add(&line, &record.business_date, &record.train_number);
+        if sqlite_path.is_some() {
+            sqlite::add_station(
+                &mut stations, &record.station_name, record.coordinates(),
+                record.passenger_boardings as i64, record.passenger_alightings as i64,
+            );
+        }
+        let (business_date, at) = day_start.business_date(&record);
+
+        // Skip data if it does not belong to --date, or the first encountered date
+        if !dates.selects(&business_date) {
+            continue;
+        }
+
+        if let Some(at) = at {
+            let entry = time_series.entry(line.clone()).or_insert_with(|| vec![0.0; total_intervals as usize]);
+
+            // Nearest block boundary, counted from the business day start;
+            // the series stops 21 hours in, so later departures are left out.
+            match at.nearest_block(block_size).filter(|&block| block < total_intervals as usize) {
+                Some(time_block) => entry[time_block] += (record.passenger_boardings + record.passenger_alightings) as f64,
+                None => past_last_block += 1,
+            }
+        }
+
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    day_filter.check()?;
+    day_filter.report();
+    time_filter.report();
+    if past_last_block > 0 {
+        eprintln!(
+            "Warning: {} departures round past the last {}-minute block of the series and were left out.",
+            past_last_block, block_size
+        );
+    }
+    dates.report()?;
+
+    timings.start("csv writing");
+    let mut rows_written = 0;
+    if !json_only {
+        for (line, counts) in &time_series {
+            let output_file_path = format!("{}/{}_{}min.csv", output_dir, line, block_size);
+            let mut file = File::create(&output_file_path)?;
+
+            writeln!(file, "{}", schema::BLOCK_MOVEMENTS.header())?;
+            for (interval, &count) in counts.iter().enumerate() {
+                let decimal_time = day_start.hour() as f64 + (interval as f64 / intervals_per_hour as f64);
+                writeln!(file, "{:.2},{}", decimal_time, float_format.format(count, 2))?;
+            }
+            rows_written += counts.len() as u64;
+        }
+    }
+
+    // Every line's totals and time series, for summary.json and --sqlite.
+    let mut summary = Summary::new(block_size, day_start);
+    for (line, &boardings) in &boardings_per_line {
+        let alightings = alightings_per_line.get(line).copied().unwrap_or(0);
+        summary.add_totals(line, boardings, alightings, services.count(line));
+    }
+    for (line, counts) in &time_series {
+        summary.add_series(line, dates.selected(), counts);
+    }
+    if json_only || options.json {
+        let output_file_path = format!("{}/summary.json", output_dir);
+        summary.add_timings(&timings);
+        summary.write(&output_file_path)?;
+        println!("JSON summary of {} lines saved to '{}'.", summary.lines.len(), output_file_path);
+    }
+    if let Some(path) = sqlite_path {
+        let hourly_rows = sqlite::export(path, &summary, &stations)?;
+        println!(
+            "{} lines, {} hourly rows and {} stations saved to '{}'.",
+            summary.lines.len(), hourly_rows, stations.len(), path
+        );
+    }
+    timings.end(Some(rows_written));
+
+    println!("Processed data saved in '{}'.", output_dir);
+    timings.print_summary();
+    issues.print_summary();
+
+    Ok(())
+}
@@ -0,0 +1,449 @@
+// `convert [<input.csv>] [<output.parquet>]`: streams the records into a
+// Parquet file with typed columns, so DuckDB, pandas or Spark can query them
+// without re-parsing the CSV. Columns keep data.csv's names and order.
+// Business_Date is a Date32, the scheduled times are Time32 (milliseconds),
+// the coordinates Float64 and the counts Int32. Times past 24:00, which
+// services running after midnight use, are stored as the clock time on the
+// next day; Business_Date still says which business day they belong to.
+// Rows whose date, coordinates or non-blank times don't parse are written to
+// `<output>_rejects.csv` instead, with the reason.
+
+use arrow::array::{ArrayRef, Date32Builder, Float64Builder, Int32Builder, StringBuilder, Time32MillisecondBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{NaiveDate, Timelike};
+use clap::Parser;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use crate::Record;
+use crate::business::parse_service_time;
+use crate::record::coordinate;
+
+use crate::format::text_field;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+
+// Written to when no output path is given.
+const DEFAULT_OUTPUT: &str = "data.parquet";
+
+// Rows buffered before each record batch is handed to the Parquet writer.
+const BATCH_ROWS: usize = 65_536;
+
+// data.csv's text columns, in order, between Business_Date and the coordinates.
+const TEXT_COLUMNS: [&str; 10] = [
+    "Day_of_Week",
+    "Day_Type",
+    "Mode",
+    "Train_Number",
+    "Line_Name",
+    "Group",
+    "Direction",
+    "Origin_Station",
+    "Destination_Station",
+    "Station_Name",
+];
+const COORDINATE_COLUMNS: [&str; 2] = ["Station_Latitude", "Station_Longitude"];
+const POSITION_COLUMNS: [&str; 2] = ["Station_Chainage", "Stop_Sequence_Number"];
+const TIME_COLUMNS: [&str; 2] = ["Arrival_Time_Scheduled", "Departure_Time_Scheduled"];
+const COUNT_COLUMNS: [&str; 4] = [
+    "Passenger_Boardings",
+    "Passenger_Alightings",
+    "Passenger_Arrival_Load",
+    "Passenger_Departure_Load",
+];
+
+/// `convert`: the records as a Parquet file with typed columns.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given, or else the Parquet file to write
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    /// Parquet file to write (default data.parquet)
+    #[arg(value_name = "OUTPUT")]
+    parquet: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    issues: IssueOptions,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+    // The output is the second positional, or the first after --input.
+    let output_path = if options.source.input.paths.is_empty() {
+        options.source.input.or_positional(options.file.take());
+        options.parquet.take()
+    } else if options.parquet.is_some() {
+        return Err("convert takes only the output path after --input".into());
+    } else {
+        options.file.take()
+    };
+    let output_path = output_path.as_deref().unwrap_or(DEFAULT_OUTPUT);
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut sink = ParquetSink::create(output_path)?;
+    let mut pb = source.progress(options.source.no_progress);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        sink.add(row as u64 + 1, &record)?;
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    issues.print_summary();
+
+    let (written, rejected) = sink.finish()?;
+    if rejected > 0 {
+        eprintln!(
+            "Warning: {} rows had a date, coordinate or time that doesn't parse and were written to '{}' instead.",
+            rejected, rejects_path(output_path)
+        );
+    }
+    println!("Converted {} rows to '{}'.", written, output_path);
+
+    Ok(())
+}
+
+/// Where the rows that can't be typed go: `data_rejects.csv` for
+/// `data.parquet`.
+fn rejects_path(output_path: &str) -> String {
+    format!("{}_rejects.csv", output_path.strip_suffix(".parquet").unwrap_or(output_path))
+}
+
+/// The Parquet file's columns, in data.csv's order.
+fn parquet_schema() -> SchemaRef {
+    let mut fields = vec![Field::new("Business_Date", DataType::Date32, false)];
+    fields.extend(TEXT_COLUMNS.iter().map(|name| Field::new(*name, DataType::Utf8, false)));
+    fields.extend(COORDINATE_COLUMNS.iter().map(|name| Field::new(*name, DataType::Float64, false)));
+    // Station_Chainage is blank when the distance is unknown.
+    fields.extend(POSITION_COLUMNS.iter().map(|name| Field::new(*name, DataType::Int32, *name == "Station_Chainage")));
+    // A blank time, as at the first and last stops, is null.
+    fields.extend(TIME_COLUMNS.iter().map(|name| Field::new(*name, DataType::Time32(TimeUnit::Millisecond), true)));
+    fields.extend(COUNT_COLUMNS.iter().map(|name| Field::new(*name, DataType::Int32, false)));
+    Arc::new(Schema::new(fields))
+}
+
+/// A record's fields that aren't text or counts, parsed into their column
+/// types.
+#[derive(Debug, PartialEq)]
+struct TypedFields {
+    /// Days since 1970-01-01.
+    business_date: i32,
+    coordinates: [f64; 2],
+    /// Milliseconds since midnight; `None` when blank.
+    times: [Option<i32>; 2],
+}
+
+impl TypedFields {
+    /// Parses a record's typed fields, or gives the reason it can't be.
+    fn parse(record: &Record) -> Result<TypedFields, String> {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let business_date = NaiveDate::parse_from_str(&record.business_date, "%Y-%m-%d")
+            .map(|date| (date - epoch).num_days() as i32)
+            .map_err(|_| format!("Business_Date '{}' is not a YYYY-MM-DD date", record.business_date))?;
+
+        let mut coordinates = [0.0; 2];
+        for ((value, name), parsed) in [&record.station_latitude, &record.station_longitude].iter()
+            .zip(COORDINATE_COLUMNS)
+            .zip(coordinates.iter_mut())
+        {
+            *parsed = coordinate(value).ok_or_else(|| format!("{} '{}' is not a number", name, value))?;
+        }
+
+        let mut times = [None; 2];
+        for ((value, name), parsed) in [&record.arrival_time_scheduled, &record.departure_time_scheduled].iter()
+            .zip(TIME_COLUMNS)
+            .zip(times.iter_mut())
+        {
+            if value.trim().is_empty() {
+                continue;
+            }
+            let time = parse_service_time(value).ok_or_else(|| format!("{} '{}' is not an HH:MM:SS time", name, value))?;
+            *parsed = Some((time.num_seconds_from_midnight() * 1000) as i32);
+        }
+
+        Ok(TypedFields { business_date, coordinates, times })
+    }
+}
+
+/// Column builders for the rows of the next record batch.
+struct Batch {
+    rows: usize,
+    business_date: Date32Builder,
+    text: [StringBuilder; 10],
+    coordinates: [Float64Builder; 2],
+    positions: [Int32Builder; 2],
+    times: [Time32MillisecondBuilder; 2],
+    counts: [Int32Builder; 4],
+}
+
+impl Batch {
+    fn new() -> Batch {
+        Batch {
+            rows: 0,
+            business_date: Date32Builder::new(),
+            text: std::array::from_fn(|_| StringBuilder::new()),
+            coordinates: std::array::from_fn(|_| Float64Builder::new()),
+            positions: std::array::from_fn(|_| Int32Builder::new()),
+            times: std::array::from_fn(|_| Time32MillisecondBuilder::new()),
+            counts: std::array::from_fn(|_| Int32Builder::new()),
+        }
+    }
+
+    fn push(&mut self, record: &Record, typed: &TypedFields) {
+        self.business_date.append_value(typed.business_date);
+        for (builder, value) in self.text.iter_mut().zip(text_values(record)) {
+            builder.append_value(value);
+        }
+        for (builder, &value) in self.coordinates.iter_mut().zip(&typed.coordinates) {
+            builder.append_value(value);
+        }
+        for (builder, value) in self.positions.iter_mut().zip([record.station_chainage, Some(record.stop_sequence_number)]) {
+            builder.append_option(value);
+        }
+        for (builder, &value) in self.times.iter_mut().zip(&typed.times) {
+            builder.append_option(value);
+        }
+        for (builder, value) in self.counts.iter_mut().zip([
+            record.passenger_boardings,
+            record.passenger_alightings,
+            record.passenger_arrival_load,
+            record.passenger_departure_load,
+        ]) {
+            builder.append_value(value);
+        }
+        self.rows += 1;
+    }
+
+    /// The rows pushed so far as a record batch, leaving the builders empty.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(self.business_date.finish())];
+        columns.extend(self.text.iter_mut().map(|builder| Arc::new(builder.finish()) as ArrayRef));
+        columns.extend(self.coordinates.iter_mut().map(|builder| Arc::new(builder.finish()) as ArrayRef));
+        columns.extend(self.positions.iter_mut().map(|builder| Arc::new(builder.finish()) as ArrayRef));
+        columns.extend(self.times.iter_mut().map(|builder| Arc::new(builder.finish()) as ArrayRef));
+        columns.extend(self.counts.iter_mut().map(|builder| Arc::new(builder.finish()) as ArrayRef));
+        self.rows = 0;
+        RecordBatch::try_new(schema.clone(), columns)
+    }
+}
+
+/// A record's text fields, in `TEXT_COLUMNS` order.
+fn text_values(record: &Record) -> [&str; 10] {
+    [
+        &record.day_of_week,
+        &record.day_type,
+        &record.mode,
+        &record.train_number,
+        &record.line_name,
+        &record.group,
+        &record.direction,
+        &record.origin_station,
+        &record.destination_station,
+        &record.station_name,
+    ]
+}
+
+/// A record as a data.csv row, fields unchanged.
+fn csv_row(record: &Record) -> String {
+    let mut fields = vec![text_field(&record.business_date)];
+    fields.extend(text_values(record).iter().map(|value| text_field(value)));
+    fields.extend([&record.station_latitude, &record.station_longitude].iter().map(|value| text_field(value)));
+    fields.push(record.station_chainage.map_or(String::new(), |chainage| chainage.to_string()));
+    fields.push(record.stop_sequence_number.to_string());
+    fields.extend([&record.arrival_time_scheduled, &record.departure_time_scheduled].iter().map(|value| text_field(value)));
+    fields.extend(
+        [
+            record.passenger_boardings,
+            record.passenger_alightings,
+            record.passenger_arrival_load,
+            record.passenger_departure_load,
+        ]
+        .iter()
+        .map(|value| value.to_string()),
+    );
+    fields.join(",")
+}
+
+/// Writes typed rows to the Parquet file in batches, and rows that can't be
+/// typed to the rejects CSV.
+struct ParquetSink {
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+    batch: Batch,
+    rejects: BufWriter<File>,
+    written: u64,
+    rejected: u64,
+}
+
+impl ParquetSink {
+    fn create(output_path: &str) -> Result<ParquetSink, Box<dyn Error>> {
+        let schema = parquet_schema();
+        let file = File::create(output_path).map_err(|err| format!("cannot create '{}': {}", output_path, err))?;
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+
+        let mut rejects = BufWriter::new(File::create(rejects_path(output_path))?);
+        let columns: Vec<&str> = std::iter::once("Business_Date")
+            .chain(TEXT_COLUMNS)
+            .chain(COORDINATE_COLUMNS)
+            .chain(POSITION_COLUMNS)
+            .chain(TIME_COLUMNS)
+            .chain(COUNT_COLUMNS)
+            .collect();
+        writeln!(rejects, "{},{}", schema::CONVERT_REJECTS.header(), columns.join(","))?;
+
+        Ok(ParquetSink { schema, writer, batch: Batch::new(), rejects, written: 0, rejected: 0 })
+    }
+
+    /// Adds the record read from data row `row` (1-based).
+    fn add(&mut self, row: u64, record: &Record) -> Result<(), Box<dyn Error>> {
+        match TypedFields::parse(record) {
+            Ok(typed) => {
+                self.batch.push(record, &typed);
+                self.written += 1;
+                if self.batch.rows >= BATCH_ROWS {
+                    self.writer.write(&self.batch.finish(&self.schema)?)?;
+                }
+            }
+            Err(reason) => {
+                writeln!(self.rejects, "{},{},{}", row, text_field(&reason), csv_row(record))?;
+                self.rejected += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the last batch and closes both files. Returns how many rows
+    /// were written and how many rejected.
+    fn finish(mut self) -> Result<(u64, u64), Box<dyn Error>> {
+        if self.batch.rows > 0 {
+            self.writer.write(&self.batch.finish(&self.schema)?)?;
+        }
+        self.writer.close()?;
+        self.rejects.flush()?;
+        Ok((self.written, self.rejected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Date32Array, Float64Array, StringArray, Time32MillisecondArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn stop(date: &str, latitude: &str, arrival: &str, departure: &str) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: "Monday".to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: "Pakenham".to_string(),
+            group: "Caulfield".to_string(),
+            direction: "D".to_string(),
+            origin_station: "Flinders Street".to_string(),
+            destination_station: "East Pakenham".to_string(),
+            station_name: "Richmond, Platform 5".to_string(),
+            station_latitude: latitude.to_string(),
+            station_longitude: "144.9905".to_string(),
+            station_chainage: Some(2400),
+            stop_sequence_number: 3,
+            arrival_time_scheduled: arrival.to_string(),
+            departure_time_scheduled: departure.to_string(),
+            passenger_boardings: 12,
+            passenger_alightings: 4,
+            passenger_arrival_load: 80,
+            passenger_departure_load: 88,
+        }
+    }
+
+    #[test]
+    fn writes_typed_columns_and_rejects_the_rest() {
+        let dir = std::env::temp_dir().join(format!("ptv_data_convert_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("data.parquet");
+        let output = output.to_str().unwrap();
+
+        let mut sink = ParquetSink::create(output).unwrap();
+        sink.add(1, &stop("2022-09-12", "-37.8240", "", "07:15:00")).unwrap();
+        sink.add(2, &stop("2022-09-12", "-37.8240", "24:05:30", "24:06:00")).unwrap();
+        sink.add(3, &stop("2022-09-12", "n/a", "07:20:00", "07:21:00")).unwrap();
+        sink.add(4, &stop("12/09/2022", "-37.8240", "07:20:00", "07:21:00")).unwrap();
+        sink.add(5, &stop("2022-09-12", "-37.8240", "7.20", "07:21:00")).unwrap();
+        assert_eq!(sink.finish().unwrap(), (2, 3));
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(output).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 2);
+        let batch = &batches[0];
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).name(), "Business_Date");
+        assert_eq!(schema.field(0).data_type(), &DataType::Date32);
+        assert_eq!(schema.field_with_name("Station_Latitude").unwrap().data_type(), &DataType::Float64);
+        assert_eq!(schema.field_with_name("Passenger_Boardings").unwrap().data_type(), &DataType::Int32);
+        assert_eq!(schema.fields().len(), 21);
+
+        let column = |name: &str| batch.column(schema.index_of(name).unwrap()).clone();
+        let dates = column("Business_Date");
+        let dates = dates.as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!(dates.value_as_date(0), NaiveDate::from_ymd_opt(2022, 9, 12));
+        let latitudes = column("Station_Latitude");
+        assert_eq!(latitudes.as_any().downcast_ref::<Float64Array>().unwrap().value(0), -37.824);
+        let stations = column("Station_Name");
+        assert_eq!(stations.as_any().downcast_ref::<StringArray>().unwrap().value(0), "Richmond, Platform 5");
+        let arrivals = column("Arrival_Time_Scheduled");
+        let arrivals = arrivals.as_any().downcast_ref::<Time32MillisecondArray>().unwrap();
+        assert!(arrivals.is_null(0));
+        // Past midnight, as the clock time on the next day.
+        assert_eq!(arrivals.value(1), (5 * 60 + 30) * 1000);
+
+        let rejects = std::fs::read_to_string(rejects_path(output)).unwrap();
+        let lines: Vec<&str> = rejects.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Row,Reason,Business_Date,Day_of_Week,"), "{}", lines[0]);
+        assert!(lines[1].starts_with("3,Station_Latitude 'n/a' is not a number,2022-09-12,Monday,"), "{}", lines[1]);
+        assert!(lines[1].contains(",\"Richmond, Platform 5\",n/a,144.9905,2400,3,07:20:00,"), "{}", lines[1]);
+        assert!(lines[2].starts_with("4,Business_Date '12/09/2022' is not a YYYY-MM-DD date,"), "{}", lines[2]);
+        assert!(lines[3].starts_with("5,Arrival_Time_Scheduled '7.20' is not an HH:MM:SS time,"), "{}", lines[3]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_sit_beside_the_output() {
+        assert_eq!(rejects_path("out/data.parquet"), "out/data_rejects.csv");
+        assert_eq!(rejects_path("records"), "records_rejects.csv");
+    }
+}
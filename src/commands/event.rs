@@ -0,0 +1,566 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use chrono::{Datelike, NaiveDate};
+use clap::{Parser, ValueEnum};
+use plotters::prelude::*;
+use serde::Serialize;
+use crate::Record;
+use crate::business::DayStart;
+
+use crate::cli::Output;
+use crate::dates::parse_date;
+use crate::filters::{Mode, ModeFilter, ServiceOptions, TrainFilter};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::skips::ChartSkips;
+
+// Comparable dates fall on the same weekday within this many days of the
+// event date.
+const WINDOW_DAYS: i64 = 28;
+
+// Business hours listed under "largest increase".
+const TOP_HOURS: usize = 5;
+
+/// Movements on one date per business hour, by line and by station.
+#[derive(Default)]
+struct DayTotals {
+    lines: HashMap<String, [i64; 24]>,
+    stations: HashMap<String, [i64; 24]>,
+}
+
+/// One line or station on the event date against the average of the
+/// comparable dates.
+struct Delta {
+    name: String,
+    event: [f64; 24],
+    baseline: [f64; 24],
+}
+
+impl Delta {
+    fn event_total(&self) -> f64 {
+        self.event.iter().sum()
+    }
+
+    fn baseline_total(&self) -> f64 {
+        self.baseline.iter().sum()
+    }
+
+    fn change(&self) -> f64 {
+        self.event_total() - self.baseline_total()
+    }
+
+    /// Percentage change in the daily total, or `None` with no baseline.
+    fn change_pct(&self) -> Option<f64> {
+        let baseline = self.baseline_total();
+        (baseline > 0.0).then(|| self.change() / baseline * 100.0)
+    }
+}
+
+/// Compares every line or station seen on any of the dates, largest
+/// absolute change first. One missing from a date counts as zero there.
+fn deltas(event: &HashMap<String, [i64; 24]>, comparable: &[&HashMap<String, [i64; 24]>]) -> Vec<Delta> {
+    let names: BTreeSet<&String> = event.keys()
+        .chain(comparable.iter().flat_map(|day| day.keys()))
+        .collect();
+    let mut deltas: Vec<Delta> = names.into_iter()
+        .map(|name| {
+            let mut baseline = [0.0; 24];
+            for day in comparable {
+                if let Some(hours) = day.get(name) {
+                    for (total, &value) in baseline.iter_mut().zip(hours) {
+                        *total += value as f64;
+                    }
+                }
+            }
+            Delta {
+                name: name.clone(),
+                event: event.get(name).map_or([0.0; 24], |hours| hours.map(|value| value as f64)),
+                baseline: baseline.map(|total| total / comparable.len() as f64),
+            }
+        })
+        .collect();
+    deltas.sort_by(|a, b| b.change().abs().total_cmp(&a.change().abs()));
+    deltas
+}
+
+/// Everything the report says about one event date.
+struct Report {
+    date: NaiveDate,
+    day_start: DayStart,
+    comparable: Vec<String>,
+    excluded_holidays: Vec<String>,
+    network: Delta,
+    lines: Vec<Delta>,
+    stations: Vec<Delta>,
+}
+
+impl Report {
+    /// Business hours where the network gained most, biggest first; hours
+    /// with no increase are left out.
+    fn largest_increase_hours(&self) -> Vec<(usize, f64)> {
+        let mut hours: Vec<(usize, f64)> = (0..24)
+            .map(|hour| (hour, self.network.event[hour] - self.network.baseline[hour]))
+            .filter(|&(_, change)| change > 0.0)
+            .collect();
+        hours.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hours.truncate(TOP_HOURS);
+        hours
+    }
+}
+
+/// Movements on the event date and every candidate comparable date, with
+/// the ones that were public holidays.
+#[derive(Default)]
+struct EventDays {
+    days: BTreeMap<String, DayTotals>,
+    holidays: BTreeSet<String>,
+}
+
+impl EventDays {
+    /// Adds the record's movements in the business hour of its departure,
+    /// if its business date is on the event's weekday within the window.
+    fn add(&mut self, record: &Record, event_date: NaiveDate, day_start: &DayStart) {
+        let Some(at) = clock_time(&record.departure_time_scheduled).map(|(hour, minute)| day_start.locate(hour, minute)) else {
+            return;
+        };
+        let business_date = at.date(&record.business_date);
+        let in_window = NaiveDate::parse_from_str(&business_date, "%Y-%m-%d").map_or(false, |date| {
+            date.weekday() == event_date.weekday() && (date - event_date).num_days().abs() <= WINDOW_DAYS
+        });
+        if in_window {
+            if record.day_of_week == "Public Holiday" && !self.holidays.contains(&business_date) {
+                self.holidays.insert(business_date.clone());
+            }
+            let movements = (record.passenger_boardings + record.passenger_alightings) as i64;
+            let day = self.days.entry(business_date).or_default();
+            day.lines.entry(record.line_name.clone()).or_insert([0; 24])[at.hour()] += movements;
+            day.stations.entry(record.station_name.clone()).or_insert([0; 24])[at.hour()] += movements;
+        }
+    }
+
+    /// Compares the event date with the average of the other non-holiday
+    /// dates. It's an error if either is missing.
+    fn report(self, event_date: NaiveDate, day_start: DayStart) -> Result<Report, Box<dyn Error>> {
+        let EventDays { days, holidays } = self;
+        let event_key = event_date.format("%Y-%m-%d").to_string();
+        let event = days.get(&event_key).ok_or_else(|| format!("no records on --date {}", event_key))?;
+        let comparable: Vec<(&String, &DayTotals)> = days.iter()
+            .filter(|(date, _)| **date != event_key && !holidays.contains(*date))
+            .collect();
+        if comparable.is_empty() {
+            return Err(format!(
+                "no comparable dates: no other non-holiday {} within {} days of {}",
+                event_date.format("%A"), WINDOW_DAYS, event_key
+            ).into());
+        }
+
+        let comparable_lines: Vec<&HashMap<String, [i64; 24]>> = comparable.iter().map(|(_, day)| &day.lines).collect();
+        let comparable_stations: Vec<&HashMap<String, [i64; 24]>> = comparable.iter().map(|(_, day)| &day.stations).collect();
+        let lines = deltas(&event.lines, &comparable_lines);
+        let mut network = Delta { name: "Network".to_string(), event: [0.0; 24], baseline: [0.0; 24] };
+        for line in &lines {
+            for hour in 0..24 {
+                network.event[hour] += line.event[hour];
+                network.baseline[hour] += line.baseline[hour];
+            }
+        }
+        Ok(Report {
+            date: event_date,
+            day_start,
+            comparable: comparable.iter().map(|(date, _)| date.to_string()).collect(),
+            excluded_holidays: holidays.into_iter().filter(|date| *date != event_key).collect(),
+            network,
+            lines,
+            stations: deltas(&event.stations, &comparable_stations),
+        })
+    }
+}
+
+/// `event`: one date's movements against comparable dates.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Business date of the event, YYYY-MM-DD
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: String,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// Write the report as event_day.md or event_day.json
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+/// How `--format` writes the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+enum Format {
+    #[default]
+    Markdown,
+    Json,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let event_date = NaiveDate::parse_from_str(&options.date, "%Y-%m-%d")?;
+    let day_start = options.day_start.unwrap_or_default();
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut days = EventDays::default();
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+
+        days.add(&record, event_date, &day_start);
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let report = days.report(event_date, day_start)?;
+    let event_key = event_date.format("%Y-%m-%d").to_string();
+
+    let (text, output_file_path) = if options.format == Format::Json {
+        (report_json(&report), format!("{}/event_day.json", output_dir))
+    } else {
+        (report_markdown(&report), format!("{}/event_day.md", output_dir))
+    };
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", text)?;
+
+    let mut skips = ChartSkips::new("event", &options.output, options.allow_empty_charts);
+    match (report.lines.first(), report.stations.first()) {
+        (Some(line), Some(station)) => {
+            let panels = [line, station];
+            let series = panels.iter().flat_map(|delta| [delta.event, delta.baseline]);
+            if skips.check("event_day_chart.png", series) {
+                generate_overlay_chart(&options.output.file("event_day_chart.png"), &event_key, &panels, &day_start, mode_filter.mode)?;
+            }
+        }
+        _ => {
+            skips.skip("event_day_chart.png", "no line or station movements on the compared dates");
+        }
+    }
+
+    println!(
+        "Event day {} compared with {} dates; report saved to '{}'.",
+        event_key, report.comparable.len(), output_file_path
+    );
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Hour and minute of an `HH:MM:SS` time, including hours past 23 for
+/// after-midnight stops.
+fn clock_time(time: &str) -> Option<(u32, u32)> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next()??))
+}
+
+fn pct_label(pct: Option<f64>) -> String {
+    pct.map_or("n/a".to_string(), |pct| format!("{:+.1}%", pct))
+}
+
+fn report_markdown(report: &Report) -> String {
+    let mut out = format!(
+        "# Event day: {} ({})\n\nCompared with the average of {} {}s within {} days: {}.\n",
+        report.date, report.date.format("%A"), report.comparable.len(), report.date.format("%A"),
+        WINDOW_DAYS, report.comparable.join(", ")
+    );
+    if !report.excluded_holidays.is_empty() {
+        out.push_str(&format!("Public holidays left out: {}.\n", report.excluded_holidays.join(", ")));
+    }
+    out.push_str(&format!(
+        "\nNetwork movements: {:.0} against {:.0} ({}).\n",
+        report.network.event_total(), report.network.baseline_total(), pct_label(report.network.change_pct())
+    ));
+
+    out.push_str("\n## Hours with the largest increase\n\n");
+    out.push_str("| Hour | Event | Comparable average | Change |\n|---|---|---|---|\n");
+    for (hour, change) in report.largest_increase_hours() {
+        out.push_str(&format!(
+            "| {} | {:.0} | {:.1} | {:+.1} |\n",
+            report.day_start.hour_label(hour), report.network.event[hour], report.network.baseline[hour], change
+        ));
+    }
+
+    for (title, deltas) in [("Lines", &report.lines), ("Stations", &report.stations)] {
+        out.push_str(&format!("\n## {}\n\n", title));
+        out.push_str("| Name | Event | Comparable average | Change | Change % |\n|---|---|---|---|---|\n");
+        for delta in deltas {
+            out.push_str(&format!(
+                "| {} | {:.0} | {:.1} | {:+.1} | {} |\n",
+                delta.name.replace('|', "\\|"), delta.event_total(), delta.baseline_total(),
+                delta.change(), pct_label(delta.change_pct())
+            ));
+        }
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    date: String,
+    weekday: String,
+    window_days: i64,
+    comparable_dates: &'a [String],
+    excluded_holidays: &'a [String],
+    network: JsonTotals,
+    largest_increase_hours: Vec<JsonHour>,
+    most_affected_line: Option<&'a str>,
+    most_affected_station: Option<&'a str>,
+    lines: Vec<JsonDelta<'a>>,
+    stations: Vec<JsonDelta<'a>>,
+}
+
+// Movements are whole numbers; averages and changes are kept to two
+// decimal places.
+#[derive(Serialize)]
+struct JsonTotals {
+    event: i64,
+    baseline: f64,
+    change: f64,
+    change_pct: Option<f64>,
+}
+
+impl JsonTotals {
+    fn new(delta: &Delta) -> JsonTotals {
+        JsonTotals {
+            event: delta.event_total().round() as i64,
+            baseline: round2(delta.baseline_total()),
+            change: round2(delta.change()),
+            change_pct: delta.change_pct().map(round2),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonDelta<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    totals: JsonTotals,
+}
+
+impl JsonDelta<'_> {
+    fn list(deltas: &[Delta]) -> Vec<JsonDelta<'_>> {
+        deltas.iter().map(|delta| JsonDelta { name: &delta.name, totals: JsonTotals::new(delta) }).collect()
+    }
+}
+
+#[derive(Serialize)]
+struct JsonHour {
+    hour: String,
+    event: i64,
+    baseline: f64,
+    change: f64,
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn report_json(report: &Report) -> String {
+    let json = JsonReport {
+        date: report.date.to_string(),
+        weekday: report.date.format("%A").to_string(),
+        window_days: WINDOW_DAYS,
+        comparable_dates: &report.comparable,
+        excluded_holidays: &report.excluded_holidays,
+        network: JsonTotals::new(&report.network),
+        largest_increase_hours: report.largest_increase_hours().into_iter()
+            .map(|(hour, change)| JsonHour {
+                hour: report.day_start.hour_label(hour),
+                event: report.network.event[hour].round() as i64,
+                baseline: round2(report.network.baseline[hour]),
+                change: round2(change),
+            })
+            .collect(),
+        most_affected_line: report.lines.first().map(|delta| delta.name.as_str()),
+        most_affected_station: report.stations.first().map(|delta| delta.name.as_str()),
+        lines: JsonDelta::list(&report.lines),
+        stations: JsonDelta::list(&report.stations),
+    };
+    serde_json::to_string_pretty(&json).expect("the report always serializes")
+}
+
+/// Generates one panel per delta, each overlaying the event date's hourly
+/// movements on the comparable average.
+fn generate_overlay_chart(
+    filename: &str,
+    date: &str,
+    deltas: &[&Delta],
+    day_start: &DayStart,
+    mode: Mode,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    for (area, delta) in root.split_evenly((deltas.len(), 1)).iter().zip(deltas) {
+        let max_value = delta.event.iter().chain(delta.baseline.iter()).cloned().fold(0.0, f64::max);
+        let mut chart = ChartBuilder::on(area)
+            .caption(
+                mode.caption(&format!("{}: {} vs Comparable Average ({})", delta.name, date, pct_label(delta.change_pct()))),
+                ("sans-serif", 50),
+            )
+            .margin(30)
+            .set_label_area_size(LabelAreaPosition::Left, 100)
+            .set_label_area_size(LabelAreaPosition::Bottom, 60)
+            .build_cartesian_2d(0usize..23, 0.0..(max_value * 1.1 + 1.0))?;
+
+        chart.configure_mesh()
+            .x_labels(12)
+            .x_label_formatter(&|hour| day_start.hour_label(*hour))
+            .y_desc("Movements")
+            .label_style(("sans-serif", 30))
+            .draw()?;
+
+        let series = [(date.to_string(), &delta.event, RED), ("Comparable average".to_string(), &delta.baseline, BLUE)];
+        for (label, values, color) in series {
+            chart.draw_series(LineSeries::new(
+                values.iter().enumerate().map(|(hour, &value)| (hour, value)),
+                color.stroke_width(3),
+            ))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(3)));
+        }
+
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(&WHITE.mix(0.8))
+            .border_style(&BLACK)
+            .label_font(("sans-serif", 30))
+            .draw()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(date: &str, day: &str, line: &str, station: &str, departure: &str, boardings: i32, alightings: i32) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: day.to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: line.to_string(),
+            group: "Caulfield".to_string(),
+            direction: "D".to_string(),
+            origin_station: "Flinders Street".to_string(),
+            destination_station: "East Pakenham".to_string(),
+            station_name: station.to_string(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: departure.to_string(),
+            passenger_boardings: boardings,
+            passenger_alightings: alightings,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    #[test]
+    fn compares_the_event_with_comparable_dates() {
+        let records = [
+            // The event: a Saturday with a crowd leaving Richmond at 17:00.
+            stop("2022-09-24", "Saturday", "Pakenham", "Richmond", "17:05:00", 900, 100),
+            stop("2022-09-24", "Saturday", "Frankston", "Caulfield", "09:05:00", 40, 0),
+            // Two comparable Saturdays.
+            stop("2022-09-17", "Saturday", "Pakenham", "Richmond", "17:05:00", 150, 50),
+            stop("2022-09-17", "Saturday", "Frankston", "Caulfield", "09:05:00", 60, 0),
+            stop("2022-10-01", "Saturday", "Pakenham", "Richmond", "17:05:00", 250, 50),
+            stop("2022-10-01", "Saturday", "Frankston", "Caulfield", "09:05:00", 20, 0),
+            // A holiday Saturday, a Friday and a Saturday outside the window
+            // are not comparable.
+            stop("2022-09-10", "Public Holiday", "Pakenham", "Richmond", "17:05:00", 5000, 0),
+            stop("2022-09-23", "Friday", "Pakenham", "Richmond", "17:05:00", 5000, 0),
+            stop("2022-08-20", "Saturday", "Pakenham", "Richmond", "17:05:00", 5000, 0),
+        ];
+        let event_date = NaiveDate::from_ymd_opt(2022, 9, 24).unwrap();
+        let mut days = EventDays::default();
+        for record in &records {
+            days.add(record, event_date, &DayStart::default());
+        }
+        let report = days.report(event_date, DayStart::default()).unwrap();
+
+        assert_eq!(report.comparable, ["2022-09-17", "2022-10-01"]);
+        assert_eq!(report.excluded_holidays, ["2022-09-10"]);
+
+        // Pakenham 1000 against an average of 250; Frankston 40 against 40.
+        let lines: Vec<(&str, f64, f64, Option<f64>)> = report.lines.iter()
+            .map(|delta| (delta.name.as_str(), delta.event_total(), delta.baseline_total(), delta.change_pct()))
+            .collect();
+        assert_eq!(lines, [("Pakenham", 1000.0, 250.0, Some(300.0)), ("Frankston", 40.0, 40.0, Some(0.0))]);
+        assert_eq!(report.stations[0].name, "Richmond");
+        assert_eq!(report.network.change(), 750.0);
+        // 17:00 is business hour 14.
+        assert_eq!(report.largest_increase_hours(), [(14, 750.0)]);
+    }
+
+    #[test]
+    fn an_event_without_comparable_dates_is_an_error() {
+        let event_date = NaiveDate::from_ymd_opt(2022, 9, 24).unwrap();
+        let mut days = EventDays::default();
+        days.add(&stop("2022-09-24", "Saturday", "Pakenham", "Richmond", "17:05:00", 900, 100), event_date, &DayStart::default());
+        let err = days.report(event_date, DayStart::default()).err().unwrap().to_string();
+        assert_eq!(err, "no comparable dates: no other non-holiday Saturday within 28 days of 2022-09-24");
+    }
+}
@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::io::{self, Read};
+use chrono::NaiveTime;
+use chrono::Timelike;
+use clap::Parser;
+use crate::business::parse_service_time;
+use crate::open_input;
+use crate::record::records;
+
+use crate::cli::{Input, input_error};
+use crate::timing::StageTimings;
+
+#[derive(Debug)]
+struct TrainService {
+    train_number: u32,
+    station_name: String,
+    arrival_time: NaiveTime,
+    departure_time: NaiveTime,
+    boardings: u32,
+    alightings: u32,
+    arrival_load: u32,
+    departure_load: u32,
+}
+
+fn parse_time(time_str: &str) -> Option<NaiveTime> {
+    parse_service_time(time_str)
+}
+
+// Counts below zero read as 0, as a train number that isn't a number does.
+fn count(value: i32) -> u32 {
+    value.max(0) as u32
+}
+
+/// Reads the stops from CSV text with a header row, by column name so
+/// quoted fields holding commas stay intact. Rows that don't parse or lack
+/// a scheduled arrival or departure time are skipped.
+fn read_data<R: Read>(reader: R) -> Vec<TrainService> {
+    let mut train_services = Vec::new();
+
+    for record in records(reader) {
+        let Ok(record) = record else {
+            continue; // Skip malformed rows
+        };
+
+        let train_number = record.train_number.parse().unwrap_or(0);
+        let station_name = record.station_name;
+
+        let arrival_time = parse_time(&record.arrival_time_scheduled);
+        let departure_time = parse_time(&record.departure_time_scheduled);
+        
+        if arrival_time.is_none() || departure_time.is_none() {
+            continue; // Skip invalid time entries
+        }
+        
+        let arrival_time = arrival_time.unwrap();
+        let departure_time = departure_time.unwrap();
+        
+        let boardings = count(record.passenger_boardings);
+        let alightings = count(record.passenger_alightings);
+        let arrival_load = count(record.passenger_arrival_load);
+        let departure_load = count(record.passenger_departure_load);
+
+        train_services.push(TrainService {
+            train_number,
+            station_name,
+            arrival_time,
+            departure_time,
+            boardings,
+            alightings,
+            arrival_load,
+            departure_load,
+        });
+    }
+
+    train_services
+}
+
+fn calculate_passenger_flow(train_services: Vec<TrainService>) -> Vec<(f64, f64)> {
+    let mut passenger_flow = Vec::new();
+
+    for service in train_services {
+        let arrival_minutes = service.arrival_time.num_seconds_from_midnight() as f64 / 60.0;
+        let departure_minutes = service.departure_time.num_seconds_from_midnight() as f64 / 60.0;
+
+        // Calculate the flow rate (change in passengers) over the time interval.
+        // A stop with no dwell, such as a terminus or a station run through,
+        // has no interval to spread the change over, so it stays flat.
+        let dwell = departure_minutes - arrival_minutes;
+        let rate_of_change = if dwell > 0.0 {
+            (service.boardings as f64 - service.alightings as f64) / dwell
+        } else {
+            0.0
+        };
+
+        // Generate points for the graph (time in minutes, passenger count)
+        passenger_flow.push((arrival_minutes, service.departure_load as f64));
+
+        // Simulate passenger change over the journey
+        let num_points = 100;
+        for i in 1..num_points {
+            let t = arrival_minutes + (departure_minutes - arrival_minutes) * i as f64 / num_points as f64;
+            let passengers_at_t = service.departure_load as f64 + rate_of_change * (t - arrival_minutes);
+            passenger_flow.push((t, passengers_at_t));
+        }
+    }
+
+    return passenger_flow
+}
+
+/// `pakenham`: passenger load over time, as x, y pairs for Desmos.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    input: Input,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+    let file_path = options.input.path();
+    match open_input(file_path) {
+        Ok(file) => {
+            let mut timings = StageTimings::new();
+            timings.start("reading");
+            let train_services = read_data(io::BufReader::new(file));
+            timings.end(Some(train_services.len() as u64));
+            timings.start("flow");
+            let passenger_flow = calculate_passenger_flow(train_services);
+            timings.end(Some(passenger_flow.len() as u64));
+
+            // Output to Desmos-friendly format
+            timings.start("output");
+            println!("x, y");
+            for (time, passengers) in passenger_flow {
+                println!("{}, {}", time, passengers);
+            }
+            timings.end(None);
+            // On stderr, since stdout is the data.
+            let _ = timings.write_summary(&mut io::stderr());
+        }
+        Err(e) => println!("Error reading data: {}", input_error(file_path, e)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+        Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+        Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+        Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+
+    #[test]
+    fn quoted_commas_and_short_rows_dont_shift_columns() {
+        let csv = format!(
+            "{}\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             \"Dandenong, Platform 1\",-37.98,145.21,30800,12,07:40:00,07:41:00,15,40,300,275\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             Pakenham,-38.07,145.48,57300,17,08:05:00,,0,275,275,0\n",
+            HEADER
+        );
+        let services = read_data(csv.as_bytes());
+
+        // The short row and the terminus, which has no departure time, are skipped.
+        assert_eq!(services.len(), 1);
+        let dandenong = &services[0];
+        assert_eq!(dandenong.train_number, 1001);
+        assert_eq!(dandenong.station_name, "Dandenong, Platform 1");
+        assert_eq!(dandenong.arrival_time, NaiveTime::from_hms_opt(7, 40, 0).unwrap());
+        assert_eq!(
+            (dandenong.boardings, dandenong.alightings, dandenong.arrival_load, dandenong.departure_load),
+            (15, 40, 300, 275)
+        );
+    }
+
+    #[test]
+    fn no_dwell_gives_a_flat_segment() {
+        let at = NaiveTime::from_hms_opt(7, 40, 0).unwrap();
+        let services = vec![TrainService {
+            train_number: 1001,
+            station_name: "Richmond".to_string(),
+            arrival_time: at,
+            departure_time: at,
+            boardings: 15,
+            alightings: 40,
+            arrival_load: 300,
+            departure_load: 275,
+        }];
+        let flow = calculate_passenger_flow(services);
+
+        assert_eq!(flow.len(), 100);
+        assert!(flow.iter().all(|&(time, passengers)| time.is_finite() && passengers.is_finite()));
+        assert!(flow.iter().all(|&(time, passengers)| time == 460.0 && passengers == 275.0));
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use chrono::NaiveDate;
+use clap::{ArgAction, Parser};
+use crate::Record;
+use crate::business::{DayStart, parse_service_time, peak_hour};
+
+use crate::aggregates::{self, AggregateOptions, Aggregates, DateSelection};
+use crate::charts::{
+    ChartOptions, DailyTrend, draw_boardings_alightings_chart, draw_cumulative_time_series_chart,
+    draw_daily_trends_chart, draw_direction_movements_chart, draw_heatmap_chart, draw_max_load_chart, draw_peak_windows_chart,
+    draw_station_heatmap_chart, draw_time_series_chart, draw_top_stations_chart, draw_total_movements_chart, line_colors, render_chart,
+};
+use crate::cli::{Output, positive};
+use crate::filters::{DayFilter, DirectionFilter, ModeFilter, TimeFilter, TrainFilter};
+use crate::format::LabelFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+use crate::timing::StageTimings;
+
+/// `generateGraph`: charts and CSVs from the aggregates.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    aggregates: AggregateOptions,
+    #[command(flatten)]
+    charts: ChartOptions,
+    /// Group the digits of value labels in thousands
+    #[arg(long)]
+    thousands_sep: bool,
+    /// Stations in the top stations chart
+    #[arg(long, value_name = "N", default_value_t = 20, value_parser = positive)]
+    top_stations: usize,
+    /// Stack boardings and alightings in the total movements chart
+    #[arg(long)]
+    stacked: bool,
+    /// Draw boardings and alightings by line
+    #[arg(long)]
+    boardings_alightings: bool,
+    /// Draw up and down movements by line
+    #[arg(long)]
+    direction_chart: bool,
+    /// Draw movements by line and hour as a heatmap
+    #[arg(long)]
+    heatmap: bool,
+    /// Draw each line's largest departure load by hour
+    #[arg(long)]
+    max_load: bool,
+    /// Passengers per train, to draw --max-load as a percentage of
+    #[arg(long, value_name = "PASSENGERS", value_parser = aggregates::capacity)]
+    capacity: Option<f64>,
+    /// Draw movements at each station of --line by hour
+    #[arg(long, requires = "line")]
+    station_heatmap: bool,
+    /// Line for --station-heatmap
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Line to draw over the others in the time series charts
+    #[arg(long, value_name = "LINE")]
+    highlight: Option<String>,
+    /// Start the cumulative chart's axis at zero
+    #[arg(long)]
+    from_zero: bool,
+    /// Write each line's peak hour to peaks.csv
+    #[arg(long)]
+    peak_hours: bool,
+    /// Write and draw movements by time band
+    #[arg(long)]
+    peak_window: bool,
+    /// Write and draw the day-over-day change in movements
+    #[arg(long)]
+    daily_trends: bool,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+    /// -vv also prints the size of each aggregate
+    #[arg(short, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+    options.source.input.or_positional(options.file.take());
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let chart_format = options.charts.format()?;
+    let chart_config = options.charts.config;
+    let palette = options.charts.palette;
+    let top_stations = options.top_stations;
+    let labels = LabelFormat { thousands_sep: options.thousands_sep };
+
+    let mut timings = StageTimings::new();
+
+    // Charts and CSVs go to the working directory unless --output-dir says otherwise.
+    if options.output.dir.is_some() {
+        options.output.create()?;
+    }
+
+    // Either reload previously saved aggregates or build them from the CSV.
+    let aggregates = match &options.aggregates.load_agg {
+        Some(path) => {
+            println!("Loading aggregates from '{}'.", path);
+            let aggregates = Aggregates::load(path)?;
+            aggregates.check(&options.aggregates)?;
+            aggregates
+        }
+        None => aggregate_csv(&options.source, &options.aggregates, &mut issues, &mut timings)?,
+    };
+
+    if let Some(path) = &options.aggregates.save_agg {
+        aggregates.save(path)?;
+        println!("Aggregates saved to '{}'.", path);
+    }
+
+    aggregates.check_selected_date()?;
+    println!("{}", aggregates.describe_time_series());
+
+    // With --direction, every output name carries it, e.g. time_series_chart_U.png.
+    let output_name = |name: &str| options.output.file(&aggregates.direction.file_name(name));
+    // Chart file names, e.g. time_series_chart.svg with --format svg.
+    let chart_name = |stem: &str| chart_format.file_name(stem);
+
+    // Compute overall total movements per line.
+    let total_movements = aggregates.total_movements();
+
+    // One color per line, shared by every per-line chart below.
+    let colors = line_colors(total_movements.keys(), palette);
+
+    // Generate the charts.
+    // Chart dimensions increased to 1600x1200.
+    timings.start("chart rendering");
+    let mut skips = ChartSkips::new("generateGraph", &options.output, options.allow_empty_charts);
+    let name = chart_name("total_movements_chart");
+    if skips.check(&name, total_movements.values().map(|&total| [total as f64])) {
+        render_chart!(chart_format, chart_config, &output_name(&name), draw_total_movements_chart(
+            &aggregates.time_range.caption(&aggregates.mode.caption("Total Movements by Line")),
+            &total_movements,
+            options.stacked.then_some((&aggregates.boardings_per_line, &aggregates.alightings_per_line)),
+            &colors,
+            labels,
+        ))?;
+    }
+    if options.boardings_alightings {
+        let name = chart_name("boardings_alightings_chart");
+        // Every counted record adds to both maps, so they share their lines.
+        let counts = aggregates.boardings_per_line.iter().map(|(line, &boardings)| [
+            boardings as f64,
+            aggregates.alightings_per_line.get(line).copied().unwrap_or(0) as f64,
+        ]);
+        if skips.check(&name, counts) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_boardings_alightings_chart(
+                &aggregates.mode.caption("Boardings and Alightings by Line"),
+                &aggregates.boardings_per_line,
+                &aggregates.alightings_per_line,
+                palette,
+                labels,
+            ))?;
+        }
+    }
+    if options.direction_chart {
+        let name = chart_name("direction_movements_chart");
+        if skips.check(&name, aggregates.direction_movements.values().map(|&total| [total as f64])) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_direction_movements_chart(
+                &aggregates.mode.caption("Up and Down Movements by Line"),
+                &aggregates.direction_movements,
+                palette,
+                labels,
+            ))?;
+        }
+    }
+    // Busiest stations across every line; equal totals go by station name.
+    let busiest: Vec<(&str, i64)> = aggregates.stations_by_movements().into_iter()
+        .take(top_stations)
+        .map(|(station, totals)| (station.as_str(), totals.movements()))
+        .collect();
+    let name = chart_name("top_stations_chart");
+    if skips.check(&name, busiest.iter().map(|&(_, movements)| [movements as f64])) {
+        render_chart!(chart_format, chart_config, &output_name(&name), draw_top_stations_chart(
+            &aggregates.mode.caption(&format!("Top {} Stations by Total Movements", busiest.len())),
+            &busiest,
+            palette,
+            labels,
+        ))?;
+    }
+    // Charts plot whole movements, so averages over several dates are rounded.
+    let time_series: HashMap<String, [i32; 24]> = aggregates.time_series().into_iter()
+        .map(|(line, hours)| (line, hours.map(|movements| movements.round() as i32)))
+        .collect();
+    let series_values = time_series.values().map(|hours| hours.map(|total| total as f64));
+    let capacity = options.capacity;
+    let station_heatmap_line = options.line.as_deref().filter(|_| options.station_heatmap);
+    if let Some(label) = aggregates.time_series_label() {
+        let highlight = match &options.highlight {
+            Some(name) => Some(resolve_line(name, &time_series)?),
+            None => None,
+        };
+        let name = chart_name("time_series_chart");
+        if skips.check(&name, series_values.clone()) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_time_series_chart(
+                &aggregates.mode.caption(&format!("Hourly Total Movements on {} (Business Day)", label)),
+                &time_series,
+                &colors,
+                highlight,
+                &aggregates.day_start,
+            ))?;
+        }
+        let name = chart_name("cumulative_time_series_chart");
+        if skips.check(&name, series_values) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_cumulative_time_series_chart(
+                &aggregates.mode.caption(&format!("Cumulative Movements on {} (Business Day)", label)),
+                &time_series,
+                &colors,
+                highlight,
+                options.from_zero,
+                &aggregates.day_start,
+            ))?;
+        }
+        if options.heatmap {
+            let name = chart_name("heatmap_chart");
+            if skips.check(&name, time_series.values().map(|hours| hours.map(|total| total as f64))) {
+                render_chart!(chart_format, chart_config, &output_name(&name), draw_heatmap_chart(
+                    &aggregates.mode.caption(&format!("Hourly Movements by Line on {} (Business Day)", label)),
+                    &time_series,
+                    palette,
+                    &aggregates.day_start,
+                    labels,
+                ))?;
+            }
+        }
+        if options.max_load {
+            let name = chart_name("max_load_chart");
+            let loads = aggregates.max_loads.values().map(|loads| loads.map(|load| load.unwrap_or(0) as f64));
+            if skips.check(&name, loads) {
+                let caption = match capacity {
+                    Some(capacity) => format!("Max Load (% of {} Passengers) on {} (Business Day)", capacity, label),
+                    None => format!("Max Departure Load on {} (Business Day)", label),
+                };
+                render_chart!(chart_format, chart_config, &output_name(&name), draw_max_load_chart(
+                    &aggregates.mode.caption(&caption),
+                    &aggregates.max_loads,
+                    &colors,
+                    capacity,
+                    &aggregates.day_start,
+                ))?;
+            }
+        }
+        if let Some(line) = station_heatmap_line {
+            let (line, stations) = aggregates.station_grid(line)?;
+            let name = chart_name("station_heatmap_chart");
+            if skips.check(&name, stations.iter().map(|(_, hours)| hours.map(|value| value.unwrap_or(0.0)))) {
+                render_chart!(chart_format, chart_config, &output_name(&name), draw_station_heatmap_chart(
+                    &aggregates.mode.caption(&format!("{} Station Movements on {} (Business Day)", line, label)),
+                    &stations,
+                    palette,
+                    &aggregates.day_start,
+                    labels,
+                ))?;
+            }
+        }
+        if options.peak_hours {
+            let peaks_path = output_name("peaks.csv");
+            write_peak_hours_csv(&peaks_path, &time_series, &aggregates.day_start)?;
+            println!("Peak hours saved to '{}'.", peaks_path);
+        }
+    }
+    if options.peak_window {
+        let peak_windows_path = output_name("peak_windows.csv");
+        write_peak_windows_csv(&peak_windows_path, &aggregates.band_movements)?;
+        let bands = aggregates.band_movements.values().map(|bands| bands.map(|total| total as f64));
+        let name = chart_name("peak_windows_chart");
+        if skips.check(&name, bands) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_peak_windows_chart(
+                &aggregates.mode.caption("Movements by Time Band"),
+                &aggregates.band_movements,
+                palette,
+            ))?;
+        }
+        println!("Peak window totals saved to '{}'.", peak_windows_path);
+    }
+    if options.daily_trends {
+        let trends = daily_trends(&aggregates.daily_movements);
+        let daily_trends_path = output_name("daily_trends.csv");
+        write_daily_trends_csv(&daily_trends_path, &trends)?;
+        let totals = trends.values().map(|trend| trend.iter().map(|&(_, total, _)| total as f64));
+        let name = chart_name("daily_trends_chart");
+        if skips.check(&name, totals) {
+            render_chart!(chart_format, chart_config, &output_name(&name), draw_daily_trends_chart(
+                &aggregates.mode.caption("Day-over-Day Change in Total Movements"),
+                &trends,
+                &colors,
+            ))?;
+        }
+        println!("Daily trends saved to '{}'.", daily_trends_path);
+    }
+
+    timings.end(None);
+
+    println!("\nCharts generated successfully.");
+    timings.print_summary();
+    issues.print_summary();
+    if options.verbose >= 2 {
+        aggregates.print_sizes();
+    }
+    skips.finish()?;
+    Ok(())
+}
+
+/// Streams the CSV once, building per-line totals and the hourly time series.
+fn aggregate_csv(
+    inputs: &Source,
+    options: &AggregateOptions,
+    issues: &mut IssueTracker,
+    timings: &mut StageTimings,
+) -> Result<Aggregates, Box<dyn Error>> {
+    // Count total number of records for the progress bar.
+    let mut source = RecordSource::open(inputs)?;
+
+    let mut pb = source.progress(inputs.no_progress);
+
+    // For time-series analysis on a selected business day,
+    // we aggregate the total movements (boardings + alightings) for each "business hour".
+    // Business day runs from --day-start (03:00 by default) for 24 hours.
+    // We'll store an array of 24 counts (one per hour) per line.
+    let mut agg = Aggregates::default();
+    agg.day_start = options.day_start.unwrap_or_default();
+    agg.date_selection = DateSelection::from_args(options);
+    agg.direction = options.direction.unwrap_or_default();
+    agg.split_direction = options.split_direction;
+    if agg.split_direction && agg.direction != DirectionFilter::Both {
+        return Err("--split-direction and --direction can't be used together".into());
+    }
+    let bands = options.bands;
+
+    // Process each record with a progress bar.
+    timings.start("aggregation");
+    let mut rows = 0;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let direction = agg.direction;
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    agg.mode = mode_filter.mode;
+    let mut day_filter = DayFilter::from_args(&options.days);
+    agg.days = day_filter.selection.clone();
+    let mut time_filter = TimeFilter::from_args(&options.days, agg.day_start)?;
+    agg.time_range = time_filter.range;
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number)
+            || !direction.matches(&record.direction)
+            || !mode_filter.matches(&record.mode)
+            || !day_filter.matches(&record.day_type, &record.day_of_week)
+            || !time_filter.matches(&record.departure_time_scheduled, &record.arrival_time_scheduled)
+        {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        let line = agg.line_key(&record.line_name, &record.direction);
+
+        // Aggregate overall totals.
+        *agg.boardings_per_line.entry(line.clone()).or_insert(0) += record.passenger_boardings;
+        *agg.alightings_per_line.entry(line.clone()).or_insert(0) += record.passenger_alightings;
+        *agg.stops_count.entry(line.clone()).or_insert(0) += 1;
+        agg.services.add(&line, &record.business_date, &record.train_number);
+        agg.add_station(&record.station_name, record.coordinates(), record.passenger_boardings, record.passenger_alightings);
+        agg.add_direction(&record.line_name, &record.direction, (record.passenger_boardings + record.passenger_alightings) as i64);
+        *agg.departure_load_per_line.entry(line.clone()).or_insert(0) += record.passenger_departure_load as i64;
+        let departure_time = parse_service_time(&record.departure_time_scheduled);
+        let (business_date, at) = agg.day_start.business_date(&record);
+        *agg.daily_movements.entry(line.clone()).or_default()
+            .entry(business_date.clone()).or_insert(0) +=
+            (record.passenger_boardings + record.passenger_alightings) as i64;
+        if let Some(departure_time) = departure_time {
+            agg.band_movements.entry(line.clone()).or_insert([0; 4])[bands.band(departure_time)] +=
+                (record.passenger_boardings + record.passenger_alightings) as i64;
+        }
+
+        // The time series covers the first business day seen, --date, or
+        // with --all-dates every date.
+        if let (true, Some(at)) = (agg.in_time_series(&business_date), at) {
+            if NaiveDate::parse_from_str(&business_date, "%Y-%m-%d").is_ok() {
+                // Sum total movements (boardings + alightings) for this business hour.
+                agg.add_hourly(&line, &business_date, at.hour(), (record.passenger_boardings + record.passenger_alightings) as i64);
+                agg.add_max_load(&line, at.hour(), record.passenger_departure_load);
+                agg.add_station_hour(
+                    &record.line_name,
+                    &record.station_name,
+                    &record.direction,
+                    record.stop_sequence_number,
+                    at.hour(),
+                    (record.passenger_boardings + record.passenger_alightings) as i64,
+                );
+            }
+        }
+        if let Some(at) = at {
+            agg.add_hourly_by_date(&business_date, &line, at.hour(), (record.passenger_boardings + record.passenger_alightings) as i64);
+        }
+        rows += 1;
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    day_filter.check()?;
+    day_filter.report();
+    time_filter.report();
+
+    Ok(agg)
+}
+
+/// Computes the day-over-day percentage change in total movements per line.
+fn daily_trends(daily_movements: &HashMap<String, HashMap<String, i64>>) -> BTreeMap<String, DailyTrend> {
+    let mut trends = BTreeMap::new();
+    for (line, by_date) in daily_movements {
+        let mut days: Vec<(NaiveDate, i64)> = by_date.iter()
+            .filter_map(|(date, &total)| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().map(|d| (d, total))
+            })
+            .collect();
+        days.sort();
+
+        let mut trend: DailyTrend = Vec::with_capacity(days.len());
+        let mut previous: Option<(NaiveDate, i64)> = None;
+        for (date, total) in days {
+            let change = match previous {
+                Some((prev_date, prev_total)) if date.pred_opt() == Some(prev_date) && prev_total != 0 => {
+                    Some((total - prev_total) as f64 / prev_total as f64 * 100.0)
+                }
+                _ => None,
+            };
+            trend.push((date, total, change));
+            previous = Some((date, total));
+        }
+        trends.insert(line.clone(), trend);
+    }
+    trends
+}
+
+/// Writes each line's daily totals and day-over-day change as a tidy CSV.
+fn write_daily_trends_csv(filename: &str, trends: &BTreeMap<String, DailyTrend>) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(filename)?;
+    writeln!(file, "{}", schema::DAILY_TRENDS.header())?;
+    for (line, trend) in trends {
+        for (date, total, change) in trend {
+            let change = change.map_or(String::new(), |c| format!("{:.2}", c));
+            writeln!(file, "{},{},{},{}", line, date, total, change)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes each line's total movements per time band.
+fn write_peak_windows_csv(filename: &str, data: &HashMap<String, [i64; 4]>) -> Result<(), Box<dyn Error>> {
+    let mut lines: Vec<&String> = data.keys().collect();
+    lines.sort();
+
+    let mut file = File::create(filename)?;
+    writeln!(file, "{}", schema::PEAK_WINDOWS.header())?;
+    for line in lines {
+        let totals: Vec<String> = data[line].iter().map(|total| total.to_string()).collect();
+        writeln!(file, "{},{}", line, totals.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes each line's peak business hour and its movements, blank for a
+/// line with none, in line name order.
+fn write_peak_hours_csv(filename: &str, data: &HashMap<String, [i32; 24]>, day_start: &DayStart) -> Result<(), Box<dyn Error>> {
+    let mut lines: Vec<&String> = data.keys().collect();
+    lines.sort();
+
+    let mut file = File::create(filename)?;
+    writeln!(file, "{}", schema::PEAK_HOURS.header())?;
+    for line in lines {
+        let hours = &data[line];
+        match peak_hour(hours) {
+            Some(hour) => writeln!(file, "{},{},{},{}", line, hour, day_start.hour_label(hour), hours[hour])?,
+            None => writeln!(file, "{},,,0", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// Finds the line named by `--highlight` (ignoring case) among the lines in
+/// the time series, erroring with the available names if it isn't there.
+fn resolve_line<'a>(name: &str, data: &'a HashMap<String, [i32; 24]>) -> Result<&'a str, Box<dyn Error>> {
+    if let Some(line) = data.keys().find(|line| line.eq_ignore_ascii_case(name)) {
+        return Ok(line);
+    }
+    let mut available: Vec<&str> = data.keys().map(|line| line.as_str()).collect();
+    available.sort();
+    Err(format!("--highlight: no line '{}' in the time series; available: {}", name, available.join(", ")).into())
+}
@@ -0,0 +1,445 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use chrono::{LocalResult, NaiveDate, TimeZone};
+use chrono_tz::Australia::Melbourne;
+use clap::{ArgAction, Parser, ValueEnum};
+use crate::Record;
+use crate::business::{DayStart, parse_service_time};
+
+use crate::aggregates::{self, AggregateOptions, Aggregates, DateSelection};
+use crate::cli::Output;
+use crate::filters::{DayFilter, DirectionFilter, ModeFilter, TimeFilter, TrainFilter};
+use crate::format::{FloatFormat, text_field};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::sqlite::{self, Stations};
+use crate::summary::{OutputFormat, Summary};
+use crate::timing::StageTimings;
+
+// Clock hour 02:00 - 02:59, the hour Melbourne skips or repeats on daylight
+// saving transition nights.
+const DST_CLOCK_HOUR: u32 = 2;
+
+/// `generateData`: hourly movements per line, one CSV per line and date.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    aggregates: AggregateOptions,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Note the business hour a daylight saving transition skips or repeats
+    #[arg(long)]
+    dst_notes: bool,
+    /// Write each line's largest departure load per hour to loads/<line>.csv
+    #[arg(long)]
+    max_load: bool,
+    /// Passengers per train, to give --max-load loads as a percentage of
+    #[arg(long, value_name = "PASSENGERS", value_parser = aggregates::capacity)]
+    capacity: Option<f64>,
+    /// csv, or json to write summary.json in place of the per-line CSVs
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Write summary.json alongside the CSVs
+    #[arg(long)]
+    json: bool,
+    /// line, or station to write station totals in place of the per-line time series
+    #[arg(long, value_enum, default_value_t)]
+    by: By,
+    /// Write each line's all-day average departure load to avg_occupancy.csv
+    #[arg(long)]
+    avg_occupancy: bool,
+    /// Save the totals and time series to a SQLite database
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<String>,
+    /// -vv also prints the size of each aggregate
+    #[arg(short, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+/// What the totals are written per, from `--by`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+enum By {
+    #[default]
+    Line,
+    Station,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+    options.source.input.or_positional(options.file.take());
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let dst_notes = options.dst_notes;
+    let float_format = options.float_format;
+    // With `--max-load`, loads are also given as a percentage of this.
+    let capacity = options.capacity;
+    // `--format json` writes summary.json in place of the per-line CSVs.
+    let json_only = options.format == OutputFormat::Json;
+    // `--by station` writes station totals in place of the per-line time series.
+    let by_station = options.by == By::Station;
+    
+    // Ensure output directory exists
+    options.output.create()?;
+
+    let mut timings = StageTimings::new();
+
+    // Either reload previously saved aggregates or build them from the CSV.
+    let aggregates = match &options.aggregates.load_agg {
+        Some(path) => {
+            println!("Loading aggregates from '{}'.", path);
+            let aggregates = Aggregates::load(path)?;
+            aggregates.check(&options.aggregates)?;
+            aggregates
+        }
+        None => aggregate_csv(&options.source, &options.aggregates, &mut issues, &mut timings)?,
+    };
+
+    if let Some(path) = &options.aggregates.save_agg {
+        aggregates.save(path)?;
+        println!("Aggregates saved to '{}'.", path);
+    }
+
+    // Without --date or --all-dates every business date gets its own files,
+    // named <date>_<line>.csv; otherwise there is one file per line.
+    let each_date = aggregates.date_selection == DateSelection::First;
+    let series: Vec<(String, String, Option<String>, [f64; 24])> = if by_station {
+        Vec::new()
+    } else if each_date {
+        let dates: BTreeSet<&String> = aggregates.hourly_by_date.keys().map(|(date, _)| date).collect();
+        println!(
+            "Time series for each of {} business dates; use --date for one date or --all-dates for their average.",
+            dates.len()
+        );
+        aggregates.hourly_by_date.iter()
+            .map(|((date, line), totals)| {
+                (format!("{}_{}.csv", date, line), line.clone(), Some(date.clone()), totals.map(|total| total as f64))
+            })
+            .collect()
+    } else {
+        aggregates.check_selected_date()?;
+        println!("{}", aggregates.describe_time_series());
+        aggregates.time_series().into_iter()
+            .map(|(line, hours)| (format!("{}.csv", line), line, aggregates.selected_business_date.clone(), hours))
+            .collect()
+    };
+    // Averages over several dates keep two decimals; a single date's totals none.
+    let decimals = if aggregates.date_selection == DateSelection::All { 2 } else { 0 };
+    let day_start = aggregates.day_start;
+    let dst_business_hour = day_start.business_hour(DST_CLOCK_HOUR);
+    // Transition nights among the dates written, for the closing note.
+    let mut dst_dates: BTreeMap<String, &str> = BTreeMap::new();
+
+    // Output formatted CSV files for each line (only if time_series data is present)
+    timings.start("csv writing");
+    let mut rows_written = 0;
+    if !json_only {
+        for (name, _, business_date, hourly_counts) in &series {
+            let output_file_path = format!("{}/{}", output_dir, aggregates.direction.file_name(name));
+            let mut file = File::create(&output_file_path)?;
+
+            if dst_notes {
+                // Annotate the business hour affected by a DST transition.
+                let dst_note = business_date.as_deref().and_then(|date| dst_transition_note(date, day_start));
+                if let (Some(note), Some(date)) = (dst_note, business_date) {
+                    dst_dates.insert(date.clone(), note);
+                }
+                writeln!(file, "{}", schema::HOURLY_MOVEMENTS_DST.header())?;
+                for (hour, &count) in hourly_counts.iter().enumerate() {
+                    let note = if hour == dst_business_hour { dst_note.unwrap_or("") } else { "" };
+                    writeln!(file, "{},{},{}", hour, float_format.format(count, decimals), note)?;
+                }
+            } else {
+                let columns = if each_date { &schema::HOURLY_MOVEMENTS_BY_DATE } else { &schema::HOURLY_MOVEMENTS };
+                writeln!(file, "{}", columns.header())?; // Writing the header
+                for (hour, &count) in hourly_counts.iter().enumerate() {
+                    writeln!(file, "{},{}", hour, float_format.format(count, decimals))?; // Writing hour and movement data
+                }
+            }
+            rows_written += hourly_counts.len() as u64;
+        }
+    }
+
+    if by_station {
+        let output_file_path = format!("{}/{}", output_dir, aggregates.direction.file_name("station_totals.csv"));
+        let mut file = File::create(&output_file_path)?;
+        writeln!(file, "{}", schema::STATION_TOTALS.header())?;
+        let stations = aggregates.stations_by_movements();
+        for (station, totals) in &stations {
+            let average = totals.boardings_per_service().map_or(String::new(), |avg| float_format.format(avg, 2));
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                text_field(station), totals.boardings, totals.alightings, totals.services, average
+            )?;
+        }
+        rows_written += stations.len() as u64;
+        println!("Totals for {} stations saved to '{}'.", stations.len(), output_file_path);
+    }
+
+    // All-day average departure load per line, one stop departure per sample.
+    let occupancy = if options.avg_occupancy {
+        let occupancy = aggregates.average_occupancy();
+        let mut file = File::create(format!("{}/{}", output_dir, aggregates.direction.file_name("avg_occupancy.csv")))?;
+        writeln!(file, "{}", schema::AVG_OCCUPANCY.header())?;
+        for (line, average) in &occupancy {
+            let stops = aggregates.stops_count.get(line).copied().unwrap_or(0);
+            let average = average.map_or(String::new(), |avg| float_format.format(avg, 2));
+            writeln!(file, "{},{},{}", line, stops, average)?;
+        }
+        rows_written += occupancy.len() as u64;
+        Some(occupancy)
+    } else {
+        None
+    };
+
+    // The largest departure load per line in each hour of the time series,
+    // one loads/<line>.csv per line.
+    if options.max_load {
+        let loads_dir = format!("{}/loads", output_dir);
+        fs::create_dir_all(&loads_dir)?;
+        let columns = if capacity.is_some() { &schema::MAX_LOADS_CAPACITY } else { &schema::MAX_LOADS };
+        let mut lines: Vec<(&String, &[Option<i32>; 24])> = aggregates.max_loads.iter().collect();
+        lines.sort();
+        for (line, loads) in &lines {
+            let mut file = File::create(format!("{}/{}", loads_dir, aggregates.direction.file_name(&format!("{}.csv", line))))?;
+            writeln!(file, "{}", columns.header())?;
+            for (hour, load) in loads.iter().enumerate() {
+                let max = load.map_or(String::new(), |load| load.to_string());
+                match capacity {
+                    Some(capacity) => {
+                        let percent = load.map_or(String::new(), |load| float_format.format(load as f64 * 100.0 / capacity, 1));
+                        writeln!(file, "{},{},{}", hour, max, percent)?;
+                    }
+                    None => writeln!(file, "{},{}", hour, max)?,
+                }
+            }
+            rows_written += 24;
+        }
+        println!("Hourly max loads for {} lines saved in '{}'.", lines.len(), loads_dir);
+    }
+
+    // Every line's totals and time series, for summary.json and --sqlite.
+    let mut summary = Summary::new(60, day_start);
+    for (line, &boardings) in &aggregates.boardings_per_line {
+        let alightings = aggregates.alightings_per_line.get(line).copied().unwrap_or(0);
+        summary.add_totals(line, boardings as i64, alightings as i64, aggregates.services.count(line));
+    }
+    for (_, line, business_date, hourly_counts) in &series {
+        summary.add_series(line, business_date.as_deref(), hourly_counts);
+    }
+    if json_only || options.json {
+        let output_file_path = format!("{}/{}", output_dir, aggregates.direction.file_name("summary.json"));
+        summary.add_timings(&timings);
+        summary.write(&output_file_path)?;
+        println!("JSON summary of {} lines saved to '{}'.", summary.lines.len(), output_file_path);
+    }
+    if let Some(path) = &options.sqlite {
+        let mut stations = Stations::new();
+        for (station, totals) in &aggregates.stations {
+            sqlite::add_station(&mut stations, station, totals.coordinates, totals.boardings, totals.alightings);
+        }
+        let hourly_rows = sqlite::export(path, &summary, &stations)?;
+        println!(
+            "{} lines, {} hourly rows and {} stations saved to '{}'.",
+            summary.lines.len(), hourly_rows, stations.len(), path
+        );
+    }
+    timings.end(Some(rows_written));
+
+    println!("Processed data saved in '{}'.", output_dir);
+    for (business_date, note) in &dst_dates {
+        println!(
+            "Note: {} is a daylight saving transition night ({}); business hour {} is not a data error.",
+            business_date, note, dst_business_hour
+        );
+    }
+
+    if let Some(occupancy) = occupancy {
+        println!("Average occupancy (departure load per stop):");
+        for (line, average) in &occupancy {
+            match average {
+                Some(avg) => println!("  {:<20} {:>8.1}", line, avg),
+                None => println!("  {:<20} {:>8}", line, "n/a"),
+            }
+        }
+    }
+
+    timings.print_summary();
+    issues.print_summary();
+    if options.verbose >= 2 {
+        aggregates.print_sizes();
+    }
+
+    Ok(())
+}
+
+/// Streams the CSV once, building per-line totals and the hourly time series.
+fn aggregate_csv(
+    inputs: &Source,
+    options: &AggregateOptions,
+    issues: &mut IssueTracker,
+    timings: &mut StageTimings,
+) -> Result<Aggregates, Box<dyn Error>> {
+    let mut source = RecordSource::open(inputs)?;
+    
+    // Initialize aggregation maps and variables.
+    let mut agg = Aggregates::default();
+    agg.day_start = options.day_start.unwrap_or_default();
+    agg.date_selection = DateSelection::from_args(options);
+    agg.direction = options.direction.unwrap_or_default();
+    agg.split_direction = options.split_direction;
+    if agg.split_direction && agg.direction != DirectionFilter::Both {
+        return Err("--split-direction and --direction can't be used together".into());
+    }
+    let bands = options.bands;
+
+    let mut pb = source.progress(inputs.no_progress);
+
+    // Process each record with a progress bar.
+    timings.start("aggregation");
+    let mut rows = 0;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let direction = agg.direction;
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    agg.mode = mode_filter.mode;
+    let mut day_filter = DayFilter::from_args(&options.days);
+    agg.days = day_filter.selection.clone();
+    let mut time_filter = TimeFilter::from_args(&options.days, agg.day_start)?;
+    agg.time_range = time_filter.range;
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number)
+            || !direction.matches(&record.direction)
+            || !mode_filter.matches(&record.mode)
+            || !day_filter.matches(&record.day_type, &record.day_of_week)
+            || !time_filter.matches(&record.departure_time_scheduled, &record.arrival_time_scheduled)
+        {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        let line = agg.line_key(&record.line_name, &record.direction);
+
+        // Aggregate totals for boardings and alightings.
+        *agg.boardings_per_line.entry(line.clone()).or_insert(0) += record.passenger_boardings;
+        *agg.alightings_per_line.entry(line.clone()).or_insert(0) += record.passenger_alightings;
+        *agg.stops_count.entry(line.clone()).or_insert(0) += 1;
+        agg.services.add(&line, &record.business_date, &record.train_number);
+        agg.add_station(&record.station_name, record.coordinates(), record.passenger_boardings, record.passenger_alightings);
+        agg.add_direction(&record.line_name, &record.direction, (record.passenger_boardings + record.passenger_alightings) as i64);
+        *agg.departure_load_per_line.entry(line.clone()).or_insert(0) += record.passenger_departure_load as i64;
+        let departure_time = parse_service_time(&record.departure_time_scheduled);
+        let (business_date, at) = agg.day_start.business_date(&record);
+        *agg.daily_movements.entry(line.clone()).or_default()
+            .entry(business_date.clone()).or_insert(0) +=
+            (record.passenger_boardings + record.passenger_alightings) as i64;
+        if let Some(departure_time) = departure_time {
+            agg.band_movements.entry(line.clone()).or_insert([0; 4])[bands.band(departure_time)] +=
+                (record.passenger_boardings + record.passenger_alightings) as i64;
+        }
+
+        // The time series covers the first business date seen, --date, or
+        // with --all-dates every date.
+        if let (true, Some(at)) = (agg.in_time_series(&business_date), at) {
+            agg.add_hourly(&line, &business_date, at.hour(), (record.passenger_boardings + record.passenger_alightings) as i64);
+            agg.add_max_load(&line, at.hour(), record.passenger_departure_load);
+            agg.add_station_hour(
+                &record.line_name,
+                &record.station_name,
+                &record.direction,
+                record.stop_sequence_number,
+                at.hour(),
+                (record.passenger_boardings + record.passenger_alightings) as i64,
+            );
+        }
+        if let Some(at) = at {
+            agg.add_hourly_by_date(&business_date, &line, at.hour(), (record.passenger_boardings + record.passenger_alightings) as i64);
+        }
+        rows += 1;
+        pb.inc(1);  // Increment the progress bar after each record is processed.
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    day_filter.check()?;
+    day_filter.report();
+    time_filter.report();
+
+    Ok(agg)
+}
+
+/// Returns a note if the business date's 02:00 - 02:59 hour (which falls on the
+/// following calendar day unless the day starts at or before 02:00) was
+/// skipped or repeated in Australia/Melbourne.
+fn dst_transition_note(business_date: &str, day_start: DayStart) -> Option<&'static str> {
+    let date = NaiveDate::parse_from_str(business_date, "%Y-%m-%d").ok()?;
+    let local = day_start.calendar_date(date, DST_CLOCK_HOUR).and_hms_opt(2, 30, 0)?;
+    match Melbourne.from_local_datetime(&local) {
+        LocalResult::None => Some("DST start: 02:00-02:59 skipped"),
+        LocalResult::Ambiguous(_, _) => Some("DST end: 02:00-02:59 repeated"),
+        LocalResult::Single(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dst_notes_fall_on_melbourne_transition_nights() {
+        let day_start = DayStart::default();
+        // 2023-04-02 02:00-02:59 was repeated; with the day starting at
+        // 03:00 it belongs to the business date before.
+        assert_eq!(dst_transition_note("2023-04-01", day_start), Some("DST end: 02:00-02:59 repeated"));
+        // 2023-10-01 02:00-02:59 was skipped.
+        assert_eq!(dst_transition_note("2023-09-30", day_start), Some("DST start: 02:00-02:59 skipped"));
+        assert_eq!(dst_transition_note("2023-04-02", day_start), None);
+        assert_eq!(dst_transition_note("2023-03-15", day_start), None);
+
+        // With the day starting at midnight the hour is on its own date.
+        let midnight = DayStart::parse("00:00").unwrap();
+        assert_eq!(dst_transition_note("2023-04-02", midnight), Some("DST end: 02:00-02:59 repeated"));
+        assert_eq!(dst_transition_note("2023-10-01", midnight), Some("DST start: 02:00-02:59 skipped"));
+        assert_eq!(dst_transition_note("2023-04-01", midnight), None);
+        assert_eq!(dst_transition_note("not a date", day_start), None);
+    }
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fs;
+use clap::Parser;
+
+use crate::cli::Input;
+use crate::index;
+
+/// `index`: builds the binary index `--from-index` reads instead of the CSV.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// Where to save the index
+    #[arg(value_name = "INDEX", default_value = "index.bin")]
+    index: String,
+    #[command(flatten)]
+    input: Input,
+    /// Don't draw progress bars
+    #[arg(long)]
+    no_progress: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let file_path = options.input.path();
+    let index_path = options.index.as_str();
+
+    let manifest = index::build(file_path, index_path, options.no_progress)?;
+    let size = fs::metadata(index_path)?.len();
+
+    println!("Index of '{}' saved to '{}'.", file_path, index_path);
+    println!("  Rows:            {}", manifest.rows);
+    println!("  Distinct values: {}", manifest.strings.len());
+    match (manifest.dates.first(), manifest.dates.last()) {
+        (Some(first), Some(last)) => println!("  Dates:           {} ({} to {})", manifest.dates.len(), first, last),
+        _ => println!("  Dates:           0"),
+    }
+    println!("  Lines:           {}", manifest.lines.join(", "));
+    println!("  Size:            {:.1} MB (CSV {:.1} MB)", size as f64 / 1e6, manifest.source_len as f64 / 1e6);
+    println!("Pass --from-index {} to read it instead of the CSV.", index_path);
+
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{Write};
+use clap::Parser;
+use crate::Record;
+use crate::business::{DayStart, parse_service_time};
+
+use crate::cli::Output;
+use crate::dates::{no_records_error, parse_date};
+use crate::filters::{DayFilter, DayOptions, ModeFilter, ServiceOptions, TimeFilter, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::timing::StageTimings;
+
+/// `generateDataSpecifier`: 15-minute movements per line or station, one CSV
+/// per business date.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    #[command(flatten)]
+    days: DayOptions,
+    /// Write only this line, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Write one station's movements instead of each line's; part of its name will do
+    #[arg(long, value_name = "NAME")]
+    station: Option<String>,
+    /// Write only this business date, YYYY-MM-DD (default: every date)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+
+    // Check if an optional line specifier (--line) is provided
+    options.source.input.or_positional(options.file.take());
+    let output_dir = options.output.path();
+    let float_format = options.float_format;
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let day_start = options.day_start.unwrap_or_default();
+    let specified_line = options.line.as_ref().map(|s| s.to_lowercase());
+    // With --station the series are kept per station instead of per line.
+    let station_query = options.station.as_deref();
+    // Every business date gets its own files unless --date picks one.
+    let requested_date = options.date.as_ref();
+
+    // Ensure output directory exists
+    options.output.create()?;
+
+    let mut timings = StageTimings::new();
+    let mut source = RecordSource::open(&options.source)?;
+
+    // Initialize aggregation maps and variables.
+    let mut time_series: HashMap<String, HashMap<String, Vec<f64>>> = HashMap::new(); // Using a HashMap to store data by date
+    let mut dates_seen: BTreeSet<String> = BTreeSet::new();
+
+    let mut pb = source.progress(options.source.no_progress);
+
+    // Process each record with a progress bar.
+    timings.start("aggregation");
+    let mut rows = 0;
+    // Departures rounding past the last interval of the business day.
+    let mut past_last_block = 0u64;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let mut day_filter = DayFilter::from_args(&options.days);
+    let mut time_filter = TimeFilter::from_args(&options.days, day_start)?;
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        rows += 1;
+        if !train_filter.matches(&record.train_number)
+            || !mode_filter.matches(&record.mode)
+            || !day_filter.matches(&record.day_type, &record.day_of_week)
+            || !time_filter.matches(&record.departure_time_scheduled, &record.arrival_time_scheduled)
+        {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        let line = record.line_name.to_lowercase();  // Ensure case-insensitivity
+
+        // If a line is specified, skip records that do not match
+        if let Some(ref line_specifier) = specified_line {
+            if line != *line_specifier {
+                continue; // Skip this record if the line doesn't match the specifier
+            }
+        }
+
+        // Parse the departure time and place it in the business day
+        if let Some(departure_time) = parse_service_time(&record.departure_time_scheduled) {
+            let at = day_start.at(departure_time);
+            let business_date = at.date(&record.business_date);
+            if let Some(date) = requested_date {
+                if *date != business_date {
+                    if !dates_seen.contains(&business_date) {
+                        dates_seen.insert(business_date);
+                    }
+                    pb.inc(1);
+                    continue;
+                }
+            }
+
+            // Initialize time_series if necessary for the specific business_date and line
+            let key = if station_query.is_some() { record.station_name.clone() } else { line.clone() };
+            let entry = time_series.entry(business_date)
+                .or_insert_with(HashMap::new)
+                .entry(key)
+                .or_insert_with(|| vec![0.0; 96]); // 96 intervals in a day
+
+            // Nearest 15-minute interval; the last minutes of the day round past it.
+            match at.nearest_block(15) {
+                Some(time_block) => entry[time_block] += (record.passenger_boardings + record.passenger_alightings) as f64,
+                None => past_last_block += 1,
+            }
+        }
+
+        pb.inc(1);  // Increment the progress bar after each record is processed.
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    day_filter.check()?;
+    day_filter.report();
+    time_filter.report();
+    if past_last_block > 0 {
+        eprintln!(
+            "Warning: {} departures in the last 7.5 minutes of the business day round past its last 15-minute interval and were left out.",
+            past_last_block
+        );
+    }
+    if let Some(date) = requested_date {
+        if !time_series.contains_key(date) {
+            return Err(no_records_error(date, &dates_seen).into());
+        }
+    }
+
+    // The station is resolved once every name in the file has been seen.
+    let station = match station_query {
+        Some(query) => {
+            let stations: BTreeSet<&str> = time_series.values().flat_map(|stations| stations.keys()).map(|s| s.as_str()).collect();
+            Some(resolve_station(query, &stations)?.to_string())
+        }
+        None => None,
+    };
+
+    // Output formatted CSV files for each line (or the station) and each business date
+    timings.start("csv writing");
+    let mut rows_written = 0;
+    for (business_date, lines) in &time_series {
+        for (line, hourly_counts) in lines {
+            if station.as_ref().is_some_and(|station| station != line) {
+                continue;
+            }
+            // Station names can hold a slash, which a file name can't.
+            let output_file_path = format!("{}/{}_{}.csv", output_dir, business_date, line.replace(['/', '\\'], "-"));
+            let mut file = File::create(&output_file_path)?;
+
+            writeln!(file, "{}", schema::QUARTER_HOUR_BY_DATE.header())?; // Writing the header
+            for (interval, &count) in hourly_counts.iter().enumerate() {
+                let time = day_start.time_label(interval * 15); // Convert interval back to a clock time
+                writeln!(file, "{},{}", time, float_format.format(count, 2))?; // Writing time and movement data
+            }
+            rows_written += hourly_counts.len() as u64;
+        }
+    }
+    timings.end(Some(rows_written));
+
+    println!("Processed data saved in '{}'.", output_dir);
+    timings.print_summary();
+    issues.print_summary();
+
+    Ok(())
+}
+
+/// Finds the station `query` names, ignoring case: an exact match, or else
+/// the only station whose name contains it. Errors list the candidates.
+fn resolve_station<'a>(query: &str, stations: &BTreeSet<&'a str>) -> Result<&'a str, String> {
+    let lowered = query.trim().to_lowercase();
+    if let Some(station) = stations.iter().find(|station| station.to_lowercase() == lowered) {
+        return Ok(station);
+    }
+    let candidates: Vec<&str> = stations.iter()
+        .filter(|station| station.to_lowercase().contains(&lowered))
+        .copied()
+        .collect();
+    match candidates.as_slice() {
+        [station] => Ok(station),
+        [] => Err(format!("--station: no station matches '{}'", query)),
+        _ => Err(format!("--station: '{}' matches several stations: {}", query, candidates.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn station_matches_partially_when_unambiguous() {
+        let stations: BTreeSet<&str> = ["South Yarra", "South Kensington", "Richmond", "North Richmond"].into_iter().collect();
+        assert_eq!(resolve_station("south y", &stations), Ok("South Yarra"));
+        assert_eq!(resolve_station("RICHMOND", &stations), Ok("Richmond"));
+        assert_eq!(
+            resolve_station("south", &stations).unwrap_err(),
+            "--station: 'south' matches several stations: South Kensington, South Yarra"
+        );
+        assert!(resolve_station("Pakenham", &stations).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use plotters::prelude::*;
+
+use crate::Record;
+use crate::chainage::ChainageUnit;
+use crate::cli::Output;
+use crate::dates::{no_records_error, parse_date};
+use crate::filters::{DirectionFilter, ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::{FloatFormat, text_field};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+
+// --peak-only keeps services at the city end of the line from 07:00 up to
+// 09:00, in seconds since midnight.
+const PEAK: std::ops::Range<u32> = 7 * 3600..9 * 3600;
+
+/// One stop of a service, with chainage still in the file's unit.
+struct Stop {
+    station: String,
+    chainage: i32,
+    // Seconds since midnight; hours past 23 are kept as they are.
+    arrival: Option<u32>,
+    departure: Option<u32>,
+    departure_load: i32,
+}
+
+/// Departure loads at one station over the services counted.
+#[derive(Debug, PartialEq)]
+struct StationLoad {
+    station: String,
+    chainage: i32,
+    services: u32,
+    total: i64,
+    max: i32,
+}
+
+impl StationLoad {
+    fn average(&self) -> f64 {
+        self.total as f64 / self.services as f64
+    }
+}
+
+/// `loadProfile`: average departure load at each station along a line.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Line to profile, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: String,
+    /// Direction to profile: U or D
+    #[arg(long, value_parser = DirectionFilter::parse)]
+    direction: DirectionFilter,
+    /// Business date to profile, YYYY-MM-DD (default: the first on the line)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Keep services at the city end of the line between 07:00 and 09:00
+    #[arg(long)]
+    peak_only: bool,
+    /// Also draw each station's largest load
+    #[arg(long)]
+    max: bool,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let float_format = options.float_format;
+    let line = options.line.to_lowercase();
+    let Some(direction_code) = options.direction.code() else {
+        return Err("--direction must be U or D; a load profile runs one way along the line".into());
+    };
+    let peak_only = options.peak_only;
+    let show_max = options.max;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    // --date, or the first business date on the line.
+    let mut selected_date = options.date.clone();
+    let mut dates_seen: BTreeSet<String> = BTreeSet::new();
+    // Train number -> stops on the selected date.
+    let mut services: HashMap<String, Vec<Stop>> = HashMap::new();
+    let mut line_name: Option<String> = None;
+    let mut max_chainage = 0;
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        if record.line_name.to_lowercase() != line || !options.direction.matches(&record.direction) {
+            pb.inc(1);
+            continue;
+        }
+
+        // A stop without a chainage can't be placed along the line.
+        let Some(chainage) = record.station_chainage else {
+            pb.inc(1);
+            continue;
+        };
+        // The unit is detected over the whole line, not just the date drawn.
+        max_chainage = max_chainage.max(chainage);
+        line_name.get_or_insert_with(|| record.line_name.clone());
+        if !dates_seen.contains(&record.business_date) {
+            dates_seen.insert(record.business_date.clone());
+        }
+        if *selected_date.get_or_insert_with(|| record.business_date.clone()) == record.business_date {
+            services.entry(record.train_number.clone()).or_default().push(Stop {
+                station: record.station_name.clone(),
+                chainage,
+                arrival: seconds(&record.arrival_time_scheduled),
+                departure: seconds(&record.departure_time_scheduled),
+                departure_load: record.passenger_departure_load,
+            });
+        }
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let line_name = line_name.ok_or_else(|| format!("no {} records for line '{}'", direction_code, line))?;
+    let date = selected_date.unwrap_or_default();
+    if services.is_empty() {
+        return Err(no_records_error(&date, &dates_seen).into());
+    }
+    let unit = ChainageUnit::detect(max_chainage);
+    println!("Chainage on {} read as {} (largest value {}).", line_name, unit.name(), max_chainage);
+
+    let total_services = services.len();
+    let stations = station_loads(services.values(), peak_only);
+    let counted = stations.iter().map(|station| station.services).max().unwrap_or(0);
+
+    let name = format!("load_profile_{}_{}_{}", line_name, date, direction_code);
+    let output_file_path = format!("{}/{}.csv", output_dir, name);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::LOAD_PROFILE.header())?;
+    for station in &stations {
+        writeln!(
+            file,
+            "{},{},{},{},{}",
+            text_field(&station.station),
+            float_format.format(unit.to_metres(station.chainage) as f64 / 1000.0, 3),
+            station.services,
+            float_format.format(station.average(), 1),
+            station.max
+        )?;
+    }
+    let peak = if peak_only { " in the 07:00-09:00 peak" } else { "" };
+    println!(
+        "Load profile of {} {} services{} on {} saved to '{}' ({} services on the date).",
+        line_name, direction_code, peak, date, output_file_path, total_services
+    );
+
+    let mut skips = ChartSkips::new("loadProfile", &options.output, options.allow_empty_charts);
+    let chart_path = options.output.file(&format!("{}.png", name));
+    let direction_name = if direction_code == "U" { "Up" } else { "Down" };
+    let caption = format!("{} {} Departure Load on {}{}", line_name, direction_name, date, peak);
+    if skips.check(&chart_path, [stations.iter().map(|station| station.average())]) {
+        let caption = mode_filter.mode.caption(&caption);
+        generate_load_profile_chart(&chart_path, &caption, &stations, unit, show_max)?;
+        println!("Load profile chart of {} stations saved to '{}'.", stations.len(), chart_path);
+    }
+    if peak_only && counted == 0 {
+        println!("No services were at the city end of the line between 07:00 and 09:00.");
+    }
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Seconds since midnight for an `HH:MM:SS` time, accepting hours past 23
+/// for after-midnight stops.
+fn seconds(time: &str) -> Option<u32> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600 + m * 60 + s)
+}
+
+/// When a service is at its stop nearest the city: the departure from it,
+/// or the arrival at a terminus it doesn't leave.
+fn city_end_time(stops: &[Stop]) -> Option<u32> {
+    let stop = stops.iter().min_by_key(|stop| stop.chainage)?;
+    stop.departure.or(stop.arrival)
+}
+
+/// Departure loads per station over `services`, ordered by chainage. Stops
+/// the service doesn't depart from, such as its terminus, aren't counted.
+/// With `peak_only`, only services at the city end during `PEAK` are.
+fn station_loads<'a>(services: impl IntoIterator<Item = &'a Vec<Stop>>, peak_only: bool) -> Vec<StationLoad> {
+    let mut stations: HashMap<&str, StationLoad> = HashMap::new();
+    for stops in services {
+        if peak_only && !city_end_time(stops).is_some_and(|time| PEAK.contains(&time)) {
+            continue;
+        }
+        for stop in stops.iter().filter(|stop| stop.departure.is_some()) {
+            let station = stations.entry(&stop.station).or_insert_with(|| StationLoad {
+                station: stop.station.clone(),
+                chainage: stop.chainage,
+                services: 0,
+                total: 0,
+                max: 0,
+            });
+            station.services += 1;
+            station.total += stop.departure_load as i64;
+            station.max = station.max.max(stop.departure_load);
+        }
+    }
+    let mut stations: Vec<StationLoad> = stations.into_values().collect();
+    stations.sort_by(|a, b| a.chainage.cmp(&b.chainage).then(a.station.cmp(&b.station)));
+    stations
+}
+
+/// Generates a line chart of average departure load against distance from
+/// the city, with a tick and station name at each station's chainage, and
+/// the maximum load dashed when `show_max` is set.
+fn generate_load_profile_chart(
+    filename: &str,
+    caption: &str,
+    stations: &[StationLoad],
+    unit: ChainageUnit,
+    show_max: bool,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    // Ticks sit at each station's chainage, in metres, and are labelled with its name.
+    let metres: Vec<i64> = stations.iter().map(|station| unit.to_metres(station.chainage)).collect();
+    let max_metres = metres.iter().copied().max().unwrap_or(0);
+    let max_load = stations.iter()
+        .map(|station| if show_max { station.max as f64 } else { station.average() })
+        .fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 260)
+        .build_cartesian_2d((0..max_metres.max(1000)).with_key_points(metres.clone()), 0.0..(max_load * 1.1 + 1.0))?;
+
+    chart.configure_mesh()
+        .x_label_formatter(&|value| {
+            metres.iter().position(|at| at == value)
+                .map_or_else(String::new, |i| stations[i].station.clone())
+        })
+        .x_desc("Station (by distance from the city)")
+        .y_desc("Departure Load")
+        .label_style(("sans-serif", 30))
+        .axis_desc_style(("sans-serif", 30))
+        // Station names are turned on their side to fit; set after label_style,
+        // which would otherwise reset it.
+        .x_label_style(("sans-serif", 20).into_font().transform(FontTransform::Rotate90))
+        .draw()?;
+
+    let color = RGBColor(0, 0, 255);
+    let average: Vec<(i64, f64)> = metres.iter().zip(stations).map(|(&at, station)| (at, station.average())).collect();
+    chart.draw_series(LineSeries::new(average.clone(), color.stroke_width(3)))?
+        .label("Average")
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(3)));
+    chart.draw_series(average.iter().map(|&point| Circle::new(point, 7, color.filled())))?;
+
+    if show_max {
+        let color = RGBColor(255, 0, 0);
+        let max: Vec<(i64, f64)> = metres.iter().zip(stations).map(|(&at, station)| (at, station.max as f64)).collect();
+        chart.draw_series(DashedLineSeries::new(max, 15, 10, color.stroke_width(3)))?
+            .label("Maximum")
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(3)));
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(&WHITE.mix(0.8))
+            .border_style(&BLACK)
+            .label_font(("sans-serif", 30))
+            .draw()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(station: &str, chainage: i32, time: &str, departure_load: i32) -> Stop {
+        Stop {
+            station: station.to_string(),
+            chainage,
+            arrival: seconds(time),
+            departure: seconds(time),
+            departure_load,
+        }
+    }
+
+    #[test]
+    fn averages_each_station_over_the_services_leaving_it() {
+        let early = vec![
+            stop("Flinders Street", 0, "06:30:00", 100),
+            stop("Richmond", 2300, "06:34:00", 300),
+            Stop { departure: None, ..stop("Pakenham", 57300, "07:40:00", 0) },
+        ];
+        let peak = vec![
+            stop("Flinders Street", 0, "07:30:00", 200),
+            stop("Richmond", 2300, "07:34:00", 500),
+            Stop { departure: None, ..stop("Pakenham", 57300, "08:40:00", 0) },
+        ];
+        let services = [early, peak];
+
+        let loads = station_loads(&services, false);
+        let summary: Vec<(&str, u32, f64, i32)> = loads.iter()
+            .map(|station| (station.station.as_str(), station.services, station.average(), station.max))
+            .collect();
+        assert_eq!(summary, [("Flinders Street", 2, 150.0, 200), ("Richmond", 2, 400.0, 500)]);
+
+        let peak_loads = station_loads(&services, true);
+        assert_eq!(peak_loads.iter().map(|station| station.total).collect::<Vec<_>>(), [200, 500]);
+    }
+}
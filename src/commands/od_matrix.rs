@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+
+use crate::Record;
+use crate::cli::{Output, positive};
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::text_field;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+
+// Stations per side of a line's matrix unless --max-stations says otherwise.
+const DEFAULT_MAX_STATIONS: usize = 50;
+
+/// Services and passenger boardings keyed by the services' Origin_Station
+/// and Destination_Station. Each direction is its own cell: nothing assumes
+/// A to B mirrors B to A. Services that start and end at the same station,
+/// such as loops or terminus shunts, are kept on the diagonal.
+#[derive(Debug, Default)]
+struct OdMatrix {
+    cells: HashMap<(String, String), OdCell>,
+}
+
+#[derive(Debug, Default)]
+struct OdCell {
+    // (business date, train number) of each service run.
+    services: HashSet<(String, String)>,
+    boardings: i64,
+}
+
+impl OdMatrix {
+    fn add(&mut self, record: &Record) {
+        let cell = self.cells.entry((record.origin_station.clone(), record.destination_station.clone())).or_default();
+        if !cell.services.contains(&(record.business_date.clone(), record.train_number.clone())) {
+            cell.services.insert((record.business_date.clone(), record.train_number.clone()));
+        }
+        cell.boardings += record.passenger_boardings as i64;
+    }
+
+    fn cell(&self, origin: &str, destination: &str) -> Option<&OdCell> {
+        self.cells.get(&(origin.to_string(), destination.to_string()))
+    }
+
+    fn boardings(&self, origin: &str, destination: &str) -> i64 {
+        self.cell(origin, destination).map_or(0, |cell| cell.boardings)
+    }
+
+    fn services(&self, origin: &str, destination: &str) -> usize {
+        self.cell(origin, destination).map_or(0, |cell| cell.services.len())
+    }
+
+    /// Every origin and destination pair, most services first; equal counts
+    /// go by boardings and then by name.
+    fn pairs(&self) -> Vec<(&str, &str, &OdCell)> {
+        let mut pairs: Vec<(&str, &str, &OdCell)> = self.cells.iter()
+            .map(|((origin, destination), cell)| (origin.as_str(), destination.as_str(), cell))
+            .collect();
+        pairs.sort_by(|a, b| {
+            b.2.services.len().cmp(&a.2.services.len())
+                .then(b.2.boardings.cmp(&a.2.boardings))
+                .then((a.0, a.1).cmp(&(b.0, b.1)))
+        });
+        pairs
+    }
+
+    /// The stations for the rows and columns, in name order: every origin
+    /// and destination, or the `limit` with the most boardings as either.
+    /// Also returns how many were left out.
+    fn stations(&self, limit: usize) -> (Vec<&str>, usize) {
+        let mut totals: BTreeMap<&str, i64> = BTreeMap::new();
+        for ((origin, destination), cell) in &self.cells {
+            *totals.entry(origin).or_insert(0) += cell.boardings;
+            if destination != origin {
+                *totals.entry(destination).or_insert(0) += cell.boardings;
+            }
+        }
+        let mut stations: Vec<(&str, i64)> = totals.into_iter().collect();
+        stations.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let dropped = stations.len().saturating_sub(limit);
+        stations.truncate(limit);
+        let mut names: Vec<&str> = stations.into_iter().map(|(station, _)| station).collect();
+        names.sort();
+        (names, dropped)
+    }
+
+    /// Writes the square matrix of `value` over `stations` to `path`.
+    fn write(&self, path: &str, stations: &[&str], value: impl Fn(&str, &str) -> String) -> Result<(), Box<dyn Error>> {
+        let mut file = File::create(path)?;
+        let columns: Vec<String> = stations.iter().map(|station| text_field(station)).collect();
+        writeln!(file, "{},{}", schema::OD_MATRIX.header(), columns.join(","))?;
+        for origin in stations {
+            let row: Vec<String> = stations.iter().map(|destination| value(origin, destination)).collect();
+            writeln!(file, "{},{}", text_field(origin), row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Warns when `matrix` had more stations than `--max-stations` allows.
+fn warn_dropped(name: &str, kept: usize, dropped: usize, max_stations: usize) {
+    if dropped > 0 {
+        eprintln!(
+            "Warning: {} has {} origin and destination stations, more than --max-stations {}; the {} with the fewest boardings were left out.",
+            name, kept + dropped, max_stations, dropped
+        );
+    }
+}
+
+/// One service pattern: a line's services from one origin to one
+/// destination in one direction, e.g. Flinders Street to Westall turnbacks.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Pattern {
+    line: String,
+    origin: String,
+    destination: String,
+    direction: String,
+}
+
+/// Totals over every stop of a pattern's services.
+#[derive(Debug, Default)]
+struct PatternTotals {
+    // (business date, train number) of each service run.
+    services: HashSet<(String, String)>,
+    boardings: i64,
+    alightings: i64,
+}
+
+/// Services, boardings and alightings per service pattern, so the patterns
+/// carrying the load stand out.
+#[derive(Debug, Default)]
+struct PatternSummary {
+    patterns: HashMap<Pattern, PatternTotals>,
+}
+
+impl PatternSummary {
+    fn add(&mut self, record: &Record) {
+        let totals = self.patterns.entry(Pattern {
+            line: record.line_name.clone(),
+            origin: record.origin_station.clone(),
+            destination: record.destination_station.clone(),
+            direction: record.direction.clone(),
+        }).or_default();
+        if !totals.services.contains(&(record.business_date.clone(), record.train_number.clone())) {
+            totals.services.insert((record.business_date.clone(), record.train_number.clone()));
+        }
+        totals.boardings += record.passenger_boardings as i64;
+        totals.alightings += record.passenger_alightings as i64;
+    }
+
+    fn lines(&self) -> BTreeSet<&str> {
+        self.patterns.keys().map(|pattern| pattern.line.as_str()).collect()
+    }
+
+    /// The patterns of `line`, or of every line, busiest first by boardings.
+    fn busiest(&self, line: Option<&str>) -> Vec<(&Pattern, &PatternTotals)> {
+        let mut patterns: Vec<_> = self.patterns.iter()
+            .filter(|(pattern, _)| line.is_none_or(|line| pattern.line == line))
+            .collect();
+        patterns.sort_by(|a, b| b.1.boardings.cmp(&a.1.boardings).then(a.0.cmp(b.0)));
+        patterns
+    }
+}
+
+/// Writes `patterns` to `path`, with a Line_Name column when they span
+/// every line.
+fn write_patterns(path: &str, patterns: &[(&Pattern, &PatternTotals)], all_lines: bool) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(path)?;
+    if all_lines {
+        writeln!(file, "{}", schema::OD_PATTERNS_ALL.header())?;
+    } else {
+        writeln!(file, "{}", schema::OD_PATTERNS.header())?;
+    }
+    for (pattern, totals) in patterns {
+        let line = if all_lines { format!("{},", text_field(&pattern.line)) } else { String::new() };
+        writeln!(
+            file,
+            "{}{},{},{},{},{},{}",
+            line,
+            text_field(&pattern.origin),
+            text_field(&pattern.destination),
+            pattern.direction,
+            totals.services.len(),
+            totals.boardings,
+            totals.alightings
+        )?;
+    }
+    Ok(())
+}
+
+/// `odMatrix`: origin-destination matrices per line and across the network.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Write only this line, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Stations kept in each role of a matrix, busiest first
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_STATIONS, value_parser = positive)]
+    max_stations: usize,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let line_filter = options.line.as_ref().map(|line| line.to_lowercase());
+    let max_stations = options.max_stations;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut matrices: BTreeMap<String, OdMatrix> = BTreeMap::new();
+    // Every line's services together, for the network-wide outputs.
+    let mut network = OdMatrix::default();
+    let mut patterns = PatternSummary::default();
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if line_filter.as_deref().is_some_and(|line| record.line_name.to_lowercase() != line) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        matrices.entry(record.line_name.clone()).or_default().add(&record);
+        network.add(&record);
+        patterns.add(&record);
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    if let (Some(line), true) = (&line_filter, matrices.is_empty()) {
+        return Err(format!("no records for line '{}'", line).into());
+    }
+
+    for (line, matrix) in &matrices {
+        let (stations, dropped) = matrix.stations(max_stations);
+        warn_dropped(line, stations.len(), dropped, max_stations);
+        let output_file_path = format!("{}/od_{}.csv", output_dir, line);
+        matrix.write(&output_file_path, &stations, |origin, destination| matrix.boardings(origin, destination).to_string())?;
+        println!("{}x{} origin-destination matrix for {} saved to '{}'.", stations.len(), stations.len(), line, output_file_path);
+    }
+
+    let (stations, dropped) = network.stations(max_stations);
+    warn_dropped("The network", stations.len(), dropped, max_stations);
+    let output_file_path = format!("{}/od_services.csv", output_dir);
+    network.write(&output_file_path, &stations, |origin, destination| network.services(origin, destination).to_string())?;
+    println!("{}x{} matrix of services between origins and destinations saved to '{}'.", stations.len(), stations.len(), output_file_path);
+
+    let output_file_path = format!("{}/od_pairs.csv", output_dir);
+    let pairs = network.pairs();
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::OD_PAIRS.header())?;
+    for (origin, destination, cell) in &pairs {
+        writeln!(file, "{},{},{},{}", text_field(origin), text_field(destination), cell.services.len(), cell.boardings)?;
+    }
+    println!("{} origin-destination pairs saved to '{}'.", pairs.len(), output_file_path);
+
+    for line in patterns.lines() {
+        let output_file_path = format!("{}/od_patterns_{}.csv", output_dir, line);
+        let busiest = patterns.busiest(Some(line));
+        write_patterns(&output_file_path, &busiest, false)?;
+        println!("{} service patterns for {} saved to '{}'.", busiest.len(), line, output_file_path);
+    }
+    let output_file_path = format!("{}/od_patterns.csv", output_dir);
+    write_patterns(&output_file_path, &patterns.busiest(None), true)?;
+    println!("Service patterns for every line saved to '{}'.", output_file_path);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directions_are_counted_separately() {
+        let mut matrix = OdMatrix::default();
+        matrix.add(&stop("1002", "U", "Pakenham", "Flinders Street", 120, 0));
+        matrix.add(&stop("1002", "U", "Pakenham", "Flinders Street", 30, 0));
+        matrix.add(&stop("1004", "U", "Pakenham", "Flinders Street", 10, 0));
+        matrix.add(&stop("1001", "D", "Flinders Street", "Pakenham", 40, 0));
+
+        assert_eq!(matrix.boardings("Pakenham", "Flinders Street"), 160);
+        assert_eq!(matrix.services("Pakenham", "Flinders Street"), 2);
+        assert_eq!(matrix.boardings("Flinders Street", "Pakenham"), 40);
+        assert_eq!(matrix.services("Flinders Street", "Pakenham"), 1);
+        assert_eq!(matrix.boardings("Pakenham", "Pakenham"), 0);
+    }
+
+    #[test]
+    fn services_starting_and_ending_at_one_station_are_on_the_diagonal() {
+        let mut matrix = OdMatrix::default();
+        matrix.add(&stop("1001", "D", "Flinders Street", "Flinders Street", 70, 0));
+        matrix.add(&stop("1003", "D", "Flinders Street", "Flinders Street", 20, 0));
+        matrix.add(&stop("1005", "D", "Flinders Street", "Pakenham", 50, 0));
+
+        assert_eq!(matrix.services("Flinders Street", "Flinders Street"), 2);
+        assert_eq!(matrix.boardings("Flinders Street", "Flinders Street"), 90);
+        assert_eq!(matrix.stations(10), (vec!["Flinders Street", "Pakenham"], 0));
+
+        let pairs: Vec<(&str, &str, usize, i64)> = matrix.pairs().into_iter()
+            .map(|(origin, destination, cell)| (origin, destination, cell.services.len(), cell.boardings))
+            .collect();
+        assert_eq!(pairs, [
+            ("Flinders Street", "Flinders Street", 2, 90),
+            ("Flinders Street", "Pakenham", 1, 50),
+        ]);
+    }
+
+    fn stop(train: &str, direction: &str, origin: &str, destination: &str, boardings: i32, alightings: i32) -> Record {
+        Record {
+            business_date: "2022-09-12".to_string(),
+            day_of_week: "Monday".to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: train.to_string(),
+            line_name: "Pakenham".to_string(),
+            group: "Caulfield".to_string(),
+            direction: direction.to_string(),
+            origin_station: origin.to_string(),
+            destination_station: destination.to_string(),
+            station_name: String::new(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: String::new(),
+            passenger_boardings: boardings,
+            passenger_alightings: alightings,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    #[test]
+    fn sums_each_service_pattern() {
+        let records = [
+            stop("1001", "D", "Flinders Street", "East Pakenham", 200, 10),
+            stop("1001", "D", "Flinders Street", "East Pakenham", 50, 120),
+            stop("1003", "D", "Flinders Street", "East Pakenham", 80, 30),
+            stop("1005", "D", "Flinders Street", "Westall", 40, 40),
+            stop("1002", "U", "East Pakenham", "Flinders Street", 90, 90),
+        ];
+        let mut summary = PatternSummary::default();
+        for record in &records {
+            summary.add(record);
+        }
+        let busiest = summary.busiest(Some("Pakenham"));
+
+        let rows: Vec<(&str, &str, &str, usize, i64, i64)> = busiest.iter()
+            .map(|(pattern, totals)| (
+                pattern.origin.as_str(),
+                pattern.destination.as_str(),
+                pattern.direction.as_str(),
+                totals.services.len(),
+                totals.boardings,
+                totals.alightings,
+            ))
+            .collect();
+        assert_eq!(rows, [
+            ("Flinders Street", "East Pakenham", "D", 2, 330, 160),
+            ("East Pakenham", "Flinders Street", "U", 1, 90, 90),
+            ("Flinders Street", "Westall", "D", 1, 40, 40),
+        ]);
+        assert!(summary.busiest(Some("Frankston")).is_empty());
+    }
+
+    #[test]
+    fn caps_the_matrix_at_the_busiest_stations() {
+        let mut matrix = OdMatrix::default();
+        matrix.add(&stop("1001", "D", "Flinders Street", "Pakenham", 100, 0));
+        matrix.add(&stop("1003", "D", "Flinders Street", "Cranbourne", 80, 0));
+        matrix.add(&stop("1006", "U", "Westall", "Flinders Street", 5, 0));
+
+        assert_eq!(matrix.stations(10), (vec!["Cranbourne", "Flinders Street", "Pakenham", "Westall"], 0));
+        assert_eq!(matrix.stations(2), (vec!["Flinders Street", "Pakenham"], 2));
+    }
+}
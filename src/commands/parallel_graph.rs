@@ -0,0 +1,572 @@
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use plotters::prelude::*;
+use chrono::NaiveDate;
+use clap::Parser;
+use rayon::prelude::*;
+use crate::Record;
+use crate::business::{DayStart, business_hour, parse_service_time};
+
+use crate::cli::{Output, positive};
+use crate::dates::{DatePicker, parse_date};
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::timing::StageTimings;
+
+// Rows read before each batch is deserialized in parallel.
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// Per-line totals and hourly series over some of the records. Each rayon
+/// thread folds its share of the records into one, and `merge` sums them.
+#[derive(Debug, Default, PartialEq)]
+struct LineTotals {
+    boardings_per_line: HashMap<String, i32>,
+    alightings_per_line: HashMap<String, i32>,
+    // Records per line, one per stop made.
+    stops_count: HashMap<String, i32>,
+    // Hourly movements per line on the charted date.
+    time_series: HashMap<String, [i32; 24]>,
+    // Hourly movements per (business date, line) for every date.
+    daily_series: HashMap<(String, String), [i32; 24]>,
+}
+
+impl LineTotals {
+    /// Adds one record; `charted` says whether it falls on the charted date.
+    fn add(&mut self, record: &Record, charted: bool, day_start: DayStart) {
+        let line = &record.line_name;
+        *self.boardings_per_line.entry(line.clone()).or_insert(0) += record.passenger_boardings;
+        *self.alightings_per_line.entry(line.clone()).or_insert(0) += record.passenger_alightings;
+        *self.stops_count.entry(line.clone()).or_insert(0) += 1;
+
+        if let Some(time) = parse_service_time(&record.departure_time_scheduled) {
+            let hour = business_hour(time, day_start.hour()) as usize;
+            let movements = record.passenger_boardings + record.passenger_alightings;
+            self.daily_series.entry((record.business_date.clone(), line.clone())).or_insert([0; 24])[hour] += movements;
+            if charted && NaiveDate::parse_from_str(&record.business_date, "%Y-%m-%d").is_ok() {
+                self.time_series.entry(line.clone()).or_insert([0; 24])[hour] += movements;
+            }
+        }
+    }
+
+    /// Sums two partial totals.
+    fn merge(mut self, other: LineTotals) -> LineTotals {
+        for (line, boardings) in other.boardings_per_line {
+            *self.boardings_per_line.entry(line).or_insert(0) += boardings;
+        }
+        for (line, alightings) in other.alightings_per_line {
+            *self.alightings_per_line.entry(line).or_insert(0) += alightings;
+        }
+        for (line, stops) in other.stops_count {
+            *self.stops_count.entry(line).or_insert(0) += stops;
+        }
+        for (line, hours) in other.time_series {
+            let entry = self.time_series.entry(line).or_insert([0; 24]);
+            for (total, count) in entry.iter_mut().zip(hours) {
+                *total += count;
+            }
+        }
+        for (key, hours) in other.daily_series {
+            let entry = self.daily_series.entry(key).or_insert([0; 24]);
+            for (total, count) in entry.iter_mut().zip(hours) {
+                *total += count;
+            }
+        }
+        self
+    }
+}
+
+/// Deserializes a batch of rows in parallel, keeping their order so
+/// errors are reported against the right row.
+fn deserialize_batch(batch: Vec<Result<StringRecord, csv::Error>>, headers: &StringRecord) -> Vec<Result<Record, csv::Error>> {
+    batch.into_par_iter()
+        .map(|result| result.and_then(|record| record.deserialize(Some(headers))))
+        .collect()
+}
+
+/// Folds a batch's kept records into per-line totals in parallel. Each
+/// record is paired with whether it falls on the charted date.
+fn aggregate(records: &[(Record, bool)], day_start: DayStart) -> LineTotals {
+    records.par_iter()
+        .fold(LineTotals::default, |mut totals, (record, charted)| {
+            totals.add(record, *charted, day_start);
+            totals
+        })
+        .reduce(LineTotals::default, LineTotals::merge)
+}
+
+/// `theotherone`: charts and CSVs like generateGraph's, aggregated in parallel.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Business date of the time series, YYYY-MM-DD (default: the first in the file)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Threads to aggregate on (default: one per core)
+    #[arg(long, value_name = "N", value_parser = positive)]
+    threads: Option<usize>,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+    let output_dir = options.output.path();
+    let float_format = options.float_format;
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let day_start = options.day_start.unwrap_or_default();
+    let mut dates = DatePicker::new(options.date.clone());
+    // Threads for deserializing and aggregating; one per core otherwise.
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = options.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build()?;
+
+    options.output.create()?;
+
+    let mut timings = StageTimings::new();
+    let mut source = RecordSource::open(&options.source)?;
+
+    let mut pb = source.progress(options.source.no_progress);
+
+    // Rows are read in batches, since splitting the CSV into records is
+    // cheap next to deserializing them: each batch is deserialized across
+    // the pool, then filtered and checked in row order, since the filters,
+    // checks and date picker depend on it. The records kept are summed
+    // across the pool and the batch dropped, so only one is held at a time.
+    timings.start("aggregation");
+    let mut totals = LineTotals::default();
+    let mut rows = 0;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    let mut records = source.records();
+    let mut row = 0;
+    loop {
+        let batch: Vec<_> = records.by_ref().take(BATCH_ROWS).collect();
+        if batch.is_empty() {
+            break;
+        }
+        let mut kept: Vec<(Record, bool)> = Vec::with_capacity(batch.len());
+        for result in pool.install(|| deserialize_batch(batch, &headers)) {
+            row += 1;
+            pb.inc(1);
+            let Some(record): Option<Record> = issues.read(row, result, &headers)? else {
+                continue;
+            };
+            rows += 1;
+            if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+                continue;
+            }
+            if issues.inspect(row, &RowFields {
+                business_date: &record.business_date,
+                train_number: &record.train_number,
+                direction: &record.direction,
+                stop_sequence: record.stop_sequence_number,
+                chainage: record.station_chainage,
+                arrival_time: &record.arrival_time_scheduled,
+                departure_time: &record.departure_time_scheduled,
+                coordinates: record.coordinates(),
+                counts: [
+                    record.passenger_boardings,
+                    record.passenger_alightings,
+                    record.passenger_arrival_load,
+                    record.passenger_departure_load,
+                ],
+            })? {
+                continue;
+            }
+            // The charts show --date, or the first encountered business date.
+            let charted = dates.selects(&record.business_date);
+            kept.push((record, charted));
+        }
+        totals = totals.merge(pool.install(|| aggregate(&kept, day_start)));
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    dates.report()?;
+
+    let LineTotals { boardings_per_line, alightings_per_line, time_series, daily_series, .. } = totals;
+
+    // Compute total movements per line
+    let total_movements: HashMap<String, i32> = boardings_per_line.iter()
+        .map(|(line, &boardings)| {
+            let alightings = alightings_per_line.get(line).unwrap_or(&0);
+            (line.clone(), boardings + alightings)
+        })
+        .collect();
+
+    // Generate the charts (total movements and time series).
+    timings.start("chart rendering");
+    if let Some(business_date) = dates.selected() {
+        let mode = mode_filter.mode;
+        generate_total_movements_chart("total_movements_chart.png", &mode.caption("Total Movements by Line"), &total_movements)?;
+        generate_time_series_chart(
+            "time_series_chart.png",
+            &mode.caption(&format!("Hourly Total Movements on {} (Business Day)", business_date)),
+            &time_series,
+        )?;
+        generate_cumulative_time_series_chart(
+            "cumulative_time_series_chart.png",
+            &mode.caption(&format!("Cumulative Movements on {} (Business Day)", business_date)),
+            &time_series,
+        )?;
+    }
+
+    timings.end(None);
+    println!("\nCharts generated successfully.");
+
+    // One CSV per business date and line, or per line for --date.
+    timings.start("csv writing");
+    let mut rows_written = 0;
+    let single_date = options.date.is_some();
+    for ((business_date, line), hourly_counts) in &daily_series {
+        let output_file_path = if single_date {
+            if dates.selected() != Some(business_date.as_str()) {
+                continue;
+            }
+            format!("{}/{}.csv", output_dir, line)
+        } else {
+            format!("{}/{}_{}.csv", output_dir, business_date, line)
+        };
+        let mut file = File::create(&output_file_path)?;
+
+        writeln!(file, "Hour,Movements")?;
+        for (hour, &count) in hourly_counts.iter().enumerate() {
+            writeln!(file, "{},{}", hour, float_format.format(count as f64, 0))?;
+        }
+        rows_written += hourly_counts.len() as u64;
+    }
+    timings.end(Some(rows_written));
+
+    println!("Processed data saved in '{}'.", output_dir);
+    timings.print_summary();
+    issues.print_summary();
+
+    Ok(())
+}
+
+fn get_color_palette() -> Vec<RGBColor> {
+    vec![
+        RGBColor(255, 0, 0),       // red
+        RGBColor(0, 0, 255),       // blue
+        RGBColor(0, 128, 0),       // green
+        RGBColor(255, 165, 0),     // orange
+        RGBColor(128, 0, 128),     // purple
+        RGBColor(0, 128, 128),     // teal
+        RGBColor(255, 192, 203),   // pink
+        RGBColor(128, 128, 0),     // olive
+        RGBColor(0, 0, 0),         // black
+        RGBColor(165, 42, 42),     // brown
+        RGBColor(0, 255, 255),     // cyan
+        RGBColor(255, 215, 0),     // gold
+        RGBColor(75, 0, 130),      // indigo
+        RGBColor(139, 69, 19),     // saddle brown
+        RGBColor(60, 179, 113),    // medium sea green
+        RGBColor(218, 112, 214),   // orchid
+        RGBColor(255, 140, 0),     // dark orange
+        RGBColor(47, 79, 79),      // dark slate gray
+        RGBColor(123, 104, 238),   // medium slate blue
+        RGBColor(255, 99, 71),     // tomato
+    ]
+}
+
+/// Generates a vertical bar chart for total movements per line.
+fn generate_total_movements_chart(
+    filename: &str,
+    caption: &str,
+    data: &HashMap<String, i32>
+) -> Result<(), Box<dyn Error>> {
+    let mut data_vec: Vec<(&String, &i32)> = data.iter().collect();
+    data_vec.sort_by(|a, b| a.0.cmp(b.0));
+
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let max_value = data_vec.iter().map(|(_, &v)| v).max().unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .x_label_area_size(100)
+        .y_label_area_size(80)
+        .build_cartesian_2d(0..data_vec.len(), 0..(max_value + max_value / 10 + 1))?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(data_vec.len())
+        .x_label_formatter(&|idx| {
+            data_vec.get(*idx).map_or("".to_string(), |&(line, _)| line.clone())
+        })
+        .x_desc("Line")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let palette = get_color_palette();
+    for (i, (_, &value)) in data_vec.iter().enumerate() {
+        let color = &palette[i % palette.len()];
+        chart.draw_series(std::iter::once(Rectangle::new(
+            [(i, 0), (i + 1, value)],
+            color.filled(),
+        )))?;
+        chart.draw_series(std::iter::once(Text::new(
+            format!("{}", value),
+            ((i + 1), value + max_value / 50),
+            ("sans-serif", 30).into_font().color(&BLACK),
+        ).into_dyn()))?;
+    }
+    Ok(())
+}
+
+/// Generates a time series chart.
+fn generate_time_series_chart(
+    filename: &str,
+    caption: &str,
+    data: &HashMap<String, [i32; 24]>
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_hourly = data.values().flat_map(|arr| arr.iter()).cloned().max().unwrap_or(0);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 80)
+        .build_cartesian_2d(0..23, 0..(max_hourly + max_hourly / 10 + 1))?;
+
+    chart.configure_mesh()
+        .x_desc("Business Hour (0 = 03:00, 23 = 02:00)")
+        .y_desc("Movements")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let palette = get_color_palette();
+    let mut color_iter = palette.into_iter().cycle();
+
+    for (line, hourly_counts) in data {
+        let color = color_iter.next().unwrap();
+        let series: Vec<(i32, i32)> = hourly_counts.iter()
+            .enumerate()
+            .map(|(hr, &count)| (hr as i32, count))
+            .collect();
+
+        chart.draw_series(LineSeries::new(series.clone(), color.stroke_width(3)))?;
+        chart.draw_series(series.iter().map(|&point| {
+            Circle::new(point, 7, color.filled())
+        }))?
+        .label(line)
+        .legend(move |(x, y)| {
+            Circle::new((x + 10, y), 7, color.filled())
+        });
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", 30))
+        .draw()?;
+
+    Ok(())
+}
+
+/// Generates a cumulative time series line chart (with markers)
+/// for hourly cumulative total movements for the selected business day.
+fn generate_cumulative_time_series_chart(
+    filename: &str,
+    caption: &str,
+    data: &HashMap<String, [i32; 24]>
+) -> Result<(), Box<dyn Error>> {
+    // Create cumulative sums for each line.
+    let mut cumulative_data: HashMap<String, Vec<i32>> = HashMap::new();
+    for (line, hourly_counts) in data {
+        let mut cum_vec = Vec::with_capacity(24);
+        let mut sum = 0;
+        for &count in hourly_counts.iter() {
+            sum += count;
+            cum_vec.push(sum);
+        }
+        cumulative_data.insert(line.clone(), cum_vec);
+    }
+
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    // Determine maximum cumulative value.
+    let max_cumulative = cumulative_data.values()
+        .flat_map(|vec| vec.iter())
+        .cloned()
+        .max()
+        .unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 80)
+        .build_cartesian_2d(0..23, 0..(max_cumulative + max_cumulative / 10 + 1))?;
+
+    chart.configure_mesh()
+        .x_desc("Business Hour (0 = 03:00, 23 = 02:00)")
+        .y_desc("Cumulative Movements")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let palette = get_color_palette();
+    let mut color_iter = palette.into_iter().cycle();
+
+    for (line, cum_series) in &cumulative_data {
+        let color = color_iter.next().unwrap();
+        let series: Vec<(i32, i32)> = cum_series
+            .iter()
+            .enumerate()
+            .map(|(hr, &value)| (hr as i32, value))
+            .collect();
+
+        chart.draw_series(LineSeries::new(series.clone(), color.stroke_width(3)))?;
+        chart.draw_series(series.iter().map(|&point| {
+            Circle::new(point, 7, color.filled())
+        }))?
+        .label(line)
+        .legend(move |(x, y)| {
+            Circle::new((x + 10, y), 7, color.filled())
+        });
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", 30))
+        .draw()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(date: &str, line: &str, departure: &str, boardings: i32, alightings: i32) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: "Monday".to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: line.to_string(),
+            group: String::new(),
+            direction: "D".to_string(),
+            origin_station: String::new(),
+            destination_station: String::new(),
+            station_name: String::new(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: departure.to_string(),
+            passenger_boardings: boardings,
+            passenger_alightings: alightings,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    #[test]
+    fn parallel_totals_match_a_single_thread() {
+        let records: Vec<(Record, bool)> = (0..2_000)
+            .map(|i| {
+                let date = if i % 3 == 0 { "2022-09-13" } else { "2022-09-12" };
+                let line = ["Pakenham", "Frankston", "Belgrave"][i % 3];
+                let departure = format!("{:02}:{:02}:00", i % 24, i % 60);
+                (stop(date, line, &departure, (i % 50) as i32, (i % 7) as i32), date == "2022-09-12")
+            })
+            .collect();
+        let day_start = DayStart::default();
+
+        let mut sequential = LineTotals::default();
+        for (record, charted) in &records {
+            sequential.add(record, *charted, day_start);
+        }
+        let parallel = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap()
+            .install(|| aggregate(&records, day_start));
+
+        assert_eq!(parallel, sequential);
+        // Batch by batch, as `run` sums them, gives the same totals.
+        let batched = records.chunks(300).fold(LineTotals::default(), |totals, batch| totals.merge(aggregate(batch, day_start)));
+        assert_eq!(batched, sequential);
+        assert_eq!(parallel.stops_count.values().sum::<i32>(), 2_000);
+        assert_eq!(parallel.time_series["Frankston"].iter().sum::<i32>(), sequential.time_series["Frankston"].iter().sum::<i32>());
+    }
+
+    #[test]
+    fn parallel_deserializing_matches_a_single_thread() {
+        let header = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+            Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+            Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+            Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+        let mut csv = format!("{}\n", header);
+        for i in 0..3_000 {
+            // Every 500th row has a count that doesn't parse.
+            let boardings = if i % 500 == 7 { "n/a".to_string() } else { (i % 40).to_string() };
+            csv.push_str(&format!(
+                "2022-09-{:02},Monday,Normal Weekday,Metro,{},{},Caulfield,U,Pakenham,Flinders Street,\
+                 Richmond,-37.82,144.99,2300,14,{:02}:58:00,{:02}:59:00,{},{},410,337\n",
+                12 + i % 2, 1000 + i, ["Pakenham", "Frankston"][i % 2], i % 24, i % 24, boardings, i % 9,
+            ));
+        }
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        let batch: Vec<_> = reader.records().collect();
+
+        let sequential: Vec<Result<Record, csv::Error>> = batch.iter()
+            .map(|result| result.as_ref().unwrap().deserialize(Some(&headers)))
+            .collect();
+        let parallel = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap()
+            .install(|| deserialize_batch(batch, &headers));
+
+        assert_eq!(parallel.len(), sequential.len());
+        for (parallel, sequential) in parallel.iter().zip(&sequential) {
+            match (parallel, sequential) {
+                (Ok(parallel), Ok(sequential)) => assert_eq!(parallel, sequential),
+                (Err(parallel), Err(sequential)) => assert_eq!(parallel.to_string(), sequential.to_string()),
+                _ => panic!("rows deserialized differently"),
+            }
+        }
+        assert_eq!(parallel.iter().filter(|result| result.is_err()).count(), 6);
+
+        // And the totals over the rows that parsed are the same.
+        let kept = |results: Vec<Result<Record, csv::Error>>| -> Vec<(Record, bool)> {
+            results.into_iter().flatten().map(|record| (record, true)).collect()
+        };
+        let day_start = DayStart::default();
+        let sequential = kept(sequential).iter().fold(LineTotals::default(), |mut totals, (record, charted)| {
+            totals.add(record, *charted, day_start);
+            totals
+        });
+        assert_eq!(aggregate(&kept(parallel), day_start), sequential);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use crate::Record;
+use crate::peaks::ServicePeaks;
+
+use crate::cli::{Output, positive};
+use crate::dates::{no_records_error, parse_date};
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::text_field;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+
+/// `peakLoads`: each service's most crowded stop, most crowded first.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Keep only this line, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Keep only this business date, YYYY-MM-DD
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Keep only the N most crowded services
+    #[arg(long, value_name = "N", value_parser = positive)]
+    top: Option<usize>,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let line_filter = options.line.as_ref().map(|line| line.to_lowercase());
+    let date = options.date.clone();
+    let top = options.top;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut peaks = ServicePeaks::default();
+    let mut dates_seen: BTreeSet<String> = BTreeSet::new();
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if line_filter.as_deref().is_some_and(|line| record.line_name.to_lowercase() != line) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        if !dates_seen.contains(&record.business_date) {
+            dates_seen.insert(record.business_date.clone());
+        }
+        if date.as_ref().is_none_or(|date| *date == record.business_date) {
+            peaks.add(&record);
+        }
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    if peaks.is_empty() {
+        if let Some(date) = &date {
+            return Err(no_records_error(date, &dates_seen).into());
+        }
+        return match &line_filter {
+            Some(line) => Err(format!("no records for line '{}'", line).into()),
+            None => Err("no records to report".into()),
+        };
+    }
+
+    let services = peaks.len();
+    let mut busiest = peaks.into_busiest();
+    if let Some(top) = top {
+        busiest.truncate(top);
+    }
+
+    let output_file_path = format!("{}/peak_loads.csv", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::PEAK_LOADS.header())?;
+    for peak in &busiest {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            peak.business_date,
+            text_field(&peak.train_number),
+            text_field(&peak.line_name),
+            peak.direction,
+            text_field(&peak.origin_station),
+            text_field(&peak.destination_station),
+            text_field(&peak.peak_station),
+            peak.peak_load,
+            peak.departure_time
+        )?;
+    }
+    println!("Peak loads of {} of {} services saved to '{}'.", busiest.len(), services, output_file_path);
+
+    Ok(())
+}
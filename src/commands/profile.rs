@@ -0,0 +1,537 @@
+use csv::{Reader, StringRecord, Writer};
+use chrono::{Datelike, NaiveDate};
+use clap::{Parser, ValueEnum};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use serde::Serialize;
+use crate::record::decompressed;
+
+use crate::cli::{Input, Output, input_error, positive};
+use crate::error::RowContext;
+use crate::progress::{BytesRead, CountingReader, Progress};
+use crate::schema;
+
+// Distinct values tracked per column before it is reported as "more than".
+const DISTINCT_LIMIT: usize = 100_000;
+// Example values kept per column.
+const SAMPLES: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Integer,
+    Number,
+    Date,
+    Time,
+    Text,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Integer => "integer",
+            Kind::Number => "number",
+            Kind::Date => "date",
+            Kind::Time => "time",
+            Kind::Text => "string",
+        }
+    }
+}
+
+// Range of the values that parsed as one kind, with the raw text of the
+// extremes so they're reported as they appear in the file.
+#[derive(Default)]
+struct Range {
+    parsed: u64,
+    min: Option<(f64, String)>,
+    max: Option<(f64, String)>,
+}
+
+impl Range {
+    fn add(&mut self, key: f64, raw: &str) {
+        self.parsed += 1;
+        if self.min.as_ref().map_or(true, |(min, _)| key < *min) {
+            self.min = Some((key, raw.to_string()));
+        }
+        if self.max.as_ref().map_or(true, |(max, _)| key > *max) {
+            self.max = Some((key, raw.to_string()));
+        }
+    }
+}
+
+/// Everything observed about one column, built up one value at a time.
+struct ColumnProfile {
+    name: String,
+    empty: u64,
+    distinct: HashSet<String>,
+    distinct_overflow: bool,
+    samples: Vec<String>,
+    integer: Range,
+    number: Range,
+    date: Range,
+    time: Range,
+}
+
+impl ColumnProfile {
+    fn new(name: &str) -> ColumnProfile {
+        ColumnProfile {
+            name: name.to_string(),
+            empty: 0,
+            distinct: HashSet::new(),
+            distinct_overflow: false,
+            samples: Vec::new(),
+            integer: Range::default(),
+            number: Range::default(),
+            date: Range::default(),
+            time: Range::default(),
+        }
+    }
+
+    fn add(&mut self, raw: &str) {
+        let value = raw.trim();
+        if value.is_empty() {
+            self.empty += 1;
+            return;
+        }
+
+        if !self.distinct.contains(value) {
+            if self.samples.len() < SAMPLES {
+                self.samples.push(value.to_string());
+            }
+            if self.distinct.len() < DISTINCT_LIMIT {
+                self.distinct.insert(value.to_string());
+            } else {
+                self.distinct_overflow = true;
+            }
+        }
+
+        if let Ok(n) = value.parse::<i64>() {
+            self.integer.add(n as f64, value);
+        }
+        if let Ok(n) = value.parse::<f64>() {
+            self.number.add(n, value);
+        }
+        if let Some(days) = parse_date(value) {
+            self.date.add(days, value);
+        }
+        if let Some(seconds) = parse_time(value) {
+            self.time.add(seconds, value);
+        }
+    }
+
+    /// The column's type is the most specific kind that most of its
+    /// non-empty values parse as; the rest count as unparseable.
+    fn kind(&self, rows: u64) -> Kind {
+        let filled = rows - self.empty;
+        let majority = |range: &Range| filled > 0 && range.parsed * 2 > filled;
+        if majority(&self.date) {
+            Kind::Date
+        } else if majority(&self.time) {
+            Kind::Time
+        } else if majority(&self.integer) {
+            Kind::Integer
+        } else if majority(&self.number) {
+            Kind::Number
+        } else {
+            Kind::Text
+        }
+    }
+
+    fn range(&self, kind: Kind) -> Option<&Range> {
+        match kind {
+            Kind::Integer => Some(&self.integer),
+            Kind::Number => Some(&self.number),
+            Kind::Date => Some(&self.date),
+            Kind::Time => Some(&self.time),
+            Kind::Text => None,
+        }
+    }
+
+    fn distinct_label(&self) -> String {
+        if self.distinct_overflow {
+            format!(">{}", DISTINCT_LIMIT)
+        } else {
+            self.distinct.len().to_string()
+        }
+    }
+}
+
+/// A row ranked by its --by value. Higher values rank higher; on a tie the
+/// earlier row does, so the output is stable across runs.
+struct Ranked {
+    value: f64,
+    row: u64,
+    record: StringRecord,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.total_cmp(&other.value).then(other.row.cmp(&self.row))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+/// Keeps the `limit` highest-ranked rows seen so far in a min-heap, so memory
+/// stays at `limit` rows however large the file is.
+struct TopRecords {
+    column: usize,
+    limit: usize,
+    heap: BinaryHeap<Reverse<Ranked>>,
+    unparseable: u64,
+}
+
+impl TopRecords {
+    fn new(headers: &StringRecord, by: &str, limit: usize) -> Result<TopRecords, Box<dyn Error>> {
+        let column = headers.iter().position(|h| h == by).ok_or_else(|| {
+            format!("unknown --by field '{}', expected one of: {}", by, headers.iter().collect::<Vec<_>>().join(", "))
+        })?;
+        Ok(TopRecords { column, limit, heap: BinaryHeap::with_capacity(limit + 1), unparseable: 0 })
+    }
+
+    fn add(&mut self, row: u64, record: &StringRecord) {
+        let value = match record.get(self.column).and_then(|v| v.trim().parse::<f64>().ok()) {
+            Some(value) if !value.is_nan() => value,
+            _ => {
+                self.unparseable += 1;
+                return;
+            }
+        };
+        // Only clone the record when it would make the cut.
+        if let Some(Reverse(lowest)) = self.heap.peek().filter(|_| self.heap.len() == self.limit) {
+            if lowest.value.total_cmp(&value).then(row.cmp(&lowest.row)) != Ordering::Less {
+                return;
+            }
+        }
+        self.heap.push(Reverse(Ranked { value, row, record: record.clone() }));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    /// Writes the kept rows, highest first, with their rank and data row.
+    fn write(self, path: &str, headers: &StringRecord) -> Result<usize, Box<dyn Error>> {
+        let mut ranked: Vec<Ranked> = self.heap.into_iter().map(|Reverse(ranked)| ranked).collect();
+        ranked.sort_by(|a, b| b.cmp(a));
+
+        let mut writer = Writer::from_path(path)?;
+        let mut header: Vec<String> = schema::TOP_RECORDS.columns.iter().map(|c| c.name.to_string()).collect();
+        header.extend(headers.iter().map(|h| h.to_string()));
+        writer.write_record(&header)?;
+        for (rank, entry) in ranked.iter().enumerate() {
+            let mut fields = vec![(rank + 1).to_string(), entry.row.to_string()];
+            fields.extend(entry.record.iter().map(|v| v.to_string()));
+            writer.write_record(&fields)?;
+        }
+        writer.flush()?;
+        Ok(ranked.len())
+    }
+}
+
+/// `profile`: the type, range and distinct values of every column.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    input: Input,
+    #[command(flatten)]
+    output: Output,
+    /// Write the report as profile.md or profile.json
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+    /// Also write the N rows with the highest --by to top_records.csv
+    #[arg(long, value_name = "N", value_parser = positive)]
+    top_records: Option<usize>,
+    /// Numeric column to rank --top-records by
+    #[arg(long, value_name = "COLUMN", default_value = "Passenger_Boardings")]
+    by: String,
+    /// Don't draw progress bars
+    #[arg(long)]
+    no_progress: bool,
+}
+
+/// How `--format` writes the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+enum Format {
+    #[default]
+    Markdown,
+    Json,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let file_path = options.input.path();
+    let output_dir = options.output.path();
+    let top_limit = options.top_records;
+    let by = options.by.as_str();
+
+    options.output.create()?;
+
+    // Stream the file once, with progress by bytes read against its size.
+    let file = File::open(file_path).map_err(|err| input_error(file_path, err))?;
+    let len = file.metadata().map_or(0, |metadata| metadata.len());
+    let read = BytesRead::default();
+    let reader = decompressed(CountingReader::new(file, read.clone())).map_err(|err| input_error(file_path, err))?;
+    let mut rdr = Reader::from_reader(reader);
+    let headers = rdr.headers()?.clone();
+
+    let mut columns: Vec<ColumnProfile> = headers.iter().map(ColumnProfile::new).collect();
+    let mut top = match top_limit {
+        Some(limit) => Some(TopRecords::new(&headers, by, limit)?),
+        None => None,
+    };
+    let mut pb = Progress::bytes(len, read, options.no_progress);
+
+    let mut rows = 0u64;
+    for (row, result) in rdr.into_records().enumerate() {
+        let record = result.with_row(row as u64 + 1, &headers)?;
+        for (column, value) in columns.iter_mut().zip(record.iter()) {
+            column.add(value);
+        }
+        if let Some(top) = top.as_mut() {
+            top.add(row as u64 + 1, &record);
+        }
+        rows += 1;
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+
+    let (report, output_file_path) = if options.format == Format::Json {
+        (profile_json(&columns, rows), format!("{}/profile.json", output_dir))
+    } else {
+        (profile_markdown(&columns, rows), format!("{}/profile.md", output_dir))
+    };
+    println!("{}", report);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", report)?;
+    eprintln!("Profile of {} rows saved to '{}'.", rows, output_file_path);
+
+    if let Some(top) = top {
+        let unparseable = top.unparseable;
+        let top_file_path = format!("{}/top_records.csv", output_dir);
+        let written = top.write(&top_file_path, &headers)?;
+        if unparseable > 0 {
+            eprintln!("Warning: {} rows have a non-numeric {} and were not ranked.", unparseable, by);
+        }
+        eprintln!("Top {} rows by {} saved to '{}'.", written, by, top_file_path);
+    }
+
+    Ok(())
+}
+
+fn profile_markdown(columns: &[ColumnProfile], rows: u64) -> String {
+    let mut out = format!("# Data profile\n\n{} rows, {} columns.\n\n", rows, columns.len());
+    out.push_str("| Column | Type | Distinct | Empty | Unparseable | Min | Max | Samples |\n");
+    out.push_str("|---|---|---|---|---|---|---|---|\n");
+    for column in columns {
+        let kind = column.kind(rows);
+        let range = column.range(kind);
+        let unparseable = range.map_or(String::new(), |r| (rows - column.empty - r.parsed).to_string());
+        let min = range.and_then(|r| r.min.as_ref()).map_or("", |(_, raw)| raw.as_str());
+        let max = range.and_then(|r| r.max.as_ref()).map_or("", |(_, raw)| raw.as_str());
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            column.name,
+            kind.name(),
+            column.distinct_label(),
+            column.empty,
+            unparseable,
+            min,
+            max,
+            column.samples.join(", ").replace('|', "\\|")
+        ));
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct ProfileReport<'a> {
+    rows: u64,
+    columns: Vec<ColumnEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct ColumnEntry<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    distinct: usize,
+    distinct_capped: bool,
+    empty: u64,
+    unparseable: Option<u64>,
+    min: Option<&'a str>,
+    max: Option<&'a str>,
+    samples: &'a [String],
+}
+
+fn profile_json(columns: &[ColumnProfile], rows: u64) -> String {
+    let report = ProfileReport {
+        rows,
+        columns: columns.iter()
+            .map(|column| {
+                let kind = column.kind(rows);
+                let range = column.range(kind);
+                ColumnEntry {
+                    name: &column.name,
+                    kind: kind.name(),
+                    distinct: column.distinct.len(),
+                    distinct_capped: column.distinct_overflow,
+                    empty: column.empty,
+                    unparseable: range.map(|r| rows - column.empty - r.parsed),
+                    min: range.and_then(|r| r.min.as_ref()).map(|(_, raw)| raw.as_str()),
+                    max: range.and_then(|r| r.max.as_ref()).map(|(_, raw)| raw.as_str()),
+                    samples: &column.samples,
+                }
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&report).expect("the profile always serializes")
+}
+
+/// Days since 0000-01-01 for a `YYYY-MM-DD` date, for ordering only.
+fn parse_date(value: &str) -> Option<f64> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some(date.num_days_from_ce() as f64)
+}
+
+/// Seconds for an `HH:MM[:SS]` time. Hours past 23 are accepted, since
+/// after-midnight stops are sometimes written that way.
+fn parse_time(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|p| !(1..=2).contains(&p.len())) {
+        return None;
+    }
+    let mut seconds = 0u32;
+    for (i, part) in parts.iter().enumerate() {
+        let n: u32 = part.parse().ok()?;
+        if i > 0 && n >= 60 {
+            return None;
+        }
+        seconds = seconds * 60 + n;
+    }
+    if parts.len() == 2 {
+        seconds *= 60;
+    }
+    Some(seconds as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+        Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+        Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+        Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn stop(station: &str, chainage: &str, sequence: u32, boardings: &str) -> String {
+        format!(
+            "2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             {},-37.82,144.99,{},{},07:40:00,07:41:00,{},4,80,88",
+            station, chainage, sequence, boardings
+        )
+    }
+
+    // Writes `rows` under the standard header to a fresh directory, returning
+    // the directory and the input path.
+    fn fixture(name: &str, rows: &[String]) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("ptv_data_profile_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("data.csv");
+        std::fs::write(&input, format!("{}\n{}\n", HEADER, rows.join("\n"))).unwrap();
+        let input = input.to_str().unwrap().to_string();
+        (dir, input)
+    }
+
+    #[test]
+    fn columns_report_their_type_range_and_distinct_values() {
+        let (dir, input) = fixture("columns", &[
+            stop("Flinders Street", "0", 1, "200"),
+            stop("Richmond", "2300", 2, "35"),
+            stop("Caulfield", "11500", 3, "120"),
+            stop("Dandenong", "", 4, "15"),
+            stop("Richmond", "2300", 5, "n/a"),
+            stop("Pakenham", "n/a", 6, "0"),
+        ]);
+        let output = dir.join("out");
+        run(&args(&[
+            "profile", "--input", &input, "--output-dir", output.to_str().unwrap(), "--format", "json", "--no-progress",
+        ])).unwrap();
+
+        let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output.join("profile.json")).unwrap()).unwrap();
+        assert_eq!(report["rows"], 6);
+        let column = |name: &str| report["columns"].as_array().unwrap().iter()
+            .find(|column| column["name"] == name)
+            .unwrap()
+            .clone();
+
+        // Chainages order numerically, not as text.
+        let chainage = column("Station_Chainage");
+        assert_eq!(chainage["type"], "integer");
+        assert_eq!((chainage["min"].as_str(), chainage["max"].as_str()), (Some("0"), Some("11500")));
+        assert_eq!((chainage["distinct"].as_u64(), chainage["empty"].as_u64(), chainage["unparseable"].as_u64()), (Some(4), Some(1), Some(1)));
+
+        let station = column("Station_Name");
+        assert_eq!(station["type"], "string");
+        assert_eq!(station["distinct"], 5);
+        assert!(station["min"].is_null() && station["unparseable"].is_null());
+
+        let departure = column("Departure_Time_Scheduled");
+        assert_eq!((departure["type"].as_str(), departure["distinct"].as_u64()), (Some("time"), Some(1)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn top_records_keep_the_highest_rows_in_file_order_on_ties() {
+        let boardings = ["35", "200", "n/a", "120", "200", "15", "120"];
+        let rows: Vec<String> = boardings.iter().enumerate()
+            .map(|(i, &value)| stop(&format!("Station {}", i + 1), "0", i as u32 + 1, value))
+            .collect();
+        let (dir, input) = fixture("top", &rows);
+        let output = dir.join("out");
+        run(&args(&[
+            "profile", "--input", &input, "--output-dir", output.to_str().unwrap(),
+            "--top-records", "3", "--by", "Passenger_Boardings", "--no-progress",
+        ])).unwrap();
+
+        let top = std::fs::read_to_string(output.join("top_records.csv")).unwrap();
+        let lines: Vec<&str> = top.lines().collect();
+        assert!(lines[0].starts_with("Rank,Row,Business_Date,"), "{}", lines[0]);
+        let ranked: Vec<(&str, &str, &str)> = lines[1..].iter()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                (fields[0], fields[1], fields[19])
+            })
+            .collect();
+        // The second 120 ties with the first and loses on file order.
+        assert_eq!(ranked, vec![("1", "2", "200"), ("2", "5", "200"), ("3", "4", "120")]);
+
+        let err = run(&args(&[
+            "profile", "--input", &input, "--output-dir", output.to_str().unwrap(),
+            "--top-records", "3", "--by", "Boardings", "--no-progress",
+        ])).unwrap_err();
+        assert!(err.to_string().starts_with("unknown --by field 'Boardings'"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Write};
+use clap::Parser;
+use crate::Record;
+use crate::business::DayStart;
+
+use crate::aggregates::ServiceCounts;
+use crate::cli::Output;
+use crate::dates::{DatePicker, parse_date};
+use crate::filters::{DayFilter, DayOptions, ModeFilter, ServiceOptions, TimeFilter, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::sqlite::{self, Stations};
+use crate::summary::{OutputFormat, Summary};
+use crate::timing::StageTimings;
+
+/// `generateData-15min`: movements per line in 15-minute intervals.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// CSV to read when no --input is given
+    #[arg(value_name = "INPUT")]
+    file: Option<String>,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    #[command(flatten)]
+    days: DayOptions,
+    /// Business date of the time series, YYYY-MM-DD (default: the first in the file)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// csv, or json to write summary.json in place of the per-line CSVs
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Write summary.json alongside the CSVs
+    #[arg(long)]
+    json: bool,
+    /// Save the totals and time series to a SQLite database
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<String>,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(mut options: Options) -> Result<(), Box<dyn Error>> {
+    options.source.input.or_positional(options.file.take());
+    let output_dir = options.output.path();
+    let float_format = options.float_format;
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let day_start = options.day_start.unwrap_or_default();
+    let mut dates = DatePicker::new(options.date.clone());
+    // `--format json` writes summary.json in place of the per-line CSVs.
+    let json_only = options.format == OutputFormat::Json;
+    let sqlite_path = options.sqlite.as_deref();
+    
+    // Ensure output directory exists
+    options.output.create()?;
+
+    let mut timings = StageTimings::new();
+    let mut source = RecordSource::open(&options.source)?;
+    
+    // Initialize aggregation maps and variables.
+    let mut boardings_per_line: HashMap<String, i32> = HashMap::new();
+    let mut alightings_per_line: HashMap<String, i32> = HashMap::new();
+    let mut services = ServiceCounts::default();
+    let mut time_series: HashMap<String, Vec<f64>> = HashMap::new();
+    // Station totals, only kept for --sqlite.
+    let mut stations = Stations::new();
+
+    let mut pb = source.progress(options.source.no_progress);
+
+    // Process each record with a progress bar.
+    timings.start("aggregation");
+    let mut rows = 0;
+    // Departures rounding past the last interval of the business day.
+    let mut past_last_block = 0u64;
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let mut day_filter = DayFilter::from_args(&options.days);
+    let mut time_filter = TimeFilter::from_args(&options.days, day_start)?;
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        rows += 1;
+        if !train_filter.matches(&record.train_number)
+            || !mode_filter.matches(&record.mode)
+            || !day_filter.matches(&record.day_type, &record.day_of_week)
+            || !time_filter.matches(&record.departure_time_scheduled, &record.arrival_time_scheduled)
+        {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        let line = record.line_name.clone();
+
+        // Aggregate totals for boardings and alightings.
+        *boardings_per_line.entry(line.clone()).or_insert(0) += record.passenger_boardings;
+        *alightings_per_line.entry(line.clone()).or_insert(0) += record.passenger_alightings;
+        services.add(&line, &record.business_date, &record.train_number);
+        if sqlite_path.is_some() {
+            sqlite::add_station(
+                &mut stations, &record.station_name, record.coordinates(),
+                record.passenger_boardings as i64, record.passenger_alightings as i64,
+            );
+        }
+
+        // Place the departure in the business day starting at --day-start.
+        let (business_date, at) = day_start.business_date(&record);
+
+        // Handle time series only for --date, or the first encountered business date.
+        if let (true, Some(at)) = (dates.selects(&business_date), at) {
+            // Initialize time_series if necessary and accumulate the count
+            let entry = time_series.entry(line.clone()).or_insert_with(|| vec![0.0; 96]); // 96 intervals in a day
+            // Nearest 15-min interval index (0-95); the last minutes of the day round past it.
+            match at.nearest_block(15) {
+                Some(time_block) => entry[time_block] += (record.passenger_boardings + record.passenger_alightings) as f64,
+                None => past_last_block += 1,
+            }
+        }
+        pb.inc(1);  // Increment the progress bar after each record is processed.
+    }
+    pb.finish("CSV processing complete.");
+    timings.end(Some(rows));
+    train_filter.report();
+    mode_filter.report();
+    day_filter.check()?;
+    day_filter.report();
+    time_filter.report();
+    if past_last_block > 0 {
+        eprintln!(
+            "Warning: {} departures in the last 7.5 minutes of the business day round past its last 15-minute interval and were left out.",
+            past_last_block
+        );
+    }
+    dates.report()?;
+
+    // Output formatted CSV files for each line (only if time_series data is present)
+    timings.start("csv writing");
+    let mut rows_written = 0;
+    if !json_only {
+        for (line, time_block_counts) in &time_series {
+            let output_file_path = format!("{}/{}.csv", output_dir, line);
+            let mut file = File::create(&output_file_path)?;
+        
+            writeln!(file, "{}", schema::QUARTER_HOUR_MOVEMENTS.header())?; // Writing the header
+            for (time_block, &count) in time_block_counts.iter().enumerate() {
+                let decimal_time = day_start.hour() as f64 + (time_block as f64 / 4.0);  // Convert back to decimal time from the day start
+                writeln!(file, "{:.2},{}", decimal_time, float_format.format(count, 0))?; // Writing time in decimal and movement data
+            }
+            rows_written += time_block_counts.len() as u64;
+        }
+    }
+
+    // Every line's totals and time series, for summary.json and --sqlite.
+    let mut summary = Summary::new(15, day_start);
+    for (line, &boardings) in &boardings_per_line {
+        let alightings = alightings_per_line.get(line).copied().unwrap_or(0);
+        summary.add_totals(line, boardings as i64, alightings as i64, services.count(line));
+    }
+    for (line, time_block_counts) in &time_series {
+        summary.add_series(line, dates.selected(), time_block_counts);
+    }
+    if json_only || options.json {
+        let output_file_path = format!("{}/summary.json", output_dir);
+        summary.add_timings(&timings);
+        summary.write(&output_file_path)?;
+        println!("JSON summary of {} lines saved to '{}'.", summary.lines.len(), output_file_path);
+    }
+    if let Some(path) = sqlite_path {
+        let hourly_rows = sqlite::export(path, &summary, &stations)?;
+        println!(
+            "{} lines, {} hourly rows and {} stations saved to '{}'.",
+            summary.lines.len(), hourly_rows, stations.len(), path
+        );
+    }
+    timings.end(Some(rows_written));
+
+    println!("Processed data saved in '{}'.", output_dir);
+    timings.print_summary();
+    issues.print_summary();
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::{Parser, ValueEnum};
+
+use crate::cli::Output;
+use serde::Serialize;
+use crate::schema::{OutputSchema, CONVENTIONS, OUTPUTS};
+
+/// `schema`: documents the columns of every output.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    output: Output,
+    /// How to print the schema; schema.json is written either way
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+/// How `--format` prints the schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+
+    let json = schema_json();
+    match options.format {
+        Format::Json => println!("{}", json),
+        Format::Text => print_text(),
+    }
+
+    options.output.create()?;
+    let output_file_path = format!("{}/schema.json", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", json)?;
+    eprintln!("Schema saved to '{}'.", output_file_path);
+
+    Ok(())
+}
+
+/// Lists every output with its columns, for reading in a terminal.
+fn print_text() {
+    println!("Conventions:");
+    for convention in CONVENTIONS {
+        println!("  - {}", convention);
+    }
+    for output in OUTPUTS {
+        println!("\n{} ({}, written by {})", output.path, output.format, output.binary);
+        println!("  {}", output.description);
+        for column in output.columns {
+            let unit = if column.unit.is_empty() { String::new() } else { format!(", {}", column.unit) };
+            println!("  {:<20} {}{}: {}", column.name, column.kind, unit, column.description);
+        }
+    }
+}
+
+// The registry as written to schema.json.
+#[derive(Serialize)]
+struct Schema {
+    conventions: &'static [&'static str],
+    outputs: &'static [OutputSchema],
+}
+
+/// Renders the registry as a JSON document.
+fn schema_json() -> String {
+    let schema = Schema { conventions: CONVENTIONS, outputs: OUTPUTS };
+    serde_json::to_string_pretty(&schema).expect("the registry always serializes")
+}
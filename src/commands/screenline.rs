@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use plotters::prelude::*;
+use crate::Record;
+use crate::business::DayStart;
+
+use crate::chainage::ChainageUnit;
+use crate::cli::Output;
+use crate::dates::parse_date;
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+
+/// One stop of a service on the chosen line, with chainage still in the
+/// file's unit.
+struct Stop {
+    sequence: i32,
+    chainage: i32,
+    arrival: Option<u32>,
+    departure: Option<u32>,
+    departure_load: i32,
+}
+
+const WEEKDAYS: [&str; 5] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday"];
+
+// 15-minute intervals in a business day.
+const INTERVALS: usize = 96;
+
+// Up services travel towards the city, down services away from it.
+const DIRECTIONS: [(&str, &str); 2] = [("U", "Up"), ("D", "Down")];
+
+/// `screenline`: passengers crossing a point on a line in 15-minute intervals.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Line the point is on, ignoring case
+    #[arg(long, value_name = "NAME")]
+    line: String,
+    /// Where the point is along the line, in metres
+    #[arg(long, value_name = "METRES")]
+    screenline_chainage: i64,
+    /// Business date to count, YYYY-MM-DD (default: the first on the line)
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: Option<String>,
+    /// Average over every Monday-Friday date, public holidays excluded
+    #[arg(long, conflicts_with = "date")]
+    weekday_average: bool,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let float_format = options.float_format;
+    let line = options.line.to_lowercase();
+    let screenline = options.screenline_chainage;
+    let weekday_average = options.weekday_average;
+    let day_start = options.day_start.unwrap_or_default();
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    // Without --weekday-average, count crossings on one date: --date, or the
+    // first business date on the line.
+    let mut selected_date: Option<String> = options.date.clone();
+    // (date, train) -> direction and stops.
+    let mut services: HashMap<(String, String), (String, Vec<Stop>)> = HashMap::new();
+    let mut line_name: Option<String> = None;
+    let mut max_chainage = 0;
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        if record.line_name.to_lowercase() != line {
+            pb.inc(1);
+            continue;
+        }
+
+        // A stop without a chainage can't be placed either side of the
+        // screenline; the service's neighbouring stops are used instead.
+        let Some(chainage) = record.station_chainage else {
+            pb.inc(1);
+            continue;
+        };
+        // The unit is detected over the whole line, not just the dates counted.
+        max_chainage = max_chainage.max(chainage);
+        line_name.get_or_insert_with(|| record.line_name.clone());
+
+        // With a single date, every date is kept: a service can cross on the
+        // next or previous business date when --day-start moves the boundary.
+        let included = if weekday_average {
+            WEEKDAYS.contains(&record.day_of_week.as_str())
+        } else {
+            selected_date.get_or_insert_with(|| record.business_date.clone());
+            true
+        };
+        if included {
+            services.entry((record.business_date.clone(), record.train_number.clone()))
+                .or_insert_with(|| (record.direction.clone(), Vec::new()))
+                .1
+                .push(Stop {
+                    sequence: record.stop_sequence_number,
+                    chainage,
+                    arrival: seconds(&record.arrival_time_scheduled),
+                    departure: seconds(&record.departure_time_scheduled),
+                    departure_load: record.passenger_departure_load,
+                });
+        }
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let line_name = line_name.ok_or_else(|| format!("no records for line '{}'", line))?;
+    let unit = ChainageUnit::detect(max_chainage);
+    println!("Chainage on {} read as {} (largest value {}).", line_name, unit.name(), max_chainage);
+
+    let Crossings { passengers, crossings, dates, crossed, not_crossing } =
+        count_crossings(&mut services, selected_date.as_deref(), unit, screenline, &day_start);
+
+    // Average over the dates seen; a single date is its own average.
+    let days = dates.len().max(1) as f64;
+    let averaged: Vec<[f64; INTERVALS]> = passengers.iter()
+        .map(|totals| totals.map(|total| total as f64 / days))
+        .collect();
+
+    let output_file_path = format!("{}/screenline.csv", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::SCREENLINE.header())?;
+    for interval in 0..INTERVALS {
+        writeln!(
+            file,
+            "{},{},{},{},{}",
+            day_start.time_label(interval * 15),
+            float_format.format(averaged[0][interval], 2),
+            float_format.format(averaged[1][interval], 2),
+            float_format.format(crossings[0][interval] as f64 / days, 2),
+            float_format.format(crossings[1][interval] as f64 / days, 2)
+        )?;
+    }
+
+    let caption = if weekday_average {
+        format!("{} at {} m: Weekday Average ({} days)", line_name, screenline, dates.len())
+    } else {
+        format!("{} at {} m on {}", line_name, screenline, selected_date.as_deref().unwrap_or("(no data)"))
+    };
+    let mut skips = ChartSkips::new("screenline", &options.output, options.allow_empty_charts);
+    if skips.check("screenline_chart.png", averaged.iter().map(|series| series.iter().cloned())) {
+        let caption = mode_filter.mode.caption(&caption);
+        generate_screenline_chart(&options.output.file("screenline_chart.png"), &caption, &averaged, &day_start)?;
+    }
+
+    println!(
+        "Screenline counts for {} services saved to '{}'; {} services don't cross {} m and were left out.",
+        crossed, output_file_path, not_crossing, screenline
+    );
+    skips.finish()?;
+
+    Ok(())
+}
+
+// Per direction: passengers and services crossing in each interval, with
+// the dates they crossed on and how many services did or didn't cross.
+struct Crossings {
+    passengers: [[i64; INTERVALS]; 2],
+    crossings: [[u32; INTERVALS]; 2],
+    dates: HashSet<String>,
+    crossed: u32,
+    not_crossing: u32,
+}
+
+/// Counts each service's crossing, keyed by (date, train), in the interval
+/// and on the business date it crosses. With `selected_date`, crossings on
+/// other dates are left out.
+fn count_crossings(
+    services: &mut HashMap<(String, String), (String, Vec<Stop>)>,
+    selected_date: Option<&str>,
+    unit: ChainageUnit,
+    screenline: i64,
+    day_start: &DayStart,
+) -> Crossings {
+    let mut counts = Crossings {
+        passengers: [[0; INTERVALS]; 2],
+        crossings: [[0; INTERVALS]; 2],
+        dates: HashSet::new(),
+        crossed: 0,
+        not_crossing: 0,
+    };
+    let on_selected_date = |date: &str| selected_date.map_or(true, |selected| selected == date);
+    for ((date, _), (direction, stops)) in services.iter_mut() {
+        let Some(d) = DIRECTIONS.iter().position(|&(code, _)| code == direction) else {
+            continue;
+        };
+        stops.sort_by_key(|stop| stop.sequence);
+        match crossing(stops, unit, screenline) {
+            Some((time, load)) => {
+                let at = day_start.locate(time / 3600, time / 60 % 60);
+                let crossing_date = at.date(date);
+                if !on_selected_date(&crossing_date) {
+                    continue;
+                }
+                counts.passengers[d][at.interval(15)] += load as i64;
+                counts.crossings[d][at.interval(15)] += 1;
+                counts.dates.insert(crossing_date);
+                counts.crossed += 1;
+            }
+            None if on_selected_date(date) => counts.not_crossing += 1,
+            None => {}
+        }
+    }
+    counts
+}
+
+/// Seconds since midnight for an `HH:MM:SS` time, accepting hours past 23
+/// for after-midnight stops.
+fn seconds(time: &str) -> Option<u32> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600 + m * 60 + s)
+}
+
+/// Finds where a service crosses the screenline: between the last stop it
+/// calls at before the screenline and the next one, whichever way it runs,
+/// so stations it runs through don't matter. Returns the crossing time,
+/// interpolated by chainage, and the departure load from that last stop.
+/// Services that stop short of the screenline give `None`; a stop exactly
+/// on it counts as before it.
+fn crossing(stops: &[Stop], unit: ChainageUnit, screenline: i64) -> Option<(u32, i32)> {
+    stops.windows(2).find_map(|pair| {
+        let (from, to) = (&pair[0], &pair[1]);
+        let (a, b) = (unit.to_metres(from.chainage), unit.to_metres(to.chainage));
+        let crosses = (a <= screenline && screenline < b) || (a >= screenline && screenline > b);
+        if !crosses {
+            return None;
+        }
+        let departure = from.departure?;
+        let time = match to.arrival {
+            Some(arrival) if arrival >= departure => {
+                let share = (screenline - a) as f64 / (b - a) as f64;
+                departure + ((arrival - departure) as f64 * share).round() as u32
+            }
+            _ => departure,
+        };
+        Some((time, from.departure_load))
+    })
+}
+
+/// Generates a line chart of passengers crossing per 15-minute interval,
+/// one line per direction.
+fn generate_screenline_chart(
+    filename: &str,
+    caption: &str,
+    data: &[[f64; INTERVALS]],
+    day_start: &DayStart,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_value = data.iter().flat_map(|series| series.iter()).cloned().fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 80)
+        .build_cartesian_2d(0..INTERVALS - 1, 0.0..(max_value * 1.1 + 1.0))?;
+
+    chart.configure_mesh()
+        .x_labels(12)
+        .x_label_formatter(&|interval| day_start.time_label(*interval * 15))
+        .x_desc("Crossing Time")
+        .y_desc("Passengers")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let colors = [RGBColor(255, 0, 0), RGBColor(0, 0, 255)];
+    for (((_, name), series), color) in DIRECTIONS.iter().zip(data).zip(colors) {
+        chart.draw_series(LineSeries::new(
+            series.iter().enumerate().map(|(interval, &value)| (interval, value)),
+            color.stroke_width(3),
+        ))?
+        .label(*name)
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(3)));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", 30))
+        .draw()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(sequence: i32, chainage: i32, arrival: &str, departure: &str, departure_load: i32) -> Stop {
+        Stop { sequence, chainage, arrival: seconds(arrival), departure: seconds(departure), departure_load }
+    }
+
+    fn service(date: &str, train: &str, direction: &str, stops: Vec<Stop>) -> ((String, String), (String, Vec<Stop>)) {
+        ((date.to_string(), train.to_string()), (direction.to_string(), stops))
+    }
+
+    #[test]
+    fn counts_passengers_crossing_per_interval_and_direction() {
+        let mut services = HashMap::from([
+            // Down, crossing 3000 m a third of the way from 08:10 to 08:16.
+            service("2022-09-12", "1001", "D", vec![
+                stop(2, 2_000, "08:09:00", "08:10:00", 400),
+                stop(1, 0, "", "08:05:00", 300),
+                stop(3, 5_000, "08:16:00", "08:17:00", 350),
+            ]),
+            // Up, crossing 3000 m at 08:16 on the load it left 5000 m with.
+            service("2022-09-12", "1002", "U", vec![
+                stop(1, 5_000, "", "08:14:00", 250),
+                stop(2, 0, "08:24:00", "", 0),
+            ]),
+            // Down, but turning back short of the screenline.
+            service("2022-09-12", "1003", "D", vec![
+                stop(1, 0, "", "08:00:00", 100),
+                stop(2, 2_000, "08:05:00", "", 0),
+            ]),
+            // Another date, left out with --date 2022-09-12.
+            service("2022-09-13", "1001", "D", vec![
+                stop(1, 0, "", "08:05:00", 999),
+                stop(2, 5_000, "08:15:00", "", 0),
+            ]),
+        ]);
+
+        let counts = count_crossings(&mut services, Some("2022-09-12"), ChainageUnit::Metres, 3_000, &DayStart::default());
+        assert_eq!((counts.crossed, counts.not_crossing), (2, 1));
+        assert_eq!(counts.dates, HashSet::from(["2022-09-12".to_string()]));
+
+        // 03:00 is interval 0, so 08:00-08:14 is 20 and 08:15-08:29 is 21.
+        let crossed = |d: usize| -> Vec<(usize, i64, u32)> {
+            (0..INTERVALS)
+                .filter(|&interval| counts.crossings[d][interval] > 0)
+                .map(|interval| (interval, counts.passengers[d][interval], counts.crossings[d][interval]))
+                .collect()
+        };
+        assert_eq!(crossed(0), [(21, 250, 1)]);
+        assert_eq!(crossed(1), [(20, 400, 1)]);
+    }
+
+    #[test]
+    fn crossing_time_is_interpolated_by_chainage() {
+        let stops = [stop(1, 2, "", "08:10:00", 400), stop(2, 5, "08:16:00", "", 0)];
+        // Kilometres: 3000 m is a third of the way from 2 km to 5 km.
+        assert_eq!(crossing(&stops, ChainageUnit::Kilometres, 3_000), seconds("08:12:00").map(|time| (time, 400)));
+    }
+}
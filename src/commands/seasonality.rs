@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use chrono::{Datelike, NaiveDate};
+use clap::Parser;
+use plotters::prelude::*;
+use crate::Record;
+use crate::business::{DayStart, parse_service_time};
+
+use crate::cli::Output;
+use crate::filters::{Mode, ModeFilter, ServiceOptions, TrainFilter};
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+
+const WEEKDAYS: [&str; 5] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday"];
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// Months averaged over fewer observed days than this are flagged, unless
+// --min-days says otherwise.
+const DEFAULT_MIN_DAYS: usize = 10;
+
+/// One line's movements in one calendar month, summed over every date seen.
+#[derive(Default)]
+struct MonthTotals {
+    hourly: [i64; 24],
+    dates: HashSet<String>,
+}
+
+impl MonthTotals {
+    /// Average movements per business hour over the dates seen, or `None`
+    /// for a month with no data.
+    fn average(&self) -> Option<[f64; 24]> {
+        if self.dates.is_empty() {
+            return None;
+        }
+        let days = self.dates.len() as f64;
+        Some(self.hourly.map(|total| total as f64 / days))
+    }
+}
+
+/// Adds the record's movements to its line's month, in the business hour
+/// and on the business date of its departure. Records without a departure
+/// time are left out.
+fn add_movements(surface: &mut BTreeMap<String, Vec<MonthTotals>>, record: &Record, day_start: &DayStart) {
+    let Some(departure_time) = parse_service_time(&record.departure_time_scheduled) else {
+        return;
+    };
+    let at = day_start.at(departure_time);
+    let business_date = at.date(&record.business_date);
+    if let Ok(date) = NaiveDate::parse_from_str(&business_date, "%Y-%m-%d") {
+        let months = surface.entry(record.line_name.clone())
+            .or_insert_with(|| (0..12).map(|_| MonthTotals::default()).collect());
+        let month = &mut months[date.month0() as usize];
+        month.hourly[at.hour()] += (record.passenger_boardings + record.passenger_alightings) as i64;
+        if !month.dates.contains(&business_date) {
+            month.dates.insert(business_date);
+        }
+    }
+}
+
+/// `seasonality`: each line's average movements per hour, month by month.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Count Saturday and Sunday dates too
+    #[arg(long)]
+    include_weekends: bool,
+    /// Dates a month needs before it isn't marked low confidence
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MIN_DAYS)]
+    min_days: usize,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let float_format = options.float_format;
+    let day_start = options.day_start.unwrap_or_default();
+    let include_weekends = options.include_weekends;
+    let min_days = options.min_days;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    // Per line, one entry per calendar month across every year in the file.
+    let mut surface: BTreeMap<String, Vec<MonthTotals>> = BTreeMap::new();
+
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        if !include_weekends && !WEEKDAYS.contains(&record.day_of_week.as_str()) {
+            pb.inc(1);
+            continue;
+        }
+
+        add_movements(&mut surface, &record, &day_start);
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let output_file_path = format!("{}/seasonality.csv", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    let hour_columns: Vec<String> = (0..24).map(|hour| format!("Hour_{}", hour)).collect();
+    writeln!(file, "{},{}", schema::SEASONALITY.header(), hour_columns.join(","))?;
+    let mut low_confidence_months = 0;
+    let mut skips = ChartSkips::new("seasonality", &options.output, options.allow_empty_charts);
+    if surface.is_empty() {
+        skips.skip("seasonality_<line>.png", "no lines to draw");
+    }
+    for (line, months) in &surface {
+        for (month, totals) in months.iter().enumerate() {
+            let low_confidence = totals.dates.len() < min_days;
+            if low_confidence && !totals.dates.is_empty() {
+                low_confidence_months += 1;
+            }
+            let values: Vec<String> = match totals.average() {
+                Some(average) => average.iter().map(|&value| float_format.format(value, 1)).collect(),
+                None => vec![String::new(); 24],
+            };
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                line, month + 1, totals.dates.len(), low_confidence, values.join(",")
+            )?;
+        }
+        let chart_path = options.output.file(&format!("seasonality_{}.png", line));
+        let averages = months.iter().filter_map(|totals| totals.average());
+        if skips.check(&chart_path, averages) {
+            generate_heatmap_chart(&chart_path, line, months, min_days, include_weekends, &day_start, mode_filter.mode)?;
+        }
+    }
+
+    let days = if include_weekends { "all days" } else { "weekdays" };
+    println!("Seasonality of {} lines ({}) saved to '{}'.", surface.len(), days, output_file_path);
+    if low_confidence_months > 0 {
+        println!(
+            "{} line-months have fewer than {} observed days and are marked Low_Confidence.",
+            low_confidence_months, min_days
+        );
+    }
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Generates a heatmap of one line's average movements, months down and
+/// business hours across, darker for busier. Months with no data are gray;
+/// months under `min_days` are starred.
+fn generate_heatmap_chart(
+    filename: &str,
+    line: &str,
+    months: &[MonthTotals],
+    min_days: usize,
+    include_weekends: bool,
+    day_start: &DayStart,
+    mode: Mode,
+) -> Result<(), Box<dyn Error>> {
+    let averages: Vec<Option<[f64; 24]>> = months.iter().map(|totals| totals.average()).collect();
+    let max_value = averages.iter().flatten().flat_map(|hours| hours.iter()).cloned().fold(0.0, f64::max);
+
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let days = if include_weekends { "Daily" } else { "Weekday" };
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            mode.caption(&format!("{}: Average {} Movements by Month (max {:.0}/h)", line, days, max_value)),
+            ("sans-serif", 50),
+        )
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 80)
+        .build_cartesian_2d((0..23).into_segmented(), (0..11).into_segmented())?;
+
+    // January is drawn at the top, so month m sits at y = 11 - m.
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(24)
+        .y_labels(12)
+        .x_label_formatter(&|value: &SegmentValue<i32>| match value {
+            SegmentValue::CenterOf(hour) => hour.to_string(),
+            _ => String::new(),
+        })
+        .y_label_formatter(&|value: &SegmentValue<i32>| match value {
+            SegmentValue::CenterOf(y) if (0..12).contains(y) => {
+                let month = (11 - y) as usize;
+                let days = months[month].dates.len();
+                let star = if days > 0 && days < min_days { "*" } else { "" };
+                format!("{}{}", MONTHS[month], star)
+            }
+            _ => String::new(),
+        })
+        .x_desc(day_start.axis_desc())
+        .y_desc("Month (* fewer than minimum days)")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    chart.draw_series(averages.iter().enumerate().flat_map(|(month, average)| {
+        let y = 11 - month as i32;
+        (0..24).map(move |hour| {
+            let color = match average {
+                Some(hours) => heat_color(if max_value > 0.0 { hours[hour as usize] / max_value } else { 0.0 }),
+                None => RGBColor(220, 220, 220),
+            };
+            Rectangle::new(
+                [(SegmentValue::Exact(hour), SegmentValue::Exact(y)), (SegmentValue::Exact(hour + 1), SegmentValue::Exact(y + 1))],
+                color.filled(),
+            )
+        })
+    }))?;
+
+    Ok(())
+}
+
+/// White for 0 through to dark blue for 1.
+fn heat_color(share: f64) -> RGBColor {
+    let share = share.clamp(0.0, 1.0);
+    let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * share).round() as u8;
+    RGBColor(mix(255, 8), mix(255, 48), mix(255, 107))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(date: &str, line: &str, departure: &str, boardings: i32, alightings: i32) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: String::new(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: "1001".to_string(),
+            line_name: line.to_string(),
+            group: "Caulfield".to_string(),
+            direction: "U".to_string(),
+            origin_station: "East Pakenham".to_string(),
+            destination_station: "Flinders Street".to_string(),
+            station_name: "Richmond".to_string(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: 0,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: departure.to_string(),
+            passenger_boardings: boardings,
+            passenger_alightings: alightings,
+            passenger_arrival_load: 0,
+            passenger_departure_load: 0,
+        }
+    }
+
+    #[test]
+    fn averages_each_month_over_its_dates() {
+        let records = [
+            stop("2022-09-12", "Pakenham", "08:10:00", 100, 20),
+            stop("2022-09-13", "Pakenham", "08:40:00", 50, 10),
+            stop("2022-09-13", "Pakenham", "17:05:00", 30, 30),
+            // The business day of 2022-09-30 runs into 1 October.
+            stop("2022-09-30", "Pakenham", "00:30:00", 8, 0),
+            stop("2023-10-02", "Pakenham", "08:15:00", 40, 0),
+            stop("2022-09-12", "Frankston", "08:10:00", 5, 5),
+            // No departure time: left out.
+            stop("2022-09-14", "Pakenham", "", 1000, 0),
+        ];
+        let mut surface = BTreeMap::new();
+        for record in &records {
+            add_movements(&mut surface, record, &DayStart::default());
+        }
+
+        assert_eq!(surface.keys().collect::<Vec<_>>(), ["Frankston", "Pakenham"]);
+        let pakenham = &surface["Pakenham"];
+        // Three September dates; 08:00 is business hour 5, 17:00 is 14 and
+        // 00:30 is 21.
+        let september = pakenham[8].average().unwrap();
+        assert_eq!(pakenham[8].dates.len(), 3);
+        assert_eq!((september[5], september[14], september[21]), (60.0, 20.0, 8.0 / 3.0));
+        assert_eq!(september.iter().sum::<f64>(), 248.0 / 3.0);
+        assert_eq!(pakenham[9].average().unwrap()[5], 40.0);
+        assert!(pakenham[0].average().is_none());
+        assert_eq!(surface["Frankston"][8].average().unwrap()[5], 10.0);
+    }
+}
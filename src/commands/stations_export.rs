@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::{Parser, ValueEnum};
+use plotters::prelude::*;
+use crate::Record;
+use crate::business::{DayStart, parse_service_time};
+
+use crate::cli::Output;
+use crate::filters::{Mode, ModeFilter, ServiceOptions, TrainFilter};
+use crate::groups::{GroupTotals, StationGroups, UNMAPPED};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+use crate::stations::{StationIndex, StationSort, percentile_threshold};
+
+/// `stations export`: the station master list and each line's stations.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[arg(value_enum)]
+    action: Action,
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    /// Also write each line's stations with their movements on it
+    #[arg(long)]
+    by_station: bool,
+    /// Order of the --by-station rows
+    #[arg(long, value_enum, default_value_t)]
+    station_sort: StationSort,
+    /// Keep only stations at or above this percentile of total movements
+    #[arg(long, value_name = "0-100", value_parser = percentile)]
+    station_percentile: Option<f64>,
+    /// Roll stations up to the groups in a Station,Group CSV
+    #[arg(long, value_name = "PATH")]
+    station_groups: Option<String>,
+    /// Hour the business day starts, HH:00 (default 03:00)
+    #[arg(long, value_name = "HH:00", value_parser = DayStart::parse)]
+    day_start: Option<DayStart>,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+/// What `stations` does; exporting is all it does so far.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Action {
+    Export,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    match options.action {
+        Action::Export => {}
+    }
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let day_start = options.day_start.unwrap_or_default();
+    let station_percentile = options.station_percentile;
+    let by_station = options.by_station;
+    let station_sort = options.station_sort;
+
+    // Load the mapping up front so a bad file fails before the long scan.
+    let station_groups = match &options.station_groups {
+        Some(path) => Some(StationGroups::load(path)?),
+        None => None,
+    };
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut index = StationIndex::new();
+    let mut pb = source.progress(options.source.no_progress);
+
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        index.add(
+            &record.line_name,
+            &record.station_name,
+            &record.station_latitude,
+            &record.station_longitude,
+            record.station_chainage,
+            record.passenger_boardings,
+            record.passenger_alightings,
+        );
+        if by_station {
+            index.add_stop(
+                &record.line_name,
+                &record.station_name,
+                &record.direction,
+                record.stop_sequence_number,
+                record.passenger_boardings,
+                record.passenger_alightings,
+            );
+        }
+        if let Some(departure_time) = parse_service_time(&record.departure_time_scheduled) {
+            index.add_hourly(
+                &record.station_name,
+                day_start.at(departure_time).hour(),
+                record.passenger_boardings + record.passenger_alightings,
+            );
+        }
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let mut skips = ChartSkips::new("stations", &options.output, options.allow_empty_charts);
+    // Optionally roll every station up to the user's groups.
+    if let Some(ref station_groups) = station_groups {
+        let totals = station_groups.roll_up(&index.stations);
+        let groups_path = format!("{}/station_groups.csv", output_dir);
+        let mut file = File::create(&groups_path)?;
+        writeln!(file, "{}", schema::STATION_GROUPS.header())?;
+        for (group, group_totals) in &totals {
+            writeln!(file, "{},{},{}", group, group_totals.stations.len(), group_totals.movements)?;
+        }
+
+        let mut file = File::create(format!("{}/station_group_hourly.csv", output_dir))?;
+        writeln!(file, "{}", schema::STATION_GROUP_HOURLY.header())?;
+        for (group, group_totals) in &totals {
+            for (hour, movements) in group_totals.hourly.iter().enumerate() {
+                writeln!(file, "{},{},{}", group, hour, movements)?;
+            }
+        }
+
+        if skips.check("station_groups_chart.png", totals.values().map(|group| [group.movements as f64])) {
+            generate_group_chart(&options.output.file("station_groups_chart.png"), &totals, mode_filter.mode)?;
+        }
+
+        if let Some(unmapped) = totals.get(UNMAPPED) {
+            eprintln!("Warning: {} stations are not in the station groups mapping:", unmapped.stations.len());
+            for station in &unmapped.stations {
+                eprintln!("  {}", station);
+            }
+        }
+        println!("Exported {} station groups to '{}'.", totals.len(), groups_path);
+    }
+
+    // Optionally keep only the stations at or above a movements percentile.
+    if let Some(p) = station_percentile {
+        let threshold = percentile_threshold(&index.stations, p);
+        let total_demand: i64 = index.stations.values().map(|info| info.total_movements()).sum();
+        let before = index.stations.len();
+        index.stations.retain(|_, info| info.total_movements() >= threshold);
+        let kept_demand: i64 = index.stations.values().map(|info| info.total_movements()).sum();
+        let share = if total_demand > 0 { kept_demand as f64 / total_demand as f64 * 100.0 } else { 0.0 };
+        println!(
+            "Kept {} of {} stations at or above the {}th percentile ({} movements), {} dropped; kept stations carry {:.1}% of total demand.",
+            index.stations.len(), before, p, threshold, before - index.stations.len(), share
+        );
+    }
+
+    // Deduplicated station list.
+    let stations_path = format!("{}/stations.csv", output_dir);
+    let mut file = File::create(&stations_path)?;
+    writeln!(file, "{}", schema::STATIONS.header())?;
+    for (station, info) in &index.stations {
+        let lines: Vec<&str> = info.lines.iter().map(|s| s.as_str()).collect();
+        writeln!(
+            file,
+            "{},{},{},{},{}",
+            station, info.latitude, info.longitude, lines.join(";"), info.total_movements()
+        )?;
+    }
+
+    // Per-line station ordering by chainage.
+    let (sequences, conflicts) = index.line_sequences();
+    let sequence_path = format!("{}/station_sequence.csv", output_dir);
+    let mut file = File::create(&sequence_path)?;
+    writeln!(file, "{}", schema::STATION_SEQUENCE.header())?;
+    for (line, ordered) in &sequences {
+        for (order, (station, chainage)) in ordered.iter().enumerate() {
+            writeln!(file, "{},{},{},{}", line, order, station, chainage)?;
+        }
+    }
+
+    // Optionally one file per line with each station's totals on it.
+    if by_station {
+        let line_stations = index.line_stations(&sequences, station_sort);
+        for (line, rows) in &line_stations {
+            let mut file = File::create(format!("{}/stations_{}.csv", output_dir, line))?;
+            writeln!(file, "{}", schema::LINE_STATIONS.header())?;
+            for row in rows.iter().filter(|row| index.stations.contains_key(row.station)) {
+                let sequence = row.sequence.map_or(String::new(), |sequence| sequence.to_string());
+                writeln!(
+                    file,
+                    "{},{},{},{},{},{}",
+                    row.station, sequence, row.chainage, row.totals.boardings, row.totals.alightings,
+                    row.totals.boardings + row.totals.alightings
+                )?;
+            }
+        }
+        println!("Exported station totals for {} lines to '{}/stations_<line>.csv'.", line_stations.len(), output_dir);
+    }
+
+    for conflict in &conflicts {
+        eprintln!("Warning: {}", conflict);
+    }
+
+    println!(
+        "Exported {} stations to '{}' and {} line sequences to '{}'.",
+        index.stations.len(), stations_path, sequences.len(), sequence_path
+    );
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Parses a `--station-percentile` value, from 0 to 100.
+fn percentile(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+        _ => Err("expected 0-100".to_string()),
+    }
+}
+
+/// Generates a bar chart of total movements per station group.
+fn generate_group_chart(
+    filename: &str,
+    totals: &BTreeMap<String, GroupTotals>,
+    mode: Mode,
+) -> Result<(), Box<dyn Error>> {
+    let groups: Vec<(&String, i64)> = totals.iter().map(|(group, t)| (group, t.movements)).collect();
+
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let max_value = groups.iter().map(|&(_, movements)| movements).max().unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(mode.caption("Total Movements by Station Group"), ("sans-serif", 50))
+        .margin(60)
+        .x_label_area_size(100)
+        .y_label_area_size(80)
+        .build_cartesian_2d(0..groups.len(), 0..(max_value + max_value / 10 + 1))?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(groups.len())
+        .x_label_formatter(&|idx| groups.get(*idx).map_or("".to_string(), |&(group, _)| group.clone()))
+        .x_desc("Group")
+        .y_desc("Total Movements")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    chart.draw_series(groups.iter().enumerate().map(|(i, &(group, movements))| {
+        // Unmapped stations are shown in gray so they stand apart from real groups.
+        let color = if group == UNMAPPED { RGBColor(160, 160, 160) } else { RGBColor(0, 0, 255) };
+        Rectangle::new([(i, 0), (i + 1, movements)], color.filled())
+    }))?;
+
+    Ok(())
+}
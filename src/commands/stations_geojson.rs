@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use serde::Serialize;
+use crate::Record;
+use crate::record::{coordinate, is_placeholder};
+
+use crate::cli::Output;
+use crate::filters::{ModeFilter, ServiceOptions, TrainFilter};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::stations::{StationIndex, StationInfo};
+
+/// `stationsGeojson`: every station as a GeoJSON point.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+    let mut index = StationIndex::new();
+    let mut unplaced_rows = 0u64;
+    let mut pb = source.progress(options.source.no_progress);
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            pb.inc(1);
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            pb.inc(1);
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            pb.inc(1);
+            continue;
+        }
+        // A row with missing, unparseable or 0,0 placeholder coordinates
+        // still counts towards its station's totals, but can't place it.
+        let (latitude, longitude) = match record.coordinates() {
+            Some((latitude, longitude)) if !is_placeholder(latitude, longitude) => {
+                (record.station_latitude.as_str(), record.station_longitude.as_str())
+            }
+            _ => {
+                unplaced_rows += 1;
+                ("", "")
+            }
+        };
+        index.add(
+            &record.line_name,
+            &record.station_name,
+            latitude,
+            longitude,
+            record.station_chainage,
+            record.passenger_boardings,
+            record.passenger_alightings,
+        );
+        pb.inc(1);
+    }
+    pb.finish("CSV processing complete.");
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let (geojson, placed, unplaced) = station_features(&index.stations);
+    let output_file_path = format!("{}/stations.geojson", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", geojson)?;
+    if unplaced_rows > 0 {
+        eprintln!("Warning: {} rows had missing, unparseable or 0,0 coordinates, which were ignored.", unplaced_rows);
+    }
+    if !unplaced.is_empty() {
+        eprintln!("Warning: {} stations have no coordinates and were left out: {}", unplaced.len(), unplaced.join(", "));
+    }
+    println!("Exported {} stations to '{}'.", placed, output_file_path);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct FeatureCollection<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    features: Vec<Feature<'a>>,
+}
+
+#[derive(Serialize)]
+struct Feature<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: Point,
+    properties: StationProperties<'a>,
+}
+
+#[derive(Serialize)]
+struct Point {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    // GeoJSON positions are longitude first.
+    coordinates: [f64; 2],
+}
+
+#[derive(Serialize)]
+struct StationProperties<'a> {
+    name: &'a str,
+    boardings: i64,
+    alightings: i64,
+    lines: &'a BTreeSet<String>,
+}
+
+/// A GeoJSON FeatureCollection with a Point for each station, carrying its
+/// boardings and alightings summed over every line and the lines serving
+/// it. Also returns how many stations were placed and the names of those
+/// without coordinates, or with the 0,0 placeholder, which are left out.
+fn station_features(stations: &BTreeMap<String, StationInfo>) -> (String, usize, Vec<&str>) {
+    let mut features = Vec::new();
+    let mut unplaced = Vec::new();
+    for (station, info) in stations {
+        let position = coordinate(&info.latitude).zip(coordinate(&info.longitude))
+            .filter(|&(latitude, longitude)| !is_placeholder(latitude, longitude));
+        let Some((latitude, longitude)) = position else {
+            unplaced.push(station.as_str());
+            continue;
+        };
+        features.push(Feature {
+            kind: "Feature",
+            geometry: Point { kind: "Point", coordinates: [longitude, latitude] },
+            properties: StationProperties {
+                name: station,
+                boardings: info.boardings,
+                alightings: info.alightings,
+                lines: &info.lines,
+            },
+        });
+    }
+    let placed = features.len();
+    let collection = FeatureCollection { kind: "FeatureCollection", features };
+    let geojson = serde_json::to_string_pretty(&collection).expect("station features always serialize");
+    (geojson, placed, unplaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn stations_on_several_lines_are_one_feature() {
+        let mut index = StationIndex::new();
+        index.add("Pakenham", "Richmond", "-37.82", "144.99", Some(2300), 120, 80);
+        index.add("Frankston", "Richmond", "-37.82", "144.99", Some(2300), 30, 20);
+        index.add("Pakenham", "Caulfield \"Racecourse\"", "-37.88", "145.04", Some(10700), 50, 5);
+        index.add("Pakenham", "Nowhere", "", "", Some(0), 1, 1);
+        index.add("Pakenham", "Null Island", "0.0", "0.0", Some(0), 1, 1);
+
+        let (geojson, placed, unplaced) = station_features(&index.stations);
+        assert_eq!((placed, unplaced), (2, vec!["Nowhere", "Null Island"]));
+
+        let collection = serde_json::from_str::<Value>(&geojson).unwrap_or_else(|err| panic!("{}:\n{}", err, geojson));
+        assert_eq!(collection["type"], "FeatureCollection");
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+
+        assert_eq!(
+            features[1],
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [144.99, -37.82]},
+                "properties": {"name": "Richmond", "boardings": 150, "alightings": 100, "lines": ["Frankston", "Pakenham"]},
+            })
+        );
+        assert_eq!(features[0]["properties"]["name"], "Caulfield \"Racecourse\"");
+
+        let empty = serde_json::from_str::<Value>(&station_features(&BTreeMap::new()).0).unwrap();
+        assert_eq!(empty, json!({"type": "FeatureCollection", "features": []}));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use clap::Parser;
+use plotters::prelude::*;
+
+use crate::Record;
+use crate::cli::Output;
+use crate::dates::parse_date;
+use crate::format::FloatFormat;
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker};
+use crate::schema;
+use crate::skips::ChartSkips;
+
+// Number of alternative train numbers suggested when the requested one is missing.
+const SUGGESTIONS: usize = 5;
+
+/// `trainProfile`: one service's stops with its loads along the way.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    /// Train number of the service
+    #[arg(long, value_name = "NUMBER")]
+    train: String,
+    /// Business date of the service, YYYY-MM-DD
+    #[arg(long, value_name = "YYYY-MM-DD", value_parser = parse_date)]
+    date: String,
+    /// Line to suggest other train numbers from if the train didn't run
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// Also draw the departure load at each stop
+    #[arg(long)]
+    chart: bool,
+    /// How decimals are written: fixed or scientific
+    #[arg(long = "csv-float-format", value_name = "FORMAT", default_value = "fixed", value_parser = FloatFormat::parse)]
+    float_format: FloatFormat,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let float_format = options.float_format;
+    let train = options.train.as_str();
+    let date = options.date.as_str();
+    let line_filter = options.line.as_ref().map(|s| s.to_lowercase());
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+
+    // A service is identified by (date, train number); the stops are keyed by
+    // stop sequence so gaps in the numbering don't affect the ordering.
+    let mut stops: BTreeMap<i32, Record> = BTreeMap::new();
+    // Every other train number running on the date, with its line, for suggestions.
+    let mut trains_on_date: BTreeMap<String, String> = BTreeMap::new();
+
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            continue;
+        };
+        if record.business_date != date {
+            continue;
+        }
+        if record.train_number == train {
+            stops.insert(record.stop_sequence_number, record);
+        } else {
+            trains_on_date.entry(record.train_number.clone()).or_insert(record.line_name.clone());
+        }
+    }
+    issues.print_summary();
+
+    if stops.is_empty() {
+        let suggestions = nearby_trains(train, &trains_on_date, line_filter.as_deref());
+        if suggestions.is_empty() {
+            return Err(format!("train {} did not run on {}", train, date).into());
+        }
+        return Err(format!(
+            "train {} did not run on {}; nearby train numbers: {}",
+            train, date, suggestions.join(", ")
+        ).into());
+    }
+
+    let output_file_path = format!("{}/train_{}_{}.csv", output_dir, train, date);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::TRAIN_PROFILE.header())?;
+    for (sequence, stop) in &stops {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{}",
+            sequence,
+            stop.station_name,
+            stop.arrival_time_scheduled,
+            stop.departure_time_scheduled,
+            float_format.format(stop.passenger_boardings as f64, 0),
+            float_format.format(stop.passenger_alightings as f64, 0),
+            float_format.format(stop.passenger_arrival_load as f64, 0),
+            float_format.format(stop.passenger_departure_load as f64, 0),
+        )?;
+    }
+    println!("Journey profile saved to '{}'.", output_file_path);
+
+    let mut skips = ChartSkips::new("trainProfile", &options.output, options.allow_empty_charts);
+    if options.chart {
+        let chart_path = options.output.file(&format!("train_{}_{}.png", train, date));
+        let stations: Vec<(String, i32)> = stops.values()
+            .map(|stop| (stop.station_name.clone(), stop.passenger_departure_load))
+            .collect();
+        let caption = format!("Train {} Load on {}", train, date);
+        if skips.check(&chart_path, [stations.iter().map(|&(_, load)| load as f64)]) {
+            generate_journey_load_chart(&chart_path, &caption, &stations)?;
+            println!("Journey load chart saved to '{}'.", chart_path);
+        }
+    }
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Suggests train numbers numerically closest to `train`, restricted to
+/// `line` when given. Non-numeric train numbers are never suggested.
+fn nearby_trains(train: &str, trains_on_date: &BTreeMap<String, String>, line: Option<&str>) -> Vec<String> {
+    let target: i64 = match train.parse() {
+        Ok(n) => n,
+        Err(_) => return Vec::new(),
+    };
+    let mut candidates: Vec<(i64, &String)> = trains_on_date.iter()
+        .filter(|(_, train_line)| line.map_or(true, |l| train_line.to_lowercase() == l))
+        .filter_map(|(number, _)| number.parse::<i64>().ok().map(|n| ((n - target).abs(), number)))
+        .collect();
+    candidates.sort();
+    candidates.into_iter().take(SUGGESTIONS).map(|(_, number)| number.clone()).collect()
+}
+
+/// Generates a line chart of departure load at each stop along the journey,
+/// with station names on the x axis.
+fn generate_journey_load_chart(
+    filename: &str,
+    caption: &str,
+    stations: &[(String, i32)]
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_load = stations.iter().map(|(_, load)| *load).max().unwrap_or(0);
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50))
+        .margin(60)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 200)
+        .build_cartesian_2d(0..stations.len(), 0..(max_load + max_load / 10 + 1))?;
+
+    chart.configure_mesh()
+        .x_labels(stations.len())
+        .x_label_formatter(&|idx| {
+            stations.get(*idx).map_or("".to_string(), |(station, _)| station.clone())
+        })
+        .x_label_style(("sans-serif", 20).into_font().transform(FontTransform::Rotate90))
+        .x_desc("Station")
+        .y_desc("Departure Load")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let color = RGBColor(0, 0, 255);
+    let series: Vec<(usize, i32)> = stations.iter()
+        .enumerate()
+        .map(|(i, (_, load))| (i, *load))
+        .collect();
+    chart.draw_series(LineSeries::new(series.clone(), color.stroke_width(3)))?;
+    chart.draw_series(series.iter().map(|&point| Circle::new(point, 7, color.filled())))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+        Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+        Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+        Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn stops_follow_the_sequence_across_gaps() {
+        let dir = std::env::temp_dir().join(format!("ptv_data_train_profile_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("data.csv");
+        // Rows out of order, with sequence numbers 2, 5 and 11.
+        let csv = format!(
+            "{}\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             Dandenong,-37.98,145.21,30800,11,07:40:00,07:41:00,15,40,300,275\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             Flinders Street,-37.82,144.97,0,2,,07:00:00,200,0,0,200\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1002,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             Richmond,-37.82,144.99,2400,3,07:20:00,07:21:00,10,5,80,85\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,D,Flinders Street,Pakenham,\
+             Caulfield,-37.88,145.04,11500,5,07:15:00,07:16:00,120,20,200,300\n",
+            HEADER
+        );
+        std::fs::write(&input, csv).unwrap();
+
+        let output = dir.join("out");
+        run(&args(&[
+            "trainProfile", "--input", input.to_str().unwrap(), "--output-dir", output.to_str().unwrap(),
+            "--train", "1001", "--date", "2022-09-12",
+        ])).unwrap();
+
+        let profile = std::fs::read_to_string(output.join("train_1001_2022-09-12.csv")).unwrap();
+        let stations: Vec<&str> = profile.lines().skip(1)
+            .map(|line| line.split(',').nth(1).unwrap())
+            .collect();
+        assert_eq!(stations, vec!["Flinders Street", "Caulfield", "Dandenong"]);
+        assert!(profile.lines().nth(3).unwrap().starts_with("11,Dandenong,"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_train_suggests_the_closest_numbers() {
+        let trains: BTreeMap<String, String> = [
+            ("1003", "Pakenham"), ("1010", "Pakenham"), ("998", "Cranbourne"), ("1100", "Pakenham"),
+            ("RRB1", "Pakenham"), ("1004", "Frankston"),
+        ].iter().map(|&(number, line)| (number.to_string(), line.to_string())).collect();
+
+        assert_eq!(nearby_trains("1001", &trains, None), vec!["1003", "1004", "998", "1010", "1100"]);
+        assert_eq!(nearby_trains("1001", &trains, Some("pakenham")), vec!["1003", "1010", "1100"]);
+        // Nothing is numerically close to a non-numeric train number.
+        assert!(nearby_trains("RRB2", &trains, None).is_empty());
+    }
+}
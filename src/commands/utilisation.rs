@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use chrono::NaiveTime;
+use clap::Parser;
+use plotters::prelude::*;
+use crate::Record;
+use crate::business::parse_service_time;
+
+use crate::aggregates;
+use crate::bands::{BAND_NAMES, TimeBands};
+use crate::cli::Output;
+use crate::filters::{Mode, ModeFilter, ServiceOptions, TrainFilter};
+use crate::index::{RecordSource, Source};
+use crate::issues::{IssueOptions, IssueTracker, RowFields};
+use crate::schema;
+use crate::skips::ChartSkips;
+
+// Seats (nominal capacity) per train when --capacity isn't given.
+const DEFAULT_CAPACITY: f64 = 800.0;
+
+// One stop of a service, enough to measure the segment to the next stop.
+struct Stop {
+    sequence: i32,
+    chainage: Option<i32>,
+    departure: Option<NaiveTime>,
+    departure_load: i32,
+}
+
+#[derive(Default, Clone, Copy)]
+struct BandTotals {
+    seat_km: f64,
+    passenger_km: f64,
+}
+
+impl BandTotals {
+    /// Passenger-km per seat-km; 0 with no seat-km.
+    fn ratio(&self) -> f64 {
+        if self.seat_km > 0.0 { self.passenger_km / self.seat_km } else { 0.0 }
+    }
+}
+
+// Seat-km and passenger-km per line and band, the services contributing to
+// each, and how many segments were measured or left out.
+struct Utilisation {
+    totals: BTreeMap<String, [BandTotals; 4]>,
+    band_services: HashMap<(String, usize), HashSet<(String, String)>>,
+    segments: u64,
+    excluded: u64,
+}
+
+/// Walks each service's segments, keyed by (date, train number), in stop
+/// sequence order, attributing seat-km and passenger-km to the band of the
+/// segment's departure time.
+fn measure(services: &mut HashMap<(String, String), (String, Vec<Stop>)>, bands: &TimeBands, capacity: f64) -> Utilisation {
+    let mut utilisation = Utilisation { totals: BTreeMap::new(), band_services: HashMap::new(), segments: 0, excluded: 0 };
+    for (service, (line, stops)) in services.iter_mut() {
+        stops.sort_by_key(|stop| stop.sequence);
+        for pair in stops.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            utilisation.segments += 1;
+            // Segments without a distance or a time are left out of both the
+            // numerator and the denominator so the ratio isn't biased.
+            let (distance_km, departure) = match (from.chainage, to.chainage, from.departure) {
+                (Some(a), Some(b), Some(departure)) => ((b - a).abs() as f64 / 1000.0, departure),
+                _ => {
+                    utilisation.excluded += 1;
+                    continue;
+                }
+            };
+            let band = bands.band(departure);
+            let entry = &mut utilisation.totals.entry(line.clone()).or_insert([BandTotals::default(); 4])[band];
+            entry.seat_km += capacity * distance_km;
+            entry.passenger_km += from.departure_load as f64 * distance_km;
+            utilisation.band_services.entry((line.clone(), band)).or_default().insert(service.clone());
+        }
+    }
+    utilisation
+}
+
+/// `utilisation`: passenger-km over seat-km per line and time band.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[command(flatten)]
+    source: Source,
+    #[command(flatten)]
+    output: Output,
+    #[command(flatten)]
+    issues: IssueOptions,
+    #[command(flatten)]
+    services: ServiceOptions,
+    #[command(flatten)]
+    bands: TimeBands,
+    /// Seats per train
+    #[arg(long, value_name = "PASSENGERS", default_value_t = DEFAULT_CAPACITY, value_parser = aggregates::capacity)]
+    capacity: f64,
+    /// Draw charts with nothing to show rather than skipping them
+    #[arg(long)]
+    allow_empty_charts: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    run_with(Options::parse_from(args))
+}
+
+pub fn run_with(options: Options) -> Result<(), Box<dyn Error>> {
+
+    let output_dir = options.output.path();
+    let mut issues = IssueTracker::from_args(&options.issues)?;
+    let capacity = options.capacity;
+    let bands = options.bands;
+
+    options.output.create()?;
+
+    let mut source = RecordSource::open(&options.source)?;
+
+    // Group stops by service, identified by (date, train number).
+    let mut services: HashMap<(String, String), (String, Vec<Stop>)> = HashMap::new();
+    let mut train_filter = TrainFilter::from_args(&options.services);
+    let mut mode_filter = ModeFilter::from_args(&options.services);
+    let headers = source.headers().clone();
+    for (row, result) in source.deserialize().enumerate() {
+        let Some(record): Option<Record> = issues.read(row as u64 + 1, result, &headers)? else {
+            continue;
+        };
+        if !train_filter.matches(&record.train_number) || !mode_filter.matches(&record.mode) {
+            continue;
+        }
+        if issues.inspect(row as u64 + 1, &RowFields {
+            business_date: &record.business_date,
+            train_number: &record.train_number,
+            direction: &record.direction,
+            stop_sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            arrival_time: &record.arrival_time_scheduled,
+            departure_time: &record.departure_time_scheduled,
+            coordinates: record.coordinates(),
+            counts: [
+                record.passenger_boardings,
+                record.passenger_alightings,
+                record.passenger_arrival_load,
+                record.passenger_departure_load,
+            ],
+        })? {
+            continue;
+        }
+        let stop = Stop {
+            sequence: record.stop_sequence_number,
+            chainage: record.station_chainage,
+            departure: parse_service_time(&record.departure_time_scheduled),
+            departure_load: record.passenger_departure_load,
+        };
+        services.entry((record.business_date, record.train_number))
+            .or_insert_with(|| (record.line_name, Vec::new()))
+            .1.push(stop);
+    }
+    train_filter.report();
+    mode_filter.report();
+    issues.print_summary();
+
+    let Utilisation { totals, band_services, segments, excluded } = measure(&mut services, &bands, capacity);
+
+    let output_file_path = format!("{}/utilisation.csv", output_dir);
+    let mut file = File::create(&output_file_path)?;
+    writeln!(file, "{}", schema::UTILISATION.header())?;
+    let mut utilisation: BTreeMap<String, [f64; 4]> = BTreeMap::new();
+    for (line, line_totals) in &totals {
+        let ratios = utilisation.entry(line.clone()).or_insert([0.0; 4]);
+        for (band, band_totals) in line_totals.iter().enumerate() {
+            let count = band_services.get(&(line.clone(), band)).map_or(0, |s| s.len());
+            let ratio = band_totals.ratio();
+            ratios[band] = ratio;
+            writeln!(
+                file,
+                "{},{},{},{:.1},{:.1},{:.4}",
+                line, BAND_NAMES[band], count, band_totals.seat_km, band_totals.passenger_km, ratio
+            )?;
+        }
+    }
+
+    let mut skips = ChartSkips::new("utilisation", &options.output, options.allow_empty_charts);
+    if skips.check("utilisation_chart.png", utilisation.values().cloned()) {
+        generate_utilisation_chart(&options.output.file("utilisation_chart.png"), &utilisation, mode_filter.mode)?;
+    }
+
+    let share = if segments > 0 { excluded as f64 / segments as f64 * 100.0 } else { 0.0 };
+    println!(
+        "Excluded {} of {} segments ({:.1}%) with missing distance or time data.",
+        excluded, segments, share
+    );
+    println!("Utilisation saved to '{}'.", output_file_path);
+    skips.finish()?;
+
+    Ok(())
+}
+
+/// Generates a grouped bar chart of utilisation per line, one bar per band.
+fn generate_utilisation_chart(
+    filename: &str,
+    data: &BTreeMap<String, [f64; 4]>,
+    mode: Mode,
+) -> Result<(), Box<dyn Error>> {
+    let lines: Vec<&String> = data.keys().collect();
+    // Each line gets a slot of one bar per band plus a gap.
+    let slot = BAND_NAMES.len() + 1;
+
+    let root = BitMapBackend::new(filename, (1600, 1200)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_ratio = data.values().flat_map(|r| r.iter()).cloned().fold(0.0, f64::max);
+    let max_percent = (max_ratio * 100.0 * 1.1).max(1.0);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(mode.caption("Utilisation by Time Band (Passenger-km / Seat-km)"), ("sans-serif", 50))
+        .margin(60)
+        .x_label_area_size(100)
+        .y_label_area_size(80)
+        .build_cartesian_2d(0..(lines.len() * slot), 0.0..max_percent)?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(lines.len() * slot)
+        .x_label_formatter(&|idx| {
+            if idx % slot == BAND_NAMES.len() / 2 {
+                lines.get(idx / slot).map_or("".to_string(), |line| line.to_string())
+            } else {
+                "".to_string()
+            }
+        })
+        .x_desc("Line")
+        .y_desc("Utilisation (%)")
+        .label_style(("sans-serif", 30))
+        .draw()?;
+
+    let palette = [
+        RGBColor(255, 0, 0),       // red
+        RGBColor(0, 0, 255),       // blue
+        RGBColor(0, 128, 0),       // green
+        RGBColor(128, 128, 128),   // grey
+    ];
+    for (band, name) in BAND_NAMES.iter().enumerate() {
+        let color = palette[band];
+        chart.draw_series(lines.iter().enumerate().map(|(i, line)| {
+            let x = i * slot + band;
+            Rectangle::new([(x, 0.0), (x + 1, data[*line][band] * 100.0)], color.filled())
+        }))?
+        .label(*name)
+        .legend(move |(x, y)| Rectangle::new([(x, y - 10), (x + 20, y + 10)], color.filled()));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .label_font(("sans-serif", 30))
+        .draw()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bands::{AM_PEAK, INTERPEAK};
+
+    fn stop(sequence: i32, chainage: Option<i32>, departure: &str, departure_load: i32) -> Stop {
+        Stop { sequence, chainage, departure: parse_service_time(departure), departure_load }
+    }
+
+    #[test]
+    fn passenger_km_over_seat_km_per_band() {
+        let mut services: HashMap<(String, String), (String, Vec<Stop>)> = HashMap::new();
+        // Out of sequence order, as rows may come; the last stop departs nothing.
+        services.insert(("2022-09-12".to_string(), "1001".to_string()), ("Pakenham".to_string(), vec![
+            stop(2, Some(2_000), "07:35:00", 400),
+            stop(1, Some(0), "07:30:00", 200),
+            stop(3, Some(5_000), "", 0),
+        ]));
+        // An interpeak service with a stop of unknown chainage: both of its
+        // segments are excluded.
+        services.insert(("2022-09-12".to_string(), "1002".to_string()), ("Pakenham".to_string(), vec![
+            stop(1, Some(0), "11:00:00", 100),
+            stop(2, None, "11:05:00", 100),
+            stop(3, Some(5_000), "", 0),
+        ]));
+
+        let utilisation = measure(&mut services, &TimeBands::default(), 800.0);
+        assert_eq!((utilisation.segments, utilisation.excluded), (4, 2));
+        let am = utilisation.totals["Pakenham"][AM_PEAK];
+        // 2 km at 200 and 3 km at 400 aboard, against 800 seats over 5 km.
+        assert_eq!((am.seat_km, am.passenger_km), (4_000.0, 1_600.0));
+        assert_eq!(am.ratio(), 0.4);
+        assert_eq!(utilisation.totals["Pakenham"][INTERPEAK].ratio(), 0.0);
+        assert_eq!(utilisation.band_services[&("Pakenham".to_string(), AM_PEAK)].len(), 1);
+        assert!(!utilisation.band_services.contains_key(&("Pakenham".to_string(), INTERPEAK)));
+    }
+}
@@ -0,0 +1,121 @@
+// Business date selection for the time-series binaries. `--date` picks the
+// date to chart; without it the first date in the file is used, and the run
+// says which one that was.
+
+use chrono::{Duration, NaiveDate};
+use std::collections::BTreeSet;
+
+/// Checks a `--date` value is a date written YYYY-MM-DD.
+pub fn parse_date(value: &str) -> Result<String, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|_| value.to_string())
+        .map_err(|_| "expected YYYY-MM-DD".to_string())
+}
+
+/// Picks the business date a read loop aggregates: `--date`, or the first
+/// date seen. Every date seen is kept to list when `--date` matches none.
+#[derive(Debug, Default)]
+pub struct DatePicker {
+    requested: Option<String>,
+    selected: Option<String>,
+    seen: BTreeSet<String>,
+}
+
+impl DatePicker {
+    /// Picks `requested`, the `--date` given, or else the first date seen.
+    pub fn new(requested: Option<String>) -> DatePicker {
+        DatePicker { selected: requested.clone(), requested, seen: BTreeSet::new() }
+    }
+
+    /// Whether a record on `business_date` falls on the selected date.
+    pub fn selects(&mut self, business_date: &str) -> bool {
+        if !self.seen.contains(business_date) {
+            self.seen.insert(business_date.to_string());
+        }
+        self.selected.get_or_insert_with(|| business_date.to_string()) == business_date
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.selected.as_deref()
+    }
+
+    /// Fails if `--date` matched no records, listing the dates there are;
+    /// otherwise prints a date picked by default.
+    pub fn report(&self) -> Result<(), String> {
+        match (&self.requested, &self.selected) {
+            (Some(date), _) if !self.seen.contains(date) => Err(no_records_error(date, &self.seen)),
+            (None, Some(date)) => {
+                println!("Time series for business date {}, the first in the file; use --date to choose.", date);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Error for a `--date` with no records, listing the dates that have them.
+pub fn no_records_error(date: &str, available: &BTreeSet<String>) -> String {
+    if available.is_empty() {
+        return format!("no records on business date {}; no dates have records", date);
+    }
+    format!("no records on business date {}; dates with records: {}", date, date_ranges(available))
+}
+
+/// `dates` with runs of consecutive days collapsed, e.g.
+/// `2022-09-05 to 2022-09-09, 2022-09-12`.
+fn date_ranges(dates: &BTreeSet<String>) -> String {
+    let mut runs: Vec<(&str, &str)> = Vec::new();
+    let mut previous: Option<NaiveDate> = None;
+    for date in dates {
+        let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        let follows = match (previous, parsed) {
+            (Some(previous), Some(parsed)) => previous + Duration::days(1) == parsed,
+            _ => false,
+        };
+        match runs.last_mut() {
+            Some(run) if follows => run.1 = date,
+            _ => runs.push((date, date)),
+        }
+        previous = parsed;
+    }
+    runs.iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{} to {}", first, last) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dates(values: &[&str]) -> BTreeSet<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn lists_available_dates_as_runs() {
+        let available = dates(&["2022-09-05", "2022-09-06", "2022-09-07", "2022-09-12", "2022-09-30", "2022-10-01"]);
+        assert_eq!(
+            no_records_error("2022-09-08", &available),
+            "no records on business date 2022-09-08; dates with records: 2022-09-05 to 2022-09-07, 2022-09-12, 2022-09-30 to 2022-10-01"
+        );
+    }
+
+    #[test]
+    fn picks_the_requested_or_first_date() {
+        let mut first = DatePicker::default();
+        assert!(first.selects("2022-09-12"));
+        assert!(!first.selects("2022-09-13"));
+        assert_eq!(first.selected(), Some("2022-09-12"));
+        assert!(first.report().is_ok());
+
+        let mut requested = DatePicker::new(Some(parse_date("2022-09-14").unwrap()));
+        assert!(!requested.selects("2022-09-12"));
+        assert!(!requested.selects("2022-09-13"));
+        assert!(requested.report().unwrap_err().ends_with("2022-09-12 to 2022-09-13"));
+        assert!(requested.selects("2022-09-14"));
+        assert!(requested.report().is_ok());
+
+        assert!(parse_date("14/09/2022").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+// Errors raised while reading records, carrying the row (and field, where
+// known) so a failure on messy data points at the offending record.
+
+use csv::{ErrorKind, StringRecord};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RecordError {
+    #[error("row {row}, field {field}: {message}")]
+    Field { row: u64, field: String, message: String },
+    #[error("row {row}: {source}")]
+    Row {
+        row: u64,
+        #[source]
+        source: csv::Error,
+    },
+    #[error("row {row}: {check}: {detail}")]
+    Issue { row: u64, check: &'static str, detail: String },
+}
+
+/// Attaches the data row number (1-based, excluding the header) and the
+/// offending column name to a CSV read or deserialize error.
+pub trait RowContext<T> {
+    fn with_row(self, row: u64, headers: &StringRecord) -> Result<T, RecordError>;
+}
+
+impl<T> RowContext<T> for Result<T, csv::Error> {
+    fn with_row(self, row: u64, headers: &StringRecord) -> Result<T, RecordError> {
+        self.map_err(|err| {
+            if let ErrorKind::Deserialize { err: de, .. } = err.kind() {
+                if let Some(index) = de.field() {
+                    let field = headers.get(index as usize)
+                        .map_or_else(|| format!("#{}", index), |name| name.to_string());
+                    return RecordError::Field { row, field, message: de.kind().to_string() };
+                }
+            }
+            RecordError::Row { row, source: err }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Record;
+
+    #[test]
+    fn errors_name_the_row_and_field() {
+        let csv = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+            Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+            Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+            Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load\n\
+            2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+            Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,12,85,410,337\n\
+            2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+            Flinders Street,-37.82,144.97,0,15,08:03:00,08:05:00,twelve,85,337,0\n\
+            2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham\n";
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        let errors: Vec<String> = reader.deserialize::<Record>().enumerate()
+            .filter_map(|(row, result)| result.with_row(row as u64 + 1, &headers).err())
+            .map(|err| err.to_string())
+            .collect();
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("row 2, field Passenger_Boardings: "), "{}", errors[0]);
+        // A short row has no one field to blame.
+        assert!(errors[1].starts_with("row 3: "), "{}", errors[1]);
+    }
+}
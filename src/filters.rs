@@ -0,0 +1,566 @@
+// Record filters applied in the read loops, before any aggregation.
+
+use chrono::{NaiveTime, Timelike};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use crate::business::{DayStart, parse_service_time};
+
+/// `--train-prefix`, `--train-range` and `--mode`: which services a command
+/// reads.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ServiceOptions {
+    /// Keep train numbers starting with PREFIX
+    #[arg(long, value_name = "PREFIX")]
+    pub train_prefix: Option<String>,
+    /// Keep train numbers in an inclusive range, e.g. 4000-4999
+    #[arg(long, value_name = "FIRST-LAST", value_parser = parse_range)]
+    pub train_range: Option<(u64, u64)>,
+    /// Keep one service mode: Metro, V/Line or all
+    #[arg(long, value_parser = parse_mode)]
+    pub mode: Option<Mode>,
+}
+
+/// `--day-type`, `--day-of-week`, `--from` and `--to`: which days, and which
+/// times of day, the time-series commands read.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DayOptions {
+    /// Keep these Day_Type values, comma-separated, e.g. "Normal Weekday"
+    #[arg(long, value_name = "TYPES")]
+    pub day_type: Option<String>,
+    /// Keep these Day_of_Week values, comma-separated, e.g. Monday,Friday
+    #[arg(long, value_name = "DAYS")]
+    pub day_of_week: Option<String>,
+    /// Keep departures from HH:MM
+    #[arg(long, value_name = "HH:MM", value_parser = clock_minutes)]
+    pub from: Option<u32>,
+    /// Keep departures before HH:MM
+    #[arg(long, value_name = "HH:MM", value_parser = clock_minutes)]
+    pub to: Option<u32>,
+}
+
+/// Restricts records to train numbers with a given prefix (`--train-prefix 4`)
+/// and/or within an inclusive numeric range (`--train-range 4000-4999`).
+/// The range only applies to train numbers that parse as integers; V/Line
+/// alphanumerics such as "8105M" are excluded by it and counted separately.
+#[derive(Debug, Default)]
+pub struct TrainFilter {
+    prefix: Option<String>,
+    range: Option<(u64, u64)>,
+    pub unparsed: u64,
+}
+
+impl TrainFilter {
+    pub fn from_args(options: &ServiceOptions) -> TrainFilter {
+        TrainFilter { prefix: options.train_prefix.clone(), range: options.train_range, unparsed: 0 }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.prefix.is_some() || self.range.is_some()
+    }
+
+    /// Returns true if the record's train number passes every active filter.
+    pub fn matches(&mut self, train_number: &str) -> bool {
+        if let Some(ref prefix) = self.prefix {
+            if !train_number.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some((low, high)) = self.range {
+            match train_number.trim().parse::<u64>() {
+                Ok(n) => return n >= low && n <= high,
+                Err(_) => {
+                    self.unparsed += 1;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Prints a warning if the range filter skipped non-numeric train numbers.
+    pub fn report(&self) {
+        if self.unparsed > 0 {
+            eprintln!(
+                "Warning: {} records with non-numeric train numbers were excluded by --train-range.",
+                self.unparsed
+            );
+        }
+    }
+}
+
+/// Restricts records to one direction of travel (`--direction U|D|both`),
+/// matched against the record's Direction field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum DirectionFilter {
+    #[default]
+    Both,
+    Up,
+    Down,
+}
+
+impl DirectionFilter {
+    /// Parses a `--direction` value, which defaults to both.
+    pub fn parse(value: &str) -> Result<DirectionFilter, String> {
+        match value {
+            "U" => Ok(DirectionFilter::Up),
+            "D" => Ok(DirectionFilter::Down),
+            "both" => Ok(DirectionFilter::Both),
+            _ => Err("expected U, D or both".to_string()),
+        }
+    }
+
+    /// The Direction code kept, or `None` for both.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            DirectionFilter::Both => None,
+            DirectionFilter::Up => Some("U"),
+            DirectionFilter::Down => Some("D"),
+        }
+    }
+
+    pub fn matches(&self, direction: &str) -> bool {
+        self.code().map_or(true, |code| direction.trim() == code)
+    }
+
+    /// `name` with the direction added before the extension, e.g.
+    /// `Pakenham_U.csv`, so runs for each direction don't overwrite each
+    /// other; unchanged for both.
+    pub fn file_name(&self, name: &str) -> String {
+        match (self.code(), name.rsplit_once('.')) {
+            (Some(code), Some((stem, extension))) => format!("{}_{}.{}", stem, code, extension),
+            (Some(code), None) => format!("{}_{}", name, code),
+            (None, _) => name.to_string(),
+        }
+    }
+}
+
+/// A service mode from the record's Mode field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Mode {
+    #[default]
+    All,
+    Metro,
+    VLine,
+}
+
+impl Mode {
+    /// Parses `metro`, `vline` or `all`, ignoring case and any `/` or `-`,
+    /// so the file's own `Metro` and `V/Line` work too.
+    pub fn parse(value: &str) -> Option<Mode> {
+        let normalized: String = value.chars()
+            .filter(|c| !matches!(c, '/' | '-' | ' '))
+            .collect::<String>()
+            .to_lowercase();
+        match normalized.as_str() {
+            "metro" => Some(Mode::Metro),
+            "vline" => Some(Mode::VLine),
+            "all" => Some(Mode::All),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::All => "all",
+            Mode::Metro => "Metro",
+            Mode::VLine => "V/Line",
+        }
+    }
+
+    /// `caption` led by the mode when records were filtered to one, e.g.
+    /// `Metro: Total Movements by Line`.
+    pub fn caption(&self, caption: &str) -> String {
+        match self {
+            Mode::All => caption.to_string(),
+            mode => format!("{}: {}", mode.name(), caption),
+        }
+    }
+}
+
+/// Restricts records to one service mode (`--mode Metro|V/Line|all`) and
+/// counts the records it leaves out.
+#[derive(Debug, Default)]
+pub struct ModeFilter {
+    pub mode: Mode,
+    pub excluded: u64,
+}
+
+impl ModeFilter {
+    pub fn from_args(options: &ServiceOptions) -> ModeFilter {
+        ModeFilter { mode: options.mode.unwrap_or_default(), excluded: 0 }
+    }
+
+    /// Returns true if the record's Mode is the selected one.
+    pub fn matches(&mut self, mode: &str) -> bool {
+        if self.mode == Mode::All || Mode::parse(mode) == Some(self.mode) {
+            return true;
+        }
+        self.excluded += 1;
+        false
+    }
+
+    /// Prints how many records the mode filter left out.
+    pub fn report(&self) {
+        if self.mode != Mode::All {
+            println!("--mode {}: {} records of other modes were excluded.", self.mode.name(), self.excluded);
+        }
+    }
+}
+
+/// The Day_Type and Day_of_Week values kept by `--day-type` and
+/// `--day-of-week`; empty keeps every day.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DaySelection {
+    pub day_types: Vec<String>,
+    pub days_of_week: Vec<String>,
+}
+
+impl DaySelection {
+    /// Reads `--day-type "Normal Weekday"` and `--day-of-week Monday,Tuesday`,
+    /// each a comma-separated list.
+    pub fn from_args(options: &DayOptions) -> DaySelection {
+        let list = |value: &Option<String>| -> Vec<String> {
+            value.as_deref()
+                .map(|value| value.split(',').map(|part| part.trim().to_string()).filter(|part| !part.is_empty()).collect())
+                .unwrap_or_default()
+        };
+        DaySelection { day_types: list(&options.day_type), days_of_week: list(&options.day_of_week) }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.day_types.is_empty() || !self.days_of_week.is_empty()
+    }
+
+    /// How the selection was given, e.g. `--day-type Normal Weekday`.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.day_types.is_empty() {
+            parts.push(format!("--day-type {}", self.day_types.join(",")));
+        }
+        if !self.days_of_week.is_empty() {
+            parts.push(format!("--day-of-week {}", self.days_of_week.join(",")));
+        }
+        if parts.is_empty() { "every day".to_string() } else { parts.join(" ") }
+    }
+}
+
+/// Restricts records to the days in a `DaySelection`, matched ignoring case.
+/// Every Day_Type and Day_of_Week seen is kept, so a selected value the file
+/// doesn't have can be reported with the ones it does.
+#[derive(Debug, Default)]
+pub struct DayFilter {
+    pub selection: DaySelection,
+    pub excluded: u64,
+    seen_types: BTreeSet<String>,
+    seen_days: BTreeSet<String>,
+}
+
+impl DayFilter {
+    pub fn from_args(options: &DayOptions) -> DayFilter {
+        DayFilter { selection: DaySelection::from_args(options), ..DayFilter::default() }
+    }
+
+    /// Returns true if the record's Day_Type and Day_of_Week are selected.
+    pub fn matches(&mut self, day_type: &str, day_of_week: &str) -> bool {
+        if !self.selection.is_active() {
+            return true;
+        }
+        if !self.seen_types.contains(day_type) {
+            self.seen_types.insert(day_type.to_string());
+        }
+        if !self.seen_days.contains(day_of_week) {
+            self.seen_days.insert(day_of_week.to_string());
+        }
+        let selected = |values: &[String], value: &str| {
+            values.is_empty() || values.iter().any(|selected| selected.eq_ignore_ascii_case(value.trim()))
+        };
+        if selected(&self.selection.day_types, day_type) && selected(&self.selection.days_of_week, day_of_week) {
+            return true;
+        }
+        self.excluded += 1;
+        false
+    }
+
+    /// Fails on a selected value no record had, listing the values there are.
+    pub fn check(&self) -> Result<(), String> {
+        let unknown = |flag: &str, values: &[String], seen: &BTreeSet<String>| -> Result<(), String> {
+            for value in values {
+                if !seen.iter().any(|seen| seen.trim().eq_ignore_ascii_case(value)) {
+                    let seen: Vec<&str> = seen.iter().map(|seen| seen.as_str()).collect();
+                    return Err(format!("{}: no records with '{}'; values in the file: {}", flag, value, seen.join(", ")));
+                }
+            }
+            Ok(())
+        };
+        unknown("--day-type", &self.selection.day_types, &self.seen_types)?;
+        unknown("--day-of-week", &self.selection.days_of_week, &self.seen_days)
+    }
+
+    /// Prints how many records the day filter left out.
+    pub fn report(&self) {
+        if self.selection.is_active() {
+            println!("{}: {} records on other days were excluded.", self.selection.describe(), self.excluded);
+        }
+    }
+}
+
+// Minutes in a business day, the end of a range given no `--to`.
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// The time-of-day range given by `--from HH:MM` and `--to HH:MM`, in
+/// minutes after midnight. `--from` is included and `--to` excluded, as with
+/// the peak bands, so 07:00 to 09:30 keeps departures from 07:00:00 to
+/// 09:29:59 and back-to-back ranges never both count a record. Without
+/// `--from` the range starts with the business day, and without `--to` it
+/// runs to the business day's end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+}
+
+impl TimeRange {
+    pub fn from_args(options: &DayOptions) -> Result<TimeRange, Box<dyn Error>> {
+        let range = TimeRange { from: options.from, to: options.to };
+        if range.from.is_some() && range.from == range.to {
+            return Err("--from and --to are the same time, which leaves no records".into());
+        }
+        Ok(range)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+
+    /// Whether a stop at `time` falls in the range. Times are compared by
+    /// their place in the business day from `day_start`, so 22:00 to 02:00
+    /// spans midnight, and a range crossing the day start, such as 02:00
+    /// to 04:00 with the default 03:00, wraps round to it.
+    pub fn contains(&self, time: NaiveTime, day_start: DayStart) -> bool {
+        let business = |minutes: u32| {
+            let clock = NaiveTime::from_hms_opt(minutes / 60, minutes % 60, 0).expect("minutes within a day");
+            day_start.at(clock).minutes
+        };
+        let minutes = day_start.at(time).minutes;
+        let from = self.from.map_or(0, business);
+        // Ending at the day start ends with the business day.
+        let to = match self.to.map(business) {
+            None | Some(0) => MINUTES_PER_DAY,
+            Some(to) => to,
+        };
+        if from < to {
+            minutes >= from && minutes < to
+        } else {
+            minutes >= from || minutes < to
+        }
+    }
+
+    /// The range as given, e.g. `07:00 to 09:30`, `from 22:00` or `until 09:30`.
+    pub fn describe(&self) -> String {
+        let label = |minutes: u32| format!("{:02}:{:02}", minutes / 60, minutes % 60);
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => format!("{} to {}", label(from), label(to)),
+            (Some(from), None) => format!("from {}", label(from)),
+            (None, Some(to)) => format!("until {}", label(to)),
+            (None, None) => "all day".to_string(),
+        }
+    }
+
+    /// `caption` followed by the range when records were filtered to one,
+    /// e.g. `Total Movements by Line (07:00 to 09:30)`.
+    pub fn caption(&self, caption: &str) -> String {
+        if self.is_active() {
+            format!("{} ({})", caption, self.describe())
+        } else {
+            caption.to_string()
+        }
+    }
+}
+
+/// Restricts records to a `TimeRange` by Departure_Time_Scheduled. A
+/// service's last stop has no departure, so its Arrival_Time_Scheduled is
+/// used instead; a record with neither is left out.
+#[derive(Debug, Default)]
+pub struct TimeFilter {
+    pub range: TimeRange,
+    day_start: DayStart,
+    pub excluded: u64,
+}
+
+impl TimeFilter {
+    pub fn from_args(options: &DayOptions, day_start: DayStart) -> Result<TimeFilter, Box<dyn Error>> {
+        Ok(TimeFilter { range: TimeRange::from_args(options)?, day_start, excluded: 0 })
+    }
+
+    /// Returns true if the record departs, or arrives at its last stop,
+    /// within the range.
+    pub fn matches(&mut self, departure_time: &str, arrival_time: &str) -> bool {
+        if !self.range.is_active() {
+            return true;
+        }
+        let time = parse_service_time(departure_time).or_else(|| parse_service_time(arrival_time));
+        if time.is_some_and(|time| self.range.contains(time, self.day_start)) {
+            return true;
+        }
+        self.excluded += 1;
+        false
+    }
+
+    /// Prints how many records the time filter left out.
+    pub fn report(&self) {
+        if self.range.is_active() {
+            println!("Departures {}: {} records at other times were excluded.", self.range.describe(), self.excluded);
+        }
+    }
+}
+
+fn parse_range(value: &str) -> Result<(u64, u64), String> {
+    let invalid = || "expected e.g. 4000-4999".to_string();
+    let (low, high) = value.split_once('-').ok_or_else(invalid)?;
+    let low: u64 = low.trim().parse().map_err(|_| invalid())?;
+    let high: u64 = high.trim().parse().map_err(|_| invalid())?;
+    if low > high {
+        return Err(invalid());
+    }
+    Ok((low, high))
+}
+
+fn parse_mode(value: &str) -> Result<Mode, String> {
+    Mode::parse(value).ok_or_else(|| "expected Metro, V/Line or all".to_string())
+}
+
+/// Minutes after midnight of an `HH:MM` clock time, for `--from` and `--to`.
+fn clock_minutes(value: &str) -> Result<u32, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map(|time| time.hour() * 60 + time.minute())
+        .map_err(|_| "expected HH:MM".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+
+    #[test]
+    fn direction_filter_keeps_one_direction_and_names_its_outputs() {
+        let up = DirectionFilter::parse("U").unwrap();
+        assert!(up.matches("U") && !up.matches("D"));
+        assert_eq!(up.file_name("Pakenham.csv"), "Pakenham_U.csv");
+        assert_eq!(DirectionFilter::parse("D").unwrap().file_name("time_series_chart.png"), "time_series_chart_D.png");
+
+        let both = DirectionFilter::parse("both").unwrap();
+        assert!(both.matches("U") && both.matches("D"));
+        assert_eq!(both.file_name("Pakenham.csv"), "Pakenham.csv");
+
+        for value in ["up", "u", "", "UD"] {
+            assert!(DirectionFilter::parse(value).is_err(), "{} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn metro_excludes_every_vline_record_of_a_mixed_file() {
+        let csv = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+            Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+            Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+            Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load\n\
+            2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+            Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,12,85,410,337\n\
+            2022-09-12,Monday,Normal Weekday,V/Line,8105,Gippsland,Regional,U,Bairnsdale,Southern Cross,\
+            Richmond,-37.82,144.99,2300,20,08:10:00,08:11:00,3,40,220,183\n\
+            2022-09-12,Monday,Normal Weekday,Metro,2040,Frankston,Caulfield,D,Flinders Street,Frankston,\
+            Caulfield,-37.88,145.02,11500,8,17:20:00,17:21:00,30,52,600,578\n\
+            2022-09-12,Monday,Normal Weekday,V/Line,8212,Geelong,Regional,D,Southern Cross,Waurn Ponds,\
+            Footscray,-37.80,144.90,5000,2,17:35:00,17:36:00,25,4,310,331\n";
+        let records = crate::read_all(csv.as_bytes()).unwrap();
+        let options = |mode: &str| -> ServiceOptions { parse_group(&["--mode", mode]).unwrap() };
+
+        let mut metro = ModeFilter::from_args(&options("metro"));
+        let kept: Vec<&crate::Record> = records.iter().filter(|record| metro.matches(&record.mode)).collect();
+        assert_eq!(kept.iter().map(|record| record.line_name.as_str()).collect::<Vec<_>>(), vec!["Pakenham", "Frankston"]);
+        assert!(kept.iter().all(|record| record.mode == "Metro"));
+        assert_eq!(metro.excluded, 2);
+
+        // `all` is the default and keeps every record.
+        let mut all = ModeFilter::from_args(&options("all"));
+        assert_eq!(records.iter().filter(|record| all.matches(&record.mode)).count(), records.len());
+        assert_eq!(all.excluded, 0);
+    }
+
+    #[test]
+    fn day_filter_keeps_selected_days_and_names_unknown_values() {
+        let options: DayOptions = parse_group(&["--day-type", "normal weekday", "--day-of-week", "Monday,Friday"]).unwrap();
+        let mut filter = DayFilter::from_args(&options);
+        assert!(filter.matches("Normal Weekday", "Monday"));
+        assert!(!filter.matches("Normal Weekday", "Tuesday"));
+        assert!(!filter.matches("Public Holiday", "Friday"));
+        assert_eq!(filter.excluded, 2);
+        // Friday is in the file, if only on a holiday, so isn't unknown.
+        assert!(filter.check().is_ok());
+
+        let mut unknown = DayFilter::from_args(&parse_group(&["--day-type", "Weekday"]).unwrap());
+        unknown.matches("Normal Weekday", "Monday");
+        unknown.matches("Saturday", "Saturday");
+        assert_eq!(
+            unknown.check().unwrap_err(),
+            "--day-type: no records with 'Weekday'; values in the file: Normal Weekday, Saturday"
+        );
+    }
+
+    #[test]
+    fn time_range_includes_from_and_excludes_to_across_midnight() {
+        let options = |args: &[&str]| -> DayOptions { parse_group(args).unwrap() };
+        let at = |hour, minute, second| NaiveTime::from_hms_opt(hour, minute, second).unwrap();
+        let start = DayStart::default();
+
+        let am = TimeRange::from_args(&options(&["--from", "07:00", "--to", "09:30"])).unwrap();
+        assert!(am.contains(at(7, 0, 0), start));
+        assert!(am.contains(at(9, 29, 59), start));
+        assert!(!am.contains(at(9, 30, 0), start));
+        assert!(!am.contains(at(6, 59, 59), start));
+        assert_eq!(am.caption("Total Movements by Line"), "Total Movements by Line (07:00 to 09:30)");
+
+        // 22:00 to 02:00 spans midnight within one business day.
+        let night = TimeRange::from_args(&options(&["--from", "22:00", "--to", "02:00"])).unwrap();
+        assert!(night.contains(at(23, 30, 0), start) && night.contains(at(1, 59, 0), start));
+        assert!(!night.contains(at(2, 0, 0), start) && !night.contains(at(12, 0, 0), start));
+
+        // One-sided ranges run to the end, or from the start, of the business day.
+        let late = TimeRange::from_args(&options(&["--from", "22:00"])).unwrap();
+        assert!(late.contains(at(2, 59, 59), start) && !late.contains(at(3, 0, 0), start));
+        let early = TimeRange::from_args(&options(&["--to", "06:00"])).unwrap();
+        assert!(early.contains(at(3, 0, 0), start) && !early.contains(at(2, 59, 0), start));
+        assert_eq!(early.describe(), "until 06:00");
+
+        // Departures past 24:00 and last stops' arrivals count too.
+        let mut filter = TimeFilter::from_args(&options(&["--from", "22:00", "--to", "02:00"]), start).unwrap();
+        assert!(filter.matches("25:10:00", ""));
+        assert!(filter.matches("", "23:45:00"));
+        assert!(!filter.matches("", ""));
+        assert!(!filter.matches("08:00:00", "07:59:00"));
+        assert_eq!(filter.excluded, 2);
+
+        assert!(TimeRange::from_args(&options(&["--from", "07:00", "--to", "07:00"])).is_err());
+        assert!(parse_group::<DayOptions>(&["--from", "7am"]).is_err());
+        assert!(!TimeRange::from_args(&options(&[])).unwrap().is_active());
+    }
+
+    #[test]
+    fn mode_names_match_ignoring_case_and_slash() {
+        for value in ["V/Line", "vline", "VLINE", "v-line"] {
+            assert_eq!(Mode::parse(value), Some(Mode::VLine), "{}", value);
+        }
+        assert_eq!(Mode::parse("Metro"), Some(Mode::Metro));
+        assert_eq!(Mode::parse("ALL"), Some(Mode::All));
+        assert_eq!(Mode::parse("tram"), None);
+        assert_eq!(Mode::VLine.caption("Total Movements by Line"), "V/Line: Total Movements by Line");
+        assert_eq!(Mode::All.caption("Total Movements by Line"), "Total Movements by Line");
+
+        let mut metro = ModeFilter { mode: Mode::Metro, excluded: 0 };
+        assert!(metro.matches("Metro"));
+        assert!(!metro.matches("V/Line"));
+        assert_eq!(metro.excluded, 1);
+    }
+}
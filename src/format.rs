@@ -0,0 +1,98 @@
+// Numeric formatting shared by every CSV writer, so all outputs honour
+// `--csv-float-format` the same way.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatFormat {
+    // Plain decimal notation, e.g. `1234567.00`. No thousands separators.
+    Fixed,
+    // Scientific notation, e.g. `1.23e6`.
+    Scientific,
+}
+
+impl FloatFormat {
+    /// Parses a `--csv-float-format` value, which defaults to fixed.
+    pub fn parse(value: &str) -> Result<FloatFormat, String> {
+        match value.to_lowercase().as_str() {
+            "fixed" => Ok(FloatFormat::Fixed),
+            "scientific" | "sci" => Ok(FloatFormat::Scientific),
+            _ => Err("expected fixed or scientific".to_string()),
+        }
+    }
+
+    /// Formats `value` with `precision` digits after the decimal point.
+    pub fn format(self, value: f64, precision: usize) -> String {
+        match self {
+            FloatFormat::Fixed => format!("{:.*}", precision, value),
+            FloatFormat::Scientific => format!("{:.*e}", precision, value),
+        }
+    }
+}
+
+/// Formats the integer value labels drawn on charts. With `--thousands-sep`
+/// digits are grouped in threes, e.g. `1,234,567`; off by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LabelFormat {
+    pub thousands_sep: bool,
+}
+
+impl LabelFormat {
+    pub fn format(self, value: i64) -> String {
+        if !self.thousands_sep {
+            return value.to_string();
+        }
+        let digits = value.unsigned_abs().to_string();
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+        if value < 0 {
+            grouped.push('-');
+        }
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+}
+
+/// A text value as a CSV field: quoted, with quotes doubled, when it holds a
+/// comma, quote or line break, so names like `Jolimont-MCG, "Gate 3"` stay
+/// in one column.
+pub fn text_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_are_written_exactly() {
+        assert_eq!(FloatFormat::Fixed.format(1234.5678, 2), "1234.57");
+        assert_eq!(FloatFormat::Fixed.format(1234.5678, 0), "1235");
+        assert_eq!(FloatFormat::Scientific.format(1234.5678, 2), "1.23e3");
+        assert_eq!(FloatFormat::Scientific.format(0.0, 2), "0.00e0");
+        assert_eq!(FloatFormat::parse("SCI").unwrap(), FloatFormat::Scientific);
+        assert!(FloatFormat::parse("engineering").is_err());
+    }
+
+    #[test]
+    fn labels_group_thousands_only_when_asked() {
+        assert_eq!(LabelFormat { thousands_sep: false }.format(1234567), "1234567");
+        assert_eq!(LabelFormat { thousands_sep: true }.format(1234567), "1,234,567");
+        assert_eq!(LabelFormat { thousands_sep: true }.format(-1234), "-1,234");
+        assert_eq!(LabelFormat { thousands_sep: true }.format(999), "999");
+    }
+
+    #[test]
+    fn text_fields_are_quoted_when_they_need_it() {
+        assert_eq!(text_field("Flinders Street"), "Flinders Street");
+        assert_eq!(text_field("Jolimont-MCG, Gate 3"), "\"Jolimont-MCG, Gate 3\"");
+        assert_eq!(text_field("Jolimont-MCG, \"Gate 3\""), "\"Jolimont-MCG, \"\"Gate 3\"\"\"");
+        assert_eq!(text_field("Line\nbreak"), "\"Line\nbreak\"");
+    }
+}
@@ -0,0 +1,154 @@
+// Station groupings (LGAs, planning corridors, precincts...) loaded from a
+// user-supplied `Station,Group` CSV, used to roll station totals up to groups.
+
+use csv::Reader;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+
+use crate::stations::{StationInfo, normalize_station};
+
+/// Group collecting every station missing from the mapping.
+pub const UNMAPPED: &str = "Unmapped";
+
+#[derive(Debug, Default)]
+pub struct StationGroups {
+    // Normalized station name -> group.
+    by_station: HashMap<String, String>,
+}
+
+/// One group's totals across its stations.
+#[derive(Debug, Default)]
+pub struct GroupTotals {
+    pub stations: BTreeSet<String>,
+    pub movements: i64,
+    pub hourly: [i64; 24],
+}
+
+impl StationGroups {
+    /// Reads a CSV with `Station` and `Group` columns. Station names are
+    /// matched after normalization, so "Flinders Street" and "flinders
+    /// street " are the same station. Listing a station twice under the same
+    /// group is allowed; listing it under two groups is an error naming
+    /// every such station.
+    pub fn load(path: &str) -> Result<StationGroups, Box<dyn Error>> {
+        let file = File::open(path).map_err(|err| format!("cannot read --station-groups '{}': {}", path, err))?;
+        let mut rdr = Reader::from_reader(file);
+        let headers = rdr.headers()?.clone();
+        let column = |name: &str| {
+            headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("{}: missing '{}' column", path, name))
+        };
+        let (station_col, group_col) = (column("Station")?, column("Group")?);
+
+        let mut groups = StationGroups::default();
+        let mut conflicts: Vec<String> = Vec::new();
+        for (row, result) in rdr.records().enumerate() {
+            let record = result?;
+            let station = record.get(station_col).unwrap_or("").trim();
+            let group = record.get(group_col).unwrap_or("").trim();
+            if station.is_empty() || group.is_empty() {
+                return Err(format!("{} row {}: station and group must both be given", path, row + 1).into());
+            }
+            match groups.by_station.get(&normalize_station(station)) {
+                Some(existing) if existing != group => conflicts.push(format!(
+                    "{} is in both '{}' and '{}' (row {})",
+                    station, existing, group, row + 1
+                )),
+                Some(_) => {}
+                None => {
+                    groups.by_station.insert(normalize_station(station), group.to_string());
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(format!("{}: conflicting station groups:\n  {}", path, conflicts.join("\n  ")).into());
+        }
+        Ok(groups)
+    }
+
+    pub fn group_of(&self, station: &str) -> Option<&str> {
+        self.by_station.get(&normalize_station(station)).map(|group| group.as_str())
+    }
+
+    /// Sums station totals and hourly profiles per group. Stations missing
+    /// from the mapping go into `UNMAPPED`.
+    pub fn roll_up(&self, stations: &BTreeMap<String, StationInfo>) -> BTreeMap<String, GroupTotals> {
+        let mut totals: BTreeMap<String, GroupTotals> = BTreeMap::new();
+        for (station, info) in stations {
+            let group = self.group_of(station).unwrap_or(UNMAPPED);
+            let entry = totals.entry(group.to_string()).or_default();
+            entry.stations.insert(station.clone());
+            entry.movements += info.total_movements();
+            for (hour, movements) in info.hourly.iter().enumerate() {
+                entry.hourly[hour] += movements;
+            }
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `contents` to a temp file named after `name` and loads it.
+    fn load(name: &str, contents: &str) -> Result<StationGroups, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("ptv_data_groups_{}_{}.csv", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let groups = StationGroups::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        groups
+    }
+
+    fn station(boardings: i64) -> StationInfo {
+        StationInfo { boardings, ..StationInfo::default() }
+    }
+
+    #[test]
+    fn a_valid_file_loads() {
+        let groups = load("valid", "Station,Group\nFlinders Street,Melbourne\nRichmond,Yarra\nSouthern Cross,Melbourne\n").unwrap();
+        assert_eq!(groups.group_of("Flinders Street"), Some("Melbourne"));
+        assert_eq!(groups.group_of(" flinders  STREET"), Some("Melbourne"));
+        assert_eq!(groups.group_of("Richmond"), Some("Yarra"));
+        assert_eq!(groups.group_of("Parliament"), None);
+    }
+
+    #[test]
+    fn a_station_listed_twice_in_one_group_is_counted_once() {
+        let groups = load("twice", "Station,Group\nRichmond,Yarra\nrichmond ,Yarra\n").unwrap();
+        assert_eq!(groups.group_of("Richmond"), Some("Yarra"));
+
+        let stations = BTreeMap::from([("Richmond".to_string(), station(100))]);
+        let totals = groups.roll_up(&stations);
+        assert_eq!(totals["Yarra"].stations.len(), 1);
+        assert_eq!(totals["Yarra"].movements, 100);
+    }
+
+    #[test]
+    fn a_station_in_two_groups_is_an_error() {
+        let err = load("conflict", "Station,Group\nRichmond,Yarra\nSouth Yarra,Stonnington\nRichmond,Melbourne\n").unwrap_err().to_string();
+        assert!(err.contains("conflicting station groups"), "{}", err);
+        assert!(err.contains("Richmond is in both 'Yarra' and 'Melbourne' (row 3)"), "{}", err);
+    }
+
+    #[test]
+    fn a_group_named_after_a_station_stays_a_group() {
+        // "Richmond" the group holds other stations; Richmond the station is
+        // in "Yarra". Each rolls up by its own mapping.
+        let groups = load("collision", "Station,Group\nRichmond,Yarra\nEast Richmond,Richmond\nBurnley,Richmond\n").unwrap();
+        assert_eq!(groups.group_of("Richmond"), Some("Yarra"));
+
+        let stations = BTreeMap::from([
+            ("Richmond".to_string(), station(100)),
+            ("East Richmond".to_string(), station(20)),
+            ("Burnley".to_string(), station(10)),
+        ]);
+        let totals = groups.roll_up(&stations);
+        assert_eq!(totals["Yarra"].movements, 100);
+        assert_eq!(totals["Richmond"].movements, 30);
+        assert_eq!(totals["Richmond"].stations.iter().collect::<Vec<_>>(), ["Burnley", "East Richmond"]);
+        assert!(!totals.contains_key(UNMAPPED));
+    }
+}
@@ -0,0 +1,447 @@
+// Compact binary copy of data.csv, written by the `index` binary, so that
+// repeated runs skip CSV parsing. Every distinct field value is interned
+// into one string table and each row is stored as indexes into it. The
+// index records a hash of the CSV it was built from; binaries given
+// `--from-index <path>` check it against data.csv and refuse a stale index.
+
+use csv::{Reader, StringRecord};
+use serde::de::DeserializeOwned;
+use crate::record::{decompressed, open_input};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+use clap::Args;
+
+use crate::cli::{Input, input_error};
+use crate::progress::{BytesRead, CountingReader, Progress};
+
+// File header: magic bytes followed by a little-endian format version.
+const MAGIC: &[u8; 6] = b"PTVIDX";
+const FORMAT_VERSION: u32 = 1;
+
+/// Everything stored ahead of the rows.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    // FNV-1a hash and length in bytes of the CSV the index was built from.
+    pub source_hash: u64,
+    pub source_len: u64,
+    pub rows: u64,
+    pub headers: Vec<String>,
+    // Distinct Business_Date and Line_Name values, sorted.
+    pub dates: Vec<String>,
+    pub lines: Vec<String>,
+    pub strings: Vec<String>,
+}
+
+// Each row is one LEB128 varint string id per column, so the common values
+// (ids under 128) take a single byte.
+fn write_id<W: Write>(writer: &mut W, mut id: u32) -> io::Result<()> {
+    while id >= 0x80 {
+        writer.write_all(&[(id as u8 & 0x7f) | 0x80])?;
+        id >>= 7;
+    }
+    writer.write_all(&[id as u8])
+}
+
+fn read_id<R: BufRead>(reader: &mut R) -> io::Result<u32> {
+    let mut id = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = match reader.fill_buf()?.first() {
+            Some(&byte) => byte,
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+        reader.consume(1);
+        id |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(id);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "string id too long"))
+}
+
+/// Hashes everything read through it with 64-bit FNV-1a, which unlike the
+/// standard library's hasher is fixed across Rust versions.
+struct HashingReader<R> {
+    inner: R,
+    hash: u64,
+    len: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> HashingReader<R> {
+        HashingReader { inner, hash: 0xcbf2_9ce4_8422_2325, len: 0 }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for &byte in &buf[..n] {
+            self.hash = (self.hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// Hash and length of a file's contents, as stored in the manifest.
+fn hash_file(path: &str) -> io::Result<(u64, u64)> {
+    let mut reader = HashingReader::new(BufReader::new(File::open(path)?));
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok((reader.hash, reader.len))
+}
+
+/// Builds an index of `csv_path` at `index_path`. The first pass hashes the
+/// CSV and interns every field; the second writes the rows.
+pub fn build(csv_path: &str, index_path: &str, no_progress: bool) -> Result<Manifest, Box<dyn Error>> {
+    let file = File::open(csv_path).map_err(|err| input_error(csv_path, err))?;
+    let mut hashing = HashingReader::new(BufReader::new(file));
+    let mut manifest = Manifest::default();
+    let mut interned: HashMap<String, u32> = HashMap::new();
+    let mut dates: BTreeSet<String> = BTreeSet::new();
+    let mut lines: BTreeSet<String> = BTreeSet::new();
+    {
+        // The hash is of the file as stored, compressed or not.
+        let mut rdr = Reader::from_reader(decompressed(&mut hashing)?);
+        let headers = rdr.headers()?.clone();
+        let date_col = headers.iter().position(|h| h == "Business_Date");
+        let line_col = headers.iter().position(|h| h == "Line_Name");
+        manifest.headers = headers.iter().map(|h| h.to_string()).collect();
+
+        for (row, result) in rdr.records().enumerate() {
+            let record = result.map_err(|err| format!("row {}: {}", row + 1, err))?;
+            for value in record.iter() {
+                if !interned.contains_key(value) {
+                    interned.insert(value.to_string(), manifest.strings.len() as u32);
+                    manifest.strings.push(value.to_string());
+                }
+            }
+            if let Some(date) = date_col.and_then(|col| record.get(col)) {
+                if !dates.contains(date) {
+                    dates.insert(date.to_string());
+                }
+            }
+            if let Some(line) = line_col.and_then(|col| record.get(col)) {
+                if !lines.contains(line) {
+                    lines.insert(line.to_string());
+                }
+            }
+            manifest.rows += 1;
+        }
+    }
+    manifest.source_hash = hashing.hash;
+    manifest.source_len = hashing.len;
+    manifest.dates = dates.into_iter().collect();
+    manifest.lines = lines.into_iter().collect();
+
+    let mut writer = BufWriter::new(File::create(index_path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut writer, &manifest)?;
+
+    let mut pb = Progress::new(manifest.rows, no_progress);
+    let mut rdr = Reader::from_reader(open_input(csv_path)?);
+    for result in rdr.records() {
+        for value in result?.iter() {
+            write_id(&mut writer, interned[value])?;
+        }
+        pb.inc(1);
+    }
+    pb.finish("Index written.");
+    writer.flush()?;
+    Ok(manifest)
+}
+
+struct IndexReader {
+    reader: BufReader<File>,
+    remaining: u64,
+    strings: Vec<String>,
+    // The current row, reused so reading allocates nothing per row.
+    record: StringRecord,
+}
+
+impl IndexReader {
+    /// Reads the next row into `record`; `None` after the last row.
+    fn next_record(&mut self) -> Option<Result<(), csv::Error>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let columns = self.record.len();
+        self.record.clear();
+        for _ in 0..columns {
+            let value = read_id(&mut self.reader).and_then(|id| {
+                self.strings.get(id as usize).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("string id {} out of range", id))
+                })
+            });
+            match value {
+                Ok(value) => self.record.push_field(value),
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+        Some(Ok(()))
+    }
+}
+
+enum Rows {
+    // The rows of the input CSVs as they're read, one file after another,
+    // with the files' total size and the bytes of them read so far; a
+    // gzipped file counts its compressed bytes.
+    Csv {
+        records: Box<dyn Iterator<Item = Result<StringRecord, csv::Error>>>,
+        len: u64,
+        read: BytesRead,
+    },
+    Index(IndexReader),
+}
+
+/// The CSVs to read: every `--input` value, each either a path or a glob
+/// such as `exports/2023-*.csv`, in sorted order; data.csv without any.
+/// A glob matching no files is an error.
+fn input_files(input: &Input) -> Result<Vec<String>, Box<dyn Error>> {
+    let patterns = match input.paths.as_slice() {
+        [] => vec![input.path()],
+        paths => paths.iter().map(String::as_str).collect(),
+    };
+    let mut files = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            files.push(pattern.to_string());
+            continue;
+        }
+        let matched = glob::glob(pattern)
+            .map_err(|err| format!("invalid --input pattern '{}': {}", pattern, err))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if matched.is_empty() {
+            return Err(format!("--input '{}' matches no files", pattern).into());
+        }
+        files.extend(matched.into_iter().map(|path| path.to_string_lossy().into_owned()));
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Opens one input CSV, adding the bytes read from it to `read`.
+fn open_csv(path: &str, read: &BytesRead) -> Result<(Reader<Box<dyn Read>>, u64), Box<dyn Error>> {
+    let file = File::open(path).map_err(|err| input_error(path, err))?;
+    let len = file.metadata().map_or(0, |metadata| metadata.len());
+    let counting = CountingReader::new(file, read.clone());
+    Ok((Reader::from_reader(decompressed(counting)?), len))
+}
+
+/// `--input`, `--from-index` and `--no-progress`: where the commands that
+/// read records through a `RecordSource` take them from.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Source {
+    #[command(flatten)]
+    pub input: Input,
+    /// Read the rows from an index built by `index` instead of parsing the CSV
+    #[arg(long, value_name = "PATH")]
+    pub from_index: Option<String>,
+    /// Don't report progress while reading
+    #[arg(long)]
+    pub no_progress: bool,
+}
+
+/// The records of data.csv, read from the CSV itself or, with
+/// `--from-index`, from an index built from it.
+pub struct RecordSource {
+    headers: StringRecord,
+    rows: Rows,
+}
+
+impl RecordSource {
+    /// Opens the file given by `--input`, or every file when it's given more
+    /// than once or as a glob, or the index given by `--from-index`.
+    /// Several CSVs are read in sorted order as if they were one, and must
+    /// share a header. The CSVs are streamed in a single pass, never held
+    /// in memory. An index with the wrong header or format version, or
+    /// built from a CSV that no longer matches the one input file, is an
+    /// error.
+    pub fn open(source: &Source) -> Result<RecordSource, Box<dyn Error>> {
+        let files = input_files(&source.input)?;
+        let Some(index_path) = source.from_index.as_deref() else {
+            let read = BytesRead::default();
+            let mut readers = Vec::new();
+            let mut headers: Option<StringRecord> = None;
+            let mut len = 0;
+            for path in &files {
+                let (mut rdr, file_len) = open_csv(path, &read)?;
+                let file_headers = rdr.headers()?;
+                match &headers {
+                    Some(first) if first != file_headers => {
+                        return Err(format!("'{}' has different columns from '{}'", path, files[0]).into());
+                    }
+                    Some(_) => {}
+                    None => headers = Some(file_headers.clone()),
+                }
+                readers.push(rdr);
+                len += file_len;
+            }
+            if files.len() > 1 {
+                println!("Reading {} input files: {}.", files.len(), files.join(", "));
+            }
+            let records = Box::new(readers.into_iter().flat_map(|rdr| rdr.into_records()));
+            let headers = headers.unwrap_or_default();
+            return Ok(RecordSource { headers, rows: Rows::Csv { records, len, read } });
+        };
+        if files.len() > 1 {
+            return Err("--from-index reads an index of a single CSV; pass one --input".into());
+        }
+        // The CSV the index is checked against, e.g. the one file a glob matched.
+        let source = &files[0];
+
+        let mut reader = BufReader::new(File::open(index_path)
+            .map_err(|err| format!("cannot read --from-index '{}': {}", index_path, err))?);
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(format!("'{}' is not an index file", index_path).into());
+        }
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "'{}' uses index format version {}, expected {}; rebuild it with `index`",
+                index_path, version, FORMAT_VERSION
+            ).into());
+        }
+        let manifest: Manifest = bincode::deserialize_from(&mut reader)?;
+
+        let (hash, len) = hash_file(source)
+            .map_err(|err| format!("cannot check '{}' against '{}': {}", index_path, source, err))?;
+        if (hash, len) != (manifest.source_hash, manifest.source_len) {
+            return Err(format!(
+                "'{}' was built from a different '{}' (hash {:016x}, now {:016x}); rebuild it with `index`",
+                index_path, source, manifest.source_hash, hash
+            ).into());
+        }
+
+        println!("Reading {} rows from index '{}'.", manifest.rows, index_path);
+        let headers = StringRecord::from(manifest.headers);
+        let record = headers.clone();
+        Ok(RecordSource {
+            headers,
+            rows: Rows::Index(IndexReader { reader, remaining: manifest.rows, strings: manifest.strings, record }),
+        })
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    /// A progress display for reading the rows: by bytes of the CSV read
+    /// against its size, or by rows of an index. Call `inc(1)` per row.
+    pub fn progress(&self, no_progress: bool) -> Progress {
+        match &self.rows {
+            Rows::Csv { len, read, .. } => Progress::bytes(*len, read.clone(), no_progress),
+            Rows::Index(index) => Progress::new(index.remaining, no_progress),
+        }
+    }
+
+    /// Each row as read, not yet deserialized, for callers that deserialize
+    /// batches of rows in parallel. Rows from an index are copied out.
+    pub fn records(&mut self) -> Box<dyn Iterator<Item = Result<StringRecord, csv::Error>> + '_> {
+        match &mut self.rows {
+            Rows::Csv { records, .. } => Box::new(records),
+            Rows::Index(index) => Box::new(std::iter::from_fn(move || {
+                let result = index.next_record()?;
+                Some(result.map(|_| index.record.clone()))
+            })),
+        }
+    }
+
+    /// Deserializes each row into `T`, the same way `csv::Reader::deserialize`
+    /// does, so row and field errors read the same from either source.
+    pub fn deserialize<'a, T: DeserializeOwned + 'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<T, csv::Error>> + 'a> {
+        let headers = self.headers.clone();
+        match self.rows {
+            Rows::Csv { .. } => Box::new(self.records().map(move |result| result.and_then(|record| record.deserialize(Some(&headers))))),
+            // Deserialized in place, without copying each row out.
+            Rows::Index(ref mut index) => {
+                Box::new(std::iter::from_fn(move || {
+                    let result = index.next_record()?;
+                    Some(result.and_then(|_| index.record.deserialize(Some(&headers))))
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Record;
+    use crate::cli::parse_group;
+
+    const HEADER: &str = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+        Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+        Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+        Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+
+    fn export(business_date: &str) -> String {
+        format!(
+            "{}\n{},Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,12,85,410,337\n",
+            HEADER, business_date
+        )
+    }
+
+    #[test]
+    fn several_inputs_read_as_one_in_sorted_order() {
+        let dir = std::env::temp_dir().join(format!("ptv_data_inputs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for month in ["2023-02", "2023-01", "2023-03"] {
+            std::fs::write(dir.join(format!("{}.csv", month)), export(&format!("{}-01", month))).unwrap();
+        }
+        let pattern = dir.join("2023-0[12].csv");
+        let third = dir.join("2023-03.csv");
+        let inputs: Source = parse_group(&["--input", pattern.to_str().unwrap(), "--input", third.to_str().unwrap()]).unwrap();
+
+        let mut source = RecordSource::open(&inputs).unwrap();
+        let dates: Vec<String> = source.deserialize::<Record>()
+            .map(|record| record.unwrap().business_date)
+            .collect();
+        assert_eq!(dates, ["2023-01-01", "2023-02-01", "2023-03-01"]);
+
+        // Inputs must share a header.
+        std::fs::write(&third, export("2023-03-01").replacen("Mode,", "Transport_Mode,", 1)).unwrap();
+        let err = RecordSource::open(&inputs).err().unwrap().to_string();
+        assert!(err.contains("has different columns"), "{}", err);
+
+        let unmatched = dir.join("2024-*.csv");
+        let inputs: Source = parse_group(&["--input", unmatched.to_str().unwrap()]).unwrap();
+        let err = RecordSource::open(&inputs).err().unwrap().to_string();
+        assert!(err.ends_with("matches no files"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn an_index_is_checked_against_the_file_a_glob_matched() {
+        let dir = std::env::temp_dir().join(format!("ptv_data_index_glob_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("2023-01.csv");
+        let index = dir.join("index.bin");
+        std::fs::write(&csv, export("2023-01-01")).unwrap();
+        build(csv.to_str().unwrap(), index.to_str().unwrap(), true).unwrap();
+
+        let pattern = dir.join("2023-*.csv");
+        let inputs: Source = parse_group(&["--input", pattern.to_str().unwrap(), "--from-index", index.to_str().unwrap()]).unwrap();
+        let mut source = RecordSource::open(&inputs).unwrap();
+        let dates: Vec<String> = source.deserialize::<Record>()
+            .map(|record| record.unwrap().business_date)
+            .collect();
+        assert_eq!(dates, ["2023-01-01"]);
+
+        // A changed CSV no longer matches the index.
+        std::fs::write(&csv, export("2023-01-02")).unwrap();
+        let err = RecordSource::open(&inputs).err().unwrap().to_string();
+        assert!(err.contains("was built from a different"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,519 @@
+// Named data-quality checks run against every record, each mapped to a
+// policy: warn and keep the row, skip the row, or abort the run. Policies
+// come from `--issue-config <path>` and `--on-issue <check>=<policy>` (the
+// flag wins), and every issue found is tallied for the run summary. Rows
+// that can't be read at all are skipped and counted too, unless `--strict`.
+// `--dedupe` skips rows repeating an earlier row's key, as overlapping
+// monthly exports do; it's short for `--on-issue duplicate-key=skip-row`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+use std::fs;
+use std::hash::{Hash, Hasher};
+
+use clap::Args;
+use csv::StringRecord;
+use crate::record::in_victoria;
+
+use crate::error::{RecordError, RowContext};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Check {
+    // A boardings, alightings or load count below zero.
+    NegativeCount,
+    // A scheduled time past 23:59:59, e.g. "25:10:00" for an after-midnight
+    // stop written against the previous day.
+    TimeOver24h,
+    // A service's chainage moving against its direction between stops.
+    ChainageReversal,
+    // More than one record for the same date, train and stop sequence.
+    DuplicateKey,
+    // Station coordinates that parse but fall far outside Victoria.
+    OutsideVictoria,
+}
+
+pub const CHECKS: [Check; 5] = [
+    Check::NegativeCount,
+    Check::TimeOver24h,
+    Check::ChainageReversal,
+    Check::DuplicateKey,
+    Check::OutsideVictoria,
+];
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::NegativeCount => "negative-count",
+            Check::TimeOver24h => "time-over-24h",
+            Check::ChainageReversal => "chainage-reversal",
+            Check::DuplicateKey => "duplicate-key",
+            Check::OutsideVictoria => "outside-victoria",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Check, String> {
+        CHECKS.iter().copied().find(|check| check.name() == name).ok_or_else(|| {
+            let known: Vec<&str> = CHECKS.iter().map(|check| check.name()).collect();
+            format!("unknown check '{}', expected one of {}", name, known.join(", "))
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    Warn,
+    SkipRow,
+    Error,
+}
+
+impl Policy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Warn => "warn",
+            Policy::SkipRow => "skip-row",
+            Policy::Error => "error",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Policy, String> {
+        match value {
+            "warn" => Ok(Policy::Warn),
+            "skip-row" => Ok(Policy::SkipRow),
+            "error" => Ok(Policy::Error),
+            other => Err(format!("unknown policy '{}', expected warn, skip-row or error", other)),
+        }
+    }
+}
+
+/// Parses a `check=policy` assignment, from `--on-issue` or a line of
+/// `--issue-config`.
+fn parse_assignment(assignment: &str) -> Result<(Check, Policy), String> {
+    let (check, policy) = assignment.split_once('=')
+        .ok_or("expected <check>=warn|skip-row|error")?;
+    Ok((Check::parse(check.trim())?, Policy::parse(policy.trim())?))
+}
+
+/// `--strict`, `--dedupe`, `--issue-config` and `--on-issue`: how a run
+/// treats rows that fail a check or can't be read.
+#[derive(Args, Debug, Clone, Default)]
+pub struct IssueOptions {
+    /// Stop at the first row that can't be read instead of skipping it
+    #[arg(long)]
+    pub strict: bool,
+    /// Skip rows repeating an earlier row's date, train and stop sequence
+    #[arg(long)]
+    pub dedupe: bool,
+    /// File of `check = policy` lines
+    #[arg(long, value_name = "PATH")]
+    pub issue_config: Option<String>,
+    /// Policy for a check, e.g. negative-count=skip-row; repeatable, and wins over --issue-config
+    #[arg(long, value_name = "CHECK=POLICY", value_parser = parse_assignment)]
+    pub on_issue: Vec<(Check, Policy)>,
+}
+
+/// The fields of a record the checks look at.
+pub struct RowFields<'a> {
+    pub business_date: &'a str,
+    pub train_number: &'a str,
+    pub direction: &'a str,
+    pub stop_sequence: i32,
+    pub chainage: Option<i32>,
+    pub arrival_time: &'a str,
+    pub departure_time: &'a str,
+    // Station latitude and longitude, when both parse.
+    pub coordinates: Option<(f64, f64)>,
+    // Boardings, alightings, arrival load and departure load.
+    pub counts: [i32; 4],
+}
+
+const COUNT_FIELDS: [&str; 4] = [
+    "Passenger_Boardings",
+    "Passenger_Alightings",
+    "Passenger_Arrival_Load",
+    "Passenger_Departure_Load",
+];
+
+// The previous record's service and position, for chainage reversals.
+#[derive(Default)]
+struct LastStop {
+    business_date: String,
+    train_number: String,
+    stop_sequence: i32,
+    chainage: Option<i32>,
+}
+
+pub struct IssueTracker {
+    policies: [Policy; 5],
+    counts: [u64; 5],
+    // Hashes of (date, train, stop sequence) keys seen so far, kept as u64s
+    // so the set stays small over the full dataset.
+    seen_keys: HashSet<u64>,
+    last_stop: Option<LastStop>,
+    // --strict: a malformed row aborts the run instead of being skipped.
+    strict: bool,
+    malformed: u64,
+    // The first malformed row's error, shown in the summary.
+    first_malformed: Option<RecordError>,
+    // Every business date inspected, for the coverage in the summary.
+    dates: BTreeSet<String>,
+}
+
+impl IssueTracker {
+    /// Every check defaults to `warn`, which keeps the previous behaviour of
+    /// aggregating the row regardless.
+    pub fn new() -> IssueTracker {
+        IssueTracker {
+            policies: [Policy::Warn; 5],
+            counts: [0; 5],
+            seen_keys: HashSet::new(),
+            last_stop: None,
+            strict: false,
+            malformed: 0,
+            first_malformed: None,
+            dates: BTreeSet::new(),
+        }
+    }
+
+    /// Reads `--dedupe`, then policies from `--issue-config` (one
+    /// `check = policy` per line, `#` starts a comment) and then from each
+    /// `--on-issue check=policy`. Unknown check or policy names are an error.
+    pub fn from_args(options: &IssueOptions) -> Result<IssueTracker, Box<dyn Error>> {
+        let mut tracker = IssueTracker::new();
+        tracker.strict = options.strict;
+        if options.dedupe {
+            tracker.policies[Check::DuplicateKey as usize] = Policy::SkipRow;
+        }
+        if let Some(path) = &options.issue_config {
+            let contents = fs::read_to_string(path)
+                .map_err(|err| format!("cannot read --issue-config '{}': {}", path, err))?;
+            for (number, line) in contents.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or("").trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let (check, policy) = parse_assignment(line)
+                    .map_err(|err| format!("{} line {}: {}", path, number + 1, err))?;
+                tracker.policies[check as usize] = policy;
+            }
+        }
+        for &(check, policy) in &options.on_issue {
+            tracker.policies[check as usize] = policy;
+        }
+        Ok(tracker)
+    }
+
+    pub fn policy(&self, check: Check) -> Policy {
+        self.policies[check as usize]
+    }
+
+    pub fn count(&self, check: Check) -> u64 {
+        self.counts[check as usize]
+    }
+
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Unwraps a row read from the input. A row that doesn't parse, such as
+    /// a non-number in a count or a truncated line, is counted and `None`
+    /// returned so the caller skips it; with `--strict` it's an error.
+    pub fn read<T>(&mut self, row: u64, result: Result<T, csv::Error>, headers: &StringRecord) -> Result<Option<T>, RecordError> {
+        match result.with_row(row, headers) {
+            Ok(record) => Ok(Some(record)),
+            Err(err) if self.strict => Err(err),
+            Err(err) => {
+                self.malformed += 1;
+                self.first_malformed.get_or_insert(err);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Runs every check against a record. Returns `Ok(true)` if the row
+    /// should be left out of the aggregation, or an error if a check with
+    /// the `error` policy failed. All issues on the row are tallied first.
+    pub fn inspect(&mut self, row: u64, fields: &RowFields) -> Result<bool, RecordError> {
+        let mut found: Vec<(Check, String)> = Vec::new();
+        if !self.dates.contains(fields.business_date) {
+            self.dates.insert(fields.business_date.to_string());
+        }
+
+        for (field, &count) in COUNT_FIELDS.iter().zip(fields.counts.iter()) {
+            if count < 0 {
+                found.push((Check::NegativeCount, format!("{} is {}", field, count)));
+            }
+        }
+
+        for (field, time) in [
+            ("Arrival_Time_Scheduled", fields.arrival_time),
+            ("Departure_Time_Scheduled", fields.departure_time),
+        ] {
+            if is_over_24h(time) {
+                found.push((Check::TimeOver24h, format!("{} is {}", field, time)));
+            }
+        }
+
+        if let Some((latitude, longitude)) = fields.coordinates {
+            if !in_victoria(latitude, longitude) {
+                found.push((Check::OutsideVictoria, format!(
+                    "station coordinates {}, {} are outside Victoria", latitude, longitude
+                )));
+            }
+        }
+
+        if let Some(detail) = self.chainage_reversal(fields) {
+            found.push((Check::ChainageReversal, detail));
+        }
+
+        let mut hasher = DefaultHasher::new();
+        (fields.business_date, fields.train_number, fields.stop_sequence).hash(&mut hasher);
+        if !self.seen_keys.insert(hasher.finish()) {
+            found.push((Check::DuplicateKey, format!(
+                "train {} on {} already has stop {}",
+                fields.train_number, fields.business_date, fields.stop_sequence
+            )));
+        }
+
+        // A check counts once per row, however many fields tripped it.
+        found.dedup_by_key(|(check, _)| *check);
+
+        let mut skip = false;
+        for (check, detail) in found {
+            self.counts[check as usize] += 1;
+            match self.policy(check) {
+                Policy::Warn => {}
+                Policy::SkipRow => skip = true,
+                Policy::Error => {
+                    return Err(RecordError::Issue { row, check: check.name(), detail });
+                }
+            }
+        }
+        Ok(skip)
+    }
+
+    /// Compares the record with the previous one when both belong to the
+    /// same service. Down services move away from the city, so chainage
+    /// should rise with the stop sequence; up services the reverse.
+    fn chainage_reversal(&mut self, fields: &RowFields) -> Option<String> {
+        let last = self.last_stop.get_or_insert_with(LastStop::default);
+        let same_service = last.business_date == fields.business_date
+            && last.train_number == fields.train_number
+            && fields.stop_sequence > last.stop_sequence;
+
+        let detail = match (same_service, last.chainage, fields.chainage) {
+            (true, Some(previous), Some(current)) => {
+                let reversed = match fields.direction {
+                    "D" => current < previous,
+                    "U" => current > previous,
+                    _ => false,
+                };
+                if reversed {
+                    Some(format!(
+                        "train {} ({}) goes from chainage {} to {} at stop {}",
+                        fields.train_number, fields.direction, previous, current, fields.stop_sequence
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if last.business_date != fields.business_date {
+            last.business_date.clear();
+            last.business_date.push_str(fields.business_date);
+        }
+        if last.train_number != fields.train_number {
+            last.train_number.clear();
+            last.train_number.push_str(fields.train_number);
+        }
+        last.stop_sequence = fields.stop_sequence;
+        last.chainage = fields.chainage;
+
+        detail
+    }
+
+    /// Prints the business dates the rows covered, how often each check
+    /// fired and the policy applied, if any did, and how many malformed rows
+    /// were skipped.
+    pub fn print_summary(&self) {
+        if let (Some(first), Some(last)) = (self.dates.first(), self.dates.last()) {
+            println!("Business dates: {} to {} ({} dates).", first, last, self.dates.len());
+        }
+        if let Some(first) = &self.first_malformed {
+            println!(
+                "Skipped {} malformed rows (first: {}); pass --strict to stop at the first one.",
+                self.malformed, first
+            );
+        }
+        if self.counts.iter().all(|&count| count == 0) {
+            return;
+        }
+        println!("Data issues:");
+        for check in CHECKS {
+            let count = self.count(check);
+            if count > 0 {
+                println!("  {:<18} {:>10} rows  ({})", check.name(), count, self.policy(check).name());
+            }
+        }
+    }
+}
+
+/// Whether a `HH:MM:SS` time has an hour of 24 or more.
+fn is_over_24h(time: &str) -> bool {
+    time.split(':').next()
+        .and_then(|hour| hour.trim().parse::<u32>().ok())
+        .map_or(false, |hour| hour >= 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+
+    fn rows() -> (StringRecord, Vec<Result<(String, i32), csv::Error>>) {
+        let data = "Station_Name,Passenger_Boardings\nRichmond,12\nSouth Yarra,twelve\nHawksburn,3\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let headers = rdr.headers().unwrap().clone();
+        (headers, rdr.deserialize().collect())
+    }
+
+    #[test]
+    fn malformed_rows_are_skipped_and_counted() {
+        let (headers, results) = rows();
+        let mut tracker = IssueTracker::new();
+        let mut kept = Vec::new();
+        for (row, result) in results.into_iter().enumerate() {
+            if let Some(record) = tracker.read(row as u64 + 1, result, &headers).unwrap() {
+                kept.push(record.0);
+            }
+        }
+        assert_eq!(kept, ["Richmond", "Hawksburn"]);
+        assert_eq!(tracker.malformed(), 1);
+    }
+
+    #[test]
+    fn strict_stops_at_the_first_malformed_row() {
+        let (headers, results) = rows();
+        let mut tracker = IssueTracker::from_args(&IssueOptions { strict: true, ..IssueOptions::default() }).unwrap();
+        let errors: Vec<String> = results.into_iter().enumerate()
+            .filter_map(|(row, result)| tracker.read(row as u64 + 1, result, &headers).err())
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("row 2, field Passenger_Boardings"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn dedupe_skips_rows_repeated_by_overlapping_exports() {
+        let stop = |business_date, stop_sequence| RowFields {
+            business_date,
+            train_number: "1001",
+            direction: "U",
+            stop_sequence,
+            chainage: None,
+            arrival_time: "07:58:00",
+            departure_time: "07:59:00",
+            coordinates: None,
+            counts: [12, 85, 410, 337],
+        };
+        let mut tracker = IssueTracker::from_args(&IssueOptions { dedupe: true, ..IssueOptions::default() }).unwrap();
+        // The end of January's export, then February's starting a day early.
+        let skipped: Vec<bool> = [stop("2023-01-31", 1), stop("2023-01-31", 2), stop("2023-01-31", 2), stop("2023-02-01", 1)]
+            .iter().enumerate()
+            .map(|(row, fields)| tracker.inspect(row as u64 + 1, fields).unwrap())
+            .collect();
+        assert_eq!(skipped, [false, false, true, false]);
+        assert_eq!(tracker.count(Check::DuplicateKey), 1);
+        assert_eq!(tracker.dates.len(), 2);
+    }
+
+    fn options(args: &[&str]) -> Result<IssueOptions, clap::Error> {
+        parse_group(args)
+    }
+
+    fn clean_stop(stop_sequence: i32, chainage: i32) -> RowFields<'static> {
+        RowFields {
+            business_date: "2023-01-31",
+            train_number: "1001",
+            direction: "D",
+            stop_sequence,
+            chainage: Some(chainage),
+            arrival_time: "07:58:00",
+            departure_time: "07:59:00",
+            coordinates: Some((-37.82, 144.99)),
+            counts: [12, 85, 410, 337],
+        }
+    }
+
+    // Rows that are clean on their own followed by one that trips `check`.
+    fn triggering_rows(check: Check) -> Vec<RowFields<'static>> {
+        let mut rows = vec![clean_stop(1, 0), clean_stop(2, 2300)];
+        let mut row = clean_stop(3, 9000);
+        match check {
+            Check::NegativeCount => row.counts[1] = -4,
+            Check::TimeOver24h => row.departure_time = "24:05:00",
+            Check::ChainageReversal => row.chainage = Some(1200),
+            Check::DuplicateKey => row.stop_sequence = 2,
+            Check::OutsideVictoria => row.coordinates = Some((-33.87, 151.21)),
+        }
+        rows.push(row);
+        rows
+    }
+
+    #[test]
+    fn each_check_follows_its_policy() {
+        for check in CHECKS {
+            for policy in [Policy::Warn, Policy::SkipRow, Policy::Error] {
+                let assignment = format!("{}={}", check.name(), policy.name());
+                let mut tracker = IssueTracker::from_args(&options(&["--on-issue", &assignment]).unwrap()).unwrap();
+                let rows = triggering_rows(check);
+                let (last, clean) = rows.split_last().unwrap();
+                for (row, fields) in clean.iter().enumerate() {
+                    assert!(!tracker.inspect(row as u64 + 1, fields).unwrap(), "{}", assignment);
+                }
+
+                let result = tracker.inspect(rows.len() as u64, last);
+                match policy {
+                    Policy::Warn => assert!(!result.unwrap(), "{}", assignment),
+                    Policy::SkipRow => assert!(result.unwrap(), "{}", assignment),
+                    Policy::Error => match result {
+                        Err(RecordError::Issue { row, check: name, .. }) => {
+                            assert_eq!((row, name), (3, check.name()));
+                        }
+                        other => panic!("{}: expected an issue error, got {:?}", assignment, other),
+                    },
+                }
+                // No other check fired on the triggering row.
+                for other in CHECKS {
+                    assert_eq!(tracker.count(other), u64::from(other == check), "{} counting {}", assignment, other.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_checks_and_policies_are_rejected() {
+        for bad in ["bogus=warn", "negative-count=ignore", "negative-count"] {
+            let err = options(&["--on-issue", bad]).err()
+                .unwrap_or_else(|| panic!("--on-issue {} was accepted", bad));
+            assert!(err.to_string().contains(&format!("invalid value '{}' for '--on-issue", bad)), "{}", err);
+        }
+        let err = options(&["--on-issue", "bogus=warn"]).err().unwrap();
+        assert!(err.to_string().contains("unknown check 'bogus'"), "{}", err);
+
+        let path = std::env::temp_dir().join(format!("ptv_data_issue_config_{}", std::process::id()));
+        std::fs::write(&path, "# policies\nnegative-count = skip-row\nbogus = error\n").unwrap();
+        let config = options(&["--issue-config", path.to_str().unwrap()]).unwrap();
+        let err = IssueTracker::from_args(&config).err().unwrap();
+        assert_eq!(err.to_string(), format!(
+            "{} line 3: unknown check 'bogus', expected one of {}",
+            path.display(), CHECKS.map(Check::name).join(", ")
+        ));
+        std::fs::write(&path, "negative-count = skip-row\n").unwrap();
+        let tracker = IssueTracker::from_args(&config).unwrap();
+        assert_eq!(tracker.policy(Check::NegativeCount), Policy::SkipRow);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+// Everything the binaries share: the row type and its readers, the
+// business-day arithmetic, each service's peak load, the helper modules
+// the commands are built from, and the commands themselves. Each binary,
+// and each `ptv-data` subcommand, calls one of `commands`.
+
+pub mod aggregates;
+pub mod bands;
+pub mod business;
+pub mod chainage;
+pub mod charts;
+pub mod cli;
+pub mod commands;
+pub mod dates;
+pub mod error;
+pub mod filters;
+pub mod format;
+pub mod groups;
+pub mod index;
+pub mod issues;
+pub mod peaks;
+pub mod progress;
+pub mod record;
+pub mod rules;
+pub mod schema;
+pub mod skips;
+pub mod sqlite;
+pub mod stations;
+pub mod summary;
+pub mod timing;
+
+pub use record::{Record, load_records, open_input, read_all, read_records};
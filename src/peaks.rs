@@ -0,0 +1,162 @@
+// Each service's most crowded stop. A service is one run of a train on one
+// business date: Train_Number repeats from day to day, so services are told
+// apart by the number and the date together.
+
+use crate::Record;
+use std::collections::HashMap;
+
+/// The stop a service left with the most passengers on board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServicePeak {
+    pub business_date: String,
+    pub train_number: String,
+    pub line_name: String,
+    pub direction: String,
+    pub origin_station: String,
+    pub destination_station: String,
+    pub peak_station: String,
+    pub peak_load: i32,
+    pub departure_time: String,
+    // Of the peak stop; of two stops with the same load, the earlier wins.
+    stop_sequence: i32,
+}
+
+/// The peak stop of every service seen, one entry per service rather than
+/// per stop.
+#[derive(Debug, Default)]
+pub struct ServicePeaks {
+    // (business date, train number) -> peak so far.
+    services: HashMap<(String, String), ServicePeak>,
+}
+
+impl ServicePeaks {
+    pub fn add(&mut self, record: &Record) {
+        let peak = ServicePeak {
+            business_date: record.business_date.clone(),
+            train_number: record.train_number.clone(),
+            line_name: record.line_name.clone(),
+            direction: record.direction.clone(),
+            origin_station: record.origin_station.clone(),
+            destination_station: record.destination_station.clone(),
+            peak_station: record.station_name.clone(),
+            peak_load: record.passenger_departure_load,
+            departure_time: record.departure_time_scheduled.clone(),
+            stop_sequence: record.stop_sequence_number,
+        };
+        let key = (record.business_date.clone(), record.train_number.clone());
+        match self.services.get_mut(&key) {
+            Some(best) => {
+                let higher = (peak.peak_load, -peak.stop_sequence) > (best.peak_load, -best.stop_sequence);
+                if higher {
+                    *best = peak;
+                }
+            }
+            None => {
+                self.services.insert(key, peak);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+
+    /// Every service's peak, most crowded first; equal loads go by date and
+    /// then train number.
+    pub fn into_busiest(self) -> Vec<ServicePeak> {
+        let mut peaks: Vec<ServicePeak> = self.services.into_values().collect();
+        peaks.sort_by(|a, b| {
+            b.peak_load.cmp(&a.peak_load)
+                .then_with(|| a.business_date.cmp(&b.business_date))
+                .then_with(|| a.train_number.cmp(&b.train_number))
+        });
+        peaks
+    }
+}
+
+/// Groups `records` into services and returns each one's peak stop, most
+/// crowded first.
+pub fn service_peaks<'a>(records: impl IntoIterator<Item = &'a Record>) -> Vec<ServicePeak> {
+    let mut peaks = ServicePeaks::default();
+    for record in records {
+        peaks.add(record);
+    }
+    peaks.into_busiest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(date: &str, train: &str, sequence: i32, station: &str, departure_load: i32) -> Record {
+        Record {
+            business_date: date.to_string(),
+            day_of_week: "Monday".to_string(),
+            day_type: "Normal Weekday".to_string(),
+            mode: "Metro".to_string(),
+            train_number: train.to_string(),
+            line_name: "Pakenham".to_string(),
+            group: "Caulfield".to_string(),
+            direction: "U".to_string(),
+            origin_station: "East Pakenham".to_string(),
+            destination_station: "Flinders Street".to_string(),
+            station_name: station.to_string(),
+            station_latitude: String::new(),
+            station_longitude: String::new(),
+            station_chainage: Some(0),
+            stop_sequence_number: sequence,
+            arrival_time_scheduled: String::new(),
+            departure_time_scheduled: format!("07:{:02}:00", sequence),
+            passenger_boardings: 0,
+            passenger_alightings: 0,
+            passenger_arrival_load: 0,
+            passenger_departure_load: departure_load,
+        }
+    }
+
+    #[test]
+    fn keeps_each_services_busiest_stop() {
+        let records = [
+            stop("2022-09-12", "1001", 1, "Dandenong", 300),
+            stop("2022-09-12", "1001", 2, "Caulfield", 650),
+            stop("2022-09-12", "1001", 3, "Richmond", 420),
+            stop("2022-09-12", "1003", 1, "Dandenong", 200),
+            stop("2022-09-12", "1003", 2, "Caulfield", 200),
+            // The same train number on another date is another service.
+            stop("2022-09-13", "1001", 1, "Dandenong", 500),
+            stop("2022-09-13", "1001", 2, "Caulfield", 720),
+        ];
+        let peaks = service_peaks(&records);
+
+        let rows: Vec<(&str, &str, &str, i32, &str)> = peaks.iter()
+            .map(|peak| (
+                peak.business_date.as_str(),
+                peak.train_number.as_str(),
+                peak.peak_station.as_str(),
+                peak.peak_load,
+                peak.departure_time.as_str(),
+            ))
+            .collect();
+        assert_eq!(rows, [
+            ("2022-09-13", "1001", "Caulfield", 720, "07:02:00"),
+            ("2022-09-12", "1001", "Caulfield", 650, "07:02:00"),
+            // A tie goes to the earlier stop.
+            ("2022-09-12", "1003", "Dandenong", 200, "07:01:00"),
+        ]);
+        assert_eq!(peaks[0].origin_station, "East Pakenham");
+        assert_eq!(peaks[0].destination_station, "Flinders Street");
+    }
+
+    #[test]
+    fn a_tie_goes_to_the_earlier_stop_whatever_the_row_order() {
+        let mut peaks = ServicePeaks::default();
+        peaks.add(&stop("2022-09-12", "1003", 4, "Richmond", 200));
+        peaks.add(&stop("2022-09-12", "1003", 2, "Caulfield", 200));
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks.into_busiest()[0].peak_station, "Caulfield");
+    }
+}
@@ -0,0 +1,129 @@
+// Progress reporting shared by the binaries.
+//
+// On a terminal this is the usual indicatif bar. When stderr is not a TTY
+// (CI logs, redirected output) it falls back to a plain-text line every 10%
+// or 30 seconds, and `--no-progress` silences it entirely.
+//
+// A CSV is streamed without counting its rows first, so its progress is the
+// bytes read so far against the file's size; an index knows its row count.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Read};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const PLAIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bytes of the input read so far, shared between the reader counting them
+/// and the progress display.
+pub type BytesRead = Rc<Cell<u64>>;
+
+/// Adds the bytes read through it to a shared count, which the progress
+/// display reads while the rows stream.
+pub struct CountingReader<R> {
+    inner: R,
+    read: BytesRead,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R, read: BytesRead) -> CountingReader<R> {
+        CountingReader { inner, read }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.set(self.read.get() + n as u64);
+        Ok(n)
+    }
+}
+
+pub enum Progress {
+    // With `bytes`, the position is the bytes read rather than rows counted.
+    Bar(ProgressBar, Option<BytesRead>),
+    Plain {
+        total: u64,
+        count: u64,
+        last_percent: u64,
+        last_print: Instant,
+        bytes: Option<BytesRead>,
+    },
+    Hidden,
+}
+
+impl Progress {
+    /// Progress over `total` rows, one `inc` per row.
+    pub fn new(total: u64, no_progress: bool) -> Progress {
+        Progress::start(total, None, no_progress)
+    }
+
+    /// Progress through a file of `total` bytes as `read` grows; each `inc`
+    /// refreshes it from `read`. A file of unknown size gets a spinner.
+    pub fn bytes(total: u64, read: BytesRead, no_progress: bool) -> Progress {
+        Progress::start(total, Some(read), no_progress)
+    }
+
+    fn start(total: u64, bytes: Option<BytesRead>, no_progress: bool) -> Progress {
+        if no_progress {
+            return Progress::Hidden;
+        }
+        if !io::stderr().is_terminal() {
+            return Progress::Plain {
+                total,
+                count: 0,
+                last_percent: 0,
+                last_print: Instant::now(),
+                bytes,
+            };
+        }
+
+        let pb = match (&bytes, total) {
+            (Some(_), 0) => ProgressBar::new_spinner(),
+            _ => ProgressBar::new(total),
+        };
+        pb.set_message("Processing CSV...");
+        let style = match (&bytes, total) {
+            (Some(_), 0) => ProgressStyle::default_spinner().template("{spinner} {msg} {bytes}"),
+            (Some(_), _) => ProgressStyle::default_bar()
+                .template("{msg} {wide_bar} {bytes}/{total_bytes} {percent}% ({eta})")
+                .progress_chars("█▒░"),
+            (None, _) => ProgressStyle::default_bar()
+                .template("{msg} {wide_bar} {pos}/{len} ({eta})")
+                .progress_chars("█▒░"),
+        };
+        pb.set_style(style);
+        pb.enable_steady_tick(100);
+        Progress::Bar(pb, bytes)
+    }
+
+    pub fn inc(&mut self, delta: u64) {
+        match self {
+            Progress::Bar(pb, None) => pb.inc(delta),
+            Progress::Bar(pb, Some(read)) => pb.set_position(read.get()),
+            Progress::Plain { total, count, last_percent, last_print, bytes } => {
+                match bytes {
+                    Some(read) => *count = read.get(),
+                    None => *count += delta,
+                }
+                let percent = if *total == 0 { 100 } else { *count * 100 / *total };
+                if percent / 10 > *last_percent / 10 || last_print.elapsed() >= PLAIN_INTERVAL {
+                    let unit = if bytes.is_some() { " bytes" } else { "" };
+                    eprintln!("Processing CSV... {}% ({}/{}{})", percent, count, total, unit);
+                    *last_percent = percent;
+                    *last_print = Instant::now();
+                }
+            }
+            Progress::Hidden => {}
+        }
+    }
+
+    pub fn finish(&self, message: &'static str) {
+        match self {
+            Progress::Bar(pb, _) => pb.finish_with_message(message),
+            Progress::Plain { .. } => eprintln!("{}", message),
+            Progress::Hidden => {}
+        }
+    }
+}
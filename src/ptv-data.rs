@@ -0,0 +1,258 @@
+// One entry point for the binaries: `ptv-data <command> [options]` runs the
+// library function behind the binary that does the work. Each command takes
+// the same options as its binary, checked by the same parser, so
+// `ptv-data <command> --help` lists them. `timeseries` and `flow` pick the
+// function from `--interval` and `--line` rather than from the binary's name;
+// for `timeseries` these come before the generator's own options.
+
+use std::error::Error;
+
+use clap::{Args, Parser, Subcommand};
+use ptv_data::cli::block_size;
+use ptv_data::commands;
+
+// Lines `flow` has a generator for.
+const FLOW_LINES: &[&str] = &["pakenham"];
+
+// The `timeseries` options that pick the generator.
+const GENERATOR_FLAGS: [&str; 3] = ["--interval", "--block-size", "--line"];
+
+#[derive(Parser)]
+#[command(name = "ptv-data", about = "Patronage outputs from the PTV train dataset")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Hourly totals; --interval <minutes> for finer blocks, --line <name> for one line
+    Timeseries(TimeseriesOptions),
+    /// Passenger flow along --line <name> (pakenham)
+    Flow(FlowOptions),
+    /// Charts and CSVs for the first business date
+    Charts(commands::graph::Options),
+    /// The main charts and hourly CSVs, read across --threads <n> threads
+    ParallelCharts(commands::parallel_graph::Options),
+    /// Load profile along each service
+    TrainProfile(commands::train_profile::Options),
+    /// Per-station totals (`stations export`)
+    Stations(commands::stations_export::Options),
+    /// Stations as GeoJSON points with their totals
+    StationsGeojson(commands::stations_geojson::Options),
+    /// Load against capacity
+    Utilisation(commands::utilisation::Options),
+    /// Boardings by service origin and destination
+    OdMatrix(commands::od_matrix::Options),
+    /// Alightings by arrival time at chosen stations
+    Arrivals(commands::arrivals::Options),
+    /// Movements by month and business hour
+    Seasonality(commands::seasonality::Options),
+    /// Passengers crossing a point on a line
+    Screenline(commands::screenline::Options),
+    /// Departure load along a line by chainage
+    LoadProfile(commands::load_profile::Options),
+    /// Each service's most crowded stop
+    PeakLoads(commands::peak_loads::Options),
+    /// An event date against comparable dates
+    Event(commands::event::Options),
+    /// Threshold rules over the aggregates
+    Alerts(commands::alerts::Options),
+    /// Binary index of the input CSV
+    Index(commands::index_build::Options),
+    /// The input CSV as a typed Parquet file
+    Convert(commands::convert::Options),
+    /// Column profile of the input CSV
+    Profile(commands::profile::Options),
+    /// Description of every output file
+    Schema(commands::schema_export::Options),
+}
+
+/// `timeseries`: the generators differ in their options, so only the ones
+/// that pick the generator are parsed here; the rest go to the generator,
+/// which checks them as its binary would.
+#[derive(Args, Debug)]
+struct TimeseriesOptions {
+    /// Minutes per block: 60 for hourly totals, or any other that divides the hour
+    #[arg(long, visible_alias = "block-size", value_name = "MINUTES", default_value_t = 60, value_parser = block_size)]
+    interval: u32,
+    /// Write only this line; needs an --interval under 60
+    #[arg(long, value_name = "NAME")]
+    line: Option<String>,
+    /// The generator's own options, after --interval and --line; add --help to list them
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, value_name = "OPTIONS")]
+    options: Vec<String>,
+}
+
+/// `flow`: the line, then the generator's options.
+#[derive(Args, Debug)]
+struct FlowOptions {
+    /// Line to trace the flow along
+    #[arg(long, value_name = "NAME", value_parser = flow_line)]
+    line: String,
+    #[command(flatten)]
+    options: commands::flow::Options,
+}
+
+impl Command {
+    /// Runs the library function behind the command with its options.
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Command::Timeseries(options) => {
+                let (run, args) = options.generator()?;
+                run(&args)
+            }
+            Command::Flow(FlowOptions { line, options }) => match line.as_str() {
+                "pakenham" => commands::flow::run_with(options),
+                _ => unreachable!("flow_line only accepts FLOW_LINES"),
+            },
+            Command::Charts(options) => commands::graph::run_with(options),
+            Command::ParallelCharts(options) => commands::parallel_graph::run_with(options),
+            Command::TrainProfile(options) => commands::train_profile::run_with(options),
+            Command::Stations(options) => commands::stations_export::run_with(options),
+            Command::StationsGeojson(options) => commands::stations_geojson::run_with(options),
+            Command::Utilisation(options) => commands::utilisation::run_with(options),
+            Command::OdMatrix(options) => commands::od_matrix::run_with(options),
+            Command::Arrivals(options) => commands::arrivals::run_with(options),
+            Command::Seasonality(options) => commands::seasonality::run_with(options),
+            Command::Screenline(options) => commands::screenline::run_with(options),
+            Command::LoadProfile(options) => commands::load_profile::run_with(options),
+            Command::PeakLoads(options) => commands::peak_loads::run_with(options),
+            Command::Event(options) => commands::event::run_with(options),
+            Command::Alerts(options) => commands::alerts::run_with(options),
+            Command::Index(options) => commands::index_build::run_with(options),
+            Command::Convert(options) => commands::convert::run_with(options),
+            Command::Profile(options) => commands::profile::run_with(options),
+            Command::Schema(options) => commands::schema_export::run_with(options),
+        }
+    }
+}
+
+/// A generator's entry point; it parses its options from `args` as the
+/// binary would, with `args[0]` the name its usage is shown under.
+type Run = fn(&[String]) -> Result<(), Box<dyn Error>>;
+
+impl TimeseriesOptions {
+    /// The generator for `--interval` and `--line`: hourly totals by default,
+    /// 15-minute blocks with `--interval 15`, or any other block size, for
+    /// one line with `--line`. Returns it with the arguments to run it with.
+    fn generator(self) -> Result<(Run, Vec<String>), String> {
+        // Everything from the first option clap doesn't know is passed on,
+        // so these would reach the generator rather than pick it.
+        let misplaced = self.options.iter()
+            .find(|arg| GENERATOR_FLAGS.iter().any(|flag| arg.split('=').next() == Some(*flag)));
+        if let Some(arg) = misplaced {
+            return Err(format!("'{}' must come before the generator's options", arg));
+        }
+        let mut args = vec!["ptv-data timeseries".to_string()];
+        let run: Run = match (self.interval, &self.line) {
+            (60, None) => commands::hourly::run,
+            (60, Some(_)) => return Err("--line needs an --interval under 60 minutes".to_string()),
+            (15, None) => commands::quarter_hour::run,
+            (15, Some(line)) => {
+                args.extend(["--line".to_string(), line.clone()]);
+                commands::line_quarter_hour::run
+            }
+            (minutes, line) => {
+                args.extend(["--block-size".to_string(), minutes.to_string()]);
+                if let Some(line) = line {
+                    args.extend(["--line".to_string(), line.clone()]);
+                }
+                commands::blocks::run
+            }
+        };
+        args.extend(self.options);
+        Ok((run, args))
+    }
+}
+
+/// Checks `flow --line` names a line with a generator, ignoring case.
+fn flow_line(value: &str) -> Result<String, String> {
+    let line = value.to_lowercase();
+    match FLOW_LINES.contains(&line.as_str()) {
+        true => Ok(line),
+        false => Err(format!("no flow generator for this line; available: {}", FLOW_LINES.join(", "))),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    Cli::parse().command.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Cli::try_parse_from(args).map(|cli| cli.command).map_err(|err| err.to_string())
+    }
+
+    // The arguments `timeseries` runs its generator with.
+    fn generator_args(args: &[&str]) -> Result<Vec<String>, String> {
+        match parse(args)? {
+            Command::Timeseries(options) => options.generator().map(|(_, args)| args),
+            _ => panic!("not timeseries"),
+        }
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn the_command_line_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn timeseries_picks_the_generator_from_interval_and_line() {
+        assert_eq!(
+            generator_args(&["ptv-data", "timeseries", "--input", "a.csv"]),
+            Ok(strings(&["ptv-data timeseries", "--input", "a.csv"]))
+        );
+        assert_eq!(
+            generator_args(&["ptv-data", "timeseries", "--interval", "15", "--line", "Pakenham", "--no-progress"]),
+            Ok(strings(&["ptv-data timeseries", "--line", "Pakenham", "--no-progress"]))
+        );
+        assert_eq!(
+            generator_args(&["ptv-data", "timeseries", "--interval", "5", "--output-dir", "out"]),
+            Ok(strings(&["ptv-data timeseries", "--block-size", "5", "--output-dir", "out"]))
+        );
+        assert_eq!(
+            generator_args(&["ptv-data", "timeseries", "--line", "Pakenham", "--block-size", "10"]),
+            Ok(strings(&["ptv-data timeseries", "--block-size", "10", "--line", "Pakenham"]))
+        );
+        assert_eq!(generator_args(&["ptv-data", "timeseries", "--interval", "15"]), Ok(strings(&["ptv-data timeseries"])));
+        assert!(generator_args(&["ptv-data", "timeseries", "--line", "Pakenham"]).is_err());
+        assert_eq!(
+            generator_args(&["ptv-data", "timeseries", "--input", "a.csv", "--interval", "15"]),
+            Err("'--interval' must come before the generator's options".to_string())
+        );
+        assert!(parse(&["ptv-data", "timeseries", "--interval", "7"]).is_err());
+        assert!(parse(&["ptv-data", "timeseries", "--interval", "0"]).is_err());
+    }
+
+    #[test]
+    fn flow_needs_a_line_with_a_generator() {
+        assert!(matches!(
+            parse(&["ptv-data", "flow", "--line", "Pakenham", "--input", "a.csv"]),
+            Ok(Command::Flow(FlowOptions { line, .. })) if line == "pakenham"
+        ));
+        assert!(parse(&["ptv-data", "flow", "--line", "Frankston"]).unwrap_err().contains("available: pakenham"));
+        assert!(parse(&["ptv-data", "flow"]).is_err());
+    }
+
+    #[test]
+    fn subcommands_check_their_own_options() {
+        assert!(matches!(parse(&["ptv-data", "charts", "--input", "a.csv", "--format", "svg"]), Ok(Command::Charts(_))));
+        assert!(matches!(parse(&["ptv-data", "od-matrix"]), Ok(Command::OdMatrix(_))));
+        assert!(parse(&["ptv-data", "charts", "--format", "gif"]).unwrap_err().contains("invalid value 'gif'"));
+        assert!(parse(&["ptv-data", "od-matrix", "--max-stations", "0"]).unwrap_err().contains("expected a positive number"));
+        // A flag's value can't be the next flag.
+        assert!(parse(&["ptv-data", "charts", "--date", "--all-dates"]).is_err());
+        assert!(parse(&["ptv-data", "screenline", "--line", "pakenham"]).unwrap_err().contains("--screenline-chainage"));
+        assert!(parse(&["ptv-data", "unknown"]).is_err());
+        assert!(parse(&["ptv-data"]).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// One row of data.csv. Field names follow Rust conventions; the serde
+// renames match the CSV header.
+
+use csv::Reader;
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Record {
+    #[serde(rename = "Business_Date")]
+    pub business_date: String,            // e.g. "2022-09-12"
+    #[serde(rename = "Day_of_Week")]
+    pub day_of_week: String,              // e.g. "Monday" or "Public Holiday"
+    #[serde(rename = "Day_Type")]
+    pub day_type: String,                 // e.g. "Normal Weekday"
+    #[serde(rename = "Mode")]
+    pub mode: String,                     // "Metro" or "V/Line"
+    #[serde(rename = "Train_Number")]
+    pub train_number: String,             // Using String to avoid parse issues
+    #[serde(rename = "Line_Name")]
+    pub line_name: String,                // e.g. "Pakenham"
+    #[serde(rename = "Group")]
+    pub group: String,
+    #[serde(rename = "Direction")]
+    pub direction: String,                // "U" (Up) or "D" (Down)
+    #[serde(rename = "Origin_Station")]
+    pub origin_station: String,
+    #[serde(rename = "Destination_Station")]
+    pub destination_station: String,
+    #[serde(rename = "Station_Name")]
+    pub station_name: String,
+    #[serde(rename = "Station_Latitude")]
+    pub station_latitude: String,
+    #[serde(rename = "Station_Longitude")]
+    pub station_longitude: String,
+    #[serde(rename = "Station_Chainage")]
+    pub station_chainage: Option<i32>,    // Blank when the distance is unknown
+    #[serde(rename = "Stop_Sequence_Number")]
+    pub stop_sequence_number: i32,
+    #[serde(rename = "Arrival_Time_Scheduled")]
+    pub arrival_time_scheduled: String,
+    #[serde(rename = "Departure_Time_Scheduled")]
+    pub departure_time_scheduled: String,
+    #[serde(rename = "Passenger_Boardings")]
+    pub passenger_boardings: i32,
+    #[serde(rename = "Passenger_Alightings")]
+    pub passenger_alightings: i32,
+    #[serde(rename = "Passenger_Arrival_Load")]
+    pub passenger_arrival_load: i32,
+    #[serde(rename = "Passenger_Departure_Load")]
+    pub passenger_departure_load: i32,
+}
+
+// Victoria's extent in degrees, widened by a degree on every side so that
+// border stations and stops just over it, such as Albury, aren't flagged.
+const VICTORIA_LATITUDE: std::ops::RangeInclusive<f64> = -40.2..=-33.0;
+const VICTORIA_LONGITUDE: std::ops::RangeInclusive<f64> = 140.0..=151.0;
+
+impl Record {
+    /// Station_Latitude in degrees; `None` when it's blank or not a number.
+    pub fn lat(&self) -> Option<f64> {
+        coordinate(&self.station_latitude)
+    }
+
+    /// Station_Longitude in degrees; `None` when it's blank or not a number.
+    pub fn lon(&self) -> Option<f64> {
+        coordinate(&self.station_longitude)
+    }
+
+    /// Latitude and longitude, when both parse.
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        Some((self.lat()?, self.lon()?))
+    }
+}
+
+/// Parses a latitude or longitude field; blanks and non-numbers are `None`.
+pub fn coordinate(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|degrees| degrees.is_finite())
+}
+
+/// Whether a point falls in or near Victoria. Coordinates far outside it,
+/// such as a dropped minus sign or latitude and longitude swapped, are
+/// data-entry errors.
+pub fn in_victoria(latitude: f64, longitude: f64) -> bool {
+    VICTORIA_LATITUDE.contains(&latitude) && VICTORIA_LONGITUDE.contains(&longitude)
+}
+
+/// Whether a point is the 0,0 placeholder some rows carry instead of a
+/// station's real coordinates.
+pub fn is_placeholder(latitude: f64, longitude: f64) -> bool {
+    latitude == 0.0 && longitude == 0.0
+}
+
+/// The first two bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Whether `header`, the start of a file, is gzip-compressed.
+pub fn is_gzip(header: &[u8]) -> bool {
+    header.starts_with(&GZIP_MAGIC)
+}
+
+/// `reader`, decompressed as gzip when it starts with the gzip magic bytes,
+/// so a compressed file is read the same whatever it's named.
+pub fn decompressed<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    if is_gzip(reader.fill_buf()?) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Opens the input CSV at `path`, decompressing a gzipped file, such as
+/// `data.csv.gz`, as it's read.
+pub fn open_input<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    decompressed(File::open(path)?)
+}
+
+/// Deserializes records from CSV text with a header row.
+pub fn records<R: Read>(reader: R) -> impl Iterator<Item = Result<Record, csv::Error>> {
+    Reader::from_reader(reader).into_deserialize()
+}
+
+/// Reads the records of the CSV at `path` one at a time. The binaries read
+/// through `RecordSource` instead, which can also read an index.
+pub fn read_records(path: &str) -> Result<impl Iterator<Item = Result<Record, csv::Error>>, csv::Error> {
+    Ok(Reader::from_reader(open_input(path)?).into_deserialize())
+}
+
+/// Reads every record of the CSV at `path` into memory in one pass; the
+/// row count is the length of the result.
+pub fn load_records<P: AsRef<Path>>(path: P) -> Result<Vec<Record>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let file = open_input(path).map_err(|err| format!("cannot read input '{}': {}", path.display(), err))?;
+    read_all(file)
+}
+
+/// Collects every record from CSV text with a header row. Errors name the
+/// 1-based data row.
+pub fn read_all<R: Read>(reader: R) -> Result<Vec<Record>, Box<dyn Error>> {
+    records(reader)
+        .enumerate()
+        .map(|(row, result)| result.map_err(|err| format!("row {}: {}", row + 1, err).into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Business_Date,Day_of_Week,Day_Type,Mode,Train_Number,Line_Name,Group,Direction,\
+        Origin_Station,Destination_Station,Station_Name,Station_Latitude,Station_Longitude,Station_Chainage,\
+        Stop_Sequence_Number,Arrival_Time_Scheduled,Departure_Time_Scheduled,Passenger_Boardings,\
+        Passenger_Alightings,Passenger_Arrival_Load,Passenger_Departure_Load";
+
+    fn two_stops() -> String {
+        format!(
+            "{}\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,12,85,410,337\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Flinders Street,-37.81,144.96,0,16,08:05:00,,0,337,337,0\n",
+            HEADER
+        )
+    }
+
+    #[test]
+    fn reads_rows_by_header_name() {
+        let rows: Vec<Record> = records(two_stops().as_bytes()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let richmond = &rows[0];
+        assert_eq!(richmond.business_date, "2022-09-12");
+        assert_eq!(richmond.train_number, "1001");
+        assert_eq!(richmond.line_name, "Pakenham");
+        assert_eq!(richmond.direction, "U");
+        assert_eq!(richmond.station_name, "Richmond");
+        assert_eq!(richmond.station_chainage, Some(2300));
+        assert_eq!(richmond.stop_sequence_number, 14);
+        assert_eq!(richmond.departure_time_scheduled, "07:59:00");
+        assert_eq!(
+            (richmond.passenger_boardings, richmond.passenger_alightings),
+            (12, 85)
+        );
+        assert_eq!(
+            (richmond.passenger_arrival_load, richmond.passenger_departure_load),
+            (410, 337)
+        );
+
+        // The terminus has no departure time.
+        assert_eq!(rows[1].departure_time_scheduled, "");
+        assert_ne!(rows[0], rows[1]);
+        assert_eq!(rows[0].clone(), rows[0]);
+    }
+
+    #[test]
+    fn rejects_a_non_numeric_count() {
+        let csv = format!(
+            "{}\n2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,twelve,85,410,337\n",
+            HEADER
+        );
+        let mut rows = records(csv.as_bytes());
+        assert!(rows.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn blank_chainage_is_unknown() {
+        let csv = format!(
+            "{}\n2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Richmond,-37.82,144.99,,14,07:58:00,07:59:00,12,85,410,337\n\
+             2022-09-12,Monday,Normal Weekday,Metro,1001,Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+             Richmond,-37.82,144.99,far,14,07:58:00,07:59:00,12,85,410,337\n",
+            HEADER
+        );
+        let mut rows = records(csv.as_bytes());
+        assert_eq!(rows.next().unwrap().unwrap().station_chainage, None);
+        // Anything else that isn't a number is still an error.
+        assert!(rows.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn parses_coordinates_and_places_them_in_victoria() {
+        let rows: Vec<Record> = records(two_stops().as_bytes()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows[0].lat(), Some(-37.82));
+        assert_eq!(rows[0].lon(), Some(144.99));
+        assert_eq!(rows[0].coordinates(), Some((-37.82, 144.99)));
+
+        let blank = Record { station_latitude: " ".to_string(), ..rows[0].clone() };
+        assert_eq!(blank.lat(), None);
+        assert_eq!(blank.coordinates(), None);
+        assert_eq!(coordinate("n/a"), None);
+        assert_eq!(coordinate("NaN"), None);
+
+        assert!(in_victoria(-37.82, 144.99));
+        // Albury, the end of the North East line, is just over the border.
+        assert!(in_victoria(-36.08, 146.92));
+        // A dropped minus sign, and latitude and longitude swapped.
+        assert!(!in_victoria(37.82, 144.99));
+        assert!(!in_victoria(144.99, -37.82));
+
+        assert!(is_placeholder(0.0, -0.0));
+        assert!(!is_placeholder(-37.82, 0.0));
+    }
+
+    /// Counts the bytes read through it.
+    struct CountingReader<R> {
+        inner: R,
+        bytes: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_a_large_file_exactly_once() {
+        let mut csv = HEADER.to_string();
+        for row in 0..10_000 {
+            csv.push_str(&format!(
+                "\n2022-09-12,Monday,Normal Weekday,Metro,{},Pakenham,Caulfield,U,Pakenham,Flinders Street,\
+                 Richmond,-37.82,144.99,2300,14,07:58:00,07:59:00,{},0,0,0",
+                1000 + row / 20,
+                row % 7
+            ));
+        }
+        let mut reader = CountingReader { inner: csv.as_bytes(), bytes: 0 };
+        let rows = read_all(&mut reader).unwrap();
+        assert_eq!(rows.len(), 10_000);
+        assert_eq!(reader.bytes, csv.len());
+        assert_eq!(rows[9_999].train_number, "1499");
+        assert_eq!(rows[9_999].passenger_boardings, 9_999 % 7);
+    }
+
+    #[test]
+    fn loads_a_file_and_names_the_bad_row() {
+        let path = std::env::temp_dir().join(format!("ptv_data_records_{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        std::fs::write(path, two_stops()).unwrap();
+        let rows = load_records(path).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].station_name, "Flinders Street");
+        assert_eq!(rows[1].passenger_alightings, 337);
+
+        std::fs::write(path, two_stops().replace(",0,337,337,0", ",0,x,337,0")).unwrap();
+        let err = load_records(path).unwrap_err().to_string();
+        std::fs::remove_file(path).unwrap();
+        assert!(err.starts_with("row 2:"), "{}", err);
+    }
+
+    #[test]
+    fn loads_a_gzipped_file_like_the_plain_one() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("ptv_data_gzip_{}.csv", std::process::id()));
+        let gzipped = dir.join(format!("ptv_data_gzip_{}.csv.gz", std::process::id()));
+        std::fs::write(&plain, two_stops()).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(two_stops().as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        std::fs::write(&gzipped, &compressed).unwrap();
+
+        let from_plain = load_records(&plain).unwrap();
+        let from_gzip = load_records(&gzipped).unwrap();
+        let streamed: Vec<Record> = read_records(gzipped.to_str().unwrap()).unwrap().collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&plain).unwrap();
+        std::fs::remove_file(&gzipped).unwrap();
+
+        assert!(is_gzip(&compressed) && !is_gzip(two_stops().as_bytes()));
+        assert_eq!(from_gzip.len(), 2);
+        assert_eq!(from_gzip, from_plain);
+        assert_eq!(streamed, from_plain);
+        // Detected by its magic bytes, not its name.
+        let unnamed = read_all(decompressed(compressed.as_slice()).unwrap()).unwrap();
+        assert_eq!(unnamed, from_plain);
+    }
+}
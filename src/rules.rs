@@ -0,0 +1,523 @@
+// Threshold alerts: user rules each compare one metric against a value, and
+// are evaluated over metric tables built while streaming the records. Rules
+// come from a TOML file of `[[rule]]` tables; only the part of TOML this
+// needs is read, i.e. `key = "string"` lines and `#` comments:
+//
+//     [[rule]]
+//     name = "Bucket surge"
+//     when = "bucket_movements > 8000"
+//     severity = "error"
+
+use chrono::{Duration, NaiveDate};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+
+use crate::business::DayStart;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    // Movements per line in a 15-minute departure interval on one date.
+    BucketMovements,
+    // Highest departure load on one service.
+    ServicePeakLoad,
+    // Movements per line on one date.
+    LineDailyMovements,
+    // Boardings per station on one date.
+    StationDailyBoardings,
+    // Percentage change in a station's boardings from the same weekday a
+    // week earlier, where that date is in the data and had boardings.
+    StationBoardingsWeekOnWeek,
+}
+
+pub const METRICS: [Metric; 5] = [
+    Metric::BucketMovements,
+    Metric::ServicePeakLoad,
+    Metric::LineDailyMovements,
+    Metric::StationDailyBoardings,
+    Metric::StationBoardingsWeekOnWeek,
+];
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::BucketMovements => "bucket_movements",
+            Metric::ServicePeakLoad => "service_peak_load",
+            Metric::LineDailyMovements => "line_daily_movements",
+            Metric::StationDailyBoardings => "station_daily_boardings",
+            Metric::StationBoardingsWeekOnWeek => "station_boardings_wow_pct",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Metric, Box<dyn Error>> {
+        METRICS.iter().copied().find(|metric| metric.name() == name).ok_or_else(|| {
+            let known: Vec<&str> = METRICS.iter().map(|metric| metric.name()).collect();
+            format!("unknown metric '{}', expected one of {}", name, known.join(", ")).into()
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+const COMPARATORS: [Comparator; 6] = [
+    Comparator::Greater,
+    Comparator::GreaterOrEqual,
+    Comparator::Less,
+    Comparator::LessOrEqual,
+    Comparator::Equal,
+    Comparator::NotEqual,
+];
+
+impl Comparator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparator::Greater => ">",
+            Comparator::GreaterOrEqual => ">=",
+            Comparator::Less => "<",
+            Comparator::LessOrEqual => "<=",
+            Comparator::Equal => "==",
+            Comparator::NotEqual => "!=",
+        }
+    }
+
+    fn parse(symbol: &str) -> Result<Comparator, Box<dyn Error>> {
+        COMPARATORS.iter().copied().find(|comparator| comparator.symbol() == symbol).ok_or_else(|| {
+            let known: Vec<&str> = COMPARATORS.iter().map(|comparator| comparator.symbol()).collect();
+            format!("unknown comparator '{}', expected one of {}", symbol, known.join(" ")).into()
+        })
+    }
+
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparator::Greater => value > threshold,
+            Comparator::GreaterOrEqual => value >= threshold,
+            Comparator::Less => value < threshold,
+            Comparator::LessOrEqual => value <= threshold,
+            Comparator::Equal => value == threshold,
+            Comparator::NotEqual => value != threshold,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Severity, Box<dyn Error>> {
+        match value {
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            other => Err(format!("unknown severity '{}', expected warning or error", other).into()),
+        }
+    }
+}
+
+pub struct Rule {
+    pub name: String,
+    pub metric: Metric,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub severity: Severity,
+}
+
+impl Rule {
+    /// Builds a rule from one `[[rule]]` table. `when` is required and reads
+    /// `<metric> <comparator> <value>`; `name` defaults to the `when` text and
+    /// `severity` to warning.
+    fn from_table(mut table: HashMap<String, String>) -> Result<Rule, Box<dyn Error>> {
+        let when = table.remove("when").ok_or("missing 'when'")?;
+        let parts: Vec<&str> = when.split_whitespace().collect();
+        let [metric, comparator, value] = parts[..] else {
+            return Err(format!("'when = \"{}\"': expected \"<metric> <comparator> <value>\"", when).into());
+        };
+        let threshold: f64 = value.replace('_', "").parse()
+            .map_err(|_| format!("'when = \"{}\"': '{}' is not a number", when, value))?;
+        let severity = match table.remove("severity") {
+            Some(severity) => Severity::parse(&severity)?,
+            None => Severity::Warning,
+        };
+        let rule = Rule {
+            metric: Metric::parse(metric)?,
+            comparator: Comparator::parse(comparator)?,
+            threshold,
+            severity,
+            name: table.remove("name").unwrap_or_else(|| when.clone()),
+        };
+        if let Some(key) = table.keys().next() {
+            return Err(format!("unknown key '{}', expected name, when or severity", key).into());
+        }
+        Ok(rule)
+    }
+}
+
+/// Reads every `[[rule]]` table from `path`. Anything outside the supported
+/// subset is an error naming the line.
+pub fn load_rules(path: &str) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read --rules '{}': {}", path, err))?;
+
+    // Each table with the line its header is on.
+    let mut tables: Vec<(usize, HashMap<String, String>)> = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let at = |message: &str| format!("{} line {}: {}", path, number + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[rule]]" {
+            tables.push((number + 1, HashMap::new()));
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| at("expected [[rule]] or key = \"value\""))?;
+        let value = parse_string(value.trim()).ok_or_else(|| at("expected a quoted string value"))?;
+        let (_, table) = tables.last_mut().ok_or_else(|| at("key outside a [[rule]] table"))?;
+        if table.insert(key.trim().to_string(), value).is_some() {
+            return Err(at(&format!("duplicate key '{}'", key.trim())).into());
+        }
+    }
+
+    tables.into_iter()
+        .map(|(line, table)| {
+            Rule::from_table(table).map_err(|err| format!("{} rule at line {}: {}", path, line, err).into())
+        })
+        .collect()
+}
+
+/// A TOML basic string, optionally followed by a comment.
+fn parse_string(value: &str) -> Option<String> {
+    let mut chars = value.strip_prefix('"')?.chars();
+    let mut parsed = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+                '"' => parsed.push('"'),
+                '\\' => parsed.push('\\'),
+                _ => return None,
+            },
+            c => parsed.push(c),
+        }
+    }
+    let rest = chars.as_str().trim();
+    (rest.is_empty() || rest.starts_with('#')).then_some(parsed)
+}
+
+/// The fields of a record the metrics are built from.
+pub struct Stop<'a> {
+    pub business_date: &'a str,
+    pub line: &'a str,
+    pub train_number: &'a str,
+    pub station: &'a str,
+    pub departure_time: &'a str,
+    pub boardings: i32,
+    pub alightings: i32,
+    pub departure_load: i32,
+}
+
+// A service's highest departure load and where it happened.
+struct Peak {
+    load: i32,
+    line: String,
+    station: String,
+    departure_time: String,
+}
+
+/// One metric value and what it was measured for.
+#[derive(Serialize)]
+pub struct Observation {
+    #[serde(serialize_with = "entity_object")]
+    pub entity: Vec<(&'static str, String)>,
+    pub timestamp: String,
+    pub value: f64,
+}
+
+/// Writes an entity as a JSON object with its keys in order, e.g. a
+/// station before the date it is compared with.
+fn entity_object<S: Serializer>(entity: &[(&'static str, String)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(entity.iter().map(|(key, value)| (key, value)))
+}
+
+#[derive(Default)]
+pub struct MetricTables {
+    day_start: DayStart,
+    // (line, date, 15-minute interval from the day start) -> movements
+    buckets: BTreeMap<(String, String, usize), i64>,
+    // (date, train) -> peak
+    service_peaks: BTreeMap<(String, String), Peak>,
+    // (line, date) -> movements
+    line_daily: BTreeMap<(String, String), i64>,
+    // (station, date) -> boardings
+    station_daily: BTreeMap<(String, String), i64>,
+}
+
+impl MetricTables {
+    pub fn new(day_start: DayStart) -> MetricTables {
+        MetricTables { day_start, ..MetricTables::default() }
+    }
+
+    pub fn add(&mut self, stop: &Stop) {
+        let movements = (stop.boardings + stop.alightings) as i64;
+        let (date, at) = self.day_start.departure(stop.business_date, stop.departure_time);
+
+        if let Some(at) = at {
+            *self.buckets.entry((stop.line.to_string(), date.clone(), at.interval(15))).or_insert(0) += movements;
+        }
+
+        // A service stays on its own Business_Date, wherever the day starts.
+        let peak = self.service_peaks.entry((stop.business_date.to_string(), stop.train_number.to_string()))
+            .or_insert_with(|| Peak {
+                load: i32::MIN,
+                line: String::new(),
+                station: String::new(),
+                departure_time: String::new(),
+            });
+        if stop.departure_load > peak.load {
+            *peak = Peak {
+                load: stop.departure_load,
+                line: stop.line.to_string(),
+                station: stop.station.to_string(),
+                departure_time: stop.departure_time.to_string(),
+            };
+        }
+
+        *self.line_daily.entry((stop.line.to_string(), date.clone())).or_insert(0) += movements;
+        *self.station_daily.entry((stop.station.to_string(), date)).or_insert(0) += stop.boardings as i64;
+    }
+
+    /// Every value of `metric`, in entity order.
+    pub fn observations(&self, metric: Metric) -> Vec<Observation> {
+        match metric {
+            Metric::BucketMovements => self.buckets.iter()
+                .map(|((line, date, interval), &movements)| Observation {
+                    entity: vec![("line", line.clone())],
+                    timestamp: self.day_start.timestamp(date, interval * 15),
+                    value: movements as f64,
+                })
+                .collect(),
+            Metric::ServicePeakLoad => self.service_peaks.iter()
+                .map(|((date, train), peak)| Observation {
+                    entity: vec![
+                        ("train", train.clone()),
+                        ("line", peak.line.clone()),
+                        ("station", peak.station.clone()),
+                    ],
+                    timestamp: format!("{} {}", date, peak.departure_time),
+                    value: peak.load as f64,
+                })
+                .collect(),
+            Metric::LineDailyMovements => self.line_daily.iter()
+                .map(|((line, date), &movements)| Observation {
+                    entity: vec![("line", line.clone())],
+                    timestamp: date.clone(),
+                    value: movements as f64,
+                })
+                .collect(),
+            Metric::StationDailyBoardings => self.station_daily.iter()
+                .map(|((station, date), &boardings)| Observation {
+                    entity: vec![("station", station.clone())],
+                    timestamp: date.clone(),
+                    value: boardings as f64,
+                })
+                .collect(),
+            Metric::StationBoardingsWeekOnWeek => self.station_daily.iter()
+                .filter_map(|((station, date), &boardings)| {
+                    let previous = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()? - Duration::days(7);
+                    let previous = previous.format("%Y-%m-%d").to_string();
+                    let before = *self.station_daily.get(&(station.clone(), previous.clone()))?;
+                    if before <= 0 {
+                        return None;
+                    }
+                    let change = (boardings - before) as f64 / before as f64 * 100.0;
+                    Some(Observation {
+                        entity: vec![("station", station.clone()), ("compared_with", previous)],
+                        timestamp: date.clone(),
+                        value: (change * 10.0).round() / 10.0,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A rule that fired, with every value that satisfied it.
+pub struct Finding<'a> {
+    pub rule: &'a Rule,
+    pub matches: Vec<Observation>,
+}
+
+/// Evaluates each rule and returns the ones that fired, in rule order.
+pub fn evaluate<'a>(rules: &'a [Rule], tables: &MetricTables) -> Vec<Finding<'a>> {
+    rules.iter()
+        .map(|rule| Finding {
+            rule,
+            matches: tables.observations(rule.metric).into_iter()
+                .filter(|observation| rule.comparator.holds(observation.value, rule.threshold))
+                .collect(),
+        })
+        .filter(|finding| !finding.matches.is_empty())
+        .collect()
+}
+
+#[derive(Serialize)]
+struct FindingsReport<'a> {
+    rules_evaluated: usize,
+    rules_triggered: usize,
+    findings: Vec<FindingEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct FindingEntry<'a> {
+    rule: &'a str,
+    severity: &'static str,
+    metric: &'static str,
+    comparator: &'static str,
+    threshold: f64,
+    matches: &'a [Observation],
+}
+
+pub fn findings_json(findings: &[Finding], rules_evaluated: usize) -> String {
+    let report = FindingsReport {
+        rules_evaluated,
+        rules_triggered: findings.len(),
+        findings: findings.iter()
+            .map(|finding| FindingEntry {
+                rule: &finding.rule.name,
+                severity: finding.rule.severity.name(),
+                metric: finding.rule.metric.name(),
+                comparator: finding.rule.comparator.symbol(),
+                threshold: finding.rule.threshold,
+                matches: &finding.matches,
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&report).expect("findings always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop<'a>(date: &'a str, train: &'a str, station: &'a str, departure: &'a str, boardings: i32, alightings: i32, departure_load: i32) -> Stop<'a> {
+        Stop {
+            business_date: date,
+            line: "Pakenham",
+            train_number: train,
+            station,
+            departure_time: departure,
+            boardings,
+            alightings,
+            departure_load,
+        }
+    }
+
+    fn tables() -> MetricTables {
+        let mut tables = MetricTables::new(DayStart::default());
+        for stop in [
+            stop("2022-09-05", "1001", "Richmond", "08:05:00", 100, 20, 500),
+            stop("2022-09-05", "1001", "Flinders Street", "08:20:00", 0, 300, 200),
+            stop("2022-09-12", "1001", "Richmond", "08:10:00", 150, 10, 650),
+        ] {
+            tables.add(&stop);
+        }
+        tables
+    }
+
+    fn rule(when: &str) -> Result<Rule, Box<dyn Error>> {
+        Rule::from_table(HashMap::from([("when".to_string(), when.to_string())]))
+    }
+
+    #[test]
+    fn each_metric_is_measured() {
+        let tables = tables();
+        let cases: [(Metric, &[(&str, &str, f64)]); 5] = [
+            (Metric::BucketMovements, &[
+                ("Pakenham", "2022-09-05 08:00", 120.0),
+                ("Pakenham", "2022-09-05 08:15", 300.0),
+                ("Pakenham", "2022-09-12 08:00", 160.0),
+            ]),
+            (Metric::ServicePeakLoad, &[
+                ("1001", "2022-09-05 08:05:00", 500.0),
+                ("1001", "2022-09-12 08:10:00", 650.0),
+            ]),
+            (Metric::LineDailyMovements, &[
+                ("Pakenham", "2022-09-05", 420.0),
+                ("Pakenham", "2022-09-12", 160.0),
+            ]),
+            (Metric::StationDailyBoardings, &[
+                ("Flinders Street", "2022-09-05", 0.0),
+                ("Richmond", "2022-09-05", 100.0),
+                ("Richmond", "2022-09-12", 150.0),
+            ]),
+            // Flinders Street had no boardings a week earlier, and nothing
+            // is a week before the 5th.
+            (Metric::StationBoardingsWeekOnWeek, &[
+                ("Richmond", "2022-09-12", 50.0),
+            ]),
+        ];
+
+        for (metric, expected) in cases {
+            let observations = tables.observations(metric);
+            let observed: Vec<(&str, &str, f64)> = observations.iter()
+                .map(|observation| (observation.entity[0].1.as_str(), observation.timestamp.as_str(), observation.value))
+                .collect();
+            assert_eq!(observed, expected, "{}", metric.name());
+        }
+    }
+
+    #[test]
+    fn comparators_hold_at_the_boundary() {
+        // Whether each comparator holds just below, at and just above 100.
+        let cases = [
+            (">", [false, false, true]),
+            (">=", [false, true, true]),
+            ("<", [true, false, false]),
+            ("<=", [true, true, false]),
+            ("==", [false, true, false]),
+            ("!=", [true, false, true]),
+        ];
+        for (symbol, expected) in cases {
+            let comparator = Comparator::parse(symbol).unwrap();
+            assert_eq!(comparator.symbol(), symbol);
+            let held = [99.9, 100.0, 100.1].map(|value| comparator.holds(value, 100.0));
+            assert_eq!(held, expected, "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn rules_fire_on_the_matching_observations() {
+        let rules = [rule("line_daily_movements >= 420").unwrap(), rule("service_peak_load > 1_000").unwrap()];
+        let findings = evaluate(&rules, &tables());
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule.name, "line_daily_movements >= 420");
+        assert_eq!(findings[0].matches.len(), 1);
+        assert_eq!(findings[0].matches[0].timestamp, "2022-09-05");
+    }
+
+    #[test]
+    fn unknown_metrics_and_comparators_are_errors() {
+        let err = rule("daily_movements > 100").err().unwrap().to_string();
+        assert!(err.starts_with("unknown metric 'daily_movements', expected one of bucket_movements, "), "{}", err);
+
+        let err = rule("bucket_movements => 100").err().unwrap().to_string();
+        assert_eq!(err, "unknown comparator '=>', expected one of > >= < <= == !=");
+    }
+}
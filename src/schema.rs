@@ -0,0 +1,725 @@
+// Registry of every file the binaries write: name pattern, producing binary,
+// and for tabular outputs the columns with their types and units. The CSV
+// writers take their header rows from here, so the registry and the files
+// can't disagree on column names.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Column {
+    pub name: &'static str,
+    // "string", "integer", "number", "date" (YYYY-MM-DD) or "time" (HH:MM).
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    // Empty when the column has no unit.
+    pub unit: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct OutputSchema {
+    // Relative to the working directory; `<...>` marks a substituted part.
+    pub path: &'static str,
+    pub binary: &'static str,
+    // "csv", "png", "png|svg" (generateGraph's --format), "bincode",
+    // "markdown", "json", "sqlite" or "parquet".
+    pub format: &'static str,
+    pub description: &'static str,
+    pub columns: &'static [Column],
+}
+
+impl OutputSchema {
+    /// The CSV header row for this output.
+    pub fn header(&self) -> String {
+        self.columns.iter().map(|column| column.name).collect::<Vec<_>>().join(",")
+    }
+}
+
+const fn column(name: &'static str, kind: &'static str, unit: &'static str, description: &'static str) -> Column {
+    Column { name, kind, unit, description }
+}
+
+const fn chart(path: &'static str, binary: &'static str, description: &'static str) -> OutputSchema {
+    OutputSchema { path, binary, format: "png", description, columns: &[] }
+}
+
+// A generateGraph chart, written as a PNG or, with --format svg, an SVG.
+const fn graph_chart(path: &'static str, binary: &'static str, description: &'static str) -> OutputSchema {
+    OutputSchema { path, binary, format: "png|svg", description, columns: &[] }
+}
+
+const LINE: Column = column("Line", "string", "", "Line name as given in Line_Name");
+
+pub const HOURLY_MOVEMENTS: OutputSchema = OutputSchema {
+    path: "processed/<line>.csv",
+    binary: "generateData",
+    format: "csv",
+    description: "Movements per business hour on --date, or averaged over every date with --all-dates",
+    columns: &[
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the hour"),
+    ],
+};
+
+pub const HOURLY_MOVEMENTS_BY_DATE: OutputSchema = OutputSchema {
+    path: "processed/<date>_<line>.csv",
+    binary: "generateData",
+    format: "csv",
+    description: "Movements per business hour on one business date, for every date in the input when neither --date nor --all-dates is given",
+    columns: &[
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the hour"),
+    ],
+};
+
+pub const STATION_TOTALS: OutputSchema = OutputSchema {
+    path: "processed/station_totals.csv",
+    binary: "generateData --by station",
+    format: "csv",
+    description: "Boardings and alightings per station across every line, busiest (most movements) first",
+    columns: &[
+        column("Station_Name", "string", "", "Station_Name, quoted if it holds a comma or quote"),
+        column("Total_Boardings", "integer", "passengers", "Passenger_Boardings at the station"),
+        column("Total_Alightings", "integer", "passengers", "Passenger_Alightings at the station"),
+        column("Services", "integer", "stops", "Records at the station, one per service stopping there"),
+        column("Avg_Boardings_Per_Service", "number", "passengers", "Total_Boardings / Services"),
+    ],
+};
+
+pub const HOURLY_MOVEMENTS_DST: OutputSchema = OutputSchema {
+    path: "processed/<line>.csv",
+    binary: "generateData --dst-notes",
+    format: "csv",
+    description: "As processed/<line>.csv or processed/<date>_<line>.csv, with the daylight saving hour annotated",
+    columns: &[
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the hour"),
+        column("Note", "string", "", "Set on the hour holding 02:00-02:59 (23 by default) when it was skipped or repeated, otherwise empty"),
+    ],
+};
+
+pub const AVG_OCCUPANCY: OutputSchema = OutputSchema {
+    path: "processed/avg_occupancy.csv",
+    binary: "generateData --avg-occupancy",
+    format: "csv",
+    description: "All-day average departure load per line, each stop departure weighted equally",
+    columns: &[
+        LINE,
+        column("Stops", "integer", "stop departures", "Records aggregated for the line"),
+        column("Avg_Departure_Load", "number", "passengers", "Mean Passenger_Departure_Load; empty for a line with no stops"),
+    ],
+};
+
+pub const MAX_LOADS: OutputSchema = OutputSchema {
+    path: "processed/loads/<line>.csv",
+    binary: "generateData --max-load",
+    format: "csv",
+    description: "The largest departure load on the line in each business hour of the time series: on --date, or over every date with --all-dates",
+    columns: &[
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Max_Departure_Load", "integer", "passengers", "Largest Passenger_Departure_Load departing in the hour; empty with no departures"),
+    ],
+};
+
+pub const MAX_LOADS_CAPACITY: OutputSchema = OutputSchema {
+    path: "processed/loads/<line>.csv",
+    binary: "generateData --max-load --capacity <n>",
+    format: "csv",
+    description: "As processed/loads/<line>.csv, with the load as a share of a nominal train capacity",
+    columns: &[
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Max_Departure_Load", "integer", "passengers", "Largest Passenger_Departure_Load departing in the hour; empty with no departures"),
+        column("Load_Percent", "number", "percent", "Max_Departure_Load as a percentage of --capacity; over 100 when crush-loaded"),
+    ],
+};
+
+pub const QUARTER_HOUR_MOVEMENTS: OutputSchema = OutputSchema {
+    path: "processed/<line>.csv",
+    binary: "generateData-15min",
+    format: "csv",
+    description: "Movements per 15-minute block on the first business date in the input, or --date",
+    columns: &[
+        column("Time (Decimal)", "number", "hours", "Block start as decimal hours from 3.00 (the --day-start hour); values past 24 are after midnight"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the block"),
+    ],
+};
+
+pub const QUARTER_HOUR_BY_DATE: OutputSchema = OutputSchema {
+    path: "processed/<date>_<line>.csv",
+    binary: "generateDataSpecifier",
+    format: "csv",
+    description: "Movements per 15-minute block for one business date and line",
+    columns: &[
+        column("Time", "time", "", "Block start from 03:00 (--day-start); hours past 23 are after midnight, e.g. 25:15"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the block"),
+    ],
+};
+
+pub const QUARTER_HOUR_STATION: OutputSchema = OutputSchema {
+    path: "processed/<date>_<station>.csv",
+    binary: "generateDataSpecifier --station",
+    format: "csv",
+    description: "Movements per 15-minute interval at one station on one business date, every date or --date; a '/' in the station name becomes '-'",
+    columns: &[
+        column("Time", "time", "HH:MM", "Start of the interval, business day order"),
+        column("Movements", "number", "passengers", "Boardings plus alightings at the station departing in the interval"),
+    ],
+};
+
+pub const BLOCK_MOVEMENTS: OutputSchema = OutputSchema {
+    path: "processed/<line>_<minutes>min.csv",
+    binary: "generateData5min",
+    format: "csv",
+    description: "Movements per block of the requested size on the first business date in the input, or --date",
+    columns: &[
+        column("Time", "number", "hours", "Block start as decimal hours from 3.00 (the --day-start hour); values past 24 are after midnight"),
+        column("Movements", "number", "passengers", "Boardings plus alightings departing in the block"),
+    ],
+};
+
+pub const SUMMARY_JSON: OutputSchema = OutputSchema {
+    path: "processed/summary.json",
+    binary: "generateData, generateData-15min or generateData5min --json or --format json",
+    format: "json",
+    description: "Every line's boardings, alightings and services (distinct business date and train number pairs) over every record read, with the time series its CSVs hold, each tagged with its business date (null when averaged); see src/summary.rs for the layout",
+    columns: &[],
+};
+
+pub const SQLITE_EXPORT: OutputSchema = OutputSchema {
+    path: "<path>",
+    binary: "generateData, generateData-15min or generateData5min --sqlite",
+    format: "sqlite",
+    description: "Tables line_totals(line, boardings, alightings, services), hourly(line, business_date, hour, movements) and station_totals(station, lat, lon, boardings, alightings), created if missing and upserted on every run; see src/sqlite.rs",
+    columns: &[],
+};
+
+pub const ARRIVAL_PROFILE: OutputSchema = OutputSchema {
+    path: "processed/arrival_profile.csv",
+    binary: "arrivals",
+    format: "csv",
+    description: "Alightings at the selected stations by scheduled arrival time, for one date or averaged over weekdays",
+    columns: &[
+        column("Time", "time", "", "Interval start from 03:00 (--day-start); hours past 23 are after midnight, e.g. 25:15"),
+        column("Alightings", "number", "passengers", "Alightings arriving in the 15-minute interval, per day"),
+    ],
+};
+
+pub const DAILY_TRENDS: OutputSchema = OutputSchema {
+    path: "daily_trends.csv",
+    binary: "generateGraph --daily-trends",
+    format: "csv",
+    description: "Total movements per line per business date and the change from the previous day",
+    columns: &[
+        LINE,
+        column("Date", "date", "", "Business date"),
+        column("Movements", "integer", "passengers", "Boardings plus alightings over the business date"),
+        column("Change_Pct", "number", "%", "Change from the previous calendar day; empty after a gap or a zero day"),
+    ],
+};
+
+pub const PEAK_WINDOWS: OutputSchema = OutputSchema {
+    path: "peak_windows.csv",
+    binary: "generateGraph --peak-window",
+    format: "csv",
+    description: "Total movements per line in each time band, by scheduled departure time",
+    columns: &[
+        LINE,
+        column("AM Peak", "integer", "passengers", "Departures in --am-peak (default 07:00-09:30)"),
+        column("Interpeak", "integer", "passengers", "Departures between the AM and PM peaks"),
+        column("PM Peak", "integer", "passengers", "Departures in --pm-peak (default 15:00-18:30)"),
+        column("Off-peak", "integer", "passengers", "All other departures, including overnight"),
+    ],
+};
+
+pub const PEAK_HOURS: OutputSchema = OutputSchema {
+    path: "peaks.csv",
+    binary: "generateGraph --peak-hours",
+    format: "csv",
+    description: "Each line's busiest business hour; the earliest on a tie, empty for a line with no movements",
+    columns: &[
+        LINE,
+        column("Peak Hour", "integer", "", "Business hour, 0 being the hour from --day-start"),
+        column("Peak Start", "time", "", "Clock time the peak hour starts"),
+        column("Movements", "integer", "passengers", "Boardings plus alightings in the peak hour"),
+    ],
+};
+
+pub const SEASONALITY: OutputSchema = OutputSchema {
+    path: "processed/seasonality.csv",
+    binary: "seasonality",
+    format: "csv",
+    description: "Average movements per business hour by calendar month, per line, over weekdays (all days with --include-weekends); followed by Hour_0 to Hour_23",
+    columns: &[
+        LINE,
+        column("Month", "integer", "", "Calendar month of the business date, 1-12, across all years"),
+        column("Days", "integer", "days", "Dates observed for the line in the month"),
+        column("Low_Confidence", "string", "", "true when Days is below --min-days (default 10)"),
+    ],
+};
+
+pub const SCREENLINE: OutputSchema = OutputSchema {
+    path: "processed/screenline.csv",
+    binary: "screenline",
+    format: "csv",
+    description: "Passengers crossing --screenline-chainage on --line by crossing time, per direction, for one date or averaged over weekdays",
+    columns: &[
+        column("Time", "time", "", "Interval start from 03:00 (--day-start); hours past 23 are after midnight, e.g. 25:15"),
+        column("Up_Passengers", "number", "passengers", "Departure load from the last stop before the screenline on up services crossing in the interval, per day"),
+        column("Down_Passengers", "number", "passengers", "The same for down services"),
+        column("Up_Services", "number", "services", "Up services crossing in the interval, per day"),
+        column("Down_Services", "number", "services", "Down services crossing in the interval, per day"),
+    ],
+};
+
+pub const LOAD_PROFILE: OutputSchema = OutputSchema {
+    path: "processed/load_profile_<line>_<date>_<direction>.csv",
+    binary: "loadProfile",
+    format: "csv",
+    description: "Departure load at each station of --line in one --direction on one business date, averaged over the services leaving it, by chainage; --peak-only keeps services at the city end from 07:00 to 09:00",
+    columns: &[
+        column("Station_Name", "string", "", "Station, nearest the city first"),
+        column("Chainage_Km", "number", "km", "Distance from the city, converted from metres or kilometres as detected for the line"),
+        column("Services", "number", "services", "Services departing the station that were counted"),
+        column("Average_Departure_Load", "number", "passengers", "Mean Passenger_Departure_Load over those services"),
+        column("Max_Departure_Load", "number", "passengers", "Largest Passenger_Departure_Load of those services"),
+    ],
+};
+
+pub const PEAK_LOADS: OutputSchema = OutputSchema {
+    path: "processed/peak_loads.csv",
+    binary: "peakLoads",
+    format: "csv",
+    description: "One row per service (Train_Number on a Business_Date) at the stop it left most crowded, most crowded first; --top keeps the first n",
+    columns: &[
+        column("Business_Date", "date", "", "Business date the service ran on"),
+        column("Train_Number", "string", "", "Train_Number; repeats across dates"),
+        column("Line_Name", "string", "", "Line the service ran on"),
+        column("Direction", "string", "", "U (Up) or D (Down)"),
+        column("Origin", "string", "", "Origin_Station of the service"),
+        column("Destination", "string", "", "Destination_Station of the service"),
+        column("Peak_Station", "string", "", "Station the service left with its largest departure load; the earlier stop on a tie"),
+        column("Peak_Load", "number", "passengers", "Passenger_Departure_Load at that station"),
+        column("Departure_Time", "time", "HH:MM:SS", "Departure_Time_Scheduled from that station"),
+    ],
+};
+
+pub const STATIONS: OutputSchema = OutputSchema {
+    path: "processed/stations.csv",
+    binary: "stations export",
+    format: "csv",
+    description: "One row per station",
+    columns: &[
+        column("Station", "string", "", "Station_Name"),
+        column("Latitude", "number", "degrees", "First latitude recorded for the station"),
+        column("Longitude", "number", "degrees", "First longitude recorded for the station"),
+        column("Lines", "string", "", "Lines serving the station, separated by ';'"),
+        column("Total_Movements", "integer", "passengers", "Boardings plus alightings across the input"),
+    ],
+};
+
+pub const STATIONS_GEOJSON: OutputSchema = OutputSchema {
+    path: "processed/stations.geojson",
+    binary: "stationsGeojson",
+    format: "json",
+    description: "GeoJSON FeatureCollection with a Point per station (longitude, latitude) and properties name, boardings and alightings summed over every line, and lines, the lines serving it; stations without coordinates are left out",
+    columns: &[],
+};
+
+pub const STATION_SEQUENCE: OutputSchema = OutputSchema {
+    path: "processed/station_sequence.csv",
+    binary: "stations export",
+    format: "csv",
+    description: "Each line's stations ordered by chainage",
+    columns: &[
+        LINE,
+        column("Order", "integer", "", "0-based position along the line"),
+        column("Station", "string", "", "Station_Name"),
+        column("Chainage", "integer", "m", "Modal chainage recorded for the station on the line"),
+    ],
+};
+
+pub const LINE_STATIONS: OutputSchema = OutputSchema {
+    path: "processed/stations_<line>.csv",
+    binary: "stations export --by-station",
+    format: "csv",
+    description: "One line's stations with their boardings and alightings on that line, in stopping order or by chainage with --station-sort chainage",
+    columns: &[
+        column("Station", "string", "", "Station_Name"),
+        column("Stop_Sequence", "integer", "", "Most common Stop_Sequence_Number on Down services, or Up services where no Down service stops; empty if neither"),
+        column("Chainage", "integer", "m", "Modal chainage recorded for the station on the line"),
+        column("Boardings", "integer", "passengers", "Passenger_Boardings at the station on the line across the input"),
+        column("Alightings", "integer", "passengers", "Passenger_Alightings at the station on the line across the input"),
+        column("Total_Movements", "integer", "passengers", "Boardings plus alightings"),
+    ],
+};
+
+pub const STATION_GROUPS: OutputSchema = OutputSchema {
+    path: "processed/station_groups.csv",
+    binary: "stations export --station-groups",
+    format: "csv",
+    description: "Station totals rolled up to the groups in the mapping file",
+    columns: &[
+        column("Group", "string", "", "Group from the mapping, or Unmapped"),
+        column("Stations", "integer", "stations", "Stations in the group seen in the data"),
+        column("Total_Movements", "integer", "passengers", "Boardings plus alightings across the input"),
+    ],
+};
+
+pub const STATION_GROUP_HOURLY: OutputSchema = OutputSchema {
+    path: "processed/station_group_hourly.csv",
+    binary: "stations export --station-groups",
+    format: "csv",
+    description: "Each group's movements per business hour, summed over every date",
+    columns: &[
+        column("Group", "string", "", "Group from the mapping, or Unmapped"),
+        column("Hour", "integer", "business hour", "0 = 03:00-03:59 through 23 = 02:00-02:59, or from the --day-start hour"),
+        column("Movements", "integer", "passengers", "Boardings plus alightings departing in the hour"),
+    ],
+};
+
+pub const TRAIN_PROFILE: OutputSchema = OutputSchema {
+    path: "processed/train_<train>_<date>.csv",
+    binary: "trainProfile",
+    format: "csv",
+    description: "One service's stops in stop-sequence order",
+    columns: &[
+        column("Stop_Sequence", "integer", "", "Stop_Sequence_Number"),
+        column("Station", "string", "", "Station_Name"),
+        column("Arrival", "string", "", "Arrival_Time_Scheduled as recorded (HH:MM:SS)"),
+        column("Departure", "string", "", "Departure_Time_Scheduled as recorded (HH:MM:SS)"),
+        column("Boardings", "number", "passengers", "Passenger_Boardings"),
+        column("Alightings", "number", "passengers", "Passenger_Alightings"),
+        column("Arrival_Load", "number", "passengers", "Passenger_Arrival_Load"),
+        column("Departure_Load", "number", "passengers", "Passenger_Departure_Load"),
+    ],
+};
+
+pub const OD_MATRIX: OutputSchema = OutputSchema {
+    path: "processed/od_<line>.csv",
+    binary: "odMatrix",
+    format: "csv",
+    description: "Passenger_Boardings on one line's services by the services' origin (rows) and destination (columns), across the input; followed by one column per destination station, in the same order as the rows",
+    columns: &[
+        column("Origin", "string", "", "Origin_Station of the services counted in the row"),
+    ],
+};
+
+pub const OD_SERVICES: OutputSchema = OutputSchema {
+    path: "processed/od_services.csv",
+    binary: "odMatrix",
+    format: "csv",
+    description: "Services on every line by their origin (rows) and destination (columns), across the input; services starting and ending at one station are on the diagonal. Followed by one column per destination station, in the same order as the rows",
+    columns: &[
+        column("Origin", "string", "", "Origin_Station of the services counted in the row"),
+    ],
+};
+
+pub const OD_PAIRS: OutputSchema = OutputSchema {
+    path: "processed/od_pairs.csv",
+    binary: "odMatrix",
+    format: "csv",
+    description: "One row per origin and destination pair on every line, most services first, across the input",
+    columns: &[
+        column("Origin_Station", "string", "", "Where the services start"),
+        column("Destination_Station", "string", "", "Where the services end, possibly the origin itself"),
+        column("Services", "number", "services", "Distinct business date and train number pairs"),
+        column("Total_Boardings", "number", "passengers", "Passenger_Boardings over every stop of the services"),
+    ],
+};
+
+pub const OD_PATTERNS: OutputSchema = OutputSchema {
+    path: "processed/od_patterns_<line>.csv",
+    binary: "odMatrix",
+    format: "csv",
+    description: "One row per service pattern on one line, busiest first by boardings, across the input",
+    columns: &[
+        column("Origin_Station", "string", "", "Where the pattern's services start"),
+        column("Destination_Station", "string", "", "Where the pattern's services end"),
+        column("Direction", "string", "", "U (Up) or D (Down)"),
+        column("Services", "number", "services", "Distinct business date and train number pairs"),
+        column("Total_Boardings", "number", "passengers", "Passenger_Boardings over every stop of the services"),
+        column("Total_Alightings", "number", "passengers", "Passenger_Alightings over every stop of the services"),
+    ],
+};
+
+pub const OD_PATTERNS_ALL: OutputSchema = OutputSchema {
+    path: "processed/od_patterns.csv",
+    binary: "odMatrix",
+    format: "csv",
+    description: "Every line's service patterns in one file, busiest first by boardings",
+    columns: &[
+        column("Line_Name", "string", "", "Line the services run on"),
+        column("Origin_Station", "string", "", "Where the pattern's services start"),
+        column("Destination_Station", "string", "", "Where the pattern's services end"),
+        column("Direction", "string", "", "U (Up) or D (Down)"),
+        column("Services", "number", "services", "Distinct business date and train number pairs"),
+        column("Total_Boardings", "number", "passengers", "Passenger_Boardings over every stop of the services"),
+        column("Total_Alightings", "number", "passengers", "Passenger_Alightings over every stop of the services"),
+    ],
+};
+
+pub const UTILISATION: OutputSchema = OutputSchema {
+    path: "processed/utilisation.csv",
+    binary: "utilisation",
+    format: "csv",
+    description: "Offered seat-km and passenger-km per line and time band",
+    columns: &[
+        LINE,
+        column("Band", "string", "", "AM Peak, Interpeak, PM Peak or Off-peak"),
+        column("Services", "integer", "services", "Services with at least one segment in the band"),
+        column("Seat_Km", "number", "seat-km", "Capacity times segment distance"),
+        column("Passenger_Km", "number", "passenger-km", "Departure load times segment distance"),
+        column("Utilisation", "number", "ratio", "Passenger_Km / Seat_Km"),
+    ],
+};
+
+pub const AGGREGATES: OutputSchema = OutputSchema {
+    path: "<path>",
+    binary: "generateData, generateGraph --save-agg",
+    format: "bincode",
+    description: "Versioned binary aggregate file, reloaded with --load-agg",
+    columns: &[],
+};
+
+pub const INDEX: OutputSchema = OutputSchema {
+    path: "<path>, default index.bin",
+    binary: "index",
+    format: "bincode",
+    description: "Versioned, interned copy of data.csv with its hash, read with --from-index",
+    columns: &[],
+};
+
+pub const CONVERT_PARQUET: OutputSchema = OutputSchema {
+    path: "<output>, default data.parquet",
+    binary: "convert",
+    format: "parquet",
+    description: "data.csv's columns with their names and order, typed: Business_Date as Date32, the scheduled times as Time32 milliseconds (null when blank), coordinates as Float64, the rest as Int32 or UTF-8",
+    columns: &[],
+};
+
+pub const CONVERT_REJECTS: OutputSchema = OutputSchema {
+    path: "<output without .parquet>_rejects.csv",
+    binary: "convert",
+    format: "csv",
+    description: "Rows left out of the Parquet file because a date, coordinate or non-blank time doesn't parse; the two columns below are followed by the row's data.csv columns unchanged",
+    columns: &[
+        column("Row", "integer", "", "Data row in the input, counting from 1 after the header"),
+        column("Reason", "string", "", "The field that doesn't parse and its value"),
+    ],
+};
+
+pub const PROFILE_MARKDOWN: OutputSchema = OutputSchema {
+    path: "processed/profile.md",
+    binary: "profile",
+    format: "markdown",
+    description: "Per-column profile of data.csv: inferred type, distinct, empty and unparseable counts, min/max, samples",
+    columns: &[],
+};
+
+pub const PROFILE_JSON: OutputSchema = OutputSchema {
+    path: "processed/profile.json",
+    binary: "profile --format json",
+    format: "json",
+    description: "The data profile as JSON",
+    columns: &[],
+};
+
+pub const TOP_RECORDS: OutputSchema = OutputSchema {
+    path: "processed/top_records.csv",
+    binary: "profile --top-records",
+    format: "csv",
+    description: "The N rows with the highest --by value (default Passenger_Boardings), followed by every data.csv column unchanged",
+    columns: &[
+        column("Rank", "integer", "", "1 for the highest value; ties keep file order"),
+        column("Row", "integer", "", "1-based data row in data.csv, excluding the header"),
+    ],
+};
+
+pub const FINDINGS: OutputSchema = OutputSchema {
+    path: "processed/findings.json",
+    binary: "alerts",
+    format: "json",
+    description: "Each --rules rule that triggered, with the entities, timestamps and values that matched",
+    columns: &[],
+};
+
+pub const EVENT_DAY_MARKDOWN: OutputSchema = OutputSchema {
+    path: "processed/event_day.md",
+    binary: "event",
+    format: "markdown",
+    description: "Movements on --date against the average of comparable dates: network, per line and per station deltas and the hours with the largest increase",
+    columns: &[],
+};
+
+pub const EVENT_DAY_JSON: OutputSchema = OutputSchema {
+    path: "processed/event_day.json",
+    binary: "event --format json",
+    format: "json",
+    description: "The event-day report as JSON",
+    columns: &[],
+};
+
+pub const CHART_SKIPS: OutputSchema = OutputSchema {
+    path: "processed/chart_skips.json",
+    binary: "every binary that draws charts",
+    format: "json",
+    description: "Charts drawn and skipped by the latest run, with the reason each skipped chart had nothing to draw",
+    columns: &[],
+};
+
+pub const SCHEMA_JSON: OutputSchema = OutputSchema {
+    path: "processed/schema.json",
+    binary: "schema",
+    format: "json",
+    description: "This registry as JSON",
+    columns: &[],
+};
+
+/// Every output, in the order they're listed by the `schema` binary.
+pub const OUTPUTS: &[OutputSchema] = &[
+    HOURLY_MOVEMENTS,
+    HOURLY_MOVEMENTS_BY_DATE,
+    HOURLY_MOVEMENTS_DST,
+    STATION_TOTALS,
+    AVG_OCCUPANCY,
+    MAX_LOADS,
+    MAX_LOADS_CAPACITY,
+    QUARTER_HOUR_MOVEMENTS,
+    QUARTER_HOUR_BY_DATE,
+    QUARTER_HOUR_STATION,
+    BLOCK_MOVEMENTS,
+    SUMMARY_JSON,
+    SQLITE_EXPORT,
+    ARRIVAL_PROFILE,
+    DAILY_TRENDS,
+    PEAK_WINDOWS,
+    PEAK_HOURS,
+    SEASONALITY,
+    SCREENLINE,
+    LOAD_PROFILE,
+    PEAK_LOADS,
+    STATIONS,
+    STATIONS_GEOJSON,
+    STATION_SEQUENCE,
+    LINE_STATIONS,
+    STATION_GROUPS,
+    STATION_GROUP_HOURLY,
+    TRAIN_PROFILE,
+    OD_MATRIX,
+    OD_SERVICES,
+    OD_PAIRS,
+    OD_PATTERNS,
+    OD_PATTERNS_ALL,
+    UTILISATION,
+    AGGREGATES,
+    INDEX,
+    CONVERT_PARQUET,
+    CONVERT_REJECTS,
+    PROFILE_MARKDOWN,
+    PROFILE_JSON,
+    TOP_RECORDS,
+    FINDINGS,
+    EVENT_DAY_MARKDOWN,
+    EVENT_DAY_JSON,
+    CHART_SKIPS,
+    SCHEMA_JSON,
+    graph_chart("total_movements_chart.<png|svg>", "generateGraph", "Total movements per line"),
+    graph_chart("time_series_chart.<png|svg>", "generateGraph", "Hourly movements per line on the first business date, --date, or averaged with --all-dates"),
+    graph_chart("cumulative_time_series_chart.<png|svg>", "generateGraph", "Cumulative hourly movements per line"),
+    graph_chart("top_stations_chart.<png|svg>", "generateGraph", "The busiest stations across every line by boardings plus alightings, 20 or --top-stations"),
+    graph_chart("daily_trends_chart.<png|svg>", "generateGraph --daily-trends", "Day-over-day change per line"),
+    graph_chart("peak_windows_chart.<png|svg>", "generateGraph --peak-window", "Movements per line stacked by time band"),
+    graph_chart("boardings_alightings_chart.<png|svg>", "generateGraph --boardings-alightings", "Boardings and alightings per line, stacked"),
+    graph_chart("direction_movements_chart.<png|svg>", "generateGraph --direction-chart", "Up and Down total movements per line, side by side"),
+    graph_chart("heatmap_chart.<png|svg>", "generateGraph --heatmap", "Hourly movements per line as a grid of shaded cells, busiest line at the top, with a color scale"),
+    graph_chart("max_load_chart.<png|svg>", "generateGraph --max-load [--capacity <n>]", "Largest departure load per line in each business hour, or its percentage of --capacity with a line at 100%"),
+    graph_chart("station_heatmap_chart.<png|svg>", "generateGraph --station-heatmap --line <name>", "Hourly movements per station on one line as a grid of shaded cells, in stop sequence order, with a color scale; white where a station had no stops"),
+    chart("station_groups_chart.png", "stations export --station-groups", "Total movements per station group"),
+    chart("arrival_profile_chart.png", "arrivals", "Alightings per 15-minute arrival interval"),
+    chart("seasonality_<line>.png", "seasonality", "Heatmap of average hourly movements by month"),
+    chart("screenline_chart.png", "screenline", "Passengers crossing the screenline per 15-minute interval, by direction"),
+    chart("load_profile_<line>_<date>_<direction>.png", "loadProfile", "Average departure load along the line by chainage, and the maximum with --max"),
+    chart("event_day_chart.png", "event", "Hourly movements on the event date against the comparable average, for the most-affected line and station"),
+    chart("utilisation_chart.png", "utilisation", "Utilisation per line and time band"),
+    chart("train_<train>_<date>.png", "trainProfile --chart", "One service's load at each stop"),
+];
+
+/// Conventions shared by every output.
+pub const CONVENTIONS: &[&str] = &[
+    "The business day runs from 03:00 to 02:59 the next calendar morning, and belongs to the date it starts on. --day-start moves the boundary to another whole hour; stops on the far side of it count on the neighbouring date.",
+    "Times are scheduled Australia/Melbourne local times.",
+    "Movements are boardings plus alightings.",
+    "Dates are YYYY-MM-DD.",
+    "Non-integer values are written in fixed or scientific notation per --csv-float-format.",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writer_headers_match_their_registry_entries() {
+        // The header row each CSV writer puts at the top of its file.
+        let writers: &[(&OutputSchema, &str)] = &[
+            (&HOURLY_MOVEMENTS, "Hour,Movements"),
+            (&HOURLY_MOVEMENTS_BY_DATE, "Hour,Movements"),
+            (&HOURLY_MOVEMENTS_DST, "Hour,Movements,Note"),
+            (&STATION_TOTALS, "Station_Name,Total_Boardings,Total_Alightings,Services,Avg_Boardings_Per_Service"),
+            (&AVG_OCCUPANCY, "Line,Stops,Avg_Departure_Load"),
+            (&MAX_LOADS, "Hour,Max_Departure_Load"),
+            (&MAX_LOADS_CAPACITY, "Hour,Max_Departure_Load,Load_Percent"),
+            (&QUARTER_HOUR_MOVEMENTS, "Time (Decimal),Movements"),
+            (&QUARTER_HOUR_BY_DATE, "Time,Movements"),
+            (&QUARTER_HOUR_STATION, "Time,Movements"),
+            (&BLOCK_MOVEMENTS, "Time,Movements"),
+            (&ARRIVAL_PROFILE, "Time,Alightings"),
+            (&DAILY_TRENDS, "Line,Date,Movements,Change_Pct"),
+            (&PEAK_WINDOWS, "Line,AM Peak,Interpeak,PM Peak,Off-peak"),
+            (&PEAK_HOURS, "Line,Peak Hour,Peak Start,Movements"),
+            (&SEASONALITY, "Line,Month,Days,Low_Confidence"),
+            (&SCREENLINE, "Time,Up_Passengers,Down_Passengers,Up_Services,Down_Services"),
+            (&LOAD_PROFILE, "Station_Name,Chainage_Km,Services,Average_Departure_Load,Max_Departure_Load"),
+            (&PEAK_LOADS, "Business_Date,Train_Number,Line_Name,Direction,Origin,Destination,Peak_Station,Peak_Load,Departure_Time"),
+            (&STATIONS, "Station,Latitude,Longitude,Lines,Total_Movements"),
+            (&STATION_SEQUENCE, "Line,Order,Station,Chainage"),
+            (&LINE_STATIONS, "Station,Stop_Sequence,Chainage,Boardings,Alightings,Total_Movements"),
+            (&STATION_GROUPS, "Group,Stations,Total_Movements"),
+            (&STATION_GROUP_HOURLY, "Group,Hour,Movements"),
+            (&TRAIN_PROFILE, "Stop_Sequence,Station,Arrival,Departure,Boardings,Alightings,Arrival_Load,Departure_Load"),
+            // The matrices append a column per destination station.
+            (&OD_MATRIX, "Origin"),
+            (&OD_SERVICES, "Origin"),
+            (&OD_PAIRS, "Origin_Station,Destination_Station,Services,Total_Boardings"),
+            (&OD_PATTERNS, "Origin_Station,Destination_Station,Direction,Services,Total_Boardings,Total_Alightings"),
+            (&OD_PATTERNS_ALL, "Line_Name,Origin_Station,Destination_Station,Direction,Services,Total_Boardings,Total_Alightings"),
+            (&UTILISATION, "Line,Band,Services,Seat_Km,Passenger_Km,Utilisation"),
+            (&CONVERT_REJECTS, "Row,Reason"),
+            (&TOP_RECORDS, "Rank,Row"),
+        ];
+
+        for (writer, header) in writers {
+            assert_eq!(writer.header(), *header, "{} ({})", writer.path, writer.binary);
+            let registered = OUTPUTS.iter()
+                .find(|output| output.path == writer.path && output.binary == writer.binary)
+                .unwrap_or_else(|| panic!("{} ({}) isn't in OUTPUTS", writer.path, writer.binary));
+            assert_eq!(registered.format, "csv", "{}", writer.path);
+            assert_eq!(registered.header(), *header, "{} ({})", writer.path, writer.binary);
+        }
+
+        // And every CSV in the registry is one of the writers above.
+        for output in OUTPUTS.iter().filter(|output| output.format == "csv") {
+            assert!(
+                writers.iter().any(|(writer, _)| writer.path == output.path && writer.binary == output.binary),
+                "{} ({}) has no writer listed", output.path, output.binary
+            );
+        }
+    }
+
+    #[test]
+    fn generate_graph_charts_follow_format() {
+        for output in OUTPUTS.iter().filter(|output| output.binary.starts_with("generateGraph") && output.format != "csv") {
+            assert_eq!(output.format, "png|svg", "{}", output.path);
+            assert!(output.path.ends_with(".<png|svg>"), "{}", output.path);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+// `screenline`: see `ptv_data::commands::screenline`, which `ptv-data` also runs.
+
+use std::env;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    ptv_data::commands::screenline::run(&args)
+}
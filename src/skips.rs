@@ -0,0 +1,162 @@
+// Empty-chart guard shared by the chart-drawing binaries. A chart with no
+// series, or only zeros, still renders as a valid-looking PNG with a blank
+// plot, which is easy to mistake for a real "no patronage" result. Such
+// charts are skipped with a logged reason and listed in the run summary and
+// in chart_skips.json in the output directory; `--allow-empty-charts` draws
+// them anyway.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::process;
+
+use serde::Serialize;
+
+use crate::cli::{Output, ensure_output_dir};
+
+/// Exit code for a run whose filters left nothing to chart.
+pub const EXIT_NO_RECORDS_MATCHED: i32 = 3;
+
+#[derive(Serialize)]
+struct SkipReport<'a> {
+    binary: &'a str,
+    charts_drawn: usize,
+    charts_skipped: usize,
+    skipped: Vec<SkippedChart<'a>>,
+}
+
+#[derive(Serialize)]
+struct SkippedChart<'a> {
+    chart: &'a str,
+    reason: &'a str,
+}
+
+pub struct ChartSkips {
+    binary: &'static str,
+    output_dir: String,
+    allow_empty: bool,
+    drawn: usize,
+    skipped: Vec<(String, String)>,
+}
+
+impl ChartSkips {
+    /// Tracks the charts drawn by `binary`, reporting to `output`'s
+    /// directory; `allow_empty` is `--allow-empty-charts`.
+    pub fn new(binary: &'static str, output: &Output, allow_empty: bool) -> ChartSkips {
+        ChartSkips {
+            binary,
+            output_dir: output.path().to_string(),
+            allow_empty,
+            drawn: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Whether to draw `chart` from `series`, one set of values per series.
+    /// A chart with no series, or nothing but zeros, is recorded as skipped
+    /// instead unless `--allow-empty-charts` was given.
+    pub fn check<S, V>(&mut self, chart: &str, series: S) -> bool
+    where
+        S: IntoIterator<Item = V>,
+        V: IntoIterator<Item = f64>,
+    {
+        let mut count = 0;
+        let mut any_nonzero = false;
+        for values in series {
+            count += 1;
+            any_nonzero |= values.into_iter().any(|value| value != 0.0);
+        }
+        if count == 0 {
+            self.skip(chart, "no series to draw")
+        } else if !any_nonzero {
+            self.skip(chart, &format!("all {} series are zero", count))
+        } else {
+            self.drawn += 1;
+            true
+        }
+    }
+
+    /// Records `chart` as skipped for `reason`, unless empty charts are
+    /// allowed; returns whether to draw it anyway.
+    pub fn skip(&mut self, chart: &str, reason: &str) -> bool {
+        if self.allow_empty {
+            self.drawn += 1;
+            return true;
+        }
+        eprintln!("Skipping '{}': {} (use --allow-empty-charts to draw it anyway).", chart, reason);
+        self.skipped.push((chart.to_string(), reason.to_string()));
+        false
+    }
+
+    fn json(&self) -> String {
+        let report = SkipReport {
+            binary: self.binary,
+            charts_drawn: self.drawn,
+            charts_skipped: self.skipped.len(),
+            skipped: self.skipped.iter()
+                .map(|(chart, reason)| SkippedChart { chart, reason })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&report).expect("chart skips always serialize")
+    }
+
+    /// Writes chart_skips.json and lists any skipped charts. If
+    /// charts were due but none was drawn, exits with
+    /// `EXIT_NO_RECORDS_MATCHED`.
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        ensure_output_dir(&self.output_dir)?;
+        let mut file = File::create(format!("{}/chart_skips.json", self.output_dir))?;
+        writeln!(file, "{}", self.json())?;
+        if self.skipped.is_empty() {
+            return Ok(());
+        }
+
+        println!("Charts skipped:");
+        for (chart, reason) in &self.skipped {
+            println!("  {:<36} {}", chart, reason);
+        }
+        if self.drawn == 0 {
+            eprintln!("No records matched: no chart had data to draw.");
+            process::exit(EXIT_NO_RECORDS_MATCHED);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_group;
+
+    #[test]
+    fn empty_and_all_zero_charts_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("ptv_data_skips_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let output: Output = parse_group(&["--output-dir", dir]).unwrap();
+        let mut skips = ChartSkips::new("generateGraph", &output, false);
+
+        assert!(!skips.check("no_series.png", Vec::<Vec<f64>>::new()));
+        assert!(!skips.check("zeros.png", [vec![0.0, 0.0], vec![0.0]]));
+        // One non-zero value in any series is enough to draw.
+        assert!(skips.check("boardings.png", [vec![0.0, 0.0], vec![0.0, 12.0]]));
+        skips.finish().unwrap();
+
+        let report: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(format!("{}/chart_skips.json", dir)).unwrap()
+        ).unwrap();
+        assert_eq!(report["binary"], "generateGraph");
+        assert_eq!((report["charts_drawn"].as_u64(), report["charts_skipped"].as_u64()), (Some(1), Some(2)));
+        assert_eq!(report["skipped"][0]["chart"], "no_series.png");
+        assert_eq!(report["skipped"][0]["reason"], "no series to draw");
+        assert_eq!(report["skipped"][1]["reason"], "all 2 series are zero");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn allow_empty_charts_draws_them_anyway() {
+        let mut skips = ChartSkips::new("generateGraph", &Output::default(), true);
+        assert!(skips.check("no_series.png", Vec::<Vec<f64>>::new()));
+        assert!(skips.check("zeros.png", [vec![0.0]]));
+        assert_eq!((skips.drawn, skips.skipped.len()), (2, 0));
+    }
+}
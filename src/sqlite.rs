@@ -0,0 +1,176 @@
+// `--sqlite <path>` for generateData, generateData-15min and generateData5min:
+// upserts a run's aggregates into a SQLite database, so several runs can be
+// queried together without re-parsing their CSVs. The tables are created if
+// missing:
+//
+//   line_totals(line, boardings, alightings, services)   one row per line
+//   hourly(line, business_date, hour, movements)         one row per line, date and hour
+//   station_totals(station, lat, lon, boardings, alightings)
+//
+// `services` counts distinct business date and train number pairs.
+// `hour` is the business hour, 0 being the hour from --day-start; 15- and
+// 5-minute series are summed into hours. A rerun over the same line, date
+// and hour replaces that hourly row, and line_totals and station_totals
+// hold the totals from the latest run to include the line or station.
+// Series averaged over several dates (--all-dates) have no business date
+// and are not written to hourly.
+
+use rusqlite::{Connection, params};
+use std::collections::BTreeMap;
+
+use crate::record::is_placeholder;
+use crate::summary::{Summary, hourly_movements};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS line_totals (
+    line TEXT PRIMARY KEY,
+    boardings INTEGER NOT NULL,
+    alightings INTEGER NOT NULL,
+    services INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hourly (
+    line TEXT NOT NULL,
+    business_date TEXT NOT NULL,
+    hour INTEGER NOT NULL,
+    movements INTEGER NOT NULL,
+    PRIMARY KEY (line, business_date, hour)
+);
+CREATE TABLE IF NOT EXISTS station_totals (
+    station TEXT PRIMARY KEY,
+    lat REAL,
+    lon REAL,
+    boardings INTEGER NOT NULL,
+    alightings INTEGER NOT NULL
+);
+";
+
+/// One station's row in station_totals.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StationRow {
+    /// From the station's first row with usable coordinates; null without.
+    pub coordinates: Option<(f64, f64)>,
+    pub boardings: i64,
+    pub alightings: i64,
+}
+
+/// Station rows by Station_Name.
+pub type Stations = BTreeMap<String, StationRow>;
+
+/// Adds one stop's boardings and alightings to its station's row.
+pub fn add_station(stations: &mut Stations, station: &str, coordinates: Option<(f64, f64)>, boardings: i64, alightings: i64) {
+    let row = stations.entry(station.to_string()).or_default();
+    if row.coordinates.is_none() {
+        row.coordinates = coordinates.filter(|&(lat, lon)| !is_placeholder(lat, lon));
+    }
+    row.boardings += boardings;
+    row.alightings += alightings;
+}
+
+/// Creates the tables in the database at `path` if missing and upserts the
+/// summary's lines and the stations into them, in one transaction. Returns
+/// the number of hourly rows written.
+pub fn export(path: &str, summary: &Summary, stations: &Stations) -> rusqlite::Result<usize> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    let mut hourly_rows = 0;
+    {
+        let mut line_totals = tx.prepare(
+            "INSERT INTO line_totals (line, boardings, alightings, services) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (line) DO UPDATE SET
+                 boardings = excluded.boardings, alightings = excluded.alightings, services = excluded.services",
+        )?;
+        let mut hourly = tx.prepare(
+            "INSERT INTO hourly (line, business_date, hour, movements) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (line, business_date, hour) DO UPDATE SET movements = excluded.movements",
+        )?;
+        for line in &summary.lines {
+            line_totals.execute(params![line.line, line.boardings, line.alightings, line.services])?;
+            for series in &line.series {
+                let Some(business_date) = &series.business_date else { continue };
+                for (hour, movements) in hourly_movements(&series.movements, summary.interval_minutes).into_iter().enumerate() {
+                    hourly.execute(params![line.line, business_date, hour as i64, movements.round() as i64])?;
+                    hourly_rows += 1;
+                }
+            }
+        }
+
+        let mut station_totals = tx.prepare(
+            "INSERT INTO station_totals (station, lat, lon, boardings, alightings) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (station) DO UPDATE SET
+                 lat = excluded.lat, lon = excluded.lon, boardings = excluded.boardings, alightings = excluded.alightings",
+        )?;
+        for (station, row) in stations {
+            let (lat, lon) = (row.coordinates.map(|(lat, _)| lat), row.coordinates.map(|(_, lon)| lon));
+            station_totals.execute(params![station, lat, lon, row.boardings, row.alightings])?;
+        }
+    }
+    tx.commit()?;
+    Ok(hourly_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business::DayStart;
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn reruns_upsert_rather_than_duplicate() {
+        let path = std::env::temp_dir().join(format!("ptv_data_export_{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        // Two days of 15-minute series, 8 intervals summing into 2 hours,
+        // and an average that has no date to be stored under.
+        let mut summary = Summary::new(15, DayStart::default());
+        summary.add_totals("Pakenham", 5_210, 4_980, 812);
+        summary.add_totals("Stony Point", 12, 10, 4);
+        summary.add_series("Pakenham", Some("2022-09-05"), &[1.0, 2.0, 3.0, 4.0, 10.0, 0.0, 0.0, 5.0]);
+        summary.add_series("Pakenham", Some("2022-09-06"), &[0.0; 8]);
+        summary.add_series("Stony Point", None, &[1.5; 8]);
+        let mut stations = Stations::new();
+        add_station(&mut stations, "Flinders Street", Some((0.0, 0.0)), 300, 200);
+        add_station(&mut stations, "Flinders Street", Some((-37.8183, 144.9671)), 10, 0);
+        add_station(&mut stations, "Stony Point", None, 12, 10);
+
+        assert_eq!(export(path, &summary, &stations).unwrap(), 4);
+        let conn = Connection::open(path).unwrap();
+        assert_eq!(count(&conn, "line_totals"), 2);
+        assert_eq!(count(&conn, "hourly"), 4);
+        assert_eq!(count(&conn, "station_totals"), 2);
+        let first_hour: i64 = conn.query_row(
+            "SELECT movements FROM hourly WHERE line = 'Pakenham' AND business_date = '2022-09-05' AND hour = 0",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+        assert_eq!(first_hour, 10);
+        let (lat, boardings): (Option<f64>, i64) = conn.query_row(
+            "SELECT lat, boardings FROM station_totals WHERE station = 'Flinders Street'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!((lat, boardings), (Some(-37.8183), 310));
+        let lat: Option<f64> = conn.query_row(
+            "SELECT lat FROM station_totals WHERE station = 'Stony Point'", [], |row| row.get(0),
+        ).unwrap();
+        assert_eq!(lat, None);
+
+        // A rerun with a new date adds its hours and replaces the rest.
+        summary.add_totals("Pakenham", 6_000, 5_000, 900);
+        summary.add_series("Pakenham", Some("2022-09-07"), &[2.0; 8]);
+        assert_eq!(export(path, &summary, &stations).unwrap(), 6);
+        assert_eq!(count(&conn, "line_totals"), 2);
+        assert_eq!(count(&conn, "hourly"), 6);
+        assert_eq!(count(&conn, "station_totals"), 2);
+        let boardings: i64 = conn.query_row(
+            "SELECT boardings FROM line_totals WHERE line = 'Pakenham'", [], |row| row.get(0),
+        ).unwrap();
+        assert_eq!(boardings, 6_000);
+        drop(conn);
+        std::fs::remove_file(path).unwrap();
+    }
+}
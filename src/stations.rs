@@ -0,0 +1,276 @@
+// Station master list built while streaming records: one entry per station
+// (coordinates, lines served, total movements) plus the chainage each line
+// places the station at, from which per-line station orderings are derived.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use clap::ValueEnum;
+
+#[derive(Debug, Default, Clone)]
+pub struct StationInfo {
+    pub latitude: String,
+    pub longitude: String,
+    pub lines: BTreeSet<String>,
+    pub boardings: i64,
+    pub alightings: i64,
+    // Movements per business hour (0 = --day-start, default 03:00) across
+    // every date.
+    pub hourly: [i64; 24],
+}
+
+impl StationInfo {
+    pub fn total_movements(&self) -> i64 {
+        self.boardings + self.alightings
+    }
+}
+
+/// One station's totals on one line, for `--by-station`.
+#[derive(Debug, Default)]
+pub struct LineStation {
+    pub boardings: i64,
+    pub alightings: i64,
+    // Stop_Sequence_Number -> number of records, for Down and Up services
+    // apart since each numbers the stops from its own origin.
+    sequences: [HashMap<i32, u32>; 2],
+}
+
+impl LineStation {
+    /// The station's most common Stop_Sequence_Number on Down services,
+    /// which number the stops from the city outwards, or on Up services
+    /// when no Down service stops there.
+    pub fn sequence(&self) -> Option<i32> {
+        self.sequences.iter().find_map(|counts| {
+            counts.iter()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                .map(|(&sequence, _)| sequence)
+        })
+    }
+}
+
+/// A station on one line, as written to the line's `--by-station` file.
+#[derive(Debug)]
+pub struct StationRow<'a> {
+    pub station: &'a str,
+    pub sequence: Option<i32>,
+    pub chainage: i32,
+    pub totals: &'a LineStation,
+}
+
+/// The order of the rows in each line's `--by-station` file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum StationSort {
+    #[default]
+    Sequence,
+    Chainage,
+}
+
+#[derive(Debug, Default)]
+pub struct StationIndex {
+    pub stations: BTreeMap<String, StationInfo>,
+    // line -> station -> chainage -> number of records observed at it
+    chainages: BTreeMap<String, BTreeMap<String, HashMap<i32, u32>>>,
+    // line -> station -> totals on that line
+    line_stations: BTreeMap<String, BTreeMap<String, LineStation>>,
+}
+
+impl StationIndex {
+    pub fn new() -> StationIndex {
+        StationIndex::default()
+    }
+
+    pub fn add(
+        &mut self,
+        line: &str,
+        station: &str,
+        latitude: &str,
+        longitude: &str,
+        chainage: Option<i32>,
+        boardings: i32,
+        alightings: i32,
+    ) {
+        let info = self.stations.entry(station.to_string()).or_default();
+        if info.latitude.is_empty() && !latitude.is_empty() {
+            info.latitude = latitude.to_string();
+            info.longitude = longitude.to_string();
+        }
+        if !info.lines.contains(line) {
+            info.lines.insert(line.to_string());
+        }
+        info.boardings += boardings as i64;
+        info.alightings += alightings as i64;
+
+        // A stop without a chainage doesn't place the station on the line.
+        if let Some(chainage) = chainage {
+            *self.chainages.entry(line.to_string()).or_default()
+                .entry(station.to_string()).or_default()
+                .entry(chainage).or_insert(0) += 1;
+        }
+    }
+
+    /// Adds a stop's boardings and alightings to the station's totals on the
+    /// line, and its position in the service's stopping pattern.
+    pub fn add_stop(&mut self, line: &str, station: &str, direction: &str, sequence: i32, boardings: i32, alightings: i32) {
+        let totals = self.line_stations.entry(line.to_string()).or_default()
+            .entry(station.to_string()).or_default();
+        totals.boardings += boardings as i64;
+        totals.alightings += alightings as i64;
+        let up = usize::from(direction.trim() == "U");
+        *totals.sequences[up].entry(sequence).or_insert(0) += 1;
+    }
+
+    /// Each line's stations with their totals on the line, modal stop
+    /// sequence and chainage (from `line_sequences`), ordered by `sort`.
+    /// Stations without a sequence sort last.
+    pub fn line_stations<'a>(
+        &'a self,
+        sequences: &BTreeMap<String, Vec<(String, i32)>>,
+        sort: StationSort,
+    ) -> BTreeMap<&'a str, Vec<StationRow<'a>>> {
+        let mut lines = BTreeMap::new();
+        for (line, stations) in &self.line_stations {
+            let chainages: HashMap<&str, i32> = sequences.get(line).into_iter()
+                .flatten()
+                .map(|(station, chainage)| (station.as_str(), *chainage))
+                .collect();
+            let mut rows: Vec<StationRow> = stations.iter()
+                .map(|(station, totals)| StationRow {
+                    station,
+                    sequence: totals.sequence(),
+                    chainage: chainages.get(station.as_str()).copied().unwrap_or(0),
+                    totals,
+                })
+                .collect();
+            match sort {
+                StationSort::Sequence => rows.sort_by_key(|row| (row.sequence.is_none(), row.sequence, row.chainage, row.station)),
+                StationSort::Chainage => rows.sort_by_key(|row| (row.chainage, row.station)),
+            }
+            lines.insert(line.as_str(), rows);
+        }
+        lines
+    }
+
+    /// Adds movements to a station's business-hour profile.
+    pub fn add_hourly(&mut self, station: &str, business_hour: usize, movements: i32) {
+        self.stations.entry(station.to_string()).or_default().hourly[business_hour] += movements as i64;
+    }
+
+    /// Orders every station seen on each line by its modal chainage, giving
+    /// the superset of stops served by expresses and stoppers alike. Returns
+    /// the orderings and a description of each conflict found: stations
+    /// recorded at more than one chainage, and stations sharing a chainage.
+    pub fn line_sequences(&self) -> (BTreeMap<String, Vec<(String, i32)>>, Vec<String>) {
+        let mut sequences = BTreeMap::new();
+        let mut conflicts = Vec::new();
+
+        for (line, stations) in &self.chainages {
+            let mut ordered: Vec<(String, i32)> = Vec::with_capacity(stations.len());
+            for (station, counts) in stations {
+                // Most frequently recorded chainage, lowest value on ties.
+                let (&modal, _) = counts.iter()
+                    .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                    .expect("station recorded without a chainage");
+                if counts.len() > 1 {
+                    conflicts.push(format!(
+                        "{}: {} recorded at {} different chainages, using {}",
+                        line, station, counts.len(), modal
+                    ));
+                }
+                ordered.push((station.clone(), modal));
+            }
+            ordered.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+            for pair in ordered.windows(2) {
+                if pair[0].1 == pair[1].1 {
+                    conflicts.push(format!(
+                        "{}: {} and {} share chainage {}",
+                        line, pair[0].0, pair[1].0, pair[0].1
+                    ));
+                }
+            }
+            sequences.insert(line.clone(), ordered);
+        }
+
+        (sequences, conflicts)
+    }
+}
+
+/// Returns the movements threshold at percentile `p` (0-100) of the
+/// stations' total movements, using the nearest-rank method.
+pub fn percentile_threshold(stations: &BTreeMap<String, StationInfo>, p: f64) -> i64 {
+    let mut totals: Vec<i64> = stations.values().map(|info| info.total_movements()).collect();
+    if totals.is_empty() {
+        return 0;
+    }
+    totals.sort();
+    let rank = ((p / 100.0) * totals.len() as f64).ceil() as usize;
+    totals[rank.clamp(1, totals.len()) - 1]
+}
+
+/// Normalizes a station name for matching across sources: surrounding and
+/// repeated whitespace is dropped and case is ignored.
+pub fn normalize_station(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_stations_follow_the_down_stopping_pattern() {
+        let mut index = StationIndex::new();
+        // Two Down stoppers and an Up stopper over the same three stations,
+        // and a Down express that skips Richmond.
+        let stopper = [("Flinders Street", 1, 0), ("Richmond", 2, 2300), ("Caulfield", 3, 9000)];
+        for (direction, stops) in [
+            ("D", stopper.as_slice()),
+            ("D", stopper.as_slice()),
+            ("U", [("Caulfield", 1, 9000), ("Richmond", 2, 2300), ("Flinders Street", 3, 0)].as_slice()),
+            ("D", [("Flinders Street", 1, 0), ("Caulfield", 2, 9000)].as_slice()),
+        ] {
+            for &(station, sequence, chainage) in stops {
+                index.add("Pakenham", station, "", "", Some(chainage), 10, 4);
+                index.add_stop("Pakenham", station, direction, sequence, 10, 4);
+            }
+        }
+        let (sequences, _) = index.line_sequences();
+
+        let by_sequence = index.line_stations(&sequences, StationSort::Sequence);
+        let rows: Vec<(&str, Option<i32>)> = by_sequence["Pakenham"].iter()
+            .map(|row| (row.station, row.sequence))
+            .collect();
+        assert_eq!(rows, vec![("Flinders Street", Some(1)), ("Richmond", Some(2)), ("Caulfield", Some(3))]);
+        let caulfield = &by_sequence["Pakenham"][2];
+        assert_eq!((caulfield.chainage, caulfield.totals.boardings, caulfield.totals.alightings), (9000, 40, 16));
+
+        let by_chainage = index.line_stations(&sequences, StationSort::Chainage);
+        let stations: Vec<&str> = by_chainage["Pakenham"].iter().map(|row| row.station).collect();
+        assert_eq!(stations, vec!["Flinders Street", "Richmond", "Caulfield"]);
+    }
+
+    #[test]
+    fn conflicting_chainages_use_the_modal_one() {
+        let mut index = StationIndex::new();
+        // Richmond is mostly recorded at 2300 but once at 9500, which would
+        // put it after Caulfield; South Yarra shares Caulfield's chainage.
+        for (station, chainage) in [
+            ("Flinders Street", 0),
+            ("Richmond", 2300),
+            ("Richmond", 9500),
+            ("Richmond", 2300),
+            ("Caulfield", 9000),
+            ("South Yarra", 9000),
+        ] {
+            index.add("Pakenham", station, "", "", Some(chainage), 10, 4);
+        }
+        let (sequences, conflicts) = index.line_sequences();
+
+        let order: Vec<(&str, i32)> = sequences["Pakenham"].iter()
+            .map(|(station, chainage)| (station.as_str(), *chainage))
+            .collect();
+        assert_eq!(order, vec![("Flinders Street", 0), ("Richmond", 2300), ("Caulfield", 9000), ("South Yarra", 9000)]);
+        assert_eq!(conflicts, vec![
+            "Pakenham: Richmond recorded at 2 different chainages, using 2300".to_string(),
+            "Pakenham: Caulfield and South Yarra share chainage 9000".to_string(),
+        ]);
+    }
+}
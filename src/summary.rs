@@ -0,0 +1,258 @@
+// processed/summary.json, written by generateData, generateData-15min and
+// generateData5min with `--json` alongside their CSVs, or with `--format
+// json` in place of the per-line ones: every line's time series and totals
+// in one file, for dashboards that want JSON rather than one CSV per line.
+// It's `Summary` serialized with serde_json, so the structs below are the
+// layout. It's stable; fields may be added but are never renamed or removed:
+//
+// {
+//   "interval_minutes": 60,
+//   "day_start": "03:00",
+//   "lines": [
+//     {
+//       "line": "Pakenham",
+//       "boardings": 5210,
+//       "alightings": 4980,
+//       "services": 812,
+//       "mean_movements_per_service": 12.55,
+//       "peak_hour": 4,
+//       "series": [
+//         {"business_date": "2022-09-05", "movements": [0.0, 0.0, 41.0, 388.0, ...]}
+//       ]
+//     }
+//   ],
+//   "timings": {"aggregation": 5120, "csv writing": 48}
+// }
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::business::{DayStart, peak_hour};
+use crate::timing::StageTimings;
+
+/// `--format csv|json`: whether summary.json replaces the per-line CSVs.
+/// CSV is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// The contents of summary.json.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    /// Minutes each entry of a series covers: 60, 15 or the block size.
+    pub interval_minutes: u32,
+    /// Clock time the first entry of every series starts at, e.g. "03:00".
+    pub day_start: String,
+    /// In line name order.
+    pub lines: Vec<LineSummary>,
+    /// Milliseconds spent in each stage of the run that wrote the file, by
+    /// stage name, up to when it was written.
+    #[serde(default)]
+    pub timings: BTreeMap<String, u64>,
+}
+
+/// One line's totals over every record read, and its time series.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineSummary {
+    pub line: String,
+    pub boardings: i64,
+    pub alightings: i64,
+    /// Distinct services, a service being a train number on one business
+    /// date.
+    pub services: i64,
+    /// Boardings plus alightings per service; null without services.
+    pub mean_movements_per_service: Option<f64>,
+    /// The business hour with the most movements over every series, the
+    /// earliest on a tie; null if the series have none.
+    pub peak_hour: Option<usize>,
+    /// In business date order.
+    pub series: Vec<Series>,
+}
+
+/// Movements in each interval of the business day, on one date or, where
+/// `business_date` is `None` (null), averaged over several.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Series {
+    pub business_date: Option<String>,
+    pub movements: Vec<f64>,
+}
+
+impl LineSummary {
+    /// Recomputes the mean and peak hour from the totals and series.
+    fn update_statistics(&mut self, interval_minutes: u32) {
+        self.mean_movements_per_service = match self.services {
+            0 => None,
+            services => Some((self.boardings + self.alightings) as f64 / services as f64),
+        };
+        let mut hours: Vec<f64> = Vec::new();
+        for series in &self.series {
+            for (hour, movements) in hourly_movements(&series.movements, interval_minutes).into_iter().enumerate() {
+                if hours.len() <= hour {
+                    hours.resize(hour + 1, 0.0);
+                }
+                hours[hour] += movements;
+            }
+        }
+        let hours: Vec<i32> = hours.iter().map(|movements| movements.round() as i32).collect();
+        self.peak_hour = peak_hour(&hours);
+    }
+}
+
+/// Sums a series of `interval_minutes` entries into business hours.
+pub fn hourly_movements(movements: &[f64], interval_minutes: u32) -> Vec<f64> {
+    let mut hours = Vec::new();
+    for (i, &count) in movements.iter().enumerate() {
+        let hour = i * interval_minutes as usize / 60;
+        if hours.len() <= hour {
+            hours.resize(hour + 1, 0.0);
+        }
+        hours[hour] += count;
+    }
+    hours
+}
+
+impl Summary {
+    pub fn new(interval_minutes: u32, day_start: DayStart) -> Summary {
+        Summary { interval_minutes, day_start: day_start.hour_label(0), lines: Vec::new(), timings: BTreeMap::new() }
+    }
+
+    /// The line's entry, added in name order if it's new.
+    fn line(&mut self, line: &str) -> &mut LineSummary {
+        let i = match self.lines.binary_search_by(|summary| summary.line.as_str().cmp(line)) {
+            Ok(i) => i,
+            Err(i) => {
+                self.lines.insert(i, LineSummary { line: line.to_string(), ..LineSummary::default() });
+                i
+            }
+        };
+        &mut self.lines[i]
+    }
+
+    /// Sets a line's totals.
+    pub fn add_totals(&mut self, line: &str, boardings: i64, alightings: i64, services: i64) {
+        let interval_minutes = self.interval_minutes;
+        let summary = self.line(line);
+        summary.boardings = boardings;
+        summary.alightings = alightings;
+        summary.services = services;
+        summary.update_statistics(interval_minutes);
+    }
+
+    /// Adds a time series to a line, keeping its series in date order.
+    pub fn add_series(&mut self, line: &str, business_date: Option<&str>, movements: &[f64]) {
+        let interval_minutes = self.interval_minutes;
+        let summary = self.line(line);
+        summary.series.push(Series {
+            business_date: business_date.map(str::to_string),
+            movements: movements.to_vec(),
+        });
+        summary.series.sort_by(|a, b| a.business_date.cmp(&b.business_date));
+        summary.update_statistics(interval_minutes);
+    }
+
+    /// Records the run's stage timings so far.
+    pub fn add_timings(&mut self, timings: &StageTimings) {
+        for (name, millis) in timings.millis() {
+            *self.timings.entry(name.to_string()).or_insert(0) += millis;
+        }
+    }
+
+    /// Writes summary.json to `path`.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut summary = Summary::new(15, DayStart::default());
+        summary.add_totals("Stony Point", 12, 10, 4);
+        summary.add_totals("Pakenham", 5_210, 4_980, 812);
+        summary.add_series("Pakenham", Some("2022-09-06"), &[2.0, 40.0, 391.0]);
+        summary.add_series("Pakenham", Some("2022-09-05"), &[0.0, 41.0, 388.5]);
+        summary.add_totals("Sandringham \"Bayside\"", 1_000, 900, 120);
+        summary.add_series("Sandringham \"Bayside\"", None, &[1.25, 0.0, 7.0]);
+
+        let names: Vec<&str> = summary.lines.iter().map(|line| line.line.as_str()).collect();
+        assert_eq!(names, ["Pakenham", "Sandringham \"Bayside\"", "Stony Point"]);
+        assert_eq!(summary.lines[0].series[0].business_date.as_deref(), Some("2022-09-05"));
+
+        let path = std::env::temp_dir().join(format!("ptv_data_summary_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        summary.write(path).unwrap();
+        let json = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let read: Summary = serde_json::from_str(&json).unwrap_or_else(|err| panic!("{}:\n{}", err, json));
+        assert_eq!(read, summary);
+
+        // The documented field names, and null for an averaged series.
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["interval_minutes"], 15);
+        assert_eq!(value["day_start"], "03:00");
+        assert_eq!(value["lines"][0]["line"], "Pakenham");
+        assert_eq!(value["lines"][0]["services"], 812);
+        assert_eq!(value["lines"][0]["series"][1]["movements"][2], 391.0);
+        assert!(value["lines"][1]["series"][0]["business_date"].is_null());
+        assert_eq!(value["lines"][2]["series"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn statistics_and_timings_survive_the_round_trip() {
+        // 15-minute series: Pakenham's two days peak in business hour 1
+        // together, though the first day alone peaks in hour 0.
+        let mut summary = Summary::new(15, DayStart::default());
+        summary.add_totals("Pakenham", 5_210, 4_980, 812);
+        summary.add_series("Pakenham", Some("2022-09-05"), &[10.0, 20.0, 30.0, 40.0, 0.0, 50.0, 0.0, 0.0]);
+        summary.add_series("Pakenham", Some("2022-09-06"), &[0.0, 0.0, 0.0, 0.0, 30.0, 30.0, 30.0, 0.0]);
+        summary.add_totals("Stony Point", 12, 10, 4);
+        summary.add_series("Stony Point", None, &[0.0; 8]);
+        summary.add_totals("Upfield", 0, 0, 0);
+
+        let mut timings = StageTimings::new();
+        timings.start("aggregation");
+        timings.end(Some(3));
+        timings.start("csv writing");
+        summary.add_timings(&timings);
+
+        let json = serde_json::to_string_pretty(&summary).unwrap();
+        let read: Summary = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.timings, summary.timings);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let stages: Vec<&String> = value["timings"].as_object().unwrap().keys().collect();
+        assert_eq!(stages, ["aggregation", "csv writing"]);
+        assert!(value["timings"]["aggregation"].is_u64());
+        let statistics: Vec<(Option<f64>, Option<usize>)> = read.lines.iter()
+            .map(|line| (line.mean_movements_per_service, line.peak_hour))
+            .collect();
+        assert_eq!(statistics, [
+            (Some(10_190.0 / 812.0), Some(1)),
+            (Some(5.5), None),
+            (None, None),
+        ]);
+
+        // Keys keep the documented order, totals before the series.
+        let positions: Vec<usize> = ["\"line\"", "\"services\"", "\"mean_movements_per_service\"", "\"peak_hour\"", "\"series\""]
+            .iter().map(|key| json.find(key).unwrap()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{}", json);
+    }
+
+    #[test]
+    fn csv_unless_json_is_asked_for() {
+        assert_eq!(OutputFormat::default(), OutputFormat::Csv);
+        assert_eq!(OutputFormat::from_str("csv", false), Ok(OutputFormat::Csv));
+        assert_eq!(OutputFormat::from_str("json", false), Ok(OutputFormat::Json));
+        assert!(OutputFormat::from_str("xml", false).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// Lightweight wall-clock instrumentation for the stages of a run
+// (aggregation, CSV writing, chart rendering).
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub struct StageTiming {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    // Rows handled by the stage, if it is row-oriented.
+    pub rows: Option<u64>,
+}
+
+impl StageTiming {
+    pub fn rows_per_sec(&self) -> Option<f64> {
+        let secs = self.elapsed.as_secs_f64();
+        self.rows.filter(|_| secs > 0.0).map(|rows| rows as f64 / secs)
+    }
+}
+
+#[derive(Default)]
+pub struct StageTimings {
+    stages: Vec<StageTiming>,
+    current: Option<(&'static str, Instant)>,
+}
+
+impl StageTimings {
+    pub fn new() -> StageTimings {
+        StageTimings::default()
+    }
+
+    /// Starts timing a stage. Any stage still running is ended without a row count.
+    pub fn start(&mut self, name: &'static str) {
+        if self.current.is_some() {
+            self.end(None);
+        }
+        self.current = Some((name, Instant::now()));
+    }
+
+    /// Ends the running stage, recording how many rows it handled.
+    pub fn end(&mut self, rows: Option<u64>) {
+        if let Some((name, started)) = self.current.take() {
+            self.stages.push(StageTiming { name, elapsed: started.elapsed(), rows });
+        }
+    }
+
+    pub fn stages(&self) -> &[StageTiming] {
+        &self.stages
+    }
+
+    /// Wall time in milliseconds per stage, in the order they ran, with the
+    /// running stage so far.
+    pub fn millis(&self) -> Vec<(&'static str, u64)> {
+        let running = self.current.map(|(name, started)| (name, started.elapsed()));
+        self.stages.iter()
+            .map(|stage| (stage.name, stage.elapsed))
+            .chain(running)
+            .map(|(name, elapsed)| (name, elapsed.as_millis() as u64))
+            .collect()
+    }
+
+    /// Prints one line per stage with its wall time and throughput.
+    pub fn print_summary(&self) {
+        // Nothing useful can be done if stdout is gone.
+        let _ = self.write_summary(&mut io::stdout());
+    }
+
+    /// Writes the lines `print_summary` prints to `out`, e.g. stderr when
+    /// stdout carries the output.
+    pub fn write_summary(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Stage timings:")?;
+        for stage in &self.stages {
+            match stage.rows_per_sec() {
+                Some(rate) => writeln!(
+                    out,
+                    "  {:<16} {:>8.2}s  {} rows ({:.0} rows/s)",
+                    stage.name,
+                    stage.elapsed.as_secs_f64(),
+                    stage.rows.unwrap_or(0),
+                    rate
+                )?,
+                None => writeln!(out, "  {:<16} {:>8.2}s", stage.name, stage.elapsed.as_secs_f64())?,
+            }
+        }
+        Ok(())
+    }
+}
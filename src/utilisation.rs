@@ -0,0 +1,9 @@
+// `utilisation`: see `ptv_data::commands::utilisation`, which `ptv-data` also runs.
+
+use std::env;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    ptv_data::commands::utilisation::run(&args)
+}